@@ -6,7 +6,10 @@ import "core:bytes"
 import "core:encoding/json"
 import "core:io"
 import "core:net"
-import "core:c"
+import "core:runtime"
+import "core:strings"
+import "core:sync"
+import "core:time"
 
 import http ".."
 import openssl "../openssl"
@@ -16,12 +19,16 @@ Request :: struct {
 	headers: http.Headers,
 	cookies: [dynamic]http.Cookie,
 	body:    bytes.Buffer,
+	// Sent as the body instead of body when set, see with_reader.
+	body_stream: Maybe(io.Reader),
+	// The length of body_stream, -1 when it is not known up front.
+	body_length: int,
 }
 
 // Initializes the request with sane defaults using the given allocator.
 request_init :: proc(r: ^Request, method := http.Method.Get, allocator := context.allocator) {
 	r.method = method
-	r.headers = make(http.Headers, 3, allocator)
+	http.headers_init(&r.headers, allocator)
 	r.cookies = make([dynamic]http.Cookie, allocator)
 	bytes.buffer_init_allocator(&r.body, 0, 0, allocator)
 }
@@ -30,14 +37,14 @@ request_init :: proc(r: ^Request, method := http.Method.Get, allocator := contex
 // Header keys and values that the user added will have to be deleted by the user.
 // Same with any strings inside the cookies.
 request_destroy :: proc(r: ^Request) {
-	delete(r.headers)
+	http.headers_destroy(&r.headers)
 	delete(r.cookies)
 	bytes.buffer_destroy(&r.body)
 }
 
 with_json :: proc(r: ^Request, v: any, opt: json.Marshal_Options = {}) -> json.Marshal_Error {
 	r.method = .Post
-	r.headers["content-type"] = http.mime_to_content_type(.Json)
+	http.headers_set(&r.headers, "content-type", http.mime_to_content_type(.Json))
 
 	stream := bytes.buffer_to_stream(&r.body)
 	opt := opt
@@ -45,6 +52,16 @@ with_json :: proc(r: ^Request, v: any, opt: json.Marshal_Options = {}) -> json.M
 	return nil
 }
 
+// Sets the body to everything read from the reader (until .EOF), it is sent as it is read, instead of being kept in memory.
+// When the length is not known up front (-1), the body is sent with "Transfer-Encoding: chunked".
+//
+// Requests with a body stream are not retried when a reused connection turns out to be closed,
+// the stream can not be read again.
+with_reader :: proc(r: ^Request, body: io.Reader, length := -1) {
+	r.body_stream = body
+	r.body_length = length
+}
+
 get :: proc(target: string, allocator := context.allocator) -> (Response, Error) {
 	r: Request
 	request_init(&r, .Get, allocator)
@@ -58,12 +75,43 @@ Request_Error :: enum {
 	Invalid_Response_Method,
 	Invalid_Response_Header,
 	Invalid_Response_Cookie,
+	// The connection was closed before a response was received.
+	Connection_Closed,
+	// Reading the body stream of the request failed, see with_reader.
+	Body_Stream_Failed,
 }
 
 SSL_Error :: enum {
+	// The server closed the connection during the handshake.
 	Controlled_Shutdown,
-	Fatal_Shutdown,
+	// The handshake failed for a reason not covered by the other errors.
+	Handshake_Failed,
 	SSL_Write_Failed,
+	// Could not create the OpenSSL context.
+	Context_Failed,
+	// Could not load the system trust store, or the given CA file/directory.
+	CA_Load_Failed,
+	// The minimum or maximum TLS version could not be set.
+	Invalid_Version,
+	// Could not load the client certificate (chain) file.
+	Certificate_Failed,
+	// Could not load the private key file of the client certificate.
+	Private_Key_Failed,
+	// The private key does not belong to the client certificate.
+	Private_Key_Mismatch,
+	// Could not set the server name or the host name to verify.
+	Invalid_Host,
+	// The client and server have no TLS version in common.
+	Protocol_Version,
+	// The certificate is not signed by a trusted CA.
+	Certificate_Untrusted,
+	Certificate_Expired,
+	Certificate_Not_Yet_Valid,
+	Certificate_Revoked,
+	// The certificate is not valid for the host name (or IP address) that was requested.
+	Hostname_Mismatch,
+	// The certificate failed verification for another reason.
+	Certificate_Invalid,
 }
 
 Error :: union {
@@ -75,58 +123,158 @@ Error :: union {
 	SSL_Error,
 }
 
+// Sends the request over a new connection, which is closed when the response is destroyed.
+// Use a Client to reuse connections between requests.
 request :: proc(target: string, request: ^Request, allocator := context.allocator) -> (res: Response, err: Error) {
-	url, endpoint := parse_endpoint(target) or_return
+	return do_request(nil, target, request, allocator)
+}
+
+Client_Opts :: struct {
+	// The maximum amount of idle connections that are kept around for a single host.
+	max_idle_per_host: int,
+
+	// Idle connections that have not been used for this long are closed instead of reused.
+	// 0 uses Default_Idle_Timeout.
+	idle_timeout:      time.Duration,
+
+	// Configuration of HTTPS connections, by default certificates are verified against the system trust store.
+	tls:               TLS_Opts,
+
+	// The maximum size of a body after decoding its gzip or deflate content encoding,
+	// a small compressed body can decompress to gigabytes (a zip bomb).
+	// 0 uses Default_Max_Decompressed_Size, -1 means no limit.
+	max_decompressed_size: int,
+}
+
+// The maximum size of a decompressed body, for clients that don't set one and requests without a Client.
+Default_Max_Decompressed_Size :: 64 << 20
+
+// How long connections are kept idle, for clients that don't set it.
+Default_Idle_Timeout :: 90 * time.Second
+
+Default_Client_Opts :: Client_Opts{
+	max_idle_per_host     = 2,
+	idle_timeout          = Default_Idle_Timeout,
+	max_decompressed_size = Default_Max_Decompressed_Size,
+}
+
+// A Client keeps connections alive after a response is destroyed, so following requests
+// to the same host can reuse them instead of connecting (and doing a TLS handshake) again.
+//
+// A Client can be used by multiple threads at the same time.
+Client :: struct {
+	opts:      Client_Opts,
+	allocator: runtime.Allocator,
+
+	// Idle connections, keyed by scheme and host, most recently used last.
+	_idle:     map[string][dynamic]Idle_Connection,
+	// Shared by all HTTPS connections of this client, created on the first HTTPS request.
+	_ssl_ctx:  ^openssl.SSL_CTX,
+	_mu:       sync.Mutex,
+}
+
+client_init :: proc(client: ^Client, opts := Default_Client_Opts, allocator := context.allocator) {
+	client.opts = opts
+	client.allocator = allocator
+	client._idle = make(map[string][dynamic]Idle_Connection, 16, allocator)
+}
+
+// Closes all idle connections and frees the client.
+// Responses that are still alive when this is called must be destroyed before it.
+client_destroy :: proc(client: ^Client) {
+	sync.guard(&client._mu)
+
+	for key, conns in client._idle {
+		for conn in conns {
+			communication_close(conn.comm)
+		}
+		delete(conns)
+		delete(key, client.allocator)
+	}
+	delete(client._idle)
+
+	if client._ssl_ctx != nil {
+		openssl.SSL_CTX_free(client._ssl_ctx)
+		client._ssl_ctx = nil
+	}
+}
+
+// Sends the request using an idle connection to the host if there is one, connecting otherwise.
+// The connection is given back to the client when the response is destroyed.
+client_request :: proc(client: ^Client, target: string, request: ^Request, allocator := context.allocator) -> (res: Response, err: Error) {
+	return do_request(client, target, request, allocator)
+}
+
+client_get :: proc(client: ^Client, target: string, allocator := context.allocator) -> (Response, Error) {
+	r: Request
+	request_init(&r, .Get, allocator)
+	defer request_destroy(&r)
+
+	return client_request(client, target, &r, allocator)
+}
+
+@(private)
+do_request :: proc(client: ^Client, target: string, request: ^Request, allocator := context.allocator) -> (res: Response, err: Error) {
+	url, endpoint, hostname := parse_endpoint(target) or_return
 	defer delete(url.queries)
 
-	// NOTE: we don't support persistent connections yet.
-	request.headers["connection"] = "close"
+	// Without a client there is nothing to give the connection back to,
+	// unless the user set the header, to upgrade the connection for example.
+	if client == nil && !http.headers_has(&request.headers, "connection") {
+		http.headers_set(&request.headers, "connection", "close")
+	}
+
+	// The body is decoded when we asked for an encoding, and not the user.
+	decode := !http.headers_has(&request.headers, "accept-encoding")
 
 	req_buf := format_request(url, request, allocator)
 	defer bytes.buffer_destroy(&req_buf)
 
-	socket := net.dial_tcp(endpoint) or_return
+	key: string
+	if client != nil {
+		key = strings.concatenate({url.scheme, "://", url.host}, allocator)
+	}
 
-	// HTTPS using openssl.
-	if url.scheme == "https" {
-		using openssl
+	// A server can close an idle connection at any time, we only find out when using it.
+	// In that case the request is retried on a new connection, if sending it twice is safe.
+	for attempt := 0; attempt < 2; attempt += 1 {
+		comm: Communication
+		reused: bool
+		if client != nil && attempt == 0 {
+			comm, reused = client_take_idle(client, key)
+		}
 
-		ctx := SSL_CTX_new(TLS_client_method())
-		ssl := SSL_new(ctx)
-		SSL_set_fd(ssl, c.int(socket))
+		if !reused {
+			comm, err = connect(client, url, endpoint, hostname)
+			if err != nil do break
+		}
 
-		switch SSL_connect(ssl) {
-		case 2:
-			err = SSL_Error.Controlled_Shutdown
-			return
-		case 1: // success
-		case:
-			err = SSL_Error.Fatal_Shutdown
+		res, err = send_request(comm, &req_buf, request.body_stream, http.headers_chunked(&request.headers), allocator)
+		if err == nil {
+			res._client   = client
+			res._pool_key = key
+			res._method   = request.method
+			res._decode   = decode
+			res._max_decompressed = client_max_decompressed(client)
 			return
 		}
 
-		buf := bytes.buffer_to_bytes(&req_buf)
-		to_write := len(buf)
-		for to_write > 0 {
-			ret := SSL_write(ssl, raw_data(buf), c.int(to_write))
-			if ret <= 0 {
-				err = SSL_Error.SSL_Write_Failed
-				return
-			}
-
-			to_write -= int(ret)
-		}
+		communication_close(comm)
 
-		return parse_response(SSL_Communication{
-			ssl    = ssl,
-			ctx    = ctx,
-			socket = socket,
-		}, allocator)
+		if !reused || !method_idempotent(request.method) || request.body_stream != nil do break
 	}
 
-	// HTTP, just send the request.
-	net.send_tcp(socket, bytes.buffer_to_bytes(&req_buf)) or_return
-	return parse_response(socket, allocator)
+	delete(key, allocator)
+	return
+}
+
+// The limit on decompressed bodies of the client, so options that leave it out are still protected.
+@(private)
+client_max_decompressed :: proc(client: ^Client) -> int {
+	if client == nil || client.opts.max_decompressed_size == 0 {
+		return Default_Max_Decompressed_Size
+	}
+	return client.opts.max_decompressed_size
 }
 
 Response :: struct {
@@ -134,49 +282,160 @@ Response :: struct {
 	// headers and cookies should be considered read-only, after a response is returned.
 	headers:   http.Headers,
 	cookies:   [dynamic]http.Cookie,
-	_socket:   Communication,
-	_body:     bufio.Scanner,
-	_body_err: http.Body_Error,
+	_socket:    Communication,
+	_body:      bufio.Scanner,
+	_body_err:  http.Body_Error,
+	_body_read: bool,
+	_version:   http.Version,
+	_method:    http.Method,
+	_client:    ^Client,
+	_pool_key:  string,
+	// The allocator given to the request, the header keys and the pool key are allocated with it.
+	_allocator: runtime.Allocator,
+	// Whether response_body decodes the content encoding.
+	_decode:    bool,
+	_max_decompressed: int,
+	// Set by response_body_reader.
+	_body_reader: ^http.Body_Reader,
+	// Whether the connection was taken over with response_hijack.
+	_hijacked:  bool,
 }
 
-// Frees the response, closes the connection.
+// Frees the response, the connection is given back to the client if it can be reused, closed otherwise.
 // Optionally pass the response_body returned 'body' and 'was_allocation' to destroy it too.
 response_destroy :: proc(res: ^Response, body: Maybe(http.Body_Type) = nil, was_allocation := false) {
-	// Header keys are allocated, values are slices into the body.
-	for k in res.headers {
-		delete(k)
-	}
-	delete(res.headers)
-
-	bufio.scanner_destroy(&res._body)
-
-	// Cookies only contain slices to memory inside the scanner body.
-	// So just deleting the array will be enough.
-	delete(res.cookies)
-
 	if body != nil {
 		body_destroy(body.(http.Body_Type), was_allocation)
 	}
 
 	// We close now and not at the time we got the response because reading the body,
 	// could make more reads need to happen (like with chunked encoding).
-	switch comm in res._socket {
-	case net.TCP_Socket:
-		net.close(comm)
-	case SSL_Communication:
-		openssl.SSL_free(comm.ssl)
-		openssl.SSL_CTX_free(comm.ctx)
-		net.close(comm.socket)
+	if res._client != nil && response_reusable(res) {
+		client_put_idle(res._client, res._pool_key, res._socket)
+	} else {
+		communication_close(res._socket)
 	}
+	delete(res._pool_key, res._allocator)
+
+	response_free(res)
 }
 
 body_destroy :: http.body_destroy
 
 // Retrieves the response's body, can only be called once.
 // Free the returned body using body_destroy().
+//
+// A body with a gzip or deflate content encoding is decompressed, unless the accept-encoding header was set on the request,
+// max_length limits both the received and the decompressed size. The content-encoding and content-length headers
+// are removed from the response after decoding, they describe the body as it was received.
+// Use response_body_raw to get the body as it was received.
 response_body :: proc(res: ^Response, max_length := -1, allocator := context.allocator) -> (body: http.Body_Type, was_allocation: bool, err: http.Body_Error) {
 	defer res._body_err = err
-	assert(res._body_err == nil)
-    body, was_allocation, err = http.parse_body(&res.headers, &res._body, max_length, allocator)
+	assert(!res._body_read)
+	res._body_read = true
+
+	plain: http.Body_Plain
+	plain, was_allocation = http.parse_body_plain(&res.headers, &res._body, max_length, allocator) or_return
+
+	if res._decode {
+		max_size := res._max_decompressed
+		if max_length > -1 && (max_size <= 0 || max_length < max_size) {
+			max_size = max_length
+		}
+		plain, was_allocation = body_decode(&res.headers, plain, was_allocation, max_size, allocator) or_return
+	}
+	body = plain
+
+	// Automatically decode url encoded bodies.
+	if typ, ok := http.headers_get(&res.headers, "content-type"); ok && typ == "application/x-www-form-urlencoded" {
+		defer if was_allocation do delete(plain, allocator)
+		body = http.body_url_decode(plain, allocator)
+	}
 	return
 }
+
+// Retrieves the response's body as it was received, without decoding its content encoding or url encoded values.
+// Like response_body, it can only be called once, free the returned body using body_destroy().
+response_body_raw :: proc(res: ^Response, max_length := -1, allocator := context.allocator) -> (body: http.Body_Plain, was_allocation: bool, err: http.Body_Error) {
+	defer res._body_err = err
+	assert(!res._body_read)
+	res._body_read = true
+	return http.parse_body_plain(&res.headers, &res._body, max_length, allocator)
+}
+
+// Returns a reader over the response body, it is read from the connection as you read from the reader,
+// instead of reading it into memory first like response_body does, so bodies of any size can be passed on.
+//
+// The body is returned as it was received, its content encoding is not decoded.
+// Trailers of a chunked body are added to the response headers once the reader returns .EOF.
+// When reading goes wrong, the reader returns an io.Error and the details can be retrieved using response_body_error.
+//
+// Can only be called once, and not together with response_body or response_body_raw.
+response_body_reader :: proc(res: ^Response, max_length := -1, allocator := context.allocator) -> (r: io.Reader, err: http.Body_Error) {
+	defer res._body_err = err
+	assert(!res._body_read)
+	res._body_read = true
+
+	br := new(http.Body_Reader, allocator)
+	res._body_reader = br
+
+	// A response without a length ends when the server closes the connection.
+	http.body_reader_init(br, &res.headers, &res._body, &res._body_err, max_length, true, allocator) or_return
+	if !response_has_body(res) do br.done = true
+
+	return http.body_reader_to_reader(br), nil
+}
+
+// Returns the error that occurred while reading the body with the reader from response_body_reader.
+response_body_error :: proc(res: ^Response) -> http.Body_Error {
+	return res._body_err
+}
+
+// Takes over the connection of a 101 Switching Protocols response, to speak the protocol it switched to, like a WebSocket.
+// Use response_conn_read and response_conn_write, the connection is closed (not reused) by response_destroy.
+//
+// Returns false when the response is not a 101, or its body has been read.
+response_hijack :: proc(res: ^Response) -> bool {
+	if res.status != .Switching_Protocols || res._body_read do return false
+
+	res._hijacked = true
+	res._body_read = true
+	return true
+}
+
+// Reads from the connection of a hijacked response, bytes that were read after the response head are returned first.
+response_conn_read :: proc(res: ^Response, p: []byte) -> (n: int, err: io.Error) {
+	assert(res._hijacked, "the response has not been hijacked")
+
+	s := &res._body
+	if s.end > s.start {
+		n = copy(p, s.buf[s.start:s.end])
+		s.start += n
+		return
+	}
+
+	return io.read(s.r, p)
+}
+
+// Writes all of p to the connection of a hijacked response.
+response_conn_write :: proc(res: ^Response, p: []byte) -> Error {
+	assert(res._hijacked, "the response has not been hijacked")
+	return communication_write(res._socket, p)
+}
+
+// Whether response_conn_read can return data without waiting for the socket to become readable.
+response_conn_has_buffered :: proc(res: ^Response) -> bool {
+	if res._body.end > res._body.start do return true
+
+	comm, is_tls := res._socket.(SSL_Communication)
+	return is_tls && openssl.SSL_pending(comm.ssl) > 0
+}
+
+// The socket of the response's connection, to wait for it to become readable after response_hijack.
+response_conn_socket :: proc(res: ^Response) -> net.TCP_Socket {
+	switch comm in res._socket {
+	case net.TCP_Socket:    return comm
+	case SSL_Communication: return comm.socket
+	}
+	return {}
+}
@@ -11,7 +11,8 @@ import "core:strings"
 import http ".."
 import openssl "../openssl"
 
-parse_endpoint :: proc(target: string) -> (url: http.URL, endpoint: net.Endpoint, err: net.Network_Error) {
+// The returned hostname is empty when the target's host is an IP address.
+parse_endpoint :: proc(target: string) -> (url: http.URL, endpoint: net.Endpoint, hostname: string, err: net.Network_Error) {
 	url = http.url_parse(target)
 	host_or_endpoint := net.parse_hostname_or_endpoint(url.host) or_return
 
@@ -20,6 +21,7 @@ parse_endpoint :: proc(target: string) -> (url: http.URL, endpoint: net.Endpoint
 		endpoint = t
 		return
 	case net.Host:
+		hostname = t.hostname
 		ep4, ep6 := net.resolve(t.hostname) or_return
 		endpoint = ep4 if ep4.address != nil else ep6
 
@@ -32,7 +34,7 @@ parse_endpoint :: proc(target: string) -> (url: http.URL, endpoint: net.Endpoint
 	}
 }
 
-// TODO: maybe net.percent_encode.
+// The query is taken from the raw target, as it was given, keeping the order of the parameters (and any duplicates).
 request_path :: proc(target: http.URL, allocator := context.allocator) -> (rq_path: string) {
 	res := strings.builder_make(0, len(target.path), allocator)
 	strings.write_string(&res, target.path)
@@ -40,22 +42,14 @@ request_path :: proc(target: http.URL, allocator := context.allocator) -> (rq_pa
 		strings.write_byte(&res, '/')
 	}
 
-	if len(target.queries) > 0 {
-		strings.write_byte(&res, '?')
-
-		i := 0
-		for key, value in target.queries {
-			strings.write_string(&res, key)
-			if value != "" {
-				strings.write_byte(&res, '=')
-				strings.write_string(&res, value)
-			}
-
-			if i != len(target.queries) -1 {
-				strings.write_byte(&res, '&')
-			}
+	if q := strings.index_byte(target.raw, '?'); q >= 0 {
+		query := target.raw[q:]
+		if hash := strings.index_byte(query, '#'); hash >= 0 {
+			query = query[:hash]
+		}
 
-			i += 1
+		if len(query) > 1 {
+			strings.write_string(&res, query)
 		}
 	}
 
@@ -75,41 +69,57 @@ format_request :: proc(target: http.URL, request: ^Request, allocator := context
 		version = http.Version{1, 1},
 	}, &buf, allocator)
 
-	if "content-length" not_in request.headers {
+	if request.body_stream != nil {
+		// Sent after the head as it is read, see send_body_stream.
+		if !http.headers_has(&request.headers, "content-length") && !http.headers_has(&request.headers, "transfer-encoding") {
+			if request.body_length > -1 {
+				buf := make([]byte, 32, allocator)
+				http.headers_set(&request.headers, "content-length", strconv.itoa(buf, request.body_length))
+			} else {
+				http.headers_set(&request.headers, "transfer-encoding", "chunked")
+			}
+		}
+	} else if !http.headers_has(&request.headers, "content-length") {
 		buf_len := bytes.buffer_length(&request.body)
 		if buf_len == 0 {
-			request.headers["content-length"] = "0"
+			http.headers_set(&request.headers, "content-length", "0")
 		} else {
 			buf := make([]byte, 32, allocator) // TODO: is this leaking?
-			request.headers["content-length"] = strconv.itoa(buf, buf_len)
+			http.headers_set(&request.headers, "content-length", strconv.itoa(buf, buf_len))
 		}
 	}
 
-	if "accept" not_in request.headers {
-		request.headers["accept"] = "*/*"
+	if !http.headers_has(&request.headers, "accept") {
+		http.headers_set(&request.headers, "accept", "*/*")
 	}
 
-	if "user-agent" not_in request.headers {
-		request.headers["user-agent"] = "odin-http"
+	if !http.headers_has(&request.headers, "user-agent") {
+		http.headers_set(&request.headers, "user-agent", "odin-http")
 	}
 
-	if "host" not_in request.headers {
-		request.headers["host"] = target.host
+	if !http.headers_has(&request.headers, "host") {
+		http.headers_set(&request.headers, "host", target.host)
 	}
 
-	for header, value in request.headers {
-		bytes.buffer_write_string(&buf, header)
+	for header in http.headers_combined(&request.headers, context.temp_allocator) {
+		bytes.buffer_write_string(&buf, header.key)
 		bytes.buffer_write_string(&buf, ": ")
 
 		// Escape newlines in headers, if we don't, an attacker can find an endpoint
 		// that returns a header with user input, and inject headers into the response.
-		esc_value, was_allocation := strings.replace_all(value, "\n", "\\n", allocator)
+		esc_value, was_allocation := strings.replace_all(header.value, "\n", "\\n", allocator)
 		defer if was_allocation do delete(esc_value)
 
 		bytes.buffer_write_string(&buf, esc_value)
 		bytes.buffer_write_string(&buf, "\r\n")
 	}
 
+	// Advertise the encodings the body can be decoded from, see response_body.
+	// When the user set the header, they decode the body themselves, so it is not added to the request's headers.
+	if !http.headers_has(&request.headers, "accept-encoding") {
+		bytes.buffer_write_string(&buf, "accept-encoding: gzip, deflate\r\n")
+	}
+
 	if len(request.cookies) > 0 {
 		bytes.buffer_write_string(&buf, "cookie: ")
 
@@ -129,7 +139,9 @@ format_request :: proc(target: http.URL, request: ^Request, allocator := context
 	// Empty line denotes end of headers and start of body.
 	bytes.buffer_write_string(&buf, "\r\n")
 
-	bytes.buffer_write(&buf, bytes.buffer_to_bytes(&request.body))
+	if request.body_stream == nil {
+		bytes.buffer_write(&buf, bytes.buffer_to_bytes(&request.body))
+	}
 	return
 }
 
@@ -146,6 +158,7 @@ Communication :: union {
 
 parse_response :: proc(socket: Communication, allocator := context.allocator) -> (res: Response, err: Error) {
 	res._socket = socket
+	res._allocator = allocator
 
 	stream: io.Stream
 	switch comm in socket {
@@ -154,18 +167,25 @@ parse_response :: proc(socket: Communication, allocator := context.allocator) ->
 	}
 
 	stream_reader := io.to_reader(stream)
-	scanner: bufio.Scanner
-	bufio.scanner_init(&scanner, stream_reader, allocator)
+	bufio.scanner_init(&res._body, stream_reader, allocator)
+	scanner := &res._body
 
-	res.headers = make(http.Headers, 3, allocator)
+	http.headers_init(&res.headers, allocator)
 
-	if !bufio.scanner_scan(&scanner) {
-		err = bufio.scanner_error(&scanner)
+	if !bufio.scanner_scan(scanner) {
+		err = bufio.scanner_error(scanner)
+		if err == nil {
+			err = Request_Error.Connection_Closed
+		}
 		return
 	}
 
-	rline_str := bufio.scanner_text(&scanner)
+	rline_str := bufio.scanner_text(scanner)
 	si := strings.index_byte(rline_str, ' ')
+	if si == -1 {
+		err = Request_Error.Invalid_Response_HTTP_Version
+		return
+	}
 
 	version, ok := http.version_parse(rline_str[:si])
 	if !ok {
@@ -179,6 +199,8 @@ parse_response :: proc(socket: Communication, allocator := context.allocator) ->
 		return
 	}
 
+	res._version = version
+
 	res.status, ok = http.status_from_string(rline_str[si+1:])
 	if !ok {
 		err = Request_Error.Invalid_Response_Method
@@ -186,34 +208,35 @@ parse_response :: proc(socket: Communication, allocator := context.allocator) ->
 	}
 
 	for {
-		if !bufio.scanner_scan(&scanner) {
-			err = bufio.scanner_error(&scanner)
+		if !bufio.scanner_scan(scanner) {
+			err = bufio.scanner_error(scanner)
+			if err == nil {
+				err = Request_Error.Connection_Closed
+			}
 			return
 		}
 
-		line := bufio.scanner_text(&scanner)
+		line := bufio.scanner_text(scanner)
 		// Empty line means end of headers.
 		if line == "" do break
 
-		key, ok := http.header_parse(&res.headers, line, allocator)
-		if !ok {
+		if _, ok := http.header_parse(&res.headers, line, allocator); !ok {
 			err = Request_Error.Invalid_Response_Header
 			return
 		}
+	}
 
-		if key == "set-cookie" {
-			cookie_str := res.headers["set-cookie"]
-			delete_key(&res.headers, key)
-			delete(key)
-
-			cookie, ok := http.cookie_parse(cookie_str, allocator)
-			if !ok {
-				err = Request_Error.Invalid_Response_Cookie
-				return
-			}
+	// Every set-cookie header is kept, they are also parsed into the cookies array.
+	for header in res.headers.entries {
+		if header.key != "set-cookie" do continue
 
-			append(&res.cookies, cookie)
+		cookie, ok := http.cookie_parse(header.value, allocator)
+		if !ok {
+			err = Request_Error.Invalid_Response_Cookie
+			return
 		}
+
+		append(&res.cookies, cookie)
 	}
 
 	if !http.headers_validate(&res.headers) {
@@ -221,6 +244,5 @@ parse_response :: proc(socket: Communication, allocator := context.allocator) ->
 		return
 	}
 
-	res._body = scanner
 	return res, nil
 }
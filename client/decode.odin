@@ -0,0 +1,155 @@
+//+private
+package client
+
+import "core:bytes"
+import "core:compress"
+import "core:compress/gzip"
+import "core:compress/zlib"
+import "core:log"
+import "core:mem"
+import "core:strings"
+
+import http ".."
+
+// Decodes the body according to the content-encoding header, RFC 9110 8.4.
+// Codings are listed in the order they were applied, so they are undone from last to first.
+//
+// The given body is freed when it is replaced by the decoded body, or when decoding fails.
+// Once decoded, the content-encoding and content-length headers are removed, they describe the body as it was received.
+body_decode :: proc(
+	headers: ^http.Headers,
+	body: string,
+	was_allocation: bool,
+	max_size: int,
+	allocator := context.allocator,
+) -> (
+	decoded: string,
+	decoded_allocation: bool,
+	err: http.Body_Error,
+) {
+	decoded, decoded_allocation = body, was_allocation
+	defer if err != nil && decoded_allocation do delete(decoded, allocator)
+
+	// Responses to HEAD requests, and 204 and 304 responses have the headers of a body that is not sent.
+	if len(body) == 0 do return
+
+	codings: [dynamic]string
+	codings.allocator = context.temp_allocator
+	for value in http.headers_get_all(headers, "content-encoding", context.temp_allocator) {
+		append(&codings, ..strings.split(value, ",", context.temp_allocator))
+	}
+
+	decoded_any: bool
+	for i := len(codings) - 1; i >= 0; i -= 1 {
+		coding := strings.trim_space(codings[i])
+
+		out: string
+		switch {
+		case coding == "" || strings.equal_fold(coding, "identity"):
+			continue
+		case strings.equal_fold(coding, "gzip") || strings.equal_fold(coding, "x-gzip"):
+			out = inflate(decoded, .Gzip, max_size, allocator) or_return
+		case strings.equal_fold(coding, "deflate"):
+			out = inflate(decoded, .Deflate, max_size, allocator) or_return
+		case:
+			log.warnf("response has an unsupported content encoding %q", coding)
+			return decoded, decoded_allocation, .Invalid_Encoding
+		}
+
+		if decoded_allocation do delete(decoded, allocator)
+		decoded, decoded_allocation = out, true
+		decoded_any = true
+	}
+
+	if decoded_any {
+		headers_delete_owned(headers, "content-encoding")
+		headers_delete_owned(headers, "content-length")
+	}
+
+	return
+}
+
+// Deletes all values of the key from response headers, freeing the keys, which are allocated when parsing.
+headers_delete_owned :: proc(headers: ^http.Headers, key: string) {
+	for i := len(headers.entries) - 1; i >= 0; i -= 1 {
+		if headers.entries[i].key != key do continue
+
+		delete(headers.entries[i].key, headers.entries.allocator)
+		ordered_remove(&headers.entries, i)
+	}
+}
+
+Inflate_Format :: enum {
+	Gzip,
+	Deflate,
+}
+
+inflate :: proc(data: string, format: Inflate_Format, max_size: int, allocator: mem.Allocator) -> (out: string, err: http.Body_Error) {
+	// The decompressor grows the output as it needs, the limit makes that fail instead of allocating without bounds.
+	// Growing can overshoot the actual size, so the size itself is checked after.
+	limit := Limit_Allocator{parent = allocator, limit = -1}
+	if max_size > 0 {
+		limit.limit = 2 * max_size + mem.Kilobyte * 64
+	}
+
+	buf: bytes.Buffer
+	bytes.buffer_init_allocator(&buf, 0, 0, limit_allocator(&limit))
+
+	input := transmute([]byte)data
+	ierr: compress.Error
+	switch format {
+	case .Gzip:
+		ierr = gzip.load_from_slice(input, &buf, len(input), -1, limit_allocator(&limit))
+	case .Deflate:
+		// The deflate content coding is the zlib format, but some servers send the raw deflate stream,
+		// a zlib stream starts with a header that is a multiple of 31 with the deflate method.
+		raw := len(input) < 2 || input[0] & 0x0f != 8 || (u16(input[0]) << 8 | u16(input[1])) % 31 != 0
+		ierr = zlib.inflate(input, &buf, raw)
+	}
+
+	if ierr != nil || (max_size > 0 && bytes.buffer_length(&buf) > max_size) {
+		bytes.buffer_destroy(&buf)
+
+		if limit.exceeded || ierr == nil {
+			return "", .Too_Long
+		}
+
+		log.warnf("could not decode %v response body: %v", format, ierr)
+		return "", .Invalid_Encoding
+	}
+
+	return string(buf.buf[:]), nil
+}
+
+// Fails allocations that are bigger than the limit.
+Limit_Allocator :: struct {
+	parent:   mem.Allocator,
+	// The maximum size of one allocation, -1 for no limit.
+	limit:    int,
+	exceeded: bool,
+}
+
+limit_allocator :: proc(l: ^Limit_Allocator) -> mem.Allocator {
+	return mem.Allocator{procedure = limit_allocator_proc, data = l}
+}
+
+limit_allocator_proc :: proc(
+	allocator_data: rawptr,
+	mode: mem.Allocator_Mode,
+	size, alignment: int,
+	old_memory: rawptr,
+	old_size: int,
+	loc := #caller_location,
+) -> ([]byte, mem.Allocator_Error) {
+	l := (^Limit_Allocator)(allocator_data)
+
+	#partial switch mode {
+	case .Alloc, .Alloc_Non_Zeroed, .Resize, .Resize_Non_Zeroed:
+		if l.limit > -1 && size > l.limit {
+			l.exceeded = true
+			return nil, .Out_Of_Memory
+		}
+	}
+
+	return l.parent.procedure(l.parent.data, mode, size, alignment, old_memory, old_size, loc)
+}
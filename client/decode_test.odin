@@ -0,0 +1,88 @@
+package client
+
+import "core:testing"
+
+import http ".."
+
+// 16KiB of 'a', gzip encoded into 52 bytes.
+@(private)
+Decode_Test_Gzip := [?]byte{
+	0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xed, 0xc1, 0x31, 0x01, 0x00, 0x00,
+	0x00, 0xc2, 0xa0, 0xac, 0xeb, 0x5f, 0xc2, 0x18, 0x3e, 0x40, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+	0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x6f, 0x03, 0xfb, 0x44, 0xee, 0xeb,
+	0x00, 0x40, 0x00, 0x00,
+}
+@(private)
+Decode_Test_Decoded_Size :: 16 << 10
+
+// Starts a server that responds with the gzip encoded body.
+@(private)
+decode_test_start :: proc(t: ^testing.T, s: ^http.Test_Server) -> bool {
+	h := http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		http.respond_plain(res, string(Decode_Test_Gzip[:]))
+		http.headers_set(&res.headers, "content-encoding", "gzip")
+	})
+	return http.test_server_start(t, s, h)
+}
+
+// Requests the gzip encoded body with a client with the options, returns the result of decoding it.
+@(private)
+decode_test_request :: proc(t: ^testing.T, s: ^http.Test_Server, opts: Client_Opts) -> (size: int, err: http.Body_Error) {
+	c: Client
+	client_init(&c, opts)
+	defer client_destroy(&c)
+
+	req: Request
+	request_init(&req)
+	defer request_destroy(&req)
+
+	res, rerr := client_request(&c, http.test_server_url(s), &req)
+	if rerr != nil {
+		testing.errorf(t, "request failed: %v", rerr)
+		return
+	}
+
+	body, was_allocation, berr := response_body(&res)
+	if berr != nil {
+		response_destroy(&res)
+		return 0, berr
+	}
+	defer response_destroy(&res, body, was_allocation)
+
+	return len(body.(http.Body_Plain)), .None
+}
+
+@(test)
+test_client_max_decompressed_default :: proc(t: ^testing.T) {
+	testing.expect_value(t, client_max_decompressed(nil), Default_Max_Decompressed_Size)
+
+	c: Client
+	c.opts = Client_Opts{max_idle_per_host = 2}
+	testing.expect_value(t, client_max_decompressed(&c), Default_Max_Decompressed_Size)
+
+	c.opts.max_decompressed_size = 1024
+	testing.expect_value(t, client_max_decompressed(&c), 1024)
+
+	c.opts.max_decompressed_size = -1
+	testing.expect_value(t, client_max_decompressed(&c), -1)
+}
+
+@(test)
+test_client_decompressed_too_long :: proc(t: ^testing.T) {
+	s: http.Test_Server
+	if !decode_test_start(t, &s) do return
+	defer http.test_server_stop(&s)
+
+	// Options without the limit get the default, which the body is well within.
+	size, err := decode_test_request(t, &s, Client_Opts{max_idle_per_host = 2})
+	testing.expect_value(t, err, http.Body_Error.None)
+	testing.expect_value(t, size, Decode_Test_Decoded_Size)
+
+	// The body decompresses to more than the limit, it is refused instead of decoded.
+	_, err = decode_test_request(t, &s, Client_Opts{max_idle_per_host = 2, max_decompressed_size = 1024})
+	testing.expect_value(t, err, http.Body_Error.Too_Long)
+
+	size, err = decode_test_request(t, &s, Client_Opts{max_idle_per_host = 2, max_decompressed_size = -1})
+	testing.expect_value(t, err, http.Body_Error.None)
+	testing.expect_value(t, size, Decode_Test_Decoded_Size)
+}
@@ -0,0 +1,316 @@
+//+private
+package client
+
+import "core:bufio"
+import "core:bytes"
+import "core:c"
+import "core:io"
+import "core:net"
+import "core:strconv"
+import "core:strings"
+import "core:sync"
+import "core:time"
+
+import http ".."
+import openssl "../openssl"
+
+// The maximum amount of unread body bytes that are read (and thrown away) to be able to reuse a connection.
+Max_Drain_Bytes :: 256 << 10
+
+Idle_Connection :: struct {
+	comm:  Communication,
+	since: time.Time,
+}
+
+// Takes the most recently used idle connection for the key, closing any that have been idle for too long.
+client_take_idle :: proc(client: ^Client, key: string) -> (comm: Communication, ok: bool) {
+	sync.guard(&client._mu)
+
+	conns, has := client._idle[key]
+	if !has do return
+
+	now := time.now()
+	timeout := client_idle_timeout(client)
+	for len(conns) > 0 {
+		conn := pop(&conns)
+		if time.diff(conn.since, now) < timeout {
+			comm, ok = conn.comm, true
+			break
+		}
+
+		communication_close(conn.comm)
+	}
+
+	client._idle[key] = conns
+	return
+}
+
+// The idle timeout of the client, so options that leave it out still reuse connections.
+client_idle_timeout :: proc(client: ^Client) -> time.Duration {
+	if client.opts.idle_timeout == 0 {
+		return Default_Idle_Timeout
+	}
+	return client.opts.idle_timeout
+}
+
+// Gives a connection back to the client, if the host already has the maximum amount of
+// idle connections, the one that has been idle the longest is closed.
+client_put_idle :: proc(client: ^Client, key: string, comm: Communication) {
+	sync.guard(&client._mu)
+
+	if client.opts.max_idle_per_host <= 0 {
+		communication_close(comm)
+		return
+	}
+
+	conns, has := client._idle[key]
+	if !has {
+		conns = make([dynamic]Idle_Connection, 0, client.opts.max_idle_per_host, client.allocator)
+	}
+
+	now := time.now()
+	timeout := client_idle_timeout(client)
+	for len(conns) > 0 && time.diff(conns[0].since, now) >= timeout {
+		communication_close(conns[0].comm)
+		ordered_remove(&conns, 0)
+	}
+
+	if len(conns) >= client.opts.max_idle_per_host {
+		communication_close(conns[0].comm)
+		ordered_remove(&conns, 0)
+	}
+
+	append(&conns, Idle_Connection{comm = comm, since = now})
+
+	if has {
+		client._idle[key] = conns
+	} else {
+		client._idle[strings.clone(key, client.allocator)] = conns
+	}
+}
+
+// Returns the SSL context shared by the client's connections, creating it the first time.
+client_ssl_ctx :: proc(client: ^Client) -> (ctx: ^openssl.SSL_CTX, err: SSL_Error) {
+	sync.guard(&client._mu)
+
+	if client._ssl_ctx == nil {
+		client._ssl_ctx = tls_ctx(client.opts.tls) or_return
+	}
+
+	return client._ssl_ctx, nil
+}
+
+// Opens a new connection to the endpoint, doing the TLS handshake if the scheme is https.
+// Without a client, the connection gets its own SSL context (with the default options),
+// freed when the connection is closed.
+connect :: proc(client: ^Client, url: http.URL, endpoint: net.Endpoint, hostname: string) -> (comm: Communication, err: Error) {
+	// HTTPS contexts are set up before dialing, so configuration errors don't need a connection.
+	ctx: ^openssl.SSL_CTX
+	verify: bool
+	if url.scheme == "https" {
+		if client != nil {
+			ctx = client_ssl_ctx(client) or_return
+			verify = !client.opts.tls.insecure_skip_verify
+		} else {
+			ctx = tls_ctx({}) or_return
+			verify = true
+		}
+	}
+	defer if err != nil && client == nil && ctx != nil do openssl.SSL_CTX_free(ctx)
+
+	socket := net.dial_tcp(endpoint) or_return
+
+	// HTTP, nothing more to do.
+	if ctx == nil {
+		return socket, nil
+	}
+
+	// HTTPS using openssl.
+	using openssl
+
+	ssl := SSL_new(ctx)
+	SSL_set_fd(ssl, c.int(socket))
+
+	if !tls_set_host(ssl, hostname, endpoint, verify) {
+		err = SSL_Error.Invalid_Host
+	} else if ret := SSL_connect(ssl); ret != 1 {
+		err = tls_handshake_error(ssl, ret)
+	}
+
+	if err != nil {
+		SSL_free(ssl)
+		net.close(socket)
+		return
+	}
+
+	return SSL_Communication{
+		socket = socket,
+		ssl    = ssl,
+		ctx    = client == nil ? ctx : nil,
+	}, nil
+}
+
+// Writes the formatted request to the connection, followed by the body stream if there is one, and parses the response head.
+send_request :: proc(
+	comm: Communication,
+	req_buf: ^bytes.Buffer,
+	body_stream: Maybe(io.Reader),
+	chunked: bool,
+	allocator := context.allocator,
+) -> (
+	res: Response,
+	err: Error,
+) {
+	communication_write(comm, bytes.buffer_to_bytes(req_buf)) or_return
+
+	if body, ok := body_stream.?; ok {
+		send_body_stream(comm, body, chunked) or_return
+	}
+
+	res, err = parse_response(comm, allocator)
+	if err != nil {
+		response_free(&res)
+	}
+	return
+}
+
+// Writes all of buf to the connection.
+communication_write :: proc(comm: Communication, buf: []byte) -> (err: Error) {
+	switch conn in comm {
+	case net.TCP_Socket:
+		net.send_tcp(conn, buf) or_return
+	case SSL_Communication:
+		to_write := len(buf)
+		for to_write > 0 {
+			ret := openssl.SSL_write(conn.ssl, raw_data(buf[len(buf)-to_write:]), c.int(to_write))
+			if ret <= 0 {
+				err = SSL_Error.SSL_Write_Failed
+				return
+			}
+
+			to_write -= int(ret)
+		}
+	}
+	return
+}
+
+// Sends everything read from the body until .EOF, as chunks when chunked, RFC 7230 4.1.
+send_body_stream :: proc(comm: Communication, body: io.Reader, chunked: bool) -> Error {
+	buf: [16 << 10]byte
+	for {
+		n, rerr := io.read(body, buf[:])
+		if n > 0 {
+			if chunked {
+				size_buf: [18]byte
+				size := strconv.append_int(size_buf[:16], i64(n), 16)
+				size_buf[len(size)], size_buf[len(size)+1] = '\r', '\n'
+				communication_write(comm, size_buf[:len(size)+2]) or_return
+			}
+
+			communication_write(comm, buf[:n]) or_return
+
+			if chunked {
+				crlf := "\r\n"
+				communication_write(comm, transmute([]byte)crlf) or_return
+			}
+		}
+
+		if rerr == .EOF do break
+		if rerr != nil do return Request_Error.Body_Stream_Failed
+	}
+
+	if chunked {
+		last := "0\r\n\r\n"
+		communication_write(comm, transmute([]byte)last) or_return
+	}
+	return nil
+}
+
+communication_close :: proc(comm: Communication) {
+	switch conn in comm {
+	case net.TCP_Socket:
+		net.close(conn)
+	case SSL_Communication:
+		openssl.SSL_free(conn.ssl)
+		if conn.ctx != nil do openssl.SSL_CTX_free(conn.ctx)
+		net.close(conn.socket)
+	}
+}
+
+// Frees everything of the response, except for the connection.
+response_free :: proc(res: ^Response) {
+	// Header keys are allocated, values are slices into the body.
+	for header in res.headers.entries {
+		delete(header.key, res._allocator)
+	}
+	http.headers_destroy(&res.headers)
+
+	bufio.scanner_destroy(&res._body)
+
+	// Cookies only contain slices to memory inside the scanner body.
+	// So just deleting the array will be enough.
+	delete(res.cookies)
+
+	if res._body_reader != nil do free(res._body_reader)
+}
+
+// Whether the connection of the response can be used for another request,
+// reads the rest of the body if the user did not, so the connection is at the start of the next response.
+response_reusable :: proc(res: ^Response) -> bool {
+	if conn, ok := http.headers_get(&res.headers, "connection"); ok && strings.equal_fold(strings.trim_space(conn), "close") {
+		return false
+	}
+
+	// Speaking another protocol now.
+	if res._hijacked do return false
+
+	// HTTP/1.0 connections are closed after the response by default.
+	if res._version.minor == 0 do return false
+
+	if response_has_body(res) {
+		if br := res._body_reader; br != nil {
+			// The body ends when the connection closes, or the user did not read all of it.
+			if br.until_close do return false
+
+			drained := 0
+			buf: [4096]byte
+			for !br.done {
+				n, err := http.body_reader_read(br, buf[:])
+				if err != nil && err != .EOF do return false
+
+				drained += n
+				if drained > Max_Drain_Bytes do return false
+			}
+		} else if res._body_read {
+			if res._body_err != nil do return false
+		} else {
+			// A body that is delimited by closing the connection gives a .No_Length error here.
+			body, was_allocation, err := http.parse_body(&res.headers, &res._body, Max_Drain_Bytes)
+			if err != nil do return false
+			body_destroy(body, was_allocation)
+		}
+	}
+
+	// The server sent more than the response, we don't know what it is, so don't reuse.
+	if res._body.end > res._body.start do return false
+
+	return true
+}
+
+// RFC 7230 3.3.3: these responses never have a body, whatever their headers say.
+response_has_body :: proc(res: ^Response) -> bool {
+	return res._method != .Head &&
+		!http.status_informational(res.status) &&
+		res.status != .No_Content &&
+		res.status != .Not_Modified
+}
+
+// RFC 7231 4.2.2: requests with an idempotent method can be retried automatically.
+method_idempotent :: proc(method: http.Method) -> bool {
+	#partial switch method {
+	case .Get, .Head, .Options, .Trace, .Put, .Delete:
+		return true
+	case:
+		return false
+	}
+}
@@ -0,0 +1,64 @@
+package client
+
+import "core:fmt"
+import "core:testing"
+
+import http ".."
+
+@(test)
+test_client_idle_timeout_default :: proc(t: ^testing.T) {
+	c: Client
+	c.opts = Client_Opts{max_idle_per_host = 2}
+	testing.expect_value(t, client_idle_timeout(&c), Default_Idle_Timeout)
+
+	c.opts.idle_timeout = Default_Idle_Timeout / 2
+	testing.expect_value(t, client_idle_timeout(&c), Default_Idle_Timeout / 2)
+}
+
+// Requests the port the server sees the connection come from.
+@(private)
+pool_test_port :: proc(t: ^testing.T, c: ^Client, s: ^http.Test_Server) -> (port: string, ok: bool) {
+	req: Request
+	request_init(&req)
+	defer request_destroy(&req)
+
+	res, err := client_request(c, http.test_server_url(s), &req)
+	if err != nil {
+		testing.errorf(t, "request failed: %v", err)
+		return
+	}
+
+	body, was_allocation, berr := response_body(&res)
+	if berr != nil {
+		testing.errorf(t, "could not read the body: %v", berr)
+		response_destroy(&res)
+		return
+	}
+	// Gives the connection back to the client.
+	defer response_destroy(&res, body, was_allocation)
+
+	return fmt.tprint(body.(http.Body_Plain)), true
+}
+
+@(test)
+test_client_reuses_connections_without_idle_timeout :: proc(t: ^testing.T) {
+	h := http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		http.respond_plain(res, fmt.tprint(req.client.port))
+	})
+
+	s: http.Test_Server
+	if !http.test_server_start(t, &s, h) do return
+	defer http.test_server_stop(&s)
+
+	// Options without an idle timeout, connections are not expired right away.
+	c: Client
+	client_init(&c, Client_Opts{max_idle_per_host = 2})
+	defer client_destroy(&c)
+
+	first, ok := pool_test_port(t, &c, &s)
+	if !ok do return
+	second, sok := pool_test_port(t, &c, &s)
+	if !sok do return
+
+	testing.expectf(t, first == second, "the second request reuses the connection, it came from port %s and then %s", first, second)
+}
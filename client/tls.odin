@@ -0,0 +1,158 @@
+package client
+
+import "core:c"
+import "core:net"
+import "core:strings"
+
+import http ".."
+import openssl "../openssl"
+
+TLS_Version :: enum {
+	// Leave it up to OpenSSL, which picks the lowest/highest version it supports.
+	Default,
+	TLS1_0,
+	TLS1_1,
+	TLS1_2,
+	TLS1_3,
+}
+
+TLS_Opts :: struct {
+	// Accept any certificate, without verifying it or checking the host name.
+	// Only meant for testing, this makes the connection vulnerable to man-in-the-middle attacks.
+	insecure_skip_verify: bool,
+
+	// Path to a PEM file of CA certificates to trust, instead of the system trust store.
+	ca_file:              string,
+	// Path to a directory of (hashed) CA certificates to trust, instead of the system trust store.
+	ca_dir:               string,
+
+	min_version:          TLS_Version,
+	max_version:          TLS_Version,
+
+	// A client certificate, sent when the server asks for one (mutual TLS).
+	// The hosts field is not used.
+	certificate:          Maybe(http.TLS_Certificate),
+}
+
+// Creates the SSL context used by HTTPS connections, configured with the given options.
+@(private)
+tls_ctx :: proc(opts: TLS_Opts) -> (ctx: ^openssl.SSL_CTX, err: SSL_Error) {
+	using openssl
+
+	ctx = SSL_CTX_new(TLS_client_method())
+	if ctx == nil {
+		return nil, .Context_Failed
+	}
+	defer if err != nil do SSL_CTX_free(ctx)
+
+	if opts.insecure_skip_verify {
+		SSL_CTX_set_verify(ctx, SSL_VERIFY_NONE, nil)
+	} else {
+		SSL_CTX_set_verify(ctx, SSL_VERIFY_PEER, nil)
+
+		if opts.ca_file != "" || opts.ca_dir != "" {
+			file := strings.clone_to_cstring(opts.ca_file, context.temp_allocator) if opts.ca_file != "" else nil
+			dir  := strings.clone_to_cstring(opts.ca_dir,  context.temp_allocator) if opts.ca_dir  != "" else nil
+			if SSL_CTX_load_verify_locations(ctx, file, dir) != 1 {
+				return nil, .CA_Load_Failed
+			}
+		} else if SSL_CTX_set_default_verify_paths(ctx) != 1 {
+			return nil, .CA_Load_Failed
+		}
+	}
+
+	if SSL_CTX_set_min_proto_version(ctx, tls_version(opts.min_version)) != 1 ||
+	   SSL_CTX_set_max_proto_version(ctx, tls_version(opts.max_version)) != 1 {
+		return nil, .Invalid_Version
+	}
+
+	if cert, ok := opts.certificate.?; ok {
+		cert_file := strings.clone_to_cstring(cert.cert_file, context.temp_allocator)
+		if SSL_CTX_use_certificate_chain_file(ctx, cert_file) != 1 {
+			return nil, .Certificate_Failed
+		}
+
+		key_file := strings.clone_to_cstring(cert.key_file, context.temp_allocator)
+		if SSL_CTX_use_PrivateKey_file(ctx, key_file, SSL_FILETYPE_PEM) != 1 {
+			return nil, .Private_Key_Failed
+		}
+
+		if SSL_CTX_check_private_key(ctx) != 1 {
+			return nil, .Private_Key_Mismatch
+		}
+	}
+
+	return ctx, nil
+}
+
+@(private)
+tls_version :: proc(v: TLS_Version) -> c.long {
+	switch v {
+	case .Default: return 0
+	case .TLS1_0:  return openssl.TLS1_VERSION
+	case .TLS1_1:  return openssl.TLS1_1_VERSION
+	case .TLS1_2:  return openssl.TLS1_2_VERSION
+	case .TLS1_3:  return openssl.TLS1_3_VERSION
+	case:          return 0
+	}
+}
+
+// Sets the server name (SNI) and the name (or address) the certificate has to be valid for.
+// An empty hostname means the target was an IP address, which is not allowed as a server name.
+@(private)
+tls_set_host :: proc(ssl: ^openssl.SSL, hostname: string, endpoint: net.Endpoint, verify: bool) -> bool {
+	using openssl
+
+	if hostname != "" {
+		name := strings.clone_to_cstring(hostname, context.temp_allocator)
+		if SSL_set_tlsext_host_name(ssl, name) != 1 do return false
+		if verify && SSL_set1_host(ssl, name) != 1 do return false
+		return true
+	}
+
+	if verify {
+		ip := strings.clone_to_cstring(net.address_to_string(endpoint.address, context.temp_allocator), context.temp_allocator)
+		if X509_VERIFY_PARAM_set1_ip_asc(SSL_get0_param(ssl), ip) != 1 do return false
+	}
+
+	return true
+}
+
+// Figures out why the handshake failed, based on the verification result and OpenSSL's error queue.
+@(private)
+tls_handshake_error :: proc(ssl: ^openssl.SSL, ret: c.int) -> SSL_Error {
+	using openssl
+	defer ERR_clear_error()
+
+	if ret == 0 {
+		return .Controlled_Shutdown
+	}
+
+	switch SSL_get_verify_result(ssl) {
+	case X509_V_OK:
+	case X509_V_ERR_CERT_HAS_EXPIRED:
+		return .Certificate_Expired
+	case X509_V_ERR_CERT_NOT_YET_VALID:
+		return .Certificate_Not_Yet_Valid
+	case X509_V_ERR_CERT_REVOKED:
+		return .Certificate_Revoked
+	case X509_V_ERR_HOSTNAME_MISMATCH, X509_V_ERR_IP_ADDRESS_MISMATCH:
+		return .Hostname_Mismatch
+	case X509_V_ERR_DEPTH_ZERO_SELF_SIGNED_CERT,
+	     X509_V_ERR_SELF_SIGNED_CERT_IN_CHAIN,
+	     X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT,
+	     X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT_LOCALLY,
+	     X509_V_ERR_UNABLE_TO_VERIFY_LEAF_SIGNATURE,
+	     X509_V_ERR_CERT_UNTRUSTED:
+		return .Certificate_Untrusted
+	case:
+		return .Certificate_Invalid
+	}
+
+	switch ERR_GET_REASON(ERR_peek_error()) {
+	case SSL_R_NO_PROTOCOLS_AVAILABLE, SSL_R_UNSUPPORTED_PROTOCOL, SSL_R_TLSV1_ALERT_PROTOCOL_VERSION:
+		return .Protocol_Version
+	}
+
+	return .Handshake_Failed
+}
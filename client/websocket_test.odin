@@ -0,0 +1,410 @@
+package client
+
+import "core:net"
+import "core:testing"
+import "core:time"
+
+import http ".."
+
+// The tests run a WebSocket echo server, and speak the protocol over hijacked responses.
+@(private)
+WS_Test_Max_Message :: 1024
+
+// The sample handshake of RFC 6455 1.3.
+@(private)
+WS_Test_Key :: "dGhlIHNhbXBsZSBub25jZQ=="
+@(private)
+WS_Test_Accept :: "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+
+@(private)
+WS_Opcode_Continuation :: 0x0
+@(private)
+WS_Opcode_Text :: 0x1
+@(private)
+WS_Opcode_Binary :: 0x2
+@(private)
+WS_Opcode_Close :: 0x8
+@(private)
+WS_Opcode_Ping :: 0x9
+@(private)
+WS_Opcode_Pong :: 0xa
+
+// Starts a server that upgrades every request, and echoes every message back until the WebSocket closes.
+@(private)
+ws_test_start :: proc(t: ^testing.T, s: ^http.Test_Server, opts := http.Default_Server_Opts) -> bool {
+	h := http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		opts := http.Default_Websocket_Opts
+		opts.max_message_size = WS_Test_Max_Message
+		opts.protocols = {"chat"}
+
+		ws, err := http.websocket_upgrade(req, res, opts)
+		if err != nil do return
+
+		for {
+			msg, rerr := http.websocket_read(ws)
+			if rerr != nil do break
+			defer delete(msg.data)
+
+			if http.websocket_write(ws, msg.data, msg.type) != nil do break
+		}
+
+		http.websocket_close(ws)
+	})
+	return http.test_server_start(t, s, h, opts)
+}
+
+// Sends the handshake, when it succeeds the response is hijacked, ready for frames.
+@(private)
+ws_test_connect :: proc(t: ^testing.T, s: ^http.Test_Server, c: ^Client, protocols := "") -> (res: Response, ok: bool) {
+	req: Request
+	request_init(&req)
+	defer request_destroy(&req)
+
+	http.headers_set(&req.headers, "upgrade", "websocket")
+	http.headers_set(&req.headers, "connection", "Upgrade")
+	http.headers_set(&req.headers, "sec-websocket-version", "13")
+	http.headers_set(&req.headers, "sec-websocket-key", WS_Test_Key)
+	if protocols != "" {
+		http.headers_set(&req.headers, "sec-websocket-protocol", protocols)
+	}
+
+	err: Error
+	res, err = client_request(c, http.test_server_url(s, "/ws"), &req)
+	if err != nil {
+		testing.errorf(t, "handshake request failed: %v", err)
+		return
+	}
+
+	if !response_hijack(&res) {
+		testing.errorf(t, "handshake responded with %v instead of 101 Switching Protocols", res.status)
+		response_destroy(&res)
+		return
+	}
+
+	// Don't hang the tests when the server does not respond.
+	net.set_option(response_conn_socket(&res), .Receive_Timeout, 5 * time.Second)
+	return res, true
+}
+
+// Sends a frame like a client does, masked unless told otherwise.
+@(private)
+ws_test_write_frame :: proc(res: ^Response, opcode: u8, payload: []byte, fin := true, masked := true) -> bool {
+	frame := make([dynamic]byte, context.temp_allocator)
+
+	append(&frame, (u8(0x80) if fin else 0) | opcode)
+
+	mask_bit := u8(0x80) if masked else 0
+	switch {
+	case len(payload) < 126:
+		append(&frame, mask_bit | u8(len(payload)))
+	case len(payload) <= 0xffff:
+		append(&frame, mask_bit | 126, u8(len(payload) >> 8), u8(len(payload)))
+	case:
+		append(&frame, mask_bit | 127)
+		for i := 7; i >= 0; i -= 1 {
+			append(&frame, u8(u64(len(payload)) >> (8 * uint(i))))
+		}
+	}
+
+	mask := [4]byte{0x12, 0x34, 0x56, 0x78}
+	if masked do append(&frame, ..mask[:])
+
+	start := len(frame)
+	append(&frame, ..payload)
+	if masked {
+		for i in start ..< len(frame) {
+			frame[i] ~= mask[(i - start) % 4]
+		}
+	}
+
+	return response_conn_write(res, frame[:]) == nil
+}
+
+@(private)
+ws_test_write_string :: proc(res: ^Response, opcode: u8, payload: string, fin := true, masked := true) -> bool {
+	return ws_test_write_frame(res, opcode, transmute([]byte)payload, fin, masked)
+}
+
+@(private)
+WS_Test_Frame :: struct {
+	fin:     bool,
+	opcode:  u8,
+	payload: []byte,
+}
+
+// Reads the next frame the server sent, fails when the connection closed or the frame is masked.
+@(private)
+ws_test_read_frame :: proc(res: ^Response) -> (f: WS_Test_Frame, ok: bool) {
+	header: [2]byte
+	if !ws_test_read_full(res, header[:]) do return
+
+	f.fin = header[0] & 0x80 != 0
+	f.opcode = header[0] & 0x0f
+
+	// Frames from the server are never masked.
+	if header[1] & 0x80 != 0 do return
+
+	length := int(header[1] & 0x7f)
+	switch length {
+	case 126:
+		ext: [2]byte
+		if !ws_test_read_full(res, ext[:]) do return
+		length = int(ext[0]) << 8 | int(ext[1])
+	case 127:
+		ext: [8]byte
+		if !ws_test_read_full(res, ext[:]) do return
+		length = 0
+		for b in ext do length = length << 8 | int(b)
+	}
+
+	f.payload = make([]byte, length, context.temp_allocator)
+	if !ws_test_read_full(res, f.payload) do return
+
+	return f, true
+}
+
+@(private)
+ws_test_read_full :: proc(res: ^Response, buf: []byte) -> bool {
+	n := 0
+	for n < len(buf) {
+		read, err := response_conn_read(res, buf[n:])
+		n += read
+
+		if err != nil && n < len(buf) do return false
+	}
+	return true
+}
+
+// Reads a frame and checks that it is a close frame with the given code.
+@(private)
+ws_test_expect_close :: proc(t: ^testing.T, res: ^Response, code: u16) {
+	f, ok := ws_test_read_frame(res)
+	if !ok || f.opcode != WS_Opcode_Close || len(f.payload) < 2 {
+		testing.errorf(t, "expected a close frame with code %i, got %v (ok: %v)", code, f, ok)
+		return
+	}
+
+	testing.expect_value(t, u16(f.payload[0]) << 8 | u16(f.payload[1]), code)
+
+	// The server closes the connection after the close frame.
+	_, ok = ws_test_read_frame(res)
+	testing.expect(t, !ok, "the connection should be closed after the close frame")
+}
+
+@(test)
+test_websocket_handshake :: proc(t: ^testing.T) {
+	s: http.Test_Server
+	if !ws_test_start(t, &s) do return
+	defer http.test_server_stop(&s)
+
+	c: Client
+	client_init(&c)
+	defer client_destroy(&c)
+
+	res, ok := ws_test_connect(t, &s, &c, "superchat, chat")
+	if !ok do return
+	defer response_destroy(&res)
+
+	testing.expect_value(t, http.headers_get(&res.headers, "sec-websocket-accept") or_else "", WS_Test_Accept)
+	testing.expect_value(t, http.headers_get(&res.headers, "sec-websocket-protocol") or_else "", "chat")
+	testing.expect_value(t, http.headers_get(&res.headers, "upgrade") or_else "", "websocket")
+}
+
+@(test)
+test_websocket_invalid_handshake :: proc(t: ^testing.T) {
+	s: http.Test_Server
+	if !ws_test_start(t, &s) do return
+	defer http.test_server_stop(&s)
+
+	c: Client
+	client_init(&c)
+	defer client_destroy(&c)
+
+	{
+		req: Request
+		request_init(&req)
+		defer request_destroy(&req)
+
+		http.headers_set(&req.headers, "upgrade", "websocket")
+		http.headers_set(&req.headers, "connection", "Upgrade")
+		http.headers_set(&req.headers, "sec-websocket-version", "13")
+
+		res, err := client_request(&c, http.test_server_url(&s, "/ws"), &req)
+		testing.expect(t, err == nil, "request failed")
+		defer response_destroy(&res)
+
+		testing.expect_value(t, res.status, http.Status.Bad_Request)
+		testing.expect(t, !response_hijack(&res), "a failed handshake can't be hijacked")
+	}
+
+	{
+		req: Request
+		request_init(&req)
+		defer request_destroy(&req)
+
+		http.headers_set(&req.headers, "upgrade", "websocket")
+		http.headers_set(&req.headers, "connection", "Upgrade")
+		http.headers_set(&req.headers, "sec-websocket-version", "8")
+		http.headers_set(&req.headers, "sec-websocket-key", WS_Test_Key)
+
+		res, err := client_request(&c, http.test_server_url(&s, "/ws"), &req)
+		testing.expect(t, err == nil, "request failed")
+		defer response_destroy(&res)
+
+		testing.expect_value(t, res.status, http.Status.Upgrade_Required)
+		testing.expect_value(t, http.headers_get(&res.headers, "sec-websocket-version") or_else "", "13")
+	}
+}
+
+@(test)
+test_websocket_echo :: proc(t: ^testing.T) {
+	s: http.Test_Server
+	if !ws_test_start(t, &s) do return
+	defer http.test_server_stop(&s)
+
+	c: Client
+	client_init(&c)
+	defer client_destroy(&c)
+
+	res, ok := ws_test_connect(t, &s, &c)
+	if !ok do return
+	defer response_destroy(&res)
+
+	testing.expect(t, ws_test_write_string(&res, WS_Opcode_Text, "hello"), "write text frame")
+	f, fok := ws_test_read_frame(&res)
+	testing.expect(t, fok, "read echoed text frame")
+	testing.expect(t, f.fin, "the echo is a single frame")
+	testing.expect_value(t, f.opcode, WS_Opcode_Text)
+	testing.expect_value(t, string(f.payload), "hello")
+
+	// Big enough for a 16 bit extended length.
+	binary := make([]byte, 300, context.temp_allocator)
+	for _, i in binary do binary[i] = u8(i)
+
+	testing.expect(t, ws_test_write_frame(&res, WS_Opcode_Binary, binary), "write binary frame")
+	f, fok = ws_test_read_frame(&res)
+	testing.expect(t, fok, "read echoed binary frame")
+	testing.expect_value(t, f.opcode, WS_Opcode_Binary)
+	testing.expect_value(t, len(f.payload), len(binary))
+	testing.expect_value(t, string(f.payload), string(binary))
+
+	testing.expect(t, ws_test_write_frame(&res, WS_Opcode_Close, {0x03, 0xe8}), "write close frame")
+	ws_test_expect_close(t, &res, 1000)
+}
+
+@(test)
+test_websocket_fragmentation_and_ping :: proc(t: ^testing.T) {
+	s: http.Test_Server
+	if !ws_test_start(t, &s) do return
+	defer http.test_server_stop(&s)
+
+	c: Client
+	client_init(&c)
+	defer client_destroy(&c)
+
+	res, ok := ws_test_connect(t, &s, &c)
+	if !ok do return
+	defer response_destroy(&res)
+
+	// Control frames can be sent in between the fragments of a message.
+	testing.expect(t, ws_test_write_string(&res, WS_Opcode_Text, "hel", fin = false), "write first fragment")
+	testing.expect(t, ws_test_write_string(&res, WS_Opcode_Ping, "are you there"), "write ping")
+	testing.expect(t, ws_test_write_string(&res, WS_Opcode_Continuation, "lo ", fin = false), "write second fragment")
+	testing.expect(t, ws_test_write_string(&res, WS_Opcode_Continuation, "world"), "write last fragment")
+
+	f, fok := ws_test_read_frame(&res)
+	testing.expect(t, fok, "read pong")
+	testing.expect_value(t, f.opcode, WS_Opcode_Pong)
+	testing.expect_value(t, string(f.payload), "are you there")
+
+	f, fok = ws_test_read_frame(&res)
+	testing.expect(t, fok, "read echoed message")
+	testing.expect_value(t, f.opcode, WS_Opcode_Text)
+	testing.expect_value(t, string(f.payload), "hello world")
+
+	// A continuation without a message to continue.
+	testing.expect(t, ws_test_write_string(&res, WS_Opcode_Continuation, "oops"), "write continuation")
+	ws_test_expect_close(t, &res, 1002)
+}
+
+@(test)
+test_websocket_close_codes :: proc(t: ^testing.T) {
+	s: http.Test_Server
+	if !ws_test_start(t, &s) do return
+	defer http.test_server_stop(&s)
+
+	c: Client
+	client_init(&c)
+	defer client_destroy(&c)
+
+	Case :: struct {
+		name:    string,
+		opcode:  u8,
+		payload: []byte,
+		masked:  bool,
+		code:    u16,
+	}
+
+	too_big := make([]byte, WS_Test_Max_Message + 1, context.temp_allocator)
+
+	cases := []Case{
+		{"going away is echoed", WS_Opcode_Close, {0x03, 0xe9, 'b', 'y', 'e'}, true, 1001},
+		{"close without a code", WS_Opcode_Close, {}, true, 1000},
+		{"reserved close code", WS_Opcode_Close, {0x03, 0xed}, true, 1002},
+		{"unmasked frame", WS_Opcode_Text, {'h', 'i'}, false, 1002},
+		{"unknown opcode", 0x3, {}, true, 1002},
+		{"invalid utf-8", WS_Opcode_Text, {0xff, 0xfe}, true, 1007},
+		{"message too big", WS_Opcode_Binary, too_big, true, 1009},
+	}
+
+	for tc in cases {
+		res, ok := ws_test_connect(t, &s, &c)
+		if !ok do return
+		defer response_destroy(&res)
+
+		if !ws_test_write_frame(&res, tc.opcode, tc.payload, masked = tc.masked) {
+			testing.errorf(t, "%s: could not write frame", tc.name)
+			continue
+		}
+
+		f, fok := ws_test_read_frame(&res)
+		if !fok || f.opcode != WS_Opcode_Close || len(f.payload) < 2 {
+			testing.errorf(t, "%s: expected a close frame, got %v (ok: %v)", tc.name, f, fok)
+			continue
+		}
+
+		if code := u16(f.payload[0]) << 8 | u16(f.payload[1]); code != tc.code {
+			testing.errorf(t, "%s: expected close code %i, got %i", tc.name, tc.code, code)
+		}
+	}
+}
+
+@(test)
+test_websocket_shutdown_timeout :: proc(t: ^testing.T) {
+	opts := http.Default_Server_Opts
+	opts.shutdown_timeout = 200 * time.Millisecond
+
+	s: http.Test_Server
+	if !ws_test_start(t, &s, opts) do return
+
+	c: Client
+	client_init(&c)
+	defer client_destroy(&c)
+
+	res, ok := ws_test_connect(t, &s, &c)
+	if !ok {
+		http.test_server_stop(&s)
+		return
+	}
+	defer response_destroy(&res)
+
+	// Open WebSocket and all, the shutdown does not wait forever.
+	start := time.now()
+	http.test_server_stop(&s)
+	if took := time.since(start); took > 3 * time.Second {
+		testing.errorf(t, "shutdown took %v with a shutdown_timeout of %v", took, opts.shutdown_timeout)
+	}
+
+	_, ok = ws_test_read_frame(&res)
+	testing.expect(t, !ok, "the connection should be closed by the shutdown")
+}
@@ -0,0 +1,345 @@
+package http
+
+import "core:bytes"
+import "core:hash"
+import "core:log"
+import "core:mem"
+import "core:strconv"
+import "core:strings"
+
+Compress_Encoding :: enum {
+	Identity,
+	Gzip,
+	Deflate,
+}
+
+Compress_Opts :: struct {
+	// Bodies smaller than this amount of bytes are sent as is, compressing them saves little or makes them bigger.
+	// Streamed bodies (see response_write) are always compressed, their size is not known up front.
+	// Files sent with respond_file are compressed as they are sent, without reading them into memory.
+	min_size:   int,
+	// The encodings that can be used, when the client accepts both equally, gzip is used.
+	encodings:  bit_set[Compress_Encoding],
+	// Content types (without parameters) that are not compressed, because they already are.
+	// A type ending with a slash, like "image/", matches every subtype.
+	skip_types: []string,
+}
+
+// Content types that are already compressed, compressing them again takes time without making them smaller.
+Default_Compress_Skip_Types := []string{
+	"image/png", "image/jpeg", "image/gif", "image/webp", "image/avif", "image/heic",
+	"video/", "audio/", "font/woff", "font/woff2",
+	"application/zip", "application/gzip", "application/x-gzip", "application/x-bzip2", "application/x-xz",
+	"application/x-7z-compressed", "application/x-rar-compressed", "application/zstd", "application/wasm",
+	"application/pdf", "application/octet-stream",
+}
+
+Default_Compress_Opts := Compress_Opts {
+	min_size   = 1024,
+	encodings  = {.Gzip, .Deflate},
+	skip_types = Default_Compress_Skip_Types,
+}
+
+// Compresses response bodies with gzip or deflate, based on the Accept-Encoding header of the request.
+//
+// Buffered bodies are compressed after the next handler returns, when they are bigger than opts.min_size.
+// Streamed bodies are compressed as they are written, a flush of the response also flushes the compressor,
+// so the client can decompress everything sent so far.
+//
+// The next handler can be nil when the middleware is added to a route group, the group sets it.
+middleware_compress :: proc(next: Maybe(^Handler), opts: ^Compress_Opts = nil) -> Handler {
+	h: Handler
+	h.user_data = opts != nil ? opts : &Default_Compress_Opts
+	h.next = next
+
+	h.handle = proc(h: ^Handler, req: ^Request, res: ^Response) {
+		opts := (^Compress_Opts)(h.user_data)
+
+		next, has_next := h.next.(^Handler)
+		if !has_next {
+			log.warn("middleware_compress does not have a next handler")
+			return
+		}
+
+		// Compressed by another compress middleware already.
+		if res._compress != nil {
+			next.handle(next, req, res)
+			return
+		}
+
+		c := new(Compressor, req.allocator)
+		c.opts = opts
+		c.encoding = compress_negotiate(req, opts.encodings)
+		c.allocator = req.allocator
+		res._compress = c
+
+		next.handle(next, req, res)
+
+		// Streamed, this was handled when the headers were sent.
+		if res._headers_sent do return
+
+		// A file body is compressed while it is sent, see respond_file.
+		if file, has_file := res._file.?; has_file {
+			if response_file_length(file) < opts.min_size do res._compress = nil
+			return
+		}
+
+		res._compress = nil
+		compress_body(c, res)
+	}
+
+	return h
+}
+
+// RFC 9110 12.5.3: chooses the encoding with the highest weight (q value) in the Accept-Encoding header.
+// Without the header, or when no encoding is acceptable, the response is not compressed.
+compress_negotiate :: proc(req: ^Request, encodings: bit_set[Compress_Encoding]) -> Compress_Encoding {
+	best := Compress_Encoding.Identity
+	best_q := 0.0
+	for e in ([]Compress_Encoding{.Gzip, .Deflate}) {
+		if e not_in encodings do continue
+
+		q := accept_encoding_weight(req, e == .Gzip ? "gzip" : "deflate")
+		if q > best_q {
+			best, best_q = e, q
+		}
+	}
+
+	return best
+}
+
+// Returns the weight (q value) the Accept-Encoding header of the request gives the content coding,
+// 0 means it is not acceptable, which is also the case when there is no Accept-Encoding header.
+accept_encoding_weight :: proc(req: ^Request, coding: string) -> f64 {
+	wildcard := 0.0
+
+	for value in headers_get_all(&req.headers, "accept-encoding", context.temp_allocator) {
+		value := value
+		for part in strings.split_iterator(&value, ",") {
+			name, params := part, ""
+			if i := strings.index_byte(part, ';'); i >= 0 {
+				name, params = part[:i], part[i+1:]
+			}
+			name = strings.trim_space(name)
+
+			q := 1.0
+			params = strings.trim_space(params)
+			if len(params) > 2 && (params[0] == 'q' || params[0] == 'Q') && params[1] == '=' {
+				q = strconv.parse_f64(strings.trim_space(params[2:])) or_else 0
+			}
+
+			switch {
+			case strings.equal_fold(name, coding), coding == "gzip" && strings.equal_fold(name, "x-gzip"):
+				return q
+			case name == "*":
+				wildcard = q
+			}
+		}
+	}
+
+	return wildcard
+}
+
+// Compresses a buffered body, when the response and its size allow it.
+@(private)
+compress_body :: proc(c: ^Compressor, res: ^Response) {
+	if !compress_response_allowed(c, res) do return
+
+	size := bytes.buffer_length(&res.body)
+	if c.encoding == .Identity || size < c.opts.min_size do return
+
+	out: bytes.Buffer
+	bytes.buffer_init_allocator(&out, 0, size / 2, c.allocator)
+
+	// Compressed in parts, so the compressor only needs its window and one block of memory.
+	data := bytes.buffer_to_bytes(&res.body)
+	for len(data) > 0 {
+		n := min(len(data), Response_Stream_Buffer_Size)
+		compressor_write(c, &out, data[:n])
+		data = data[n:]
+	}
+	compressor_finish(c, &out)
+
+	// Not worth it.
+	if bytes.buffer_length(&out) >= size {
+		bytes.buffer_destroy(&out)
+		return
+	}
+
+	bytes.buffer_destroy(&res.body)
+	res.body = out
+
+	compress_set_headers(c, res)
+	if headers_has(&res.headers, "content-length") {
+		buf := make([]byte, 32, c.allocator)
+		headers_set(&res.headers, "content-length", strconv.itoa(buf, bytes.buffer_length(&res.body)))
+	}
+}
+
+// Called when the headers of a streamed response are sent, decides if the body is compressed.
+@(private)
+compress_stream_start :: proc(r: ^Response) {
+	c := r._compress
+	if !response_needs_content_length(r, r._conn) || !compress_response_allowed(c, r) || c.encoding == .Identity {
+		r._compress = nil
+		return
+	}
+
+	// The length changes, the body is sent chunked instead.
+	headers_delete(&r.headers, "content-length")
+	compress_set_headers(c, r)
+
+	// A response to a HEAD request gets the headers of the compressed GET response, without a body to compress.
+	if !r._send_body {
+		r._compress = nil
+		return
+	}
+
+	c.streaming = true
+}
+
+// Compresses the next part of a streamed body, finishing the stream when it is the last part.
+// The returned bytes are valid until the next call.
+@(private)
+compress_stream_part :: proc(c: ^Compressor, data: []byte, last: bool) -> []byte {
+	if len(data) == 0 && !last do return nil
+
+	if c.out.buf == nil {
+		bytes.buffer_init_allocator(&c.out, 0, Response_Stream_Buffer_Size, c.allocator)
+	}
+	bytes.buffer_reset(&c.out)
+
+	compressor_write(c, &c.out, data)
+	if last {
+		compressor_finish(c, &c.out)
+	} else {
+		compressor_flush(c, &c.out)
+	}
+
+	return bytes.buffer_to_bytes(&c.out)
+}
+
+// Checks if the response can be compressed, adding Vary when its representation depends on the request's encodings.
+@(private)
+compress_response_allowed :: proc(c: ^Compressor, res: ^Response) -> bool {
+	#partial switch res.status {
+	case .No_Content, .Not_Modified, .Partial_Content:
+		// No body, or a range of the uncompressed representation.
+		return false
+	}
+	if status_informational(res.status) do return false
+
+	if headers_has(&res.headers, "content-encoding") do return false
+
+	// RFC 9111 5.2.2.6: no-transform forbids intermediaries (and us) to change the content.
+	for cc in headers_get_all(&res.headers, "cache-control", context.temp_allocator) {
+		if strings.contains(cc, "no-transform") do return false
+	}
+
+	content_type, has_type := headers_get(&res.headers, "content-type")
+	if !has_type do return false
+
+	mime := content_type
+	if i := strings.index_byte(mime, ';'); i >= 0 do mime = mime[:i]
+	mime = strings.trim_space(mime)
+	for skip in c.opts.skip_types {
+		if strings.has_suffix(skip, "/") {
+			if len(mime) > len(skip) && strings.equal_fold(mime[:len(skip)], skip) do return false
+		} else if strings.equal_fold(mime, skip) {
+			return false
+		}
+	}
+
+	// RFC 9110 12.5.5: caches need to know the response differs based on the Accept-Encoding of the request.
+	headers_add_vary(&res.headers, "accept-encoding")
+
+	return true
+}
+
+@(private)
+compress_set_headers :: proc(c: ^Compressor, res: ^Response) {
+	headers_set(&res.headers, "content-encoding", c.encoding == .Gzip ? "gzip" : "deflate")
+
+	// RFC 9110 8.8.3: the compressed representation is not byte for byte the same, a strong validator would be wrong.
+	if etag, ok := headers_get(&res.headers, "etag"); ok && strings.has_prefix(etag, "\"") {
+		headers_set(&res.headers, "etag", strings.concatenate({"W/", etag}, c.allocator))
+	}
+}
+
+// Wraps the deflate compressor with the gzip (RFC 1952) or zlib (RFC 1950) header and trailer.
+// The "deflate" content coding is the zlib format, RFC 9110 8.4.1.2.
+@(private)
+Compressor :: struct {
+	opts:      ^Compress_Opts,
+	encoding:  Compress_Encoding,
+	allocator: mem.Allocator,
+
+	deflater:  Deflater,
+	started:   bool,
+	// Whether this compresses a streamed body.
+	streaming: bool,
+	// Output buffer for the parts of a streamed body.
+	out:       bytes.Buffer,
+
+	// CRC-32 for gzip, Adler-32 for zlib.
+	checksum:  u32,
+	size:      u32,
+}
+
+@(private)
+compressor_write :: proc(c: ^Compressor, out: ^bytes.Buffer, data: []byte) {
+	if !c.started {
+		c.started = true
+		deflater_init(&c.deflater, c.allocator)
+
+		switch c.encoding {
+		case .Gzip:
+			// ID1, ID2, CM (deflate), FLG, MTIME (4, unknown), XFL, OS (unknown).
+			bytes.buffer_write(out, {0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff})
+		case .Deflate:
+			// CMF (deflate, 32K window), FLG (default level, check bits).
+			bytes.buffer_write(out, {0x78, 0x9c})
+			c.checksum = 1
+		case .Identity:
+			panic("compressor used without an encoding")
+		}
+	}
+
+	if len(data) == 0 do return
+
+	switch c.encoding {
+	case .Gzip:     c.checksum = hash.crc32(data, c.checksum)
+	case .Deflate:  c.checksum = hash.adler32(data, c.checksum)
+	case .Identity:
+	}
+	c.size += u32(len(data))
+
+	deflater_write(&c.deflater, out, data)
+}
+
+@(private)
+compressor_flush :: proc(c: ^Compressor, out: ^bytes.Buffer) {
+	deflater_flush(&c.deflater, out)
+}
+
+@(private)
+compressor_finish :: proc(c: ^Compressor, out: ^bytes.Buffer) {
+	compressor_write(c, out, nil)
+	deflater_finish(&c.deflater, out)
+	defer deflater_destroy(&c.deflater)
+
+	switch c.encoding {
+	case .Gzip:
+		// CRC-32 and the size modulo 2^32, both little endian.
+		trailer: [8]byte
+		for i in 0..<4 {
+			trailer[i] = byte(c.checksum >> uint(8 * i))
+			trailer[4 + i] = byte(c.size >> uint(8 * i))
+		}
+		bytes.buffer_write(out, trailer[:])
+	case .Deflate:
+		// Adler-32, big endian.
+		bytes.buffer_write(out, {byte(c.checksum >> 24), byte(c.checksum >> 16), byte(c.checksum >> 8), byte(c.checksum)})
+	case .Identity:
+	}
+}
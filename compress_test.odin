@@ -0,0 +1,233 @@
+package http
+
+import "core:bytes"
+import "core:compress"
+import "core:compress/gzip"
+import "core:compress/zlib"
+import "core:fmt"
+import "core:strings"
+import "core:testing"
+
+// Compresses the parts like a streamed body would be, without the flushes.
+@(private)
+compress_test_encode :: proc(encoding: Compress_Encoding, parts: ..[]byte) -> []byte {
+	c := Compressor{encoding = encoding, allocator = context.temp_allocator}
+
+	out: bytes.Buffer
+	bytes.buffer_init_allocator(&out, 0, 0, context.temp_allocator)
+	for part in parts {
+		compressor_write(&c, &out, part)
+	}
+	compressor_finish(&c, &out)
+	return bytes.buffer_to_bytes(&out)
+}
+
+// Checks that the decoders of the core library decode the compressed bytes to the expected bytes.
+@(private)
+compress_test_expect_decodes :: proc(t: ^testing.T, encoding: Compress_Encoding, compressed, expected: []byte, loc := #caller_location) {
+	buf: bytes.Buffer
+	defer bytes.buffer_destroy(&buf)
+
+	err: compress.Error
+	if encoding == .Gzip {
+		err = gzip.load_from_slice(compressed, &buf, len(compressed))
+	} else {
+		err = zlib.inflate(compressed, &buf)
+	}
+
+	if err != nil {
+		testing.errorf(t, "%v output could not be decoded: %v", encoding, err, loc = loc)
+		return
+	}
+
+	decoded := bytes.buffer_to_bytes(&buf)
+	if !bytes.equal(decoded, expected) {
+		testing.errorf(t, "%v output decodes to %i bytes that differ from the %i bytes that went in", encoding, len(decoded), len(expected), loc = loc)
+	}
+}
+
+// Compresses the data in parts of the size compress_body uses, with both encodings, and decodes it again.
+@(private)
+compress_test_roundtrip :: proc(t: ^testing.T, data: []byte, loc := #caller_location) {
+	parts := make([dynamic][]byte, context.temp_allocator)
+	rest := data
+	for len(rest) > 0 {
+		n := min(len(rest), Response_Stream_Buffer_Size)
+		append(&parts, rest[:n])
+		rest = rest[n:]
+	}
+
+	for encoding in ([]Compress_Encoding{.Gzip, .Deflate}) {
+		compress_test_expect_decodes(t, encoding, compress_test_encode(encoding, ..parts[:]), data, loc)
+	}
+}
+
+// The type of the first block of a zlib stream, 1 for the fixed codes, 2 for dynamic codes.
+@(private)
+compress_test_first_block_type :: proc(zlib_stream: []byte) -> int {
+	return int(zlib_stream[2] >> 1) & 0b11
+}
+
+// Text that repeats, but not exactly, like most bodies.
+@(private)
+compress_test_text :: proc(sentences: int) -> []byte {
+	b := strings.builder_make(context.temp_allocator)
+	for i in 0..<sentences {
+		fmt.sbprintf(&b, "The quick brown fox jumps over the lazy dog %i. ", i)
+	}
+	return b.buf[:]
+}
+
+// Bytes that don't compress, from a xorshift generator so a failure can be reproduced.
+@(private)
+compress_test_random :: proc(n: int) -> []byte {
+	data := make([]byte, n, context.temp_allocator)
+	state: u32 = 2463534242
+	for _, i in data {
+		state ~= state << 13
+		state ~= state >> 17
+		state ~= state << 5
+		data[i] = byte(state)
+	}
+	return data
+}
+
+@(test)
+test_compress_empty :: proc(t: ^testing.T) {
+	for encoding in ([]Compress_Encoding{.Gzip, .Deflate}) {
+		compress_test_expect_decodes(t, encoding, compress_test_encode(encoding), nil)
+		compress_test_expect_decodes(t, encoding, compress_test_encode(encoding, nil, nil), nil)
+	}
+}
+
+@(test)
+test_compress_window_slides :: proc(t: ^testing.T) {
+	// Well over the two windows that are kept, with matches at every distance.
+	data := compress_test_text(5000)
+	testing.expect(t, len(data) > 4 * Deflate_Window_Size, "the text is bigger than the window")
+
+	compress_test_roundtrip(t, data)
+}
+
+@(test)
+test_compress_flush_decodes_prefix :: proc(t: ^testing.T) {
+	first := compress_test_text(100)
+	second := compress_test_text(300)[len(first):]
+
+	d: Deflater
+	deflater_init(&d, context.temp_allocator)
+	defer deflater_destroy(&d)
+
+	out: bytes.Buffer
+	bytes.buffer_init_allocator(&out, 0, 0, context.temp_allocator)
+
+	deflater_write(&d, &out, first)
+	deflater_flush(&d, &out)
+
+	// What was sent so far is complete, ending it with an empty final block (fixed codes, end of block) decodes the first part.
+	prefix := make([dynamic]byte, context.temp_allocator)
+	append(&prefix, ..bytes.buffer_to_bytes(&out))
+	append(&prefix, 0x03, 0x00)
+
+	buf: bytes.Buffer
+	defer bytes.buffer_destroy(&buf)
+	if err := zlib.inflate(prefix[:], &buf, true); err != nil {
+		testing.errorf(t, "the flushed prefix could not be decoded: %v", err)
+	} else {
+		testing.expect(t, bytes.equal(bytes.buffer_to_bytes(&buf), first), "the flushed prefix decodes to what was written before the flush")
+	}
+
+	// The stream goes on after the flush, with matches reaching back before it.
+	deflater_write(&d, &out, second)
+	deflater_flush(&d, &out)
+	deflater_finish(&d, &out)
+
+	bytes.buffer_reset(&buf)
+	if err := zlib.inflate(bytes.buffer_to_bytes(&out), &buf, true); err != nil {
+		testing.errorf(t, "the flushed stream could not be decoded: %v", err)
+	} else {
+		testing.expect(t, bytes.equal(bytes.buffer_to_bytes(&buf), compress_test_text(300)), "the flushed stream decodes to everything that was written")
+	}
+}
+
+@(test)
+test_compress_long_runs :: proc(t: ^testing.T) {
+	// Most symbols are unused, so the code lengths are long runs of zeros, with the maximum match length over and over.
+	data := make([]byte, 100_000 + 5_000, context.temp_allocator)
+	for _, i in data {
+		data[i] = i < 100_000 ? 'a' : 'b'
+	}
+	compress_test_roundtrip(t, data)
+
+	// Many symbols with the same code length, which are repeated runs of a non-zero length.
+	alphabet := make([]byte, 64 << 10, context.temp_allocator)
+	for _, i in alphabet {
+		alphabet[i] = byte(i % 256)
+	}
+	compress_test_roundtrip(t, alphabet)
+}
+
+@(test)
+test_compress_incompressible :: proc(t: ^testing.T) {
+	data := compress_test_random(100_000)
+	compress_test_roundtrip(t, data)
+
+	// Blocks fall back to codes that cost about 8 bits per byte, without blowing up the size.
+	compressed := compress_test_encode(.Gzip, data)
+	testing.expectf(t, len(compressed) < len(data) + len(data) / 20, "random data grows to %i bytes", len(compressed))
+}
+
+@(test)
+test_compress_fixed_and_dynamic_blocks :: proc(t: ^testing.T) {
+	short := transmute([]byte)string("hello hello hello world")
+	compressed := compress_test_encode(.Deflate, short)
+	testing.expect_value(t, compress_test_first_block_type(compressed), 1)
+	compress_test_expect_decodes(t, .Deflate, compressed, short)
+	compress_test_expect_decodes(t, .Gzip, compress_test_encode(.Gzip, short), short)
+
+	text := compress_test_text(3000)
+	compressed = compress_test_encode(.Deflate, text)
+	testing.expect_value(t, compress_test_first_block_type(compressed), 2)
+	compress_test_expect_decodes(t, .Deflate, compressed, text)
+	compress_test_expect_decodes(t, .Gzip, compress_test_encode(.Gzip, text), text)
+}
+
+@(test)
+test_compress_negotiate :: proc(t: ^testing.T) {
+	Case :: struct {
+		accept_encoding:   Maybe(string),
+		gzip_q, deflate_q: f64,
+		expected:          Compress_Encoding,
+	}
+
+	cases := []Case{
+		{nil, 0, 0, .Identity},
+		{"gzip", 1, 0, .Gzip},
+		{"gzip;q=0", 0, 0, .Identity},
+		{"gzip;q=0, deflate", 0, 1, .Deflate},
+		{"*;q=0.5", 0.5, 0.5, .Gzip},
+		{"*;q=0.5, gzip;q=0", 0, 0.5, .Deflate},
+		{"x-gzip", 1, 0, .Gzip},
+		{"deflate;q=0.8, gzip;q=0.5", 0.5, 0.8, .Deflate},
+		{"br, identity", 0, 0, .Identity},
+	}
+
+	for c in cases {
+		req: Request
+		request_init(&req, context.temp_allocator)
+		if value, ok := c.accept_encoding.?; ok {
+			headers_set(&req.headers, "accept-encoding", value)
+		}
+
+		testing.expectf(t, accept_encoding_weight(&req, "gzip") == c.gzip_q, "%v: gzip has weight %v, expected %v", c.accept_encoding, accept_encoding_weight(&req, "gzip"), c.gzip_q)
+		testing.expectf(t, accept_encoding_weight(&req, "deflate") == c.deflate_q, "%v: deflate has weight %v, expected %v", c.accept_encoding, accept_encoding_weight(&req, "deflate"), c.deflate_q)
+		testing.expectf(t, compress_negotiate(&req, {.Gzip, .Deflate}) == c.expected, "%v: negotiated %v, expected %v", c.accept_encoding, compress_negotiate(&req, {.Gzip, .Deflate}), c.expected)
+	}
+
+	// Only the enabled encodings are chosen.
+	req: Request
+	request_init(&req, context.temp_allocator)
+	headers_set(&req.headers, "accept-encoding", "gzip, deflate;q=0.5")
+	testing.expect_value(t, compress_negotiate(&req, {.Deflate}), Compress_Encoding.Deflate)
+	testing.expect_value(t, compress_negotiate(&req, {}), Compress_Encoding.Identity)
+}
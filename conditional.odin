@@ -0,0 +1,163 @@
+package http
+
+import "core:hash"
+import "core:strconv"
+import "core:strings"
+import "core:time"
+
+// Formats a strong entity tag for the opaque value, which should not contain double quotes.
+etag_strong :: proc(opaque: string, allocator := context.allocator) -> string {
+	return strings.concatenate({"\"", opaque, "\""}, allocator)
+}
+
+// Formats a weak entity tag for the opaque value, which should not contain double quotes.
+// A weak tag says two representations are equivalent, not that they are byte for byte the same.
+etag_weak :: proc(opaque: string, allocator := context.allocator) -> string {
+	return strings.concatenate({"W/\"", opaque, "\""}, allocator)
+}
+
+// A strong entity tag of a file, based on its size and modification time, like most servers do.
+etag_file :: proc(size: i64, modified: time.Time, allocator := context.allocator) -> string {
+	buf: [48]byte
+	b := strconv.append_int(buf[:16], size, 16)
+	n := len(b)
+	buf[n] = '-'
+	m := strconv.append_int(buf[n+1:], time.to_unix_nanoseconds(modified), 16)
+	return etag_strong(string(buf[:n+1+len(m)]), allocator)
+}
+
+// A strong entity tag of content, based on a hash of it.
+etag_content :: proc(content: []byte, allocator := context.allocator) -> string {
+	buf: [16]byte
+	return etag_strong(strconv.append_u64(buf[:], hash.fnv64a(content), 16), allocator)
+}
+
+// Sets the validators of the response and evaluates the conditional headers of the request against them,
+// following the order of RFC 9110 13.2.2.
+//
+// etag is a formatted entity tag (see etag_strong and etag_weak), empty when there is none,
+// last_modified is the zero time when there is none.
+//
+// Returns false when the request's preconditions mean the content should not be sent,
+// the status is then set to 304 Not Modified or 412 Precondition Failed.
+response_preconditions :: proc(r: ^Response, etag: string, last_modified: time.Time) -> bool {
+	has_modified := last_modified != {}
+
+	// RFC 9110 8.8.2.1: a last modified time in the future is replaced by the time the response is created.
+	modified := last_modified
+	if has_modified {
+		now := time.now()
+		if time.diff(modified, now) < 0 do modified = now
+		headers_set(&r.headers, "last-modified", format_date_header(modified, context.temp_allocator))
+	}
+
+	if etag != "" {
+		headers_set(&r.headers, "etag", etag)
+	}
+
+	if r._conn == nil || r._conn.curr_req == nil do return true
+	req := r._conn.curr_req
+
+	rline, _ := req.line.(Requestline)
+	get_or_head := rline.method == .Get || rline.method == .Head
+
+	// 1. RFC 9110 13.1.1: If-Match uses the strong comparison.
+	if values := headers_get_all(&req.headers, "if-match", context.temp_allocator); len(values) > 0 {
+		if !etag_list_matches(values, etag, false) {
+			r.status = .Precondition_Failed
+			return false
+		}
+	} else if value, ok := headers_get(&req.headers, "if-unmodified-since"); ok && has_modified {
+		// 2. RFC 9110 13.1.4: only evaluated without If-Match, an invalid date is ignored.
+		if since, valid := parse_date_header(value); valid && date_after(modified, since) {
+			r.status = .Precondition_Failed
+			return false
+		}
+	}
+
+	// 3. RFC 9110 13.1.2: If-None-Match uses the weak comparison.
+	if values := headers_get_all(&req.headers, "if-none-match", context.temp_allocator); len(values) > 0 {
+		if etag_list_matches(values, etag, true) {
+			r.status = get_or_head ? .Not_Modified : .Precondition_Failed
+			return false
+		}
+	} else if value, ok := headers_get(&req.headers, "if-modified-since"); ok && has_modified && get_or_head {
+		// 4. RFC 9110 13.1.3: only evaluated without If-None-Match, and for GET and HEAD.
+		if since, valid := parse_date_header(value); valid && !date_after(modified, since) {
+			r.status = .Not_Modified
+			return false
+		}
+	}
+
+	return true
+}
+
+// Evaluates the If-Range header of the request, RFC 9110 13.1.5.
+// Returns true when there is no If-Range header, or when it matches the validators set on the response,
+// meaning the Range header can be used. Otherwise the full representation should be sent.
+request_if_range :: proc(req: ^Request, res: ^Response) -> bool {
+	value, has := headers_get(&req.headers, "if-range")
+	if !has do return true
+	value = strings.trim_space(value)
+
+	// An entity tag, compared with the strong comparison.
+	if strings.has_prefix(value, "\"") || strings.has_prefix(value, "W/") {
+		etag, has_etag := headers_get(&res.headers, "etag")
+		return has_etag && etag_compare(value, etag, false)
+	}
+
+	// A date, which has to be an exact match.
+	since, valid := parse_date_header(value)
+	if !valid do return false
+
+	modified_value, has_modified := headers_get(&res.headers, "last-modified")
+	if !has_modified do return false
+
+	modified, modified_valid := parse_date_header(modified_value)
+	return modified_valid && time.to_unix_seconds(modified) == time.to_unix_seconds(since)
+}
+
+// Whether the entity tag matches one in the list values of a conditional header, "*" matches any existing etag.
+@(private)
+etag_list_matches :: proc(values: []string, etag: string, weak: bool) -> bool {
+	for value in values {
+		rest := strings.trim_space(value)
+		if rest == "*" do return etag != ""
+
+		for len(rest) > 0 {
+			// Entity tags can contain commas, so the list is split on the quotes.
+			rest = strings.trim_left(rest, ", \t")
+			if rest == "" do break
+
+			start := 0
+			if strings.has_prefix(rest, "W/") do start = 2
+			if start >= len(rest) || rest[start] != '"' do break
+
+			end := strings.index_byte(rest[start+1:], '"')
+			if end == -1 do break
+			end += start + 2
+
+			if etag != "" && etag_compare(rest[:end], etag, weak) do return true
+			rest = rest[end:]
+		}
+	}
+	return false
+}
+
+// Compares two entity tags, RFC 9110 8.8.3.2.
+// The strong comparison requires both to be strong, the weak comparison ignores the weakness.
+@(private)
+etag_compare :: proc(a, b: string, weak: bool) -> bool {
+	a_weak, b_weak := strings.has_prefix(a, "W/"), strings.has_prefix(b, "W/")
+	if !weak && (a_weak || b_weak) do return false
+
+	a_opaque := a[2:] if a_weak else a
+	b_opaque := b[2:] if b_weak else b
+	return a_opaque == b_opaque
+}
+
+// Whether a is later than b, with the one second precision of HTTP dates.
+@(private)
+date_after :: proc(a, b: time.Time) -> bool {
+	return time.to_unix_seconds(a) > time.to_unix_seconds(b)
+}
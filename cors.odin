@@ -0,0 +1,179 @@
+package http
+
+import "core:log"
+import "core:strconv"
+import "core:strings"
+import "core:time"
+
+Cors_Opts :: struct {
+	// The origins that are allowed to read responses, like "https://example.com".
+	// A "*" inside an origin matches subdomains, like "https://*.example.com", a lone "*" matches every origin.
+	// A lone "*" is ignored when credentials are allowed, any site could read responses meant for the user otherwise.
+	origins:        []string,
+	// Called with the Origin header when it does not match any of the origins, return true to allow it.
+	check_origin:   proc(req: ^Request, origin: string) -> bool,
+	// The methods that are allowed, sent in answer to preflight requests.
+	methods:        bit_set[Method],
+	// The request headers that are allowed, sent in answer to preflight requests.
+	// When empty, every header that the preflight request asks for is allowed.
+	headers:        []string,
+	// The response headers that scripts are allowed to read, next to the CORS-safelisted ones.
+	expose_headers: []string,
+	// Whether requests can include credentials (cookies, the authorization header and client certificates).
+	// The origin itself is sent as the allowed origin then, browsers don't accept "*" for a request with credentials.
+	credentials:    bool,
+	// How long browsers can cache the answer to a preflight request,
+	// 0 to not send Access-Control-Max-Age, in which case they cache it for 5 seconds.
+	max_age:        time.Duration,
+}
+
+Default_Cors_Opts := Cors_Opts {
+	origins = []string{"*"},
+	methods = {.Get, .Head, .Post, .Put, .Patch, .Delete},
+}
+
+// Adds the headers that allow cross-origin requests from the origins in opts (Cross-Origin Resource Sharing).
+//
+// Preflight requests (an OPTIONS request with an Access-Control-Request-Method header) are answered by the middleware,
+// they don't reach the next handler. Wrap the router with this middleware, so preflight requests for every route are answered,
+// a route group only sees the methods that are routed to it.
+//
+// Requests from origins that are not allowed are handled as usual, just without the headers that allow browsers
+// to read the response, preflight requests from them are answered with 403 Forbidden.
+//
+// The next handler can be nil when the middleware is added to a route group, the group sets it.
+middleware_cors :: proc(next: Maybe(^Handler), opts: ^Cors_Opts = nil) -> Handler {
+	h: Handler
+	h.user_data = opts != nil ? opts : &Default_Cors_Opts
+	h.next = next
+
+	if o := (^Cors_Opts)(h.user_data); o.credentials && cors_any_origin_listed(o) {
+		log.warn("middleware_cors ignores the \"*\" origin because credentials are allowed, list the origins instead")
+	}
+
+	h.handle = proc(h: ^Handler, req: ^Request, res: ^Response) {
+		opts := (^Cors_Opts)(h.user_data)
+
+		next, has_next := h.next.(^Handler)
+		if !has_next {
+			log.warn("middleware_cors does not have a next handler")
+			return
+		}
+
+		// The response depends on the Origin header, unless every origin gets "*".
+		// Also when there is no Origin header, so a cache does not hand that response to a cross-origin request.
+		if !cors_any_origin(opts) {
+			headers_add_vary(&res.headers, "origin")
+		}
+
+		origin, has_origin := headers_get(&req.headers, "origin")
+		if !has_origin {
+			next.handle(next, req, res)
+			return
+		}
+
+		allowed := cors_origin_allowed(opts, req, origin)
+
+		rline := req.line.(Requestline)
+		if rline.method == .Options && headers_has(&req.headers, "access-control-request-method") {
+			cors_preflight(opts, req, res, origin, allowed)
+			return
+		}
+
+		// Set before the next handler, which could stream the response.
+		if allowed {
+			cors_allow_origin(opts, res, origin)
+
+			if len(opts.expose_headers) > 0 {
+				headers_set(&res.headers, "access-control-expose-headers", strings.join(opts.expose_headers, ", ", context.temp_allocator))
+			}
+		}
+
+		next.handle(next, req, res)
+	}
+
+	return h
+}
+
+// Answers a preflight request, which asks whether the actual request is allowed.
+@(private)
+cors_preflight :: proc(opts: ^Cors_Opts, req: ^Request, res: ^Response, origin: string, allowed: bool) {
+	headers_add_vary(&res.headers, "access-control-request-method")
+	headers_add_vary(&res.headers, "access-control-request-headers")
+
+	if !allowed {
+		res.status = .Forbidden
+		return
+	}
+
+	cors_allow_origin(opts, res, origin)
+	headers_set(&res.headers, "access-control-allow-methods", router_allow_header(opts.methods))
+
+	if len(opts.headers) > 0 {
+		headers_set(&res.headers, "access-control-allow-headers", strings.join(opts.headers, ", ", context.temp_allocator))
+	} else if requested := headers_get_all(&req.headers, "access-control-request-headers", context.temp_allocator); len(requested) > 0 {
+		headers_set(&res.headers, "access-control-allow-headers", strings.join(requested, ", ", context.temp_allocator))
+	}
+
+	if opts.max_age > 0 {
+		buf := make([]byte, 32, context.temp_allocator)
+		headers_set(&res.headers, "access-control-max-age", strconv.itoa(buf, int(opts.max_age / time.Second)))
+	}
+
+	res.status = .No_Content
+}
+
+@(private)
+cors_allow_origin :: proc(opts: ^Cors_Opts, res: ^Response, origin: string) {
+	headers_set(&res.headers, "access-control-allow-origin", cors_any_origin(opts) ? "*" : origin)
+
+	if opts.credentials {
+		headers_set(&res.headers, "access-control-allow-credentials", "true")
+	}
+}
+
+// Whether every origin is allowed, and can be answered with "*".
+@(private)
+cors_any_origin :: proc(opts: ^Cors_Opts) -> bool {
+	return !opts.credentials && cors_any_origin_listed(opts)
+}
+
+@(private)
+cors_any_origin_listed :: proc(opts: ^Cors_Opts) -> bool {
+	for allowed in opts.origins {
+		if allowed == "*" do return true
+	}
+	return false
+}
+
+@(private)
+cors_origin_allowed :: proc(opts: ^Cors_Opts, req: ^Request, origin: string) -> bool {
+	for allowed in opts.origins {
+		if allowed == "*" {
+			if opts.credentials do continue
+			return true
+		}
+
+		star := strings.index_byte(allowed, '*')
+		if star < 0 {
+			if strings.equal_fold(origin, allowed) do return true
+			continue
+		}
+
+		prefix, suffix := allowed[:star], allowed[star+1:]
+		if len(origin) <= len(prefix) + len(suffix) do continue
+		if !strings.equal_fold(origin[:len(prefix)], prefix) do continue
+		if !strings.equal_fold(origin[len(origin)-len(suffix):], suffix) do continue
+
+		// Only subdomains match, "https://*.example.com" does not match "https://evil.com/.example.com".
+		subdomain := origin[len(prefix):len(origin)-len(suffix)]
+		if strings.trim_left(subdomain, "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-.") == "" {
+			return true
+		}
+	}
+
+	if opts.check_origin != nil {
+		return opts.check_origin(req, origin)
+	}
+	return false
+}
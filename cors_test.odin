@@ -0,0 +1,145 @@
+package http
+
+import "core:testing"
+
+// Calls the CORS middleware like the server would, the handler behind it responds with "handled".
+// The origin and the requested method (for a preflight request) are only sent when they are not empty.
+@(private)
+cors_test_request :: proc(opts: ^Cors_Opts, method: Method, origin: string, request_method := "") -> (res: Response) {
+	next := handler(proc(req: ^Request, res: ^Response) {
+		respond_plain(res, "handled")
+	})
+	h := middleware_cors(&next, opts)
+
+	req: Request
+	request_init(&req, context.temp_allocator)
+	req.line = Requestline{method = method, target = "/", version = {1, 1}}
+	req.url = url_parse("/", context.temp_allocator)
+	if origin != "" {
+		headers_set(&req.headers, "origin", origin)
+	}
+	if request_method != "" {
+		headers_set(&req.headers, "access-control-request-method", request_method)
+	}
+
+	response_init(&res, context.temp_allocator)
+	h.handle(&h, &req, &res)
+	return
+}
+
+@(test)
+test_cors_wildcard_subdomains :: proc(t: ^testing.T) {
+	opts := Cors_Opts{origins = {"https://*.example.com"}}
+
+	Case :: struct {
+		origin:  string,
+		allowed: bool,
+	}
+
+	cases := []Case{
+		{"https://a.example.com", true},
+		{"https://a.b.example.com", true},
+		{"https://A.Example.com", true},
+		{"https://evil.com/.example.com", false},
+		{"https://evil.com?.example.com", false},
+		{"https://evil.com#.example.com", false},
+		{"https://user@evil.com.example.com", false},
+		{"https://example.com", false},
+		{"https://.example.com", false},
+		{"https://evilexample.com", false},
+		{"https://a.example.com.evil.com", false},
+		{"http://a.example.com", false},
+		{"null", false},
+	}
+
+	for c in cases {
+		req: Request
+		request_init(&req, context.temp_allocator)
+		testing.expectf(t, cors_origin_allowed(&opts, &req, c.origin) == c.allowed, "%q: expected allowed to be %v", c.origin, c.allowed)
+	}
+
+	// Through the middleware, only allowed origins get the header.
+	res := cors_test_request(&opts, .Get, "https://a.example.com")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-origin") or_else "", "https://a.example.com")
+
+	res = cors_test_request(&opts, .Get, "https://evil.com/.example.com")
+	testing.expect(t, !headers_has(&res.headers, "access-control-allow-origin"), "a disallowed origin is not allowed to read the response")
+	testing.expect_value(t, string(res.body.buf[:]), "handled")
+}
+
+@(test)
+test_cors_preflight_disallowed_origin :: proc(t: ^testing.T) {
+	opts := Cors_Opts{origins = {"https://example.com"}, methods = {.Get, .Post}}
+
+	res := cors_test_request(&opts, .Options, "https://evil.com", "POST")
+	testing.expect_value(t, res.status, Status.Forbidden)
+	testing.expect(t, !headers_has(&res.headers, "access-control-allow-origin"), "a disallowed origin is not allowed")
+	testing.expect(t, !headers_has(&res.headers, "access-control-allow-methods"), "a disallowed origin gets no methods")
+	testing.expect(t, len(res.body.buf) == 0, "a preflight request does not reach the handler")
+
+	res = cors_test_request(&opts, .Options, "https://example.com", "POST")
+	testing.expect_value(t, res.status, Status.No_Content)
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-origin") or_else "", "https://example.com")
+	testing.expect(t, headers_has(&res.headers, "access-control-allow-methods"), "an allowed origin gets the methods")
+	testing.expect(t, len(res.body.buf) == 0, "a preflight request does not reach the handler")
+}
+
+@(test)
+test_cors_credentials_echo_origin :: proc(t: ^testing.T) {
+	opts := Cors_Opts{origins = {"https://example.com"}, methods = {.Get}}
+
+	res := cors_test_request(&opts, .Get, "https://example.com")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-origin") or_else "", "https://example.com")
+	testing.expect(t, !headers_has(&res.headers, "access-control-allow-credentials"), "credentials are not allowed by default")
+
+	// Browsers refuse "*" for requests with credentials.
+	opts.credentials = true
+	res = cors_test_request(&opts, .Get, "https://example.com")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-origin") or_else "", "https://example.com")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-credentials") or_else "", "true")
+	testing.expect(t, header_has_token(headers_get(&res.headers, "vary") or_else "", "origin"), "the response depends on the origin")
+
+	res = cors_test_request(&opts, .Options, "https://example.com", "GET")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-origin") or_else "", "https://example.com")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-credentials") or_else "", "true")
+}
+
+@(test)
+test_cors_credentials_ignore_any_origin :: proc(t: ^testing.T) {
+	opts := Cors_Opts{origins = {"*"}, methods = {.Get}}
+
+	res := cors_test_request(&opts, .Get, "https://evil.com")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-origin") or_else "", "*")
+
+	// Any site could read the responses of the user otherwise.
+	opts.credentials = true
+	res = cors_test_request(&opts, .Get, "https://evil.com")
+	testing.expect(t, !headers_has(&res.headers, "access-control-allow-origin"), "\"*\" does not allow every origin with credentials")
+	testing.expect(t, !headers_has(&res.headers, "access-control-allow-credentials"), "\"*\" does not allow every origin with credentials")
+	testing.expect_value(t, string(res.body.buf[:]), "handled")
+
+	res = cors_test_request(&opts, .Options, "https://evil.com", "GET")
+	testing.expect_value(t, res.status, Status.Forbidden)
+	testing.expect(t, !headers_has(&res.headers, "access-control-allow-credentials"), "\"*\" does not allow every origin with credentials")
+
+	// The origins that are listed next to it are still allowed.
+	opts.origins = {"*", "https://example.com"}
+	res = cors_test_request(&opts, .Get, "https://example.com")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-origin") or_else "", "https://example.com")
+	testing.expect_value(t, headers_get(&res.headers, "access-control-allow-credentials") or_else "", "true")
+}
+
+@(test)
+test_cors_vary_without_origin :: proc(t: ^testing.T) {
+	opts := Cors_Opts{origins = {"https://example.com"}, methods = {.Get}}
+
+	res := cors_test_request(&opts, .Get, "")
+	testing.expect(t, header_has_token(headers_get(&res.headers, "vary") or_else "", "origin"), "vary has origin without an Origin header")
+	testing.expect(t, !headers_has(&res.headers, "access-control-allow-origin"), "a request without an origin gets no CORS headers")
+	testing.expect_value(t, string(res.body.buf[:]), "handled")
+
+	// Every origin gets the same response, nothing varies.
+	any_origin := Cors_Opts{origins = {"*"}, methods = {.Get}}
+	res = cors_test_request(&any_origin, .Get, "")
+	testing.expect(t, !headers_has(&res.headers, "vary"), "vary is not needed when every origin gets \"*\"")
+}
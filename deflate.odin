@@ -0,0 +1,538 @@
+//+private
+package http
+
+import "core:bytes"
+import "core:mem"
+import "core:slice"
+
+// A streaming DEFLATE (RFC 1951) compressor.
+//
+// Input is matched against a sliding window of the last 32KiB using hash chains (LZ77),
+// the resulting literals and matches are buffered and written out as blocks with Huffman codes built
+// from their frequencies, or with the fixed codes when that is smaller.
+//
+// Memory use is bounded, no matter how much is compressed: the window, the hash chains and one block of tokens.
+
+Deflate_Window_Size :: 1 << 15
+Deflate_Window_Mask :: Deflate_Window_Size - 1
+Deflate_Min_Match   :: 3
+Deflate_Max_Match   :: 258
+Deflate_Hash_Bits   :: 15
+Deflate_Hash_Size   :: 1 << Deflate_Hash_Bits
+// How many earlier positions are compared when looking for a match, more compresses better, but slower.
+Deflate_Max_Chain   :: 64
+// The amount of literals and matches that are written as one block.
+Deflate_Block_Tokens :: 1 << 14
+
+Deflate_Lit_Codes  :: 286
+Deflate_Dist_Codes :: 30
+Deflate_End_Block  :: 256
+
+// A literal byte, or when the high bit is set, a match with the length in bits 16-24 and the distance in bits 0-15.
+Deflate_Token :: distinct u32
+
+Deflate_Token_Match :: 1 << 31
+
+Deflater :: struct {
+	// Twice the window size, input is appended and slid back by a window when it is full.
+	window:     []byte,
+	window_end: int,
+	// The position in the window of the next byte to compress.
+	pos:        int,
+	// The most recent position of each hash, -1 for none.
+	head:       []i32,
+	// The previous position with the same hash, indexed by position & Deflate_Window_Mask.
+	prev:       []i32,
+
+	tokens:     [dynamic]Deflate_Token,
+	lit_freq:   [Deflate_Lit_Codes]int,
+	dist_freq:  [Deflate_Dist_Codes]int,
+
+	bits:       u64,
+	nbits:      uint,
+
+	allocator:  mem.Allocator,
+}
+
+Deflate_Length_Base := [29]u16{
+	3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+	35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+}
+
+Deflate_Length_Extra := [29]u8{
+	0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+	3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+}
+
+Deflate_Dist_Base := [30]u16{
+	1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+	257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+}
+
+Deflate_Dist_Extra := [30]u8{
+	0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+	7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+}
+
+// RFC 1951 3.2.7: the order in which the code length code lengths are written.
+Deflate_Code_Length_Order := [19]u8{16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15}
+
+deflater_init :: proc(d: ^Deflater, allocator := context.allocator) {
+	d.allocator = allocator
+	d.window = make([]byte, 2 * Deflate_Window_Size, allocator)
+	d.head = make([]i32, Deflate_Hash_Size, allocator)
+	d.prev = make([]i32, Deflate_Window_Size, allocator)
+	d.tokens = make([dynamic]Deflate_Token, 0, Deflate_Block_Tokens, allocator)
+	slice.fill(d.head, -1)
+	slice.fill(d.prev, -1)
+}
+
+deflater_destroy :: proc(d: ^Deflater) {
+	delete(d.window, d.allocator)
+	delete(d.head, d.allocator)
+	delete(d.prev, d.allocator)
+	delete(d.tokens)
+}
+
+// Compresses the data, output is written to out as blocks fill up.
+deflater_write :: proc(d: ^Deflater, out: ^bytes.Buffer, data: []byte) {
+	data := data
+	for len(data) > 0 {
+		if d.window_end == len(d.window) {
+			deflater_slide(d)
+		}
+
+		n := copy(d.window[d.window_end:], data)
+		d.window_end += n
+		data = data[n:]
+
+		deflater_compress(d, out, false)
+	}
+}
+
+// Compresses everything written so far and aligns the output to a byte with an empty stored block,
+// so the receiver can decompress everything up to here (a sync flush).
+deflater_flush :: proc(d: ^Deflater, out: ^bytes.Buffer) {
+	deflater_compress(d, out, true)
+	if len(d.tokens) > 0 {
+		deflater_block(d, out, false)
+	}
+
+	// Stored block header: BFINAL 0, BTYPE 00, then LEN 0 and NLEN 0xFFFF on a byte boundary.
+	deflater_bits(d, out, 0, 3)
+	deflater_align(d, out)
+	bytes.buffer_write(out, {0x00, 0x00, 0xFF, 0xFF})
+}
+
+// Compresses everything written so far and writes the final block.
+deflater_finish :: proc(d: ^Deflater, out: ^bytes.Buffer) {
+	deflater_compress(d, out, true)
+	deflater_block(d, out, true)
+	deflater_align(d, out)
+}
+
+// Moves the second half of the window to the first, making room for more input.
+deflater_slide :: proc(d: ^Deflater) {
+	assert(d.pos >= Deflate_Window_Size)
+
+	copy(d.window, d.window[Deflate_Window_Size:d.window_end])
+	d.window_end -= Deflate_Window_Size
+	d.pos -= Deflate_Window_Size
+
+	for v, i in d.head {
+		d.head[i] = v >= Deflate_Window_Size ? v - Deflate_Window_Size : -1
+	}
+	for v, i in d.prev {
+		d.prev[i] = v >= Deflate_Window_Size ? v - Deflate_Window_Size : -1
+	}
+}
+
+deflater_hash :: #force_inline proc(b: []byte) -> int {
+	return int((u32(b[0]) << 10 ~ u32(b[1]) << 5 ~ u32(b[2])) & (Deflate_Hash_Size - 1))
+}
+
+deflater_insert :: #force_inline proc(d: ^Deflater, pos: int) {
+	h := deflater_hash(d.window[pos:])
+	d.prev[pos & Deflate_Window_Mask] = d.head[h]
+	d.head[h] = i32(pos)
+}
+
+// Turns the input into literals and matches, keeping a full match worth of lookahead unless flushing.
+deflater_compress :: proc(d: ^Deflater, out: ^bytes.Buffer, flush: bool) {
+	for d.pos < d.window_end {
+		avail := d.window_end - d.pos
+		if !flush && avail < Deflate_Max_Match do break
+
+		length, dist := 0, 0
+		if avail >= Deflate_Min_Match {
+			length, dist = deflater_longest_match(d, min(avail, Deflate_Max_Match))
+			deflater_insert(d, d.pos)
+		}
+
+		if length >= Deflate_Min_Match {
+			deflater_token_match(d, length, dist)
+
+			// The positions inside the match can be the start of later matches.
+			for i in 1..<length {
+				p := d.pos + i
+				if d.window_end - p >= Deflate_Min_Match {
+					deflater_insert(d, p)
+				}
+			}
+			d.pos += length
+		} else {
+			deflater_token_literal(d, d.window[d.pos])
+			d.pos += 1
+		}
+
+		if len(d.tokens) >= Deflate_Block_Tokens {
+			deflater_block(d, out, false)
+		}
+	}
+}
+
+deflater_longest_match :: proc(d: ^Deflater, max_len: int) -> (best_len: int, best_dist: int) {
+	best_len = Deflate_Min_Match - 1
+
+	w := d.window
+	cand := int(d.head[deflater_hash(w[d.pos:])])
+	for chain := Deflate_Max_Chain; cand >= 0 && chain > 0; chain -= 1 {
+		dist := d.pos - cand
+		// Older entries of the chain are overwritten once they are a window behind.
+		if dist <= 0 || dist >= Deflate_Window_Size do break
+
+		// Only a match that is longer than the best so far is interesting, check that byte first.
+		if w[cand + best_len] == w[d.pos + best_len] {
+			l := 0
+			for l < max_len && w[cand + l] == w[d.pos + l] do l += 1
+
+			if l > best_len {
+				best_len, best_dist = l, dist
+				if l >= max_len do break
+			}
+		}
+
+		next := int(d.prev[cand & Deflate_Window_Mask])
+		if next >= cand do break
+		cand = next
+	}
+
+	return
+}
+
+deflater_token_literal :: proc(d: ^Deflater, b: byte) {
+	append(&d.tokens, Deflate_Token(b))
+	d.lit_freq[b] += 1
+}
+
+deflater_token_match :: proc(d: ^Deflater, length, dist: int) {
+	append(&d.tokens, Deflate_Token(Deflate_Token_Match | u32(length) << 16 | u32(dist)))
+	d.lit_freq[257 + deflate_length_code(length)] += 1
+	d.dist_freq[deflate_dist_code(dist)] += 1
+}
+
+deflate_length_code :: proc(length: int) -> int {
+	i := len(Deflate_Length_Base) - 1
+	for int(Deflate_Length_Base[i]) > length do i -= 1
+	return i
+}
+
+deflate_dist_code :: proc(dist: int) -> int {
+	i := len(Deflate_Dist_Base) - 1
+	for int(Deflate_Dist_Base[i]) > dist do i -= 1
+	return i
+}
+
+// Writes the buffered tokens as one block, using either the fixed codes or codes built for this block,
+// whichever is smaller.
+deflater_block :: proc(d: ^Deflater, out: ^bytes.Buffer, final: bool) {
+	d.lit_freq[Deflate_End_Block] += 1
+	defer {
+		clear(&d.tokens)
+		d.lit_freq = {}
+		d.dist_freq = {}
+	}
+
+	lit_lens: [Deflate_Lit_Codes]u8
+	dist_lens: [Deflate_Dist_Codes]u8
+	huffman_lengths(d.lit_freq[:], lit_lens[:], 15)
+	huffman_lengths(d.dist_freq[:], dist_lens[:], 15)
+
+	// The code lengths are sent run-length encoded, with their own code.
+	nlit := Deflate_Lit_Codes
+	for nlit > 257 && lit_lens[nlit - 1] == 0 do nlit -= 1
+	ndist := Deflate_Dist_Codes
+	for ndist > 1 && dist_lens[ndist - 1] == 0 do ndist -= 1
+
+	all_lens := make([]u8, nlit + ndist, context.temp_allocator)
+	copy(all_lens, lit_lens[:nlit])
+	copy(all_lens[nlit:], dist_lens[:ndist])
+
+	rle := deflate_rle(all_lens, context.temp_allocator)
+	cl_freq: [19]int
+	for sym in rle do cl_freq[sym & 0xFF] += 1
+
+	cl_lens: [19]u8
+	huffman_lengths(cl_freq[:], cl_lens[:], 7)
+
+	ncl := 19
+	for ncl > 4 && cl_lens[Deflate_Code_Length_Order[ncl - 1]] == 0 do ncl -= 1
+
+	fixed_lit, fixed_dist := deflate_fixed_lengths()
+
+	dynamic_size := 5 + 5 + 4 + 3 * ncl
+	for sym in rle {
+		code := sym & 0xFF
+		dynamic_size += int(cl_lens[code])
+		switch code {
+		case 16: dynamic_size += 2
+		case 17: dynamic_size += 3
+		case 18: dynamic_size += 7
+		}
+	}
+	dynamic_size += deflater_data_size(d, lit_lens[:], dist_lens[:])
+	fixed_size := deflater_data_size(d, fixed_lit[:], fixed_dist[:])
+
+	deflater_bits(d, out, final ? 1 : 0, 1)
+
+	if fixed_size <= dynamic_size {
+		deflater_bits(d, out, 1, 2)
+		deflater_data(d, out, fixed_lit[:], fixed_dist[:])
+		return
+	}
+
+	deflater_bits(d, out, 2, 2)
+	deflater_bits(d, out, u32(nlit - 257), 5)
+	deflater_bits(d, out, u32(ndist - 1), 5)
+	deflater_bits(d, out, u32(ncl - 4), 4)
+	for i in 0..<ncl {
+		deflater_bits(d, out, u32(cl_lens[Deflate_Code_Length_Order[i]]), 3)
+	}
+
+	cl_codes: [19]u16
+	huffman_codes(cl_lens[:], cl_codes[:])
+	for sym in rle {
+		code := sym & 0xFF
+		deflater_bits(d, out, u32(cl_codes[code]), uint(cl_lens[code]))
+		switch code {
+		case 16: deflater_bits(d, out, u32(sym >> 8), 2)
+		case 17: deflater_bits(d, out, u32(sym >> 8), 3)
+		case 18: deflater_bits(d, out, u32(sym >> 8), 7)
+		}
+	}
+
+	deflater_data(d, out, lit_lens[:], dist_lens[:])
+}
+
+// The size in bits of the tokens (and end of block) when written with the given code lengths.
+deflater_data_size :: proc(d: ^Deflater, lit_lens, dist_lens: []u8) -> (size: int) {
+	for freq, sym in d.lit_freq {
+		if freq == 0 do continue
+		size += freq * int(lit_lens[sym])
+		if sym > Deflate_End_Block do size += freq * int(Deflate_Length_Extra[sym - 257])
+	}
+	for freq, sym in d.dist_freq {
+		size += freq * (int(dist_lens[sym]) + int(Deflate_Dist_Extra[sym]))
+	}
+	return
+}
+
+deflater_data :: proc(d: ^Deflater, out: ^bytes.Buffer, lit_lens, dist_lens: []u8) {
+	lit_codes: [288]u16
+	dist_codes: [32]u16
+	huffman_codes(lit_lens, lit_codes[:len(lit_lens)])
+	huffman_codes(dist_lens, dist_codes[:len(dist_lens)])
+
+	for token in d.tokens {
+		if u32(token) & Deflate_Token_Match == 0 {
+			deflater_bits(d, out, u32(lit_codes[token]), uint(lit_lens[token]))
+			continue
+		}
+
+		length := int((u32(token) >> 16) & 0x1FF)
+		dist := int(u32(token) & 0xFFFF)
+
+		lc := deflate_length_code(length)
+		deflater_bits(d, out, u32(lit_codes[257 + lc]), uint(lit_lens[257 + lc]))
+		deflater_bits(d, out, u32(length - int(Deflate_Length_Base[lc])), uint(Deflate_Length_Extra[lc]))
+
+		dc := deflate_dist_code(dist)
+		deflater_bits(d, out, u32(dist_codes[dc]), uint(dist_lens[dc]))
+		deflater_bits(d, out, u32(dist - int(Deflate_Dist_Base[dc])), uint(Deflate_Dist_Extra[dc]))
+	}
+
+	deflater_bits(d, out, u32(lit_codes[Deflate_End_Block]), uint(lit_lens[Deflate_End_Block]))
+}
+
+// RFC 1951 3.2.6: the code lengths of the fixed Huffman codes.
+deflate_fixed_lengths :: proc() -> (lit: [288]u8, dist: [30]u8) {
+	for i in 0..<144   do lit[i] = 8
+	for i in 144..<256 do lit[i] = 9
+	for i in 256..<280 do lit[i] = 7
+	for i in 280..<288 do lit[i] = 8
+	for i in 0..<30    do dist[i] = 5
+	return
+}
+
+// Run-length encodes code lengths, RFC 1951 3.2.7.
+// Every symbol is the code (0-18) in the low byte, and the value of its extra bits in the high byte.
+deflate_rle :: proc(lens: []u8, allocator := context.allocator) -> []u16 {
+	syms := make([dynamic]u16, 0, len(lens), allocator)
+
+	i := 0
+	for i < len(lens) {
+		l := lens[i]
+		run := 1
+		for i + run < len(lens) && lens[i + run] == l do run += 1
+
+		if l == 0 && run >= 3 {
+			run = min(run, 138)
+			if run <= 10 {
+				append(&syms, 17 | u16(run - 3) << 8)
+			} else {
+				append(&syms, 18 | u16(run - 11) << 8)
+			}
+			i += run
+			continue
+		}
+
+		// The first length is sent as is, repeats of it are sent in runs of 3 to 6.
+		append(&syms, u16(l))
+		i += 1
+		run -= 1
+
+		for run >= 3 {
+			n := min(run, 6)
+			append(&syms, 16 | u16(n - 3) << 8)
+			i += n
+			run -= n
+		}
+	}
+
+	return syms[:]
+}
+
+// Calculates the lengths of the Huffman codes for the frequencies, no longer than max_len.
+// At least two symbols get a code, so that the code is always complete.
+huffman_lengths :: proc(freqs: []int, lens: []u8, max_len: u8) {
+	Leaf :: struct {
+		sym:  int,
+		freq: int,
+	}
+
+	freqs := slice.clone(freqs, context.temp_allocator)
+
+	used := 0
+	for f in freqs do if f > 0 do used += 1
+	for i := 0; used < 2; i += 1 {
+		if freqs[i] == 0 {
+			freqs[i] = 1
+			used += 1
+		}
+	}
+
+	leaves := make([]Leaf, used, context.temp_allocator)
+	parent := make([]int, 2 * used - 1, context.temp_allocator)
+	weight := make([]int, 2 * used - 1, context.temp_allocator)
+	depth := make([]int, 2 * used - 1, context.temp_allocator)
+
+	for {
+		li := 0
+		for f, sym in freqs {
+			if f > 0 {
+				leaves[li] = {sym, f}
+				li += 1
+			}
+		}
+		slice.sort_by(leaves, proc(a, b: Leaf) -> bool { return a.freq < b.freq })
+
+		// Two queues: the sorted leaves, and the internal nodes, which are created in order of weight.
+		for leaf, i in leaves do weight[i] = leaf.freq
+
+		next_leaf, next_node, nodes := 0, used, used
+		for nodes < 2 * used - 1 {
+			children: [2]int
+			for c in 0..<2 {
+				if next_leaf < used && (next_node >= nodes || weight[next_leaf] <= weight[next_node]) {
+					children[c] = next_leaf
+					next_leaf += 1
+				} else {
+					children[c] = next_node
+					next_node += 1
+				}
+			}
+
+			weight[nodes] = weight[children[0]] + weight[children[1]]
+			parent[children[0]] = nodes
+			parent[children[1]] = nodes
+			nodes += 1
+		}
+
+		// Parents are created after their children, so going backwards every parent has its depth.
+		root := 2 * used - 2
+		depth[root] = 0
+		longest: int
+		for i := root - 1; i >= 0; i -= 1 {
+			depth[i] = depth[parent[i]] + 1
+			if i < used do longest = max(longest, depth[i])
+		}
+
+		if longest <= int(max_len) {
+			slice.fill(lens, 0)
+			for leaf, i in leaves do lens[leaf.sym] = u8(depth[i])
+			return
+		}
+
+		// Too long, flatten the distribution and try again.
+		for f, i in freqs do if f > 0 do freqs[i] = max(f >> 1, 1)
+	}
+}
+
+// Assigns the canonical codes for the lengths, RFC 1951 3.2.2.
+// The codes are bit reversed, because Huffman codes are packed starting with their most significant bit.
+huffman_codes :: proc(lens: []u8, codes: []u16) {
+	bl_count: [16]u16
+	for l in lens do bl_count[l] += 1
+	bl_count[0] = 0
+
+	next_code: [16]u16
+	code: u16
+	for bits in 1..<16 {
+		code = (code + bl_count[bits - 1]) << 1
+		next_code[bits] = code
+	}
+
+	for l, i in lens {
+		if l == 0 do continue
+
+		c := next_code[l]
+		next_code[l] += 1
+
+		rev: u16
+		for _ in 0..<l {
+			rev = rev << 1 | c & 1
+			c >>= 1
+		}
+		codes[i] = rev
+	}
+}
+
+// Writes the lowest n bits of value, least significant bit first.
+deflater_bits :: #force_inline proc(d: ^Deflater, out: ^bytes.Buffer, value: u32, n: uint) {
+	d.bits |= u64(value) << d.nbits
+	d.nbits += n
+	for d.nbits >= 8 {
+		bytes.buffer_write_byte(out, byte(d.bits))
+		d.bits >>= 8
+		d.nbits -= 8
+	}
+}
+
+// Pads the output to a byte boundary with zero bits.
+deflater_align :: proc(d: ^Deflater, out: ^bytes.Buffer) {
+	if d.nbits > 0 {
+		bytes.buffer_write_byte(out, byte(d.bits))
+	}
+	d.bits = 0
+	d.nbits = 0
+}
@@ -7,6 +7,7 @@ import "../../client"
 main :: proc() {
 	get()
 	post()
+	reuse()
 }
 
 // basic get request.
@@ -68,3 +69,23 @@ post :: proc() {
 
 	fmt.println(body)
 }
+
+// Multiple requests using a client, which reuses the connection.
+reuse :: proc() {
+	c: client.Client
+	client.client_init(&c)
+	defer client.client_destroy(&c)
+
+	for _ in 0..<3 {
+		res, err := client.client_get(&c, "https://www.google.com/")
+		if err != nil {
+			fmt.printf("Request failed: %s", err)
+			return
+		}
+
+		fmt.printf("Status: %s\n", res.status)
+
+		// Destroying the response gives the connection back to the client, for the next request to use.
+		client.response_destroy(&res)
+	}
+}
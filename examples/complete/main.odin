@@ -4,6 +4,7 @@ import "core:net"
 import "core:log"
 import "core:time"
 import "core:fmt"
+import "core:io"
 import "core:mem"
 
 import http "../.."
@@ -43,15 +44,22 @@ serve :: proc() {
 	http.router_init(&router)
 	defer http.router_destroy(&router)
 
-	// Routes are tried in order.
-	// Route matching is implemented using an implementation of Lua patterns, see the docs on them here:
-	// https://www.lua.org/pil/20.2.html
-	// They are very similar to regex patterns but a bit more limited, which makes them much easier to implement since Odin does not have a regex implementation.
+	// Routes are matched segment by segment, static segments like "users" match exactly,
+	// ":name" segments match any segment and "*name" segments match the rest of the path.
+	// The most specific route matches, regardless of the order they are added in.
 
-	// Matches /users followed by any word (alphanumeric) followed by /comments and then / with any number.
-	// The word is available as params[0], and the number as params[1].
-	http.route_get(&router, "/users/(%w+)/comments/(%d+)", http.handler(proc(req: ^http.Request, res: ^http.Response) {
-		http.respond_plain(res, fmt.tprintf("user %s, comment: %s", req.url_params[0], req.url_params[1]))
+	// Matches /users followed by any segment followed by /comments and then / with any segment.
+	http.route_get(&router, "/users/:user/comments/:comment", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		user, _    := http.request_param(req, "user")
+		comment, _ := http.request_param(req, "comment")
+		http.respond_plain(res, fmt.tprintf("user %s, comment: %s", user, comment))
+	}))
+
+	// Lua patterns can be used for paths the route tree can't express, these are tried when nothing in the tree matched.
+	// See the docs on them here: https://www.lua.org/pil/20.2.html
+	// The captures are available in order, as req.url_params[0] and req.url_params[1] here.
+	http.route_pattern(&router, .Get, "/archive/(%d%d%d%d)%-(%d%d)", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		http.respond_plain(res, fmt.tprintf("archive of %s-%s", req.url_params[0], req.url_params[1]))
 	}))
 
 	// You can apply a rate limit just like any other middleware,
@@ -67,6 +75,7 @@ serve :: proc() {
 
 	http.route_get(&router, "/api", http.handler(api))
 	http.route_get(&router, "/ping", http.handler(ping))
+	http.route_get(&router, "/stream", http.handler(stream))
 
 	// Can also have specific middleware for each route:
 	index_handler := http.handler(index)
@@ -84,14 +93,25 @@ serve :: proc() {
 	http.route_get(&router, "/", index_with_middleware)
 
 	// Matches every get request that did not match another route.
-	http.route_get(&router, "(.*)", http.handler(static))
+	http.route_get(&router, "/*path", http.handler(static))
 
 	http.route_post(&router, "/ping", http.handler(post_ping))
+	http.route_post(&router, "/upload", http.handler(upload))
 
 	route_handler := http.router_handler(&router)
 
+	// Compress responses for clients that accept gzip or deflate.
+	compressed := http.middleware_compress(&route_handler)
+
+	// Allow scripts on other origins to call the API, preflight requests are answered before they reach the router.
+	with_cors := http.middleware_cors(&compressed, &http.Cors_Opts{
+		origins = {"http://localhost:3000", "https://*.example.com"},
+		methods = {.Get, .Head, .Post},
+		max_age = 10 * time.Minute,
+	})
+
 	// Wrap our handler with a logger middleware.
-	with_logger := http.middleware_logger(&route_handler, &http.Logger_Opts{log_time = true})
+	with_logger := http.middleware_logger(&with_cors, &http.Logger_Opts{log_time = true})
 
 	// Start the server on 127.0.0.1:6969.
 	err := http.listen_and_serve(
@@ -127,12 +147,30 @@ ping :: proc(req: ^http.Request, res: ^http.Response) {
 	http.respond_plain(res, "pong")
 }
 
+// Streams the body in chunks, instead of building it in memory first.
+stream :: proc(req: ^http.Request, res: ^http.Response) {
+	res.status = .Ok
+	http.headers_set(&res.headers, "content-type", http.mime_to_content_type(.Plain))
+	http.response_trailer_set(res, "x-lines", "10")
+
+	for i in 1..=10 {
+		if _, err := http.response_write_string(res, fmt.tprintf("line %i\n", i)); err != nil {
+			log.warnf("could not stream response: %s", err)
+			return
+		}
+
+		// Send each line right away, the client sees them coming in one by one.
+		http.response_flush(res)
+		time.sleep(100 * time.Millisecond)
+	}
+}
+
 index :: proc(req: ^http.Request, res: ^http.Response) {
 	http.respond_file(res, "examples/complete/static/index.html", req.allocator)
 }
 
 static :: proc(req: ^http.Request, res: ^http.Response) {
-	http.respond_dir(res, "/", "examples/complete/static", req.url_params[0], req.allocator)
+	http.respond_dir(res, "/", "examples/complete/static", req.url.path, req.allocator)
 }
 
 post_ping :: proc(req: ^http.Request, res: ^http.Response) {
@@ -149,3 +187,27 @@ post_ping :: proc(req: ^http.Request, res: ^http.Response) {
 
 	http.respond_plain(res, "pong")
 }
+
+// Reads the body as it comes in, without having it in memory all at once.
+upload :: proc(req: ^http.Request, res: ^http.Response) {
+	body, err := http.request_body_reader(req, 100 << 20)
+	if err != nil {
+		res.status = http.body_error_status(err)
+		return
+	}
+
+	total := 0
+	buf: [4096]byte
+	for {
+		n, rerr := io.read(body, buf[:])
+		total += n
+
+		if rerr == .EOF do break
+		if rerr != nil {
+			res.status = http.body_error_status(http.request_body_error(req))
+			return
+		}
+	}
+
+	http.respond_plain(res, fmt.tprintf("received %i bytes", total))
+}
@@ -0,0 +1,55 @@
+package main
+
+import "core:fmt"
+import "core:log"
+import "core:net"
+import "core:thread"
+
+import http "../.."
+import proxy "../../proxy"
+
+// Reverse proxy on 127.0.0.1:8080, balancing requests over two upstream servers on ports 8081 and 8082,
+// which are started by this example too, standing in for real services.
+//
+// curl http://localhost:8080/ a couple of times to see the upstreams take turns.
+main :: proc() {
+	context.logger = log.create_console_logger()
+
+	for port in ([]int{8081, 8082}) {
+		thread.create_and_start_with_poly_data(port, upstream, context, self_cleanup = true)
+	}
+
+	opts := proxy.Default_Proxy_Opts
+	opts.balancer = .Least_Connections
+	opts.health_path = "/health"
+
+	p: proxy.Proxy
+	proxy.proxy_init(&p, {"http://127.0.0.1:8081", "http://127.0.0.1:8082"}, opts)
+	defer proxy.proxy_destroy(&p)
+
+	s: http.Server
+	http.server_shutdown_on_interrupt(&s)
+
+	handler := proxy.proxy_handler(&p)
+
+	err := http.listen_and_serve(&s, &handler, net.Endpoint{address = net.IP4_Loopback, port = 8080})
+	fmt.printf("Server stopped: %v", err)
+}
+
+upstream :: proc(port: int) {
+	s: http.Server
+
+	handler := http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		if req.url.path == "/health" {
+			res.status = .No_Content
+			return
+		}
+
+		host := http.headers_get(&req.headers, "host") or_else ""
+		forwarded := http.headers_get(&req.headers, "forwarded") or_else ""
+		http.respond_plain(res, fmt.tprintf("Hello from %s, %s\n", host, forwarded))
+	})
+
+	err := http.listen_and_serve(&s, &handler, net.Endpoint{address = net.IP4_Loopback, port = port})
+	log.warnf("upstream on port %i stopped: %v", port, err)
+}
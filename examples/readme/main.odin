@@ -19,15 +19,16 @@ main :: proc() {
 	http.router_init(&router)
 	defer http.router_destroy(&router)
 
-	// Routes are tried in order.
-	// Route matching is implemented using an implementation of Lua patterns, see the docs on them here:
-	// https://www.lua.org/pil/20.2.html
-	// They are very similar to regex patterns but a bit more limited, which makes them much easier to implement since Odin does not have a regex implementation.
-
-	// Matches /users followed by any word (alphanumeric) followed by /comments and then / with any number.
-	// The word is available as req.url_params[0], and the number as req.url_params[1].
-	http.route_get(&router, "/users/(%w+)/comments/(%d+)", http.handler(proc(req: ^http.Request, res: ^http.Response) {
-		http.respond_plain( res, fmt.tprintf("user %s, comment: %s", req.url_params[0], req.url_params[1]))
+	// Routes are matched segment by segment, static segments like "users" match exactly,
+	// ":name" segments match any segment and "*name" segments match the rest of the path.
+	// The most specific route matches, regardless of the order they are added in.
+
+	// Matches /users followed by any segment followed by /comments and then / with any segment.
+	// The segments are available as request_param(req, "user") and request_param(req, "comment").
+	http.route_get(&router, "/users/:user/comments/:comment", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		user, _    := http.request_param(req, "user")
+		comment, _ := http.request_param(req, "comment")
+		http.respond_plain( res, fmt.tprintf("user %s, comment: %s", user, comment))
 	}))
 	http.route_get(&router, "/cookies", http.handler(cookies))
 	http.route_get(&router, "/api", http.handler(api))
@@ -35,7 +36,7 @@ main :: proc() {
 	http.route_get(&router, "/index", http.handler(index))
 
 	// Matches every get request that did not match another route.
-	http.route_get(&router, "(.*)", http.handler(static))
+	http.route_get(&router, "/*path", http.handler(static))
 
 	http.route_post(&router, "/ping", http.handler(post_ping))
 
@@ -84,7 +85,7 @@ index :: proc(req: ^http.Request, res: ^http.Response) {
 }
 
 static :: proc(req: ^http.Request, res: ^http.Response) {
-	http.respond_dir(res, "/", "examples/complete/static", req.url_params[0])
+	http.respond_dir(res, "/", "examples/complete/static", req.url.path)
 }
 
 post_ping :: proc(req: ^http.Request, res: ^http.Response) {
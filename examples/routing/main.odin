@@ -35,13 +35,17 @@ main :: proc() {
 			)
 		}))
 
-	// Route matching is implemented using an implementation of Lua patterns, see the docs on them here:
-	// https://www.lua.org/pil/20.2.html
-	// They are very similar to regex patterns but a bit more limited, which makes them much easier to implement since Odin does not have a regex implementation.
-
-	http.route_get(&router, "/hello/(%w+)", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+	// ":name" matches any segment, the value is retrieved with request_param.
+	http.route_get(&router, "/hello/:name", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		name, _ := http.request_param(req, "name")
 		http.respond_plain(res, "Hello, ")
-		bytes.buffer_write_string(&res.body, req.url_params[0])
+		bytes.buffer_write_string(&res.body, name)
+	}))
+
+	// "*name" matches the rest of the path, slashes included.
+	http.route_get(&router, "/files/*path", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		path, _ := http.request_param(req, "path")
+		http.respond_plain(res, fmt.tprintf("You requested the file %q", path))
 	}))
 
 	// JSON/body example.
@@ -63,14 +67,40 @@ main :: proc() {
 			http.respond_json(res, Hello_Res_Payload{message = fmt.tprintf("Hello %s!", p.name)})
 		}))
 
+	// Routes can be grouped under a prefix, with middleware that runs for every route in the group.
+	api := http.router_group(&router, "/api")
+	http.group_use(api, http.middleware_logger(nil))
+
+	// Groups can be nested, this one adds "/v1" to the prefix, and keeps the logger of its parent.
+	v1 := http.group_group(api, "/v1")
+	http.group_use(v1, http.middleware_proc(nil, proc(h: ^http.Handler, req: ^http.Request, res: ^http.Response) {
+		if !http.headers_has(&req.headers, "authorization") {
+			res.status = .Unauthorized
+			return
+		}
+
+		next := h.next.(^http.Handler)
+		next.handle(next, req, res)
+	}))
+
+	// Matches /api/v1/users/:id.
+	http.group_get(v1, "/users/:id", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		id, _ := http.request_param(req, "id")
+		http.respond_plain(res, fmt.tprintf("user %s (group %s)", id, req.route_prefix))
+	}))
+
 	// Custom 404 page.
-	http.route_all(&router, "(.*)", http.handler(proc(req: ^http.Request, res: ^http.Response) {
-			http.respond_plain(
-				res,
-				fmt.tprintf("Welcome, could not find the path %q", req.url_params[0]),
-			)
-			res.status = .NotFound
-		}))
+	router.not_found = http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		http.respond_plain(res, fmt.tprintf("Welcome, could not find the path %q", req.url.path))
+		res.status = .NotFound
+	})
+
+	// Custom 405 page, for a path that exists but not with the request's method, like a GET /ping.
+	// The Allow header is already set to the methods that are allowed.
+	router.method_not_allowed = http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		http.respond_plain(res, fmt.tprintf("Method not allowed, try one of: %s", http.headers_get(&res.headers, "allow") or_else ""))
+		res.status = .Method_Not_Allowed
+	})
 
 	handler := http.router_handler(&router)
 	fmt.printf("Server stopped: %s", http.listen_and_serve(&s, &handler))
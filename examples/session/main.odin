@@ -0,0 +1,73 @@
+package main
+
+import "core:fmt"
+import "core:log"
+import "core:net"
+import "core:time"
+
+import http "../.."
+
+// Server on 127.0.0.1:8080 that remembers who logged in, in a session kept in the "sessions" directory.
+//
+// Visit /login?name=odin, then /, then /logout.
+main :: proc() {
+	context.logger = log.create_console_logger()
+
+	store: http.File_Session_Store
+	if !http.file_session_store_init(&store, "sessions") do return
+	defer http.file_session_store_destroy(&store)
+
+	// Don't do this, generate a random secret once and load it from a file or the environment.
+	secret := "an example secret of at least 32 bytes, not a secret anymore"
+
+	opts := http.Default_Session_Opts
+	opts.secret = transmute([]byte)secret
+	opts.store = &store
+	opts.lifetime = 30 * time.Minute
+	// Browsers don't send secure cookies over plain HTTP, except to localhost.
+	opts.cookie.secure = false
+
+	s: http.Server
+	http.server_shutdown_on_interrupt(&s)
+
+	router: http.Router
+	http.router_init(&router)
+	defer http.router_destroy(&router)
+
+	http.route_get(&router, "/", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		name, logged_in := http.session_get(req, "name")
+		if !logged_in {
+			http.respond_plain(res, "Hello stranger, log in at /login?name=you\n")
+			return
+		}
+		http.respond_plain(res, fmt.tprintf("Hello %s!\n", name))
+	}))
+
+	http.route_get(&router, "/login", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		name, ok := req.url.queries["name"]
+		if !ok || name == "" {
+			res.status = .Bad_Request
+			return
+		}
+
+		// A new id on login, so an id that was known before logging in is worthless.
+		http.session_regenerate(req)
+		http.session_set(req, "name", name)
+
+		http.headers_set(&res.headers, "location", "/")
+		res.status = .Found
+	}))
+
+	http.route_get(&router, "/logout", http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		http.session_destroy(req)
+
+		http.headers_set(&res.headers, "location", "/")
+		res.status = .Found
+	}))
+
+	route_handler := http.router_handler(&router)
+	with_session := http.middleware_session(&route_handler, &opts)
+
+	err := http.listen_and_serve(&s, &with_session, net.Endpoint{address = net.IP4_Loopback, port = 8080})
+	fmt.printf("Server stopped: %v", err)
+}
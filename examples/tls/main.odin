@@ -0,0 +1,31 @@
+package main
+
+import "core:fmt"
+import "core:log"
+import "core:net"
+
+import http "../.."
+
+// Serves HTTPS on 127.0.0.1:8443.
+//
+// Generate a self-signed certificate for local testing with:
+// openssl req -x509 -newkey rsa:2048 -nodes -keyout key.pem -out cert.pem -days 365 -subj "/CN=localhost"
+main :: proc() {
+	context.logger = log.create_console_logger()
+
+	s: http.Server
+	http.server_shutdown_on_interrupt(&s)
+
+	handler := http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		http.respond_plain(res, "Hello, TLS!")
+	})
+
+	err := http.listen_and_serve_tls(
+		&s,
+		&handler,
+		"cert.pem",
+		"key.pem",
+		net.Endpoint{address = net.IP4_Loopback, port = 8443},
+	)
+	fmt.printf("Server stopped: %v", err)
+}
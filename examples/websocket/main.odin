@@ -0,0 +1,50 @@
+package main
+
+import "core:fmt"
+import "core:log"
+import "core:net"
+import "core:thread"
+
+import http "../.."
+
+// WebSocket echo server on 127.0.0.1:8080, every message is sent back to the client.
+//
+// Try it from the console of a browser:
+// ws := new WebSocket("ws://localhost:8080"); ws.onmessage = e => console.log(e.data); ws.send("Hello!")
+main :: proc() {
+	context.logger = log.create_console_logger()
+
+	s: http.Server
+	http.server_shutdown_on_interrupt(&s)
+
+	handler := http.handler(proc(req: ^http.Request, res: ^http.Response) {
+		ws, err := http.websocket_upgrade(req, res)
+		if err != nil {
+			log.warnf("websocket upgrade failed: %v", err)
+			return
+		}
+
+		// Run the WebSocket on its own thread, so the worker thread can go back to handling requests.
+		thread.create_and_start_with_poly_data(ws, echo, context, self_cleanup = true)
+	})
+
+	err := http.listen_and_serve(&s, &handler, net.Endpoint{address = net.IP4_Loopback, port = 8080})
+	fmt.printf("Server stopped: %v", err)
+}
+
+echo :: proc(ws: ^http.Websocket) {
+	for {
+		msg, err := http.websocket_read(ws)
+		if err != nil {
+			log.infof("websocket closed: %v (%v)", err, ws.close_code)
+			break
+		}
+		defer delete(msg.data)
+
+		if err := http.websocket_write(ws, msg.data, msg.type); err != nil {
+			break
+		}
+	}
+
+	http.websocket_close(ws)
+}
@@ -109,9 +109,8 @@ Rate_Limit_Data :: struct {
 }
 
 // Basic rate limit based on IP address.
-middleware_rate_limit :: proc(next: ^Handler, opts: ^Rate_Limit_Opts, allocator := context.allocator) -> Handler {
-	assert(next != nil)
-
+// The next handler can be nil when the middleware is added to a route group, the group sets it.
+middleware_rate_limit :: proc(next: Maybe(^Handler), opts: ^Rate_Limit_Opts, allocator := context.allocator) -> Handler {
 	h: Handler
 	h.next = next
 
@@ -142,7 +141,7 @@ middleware_rate_limit :: proc(next: ^Handler, opts: ^Rate_Limit_Opts, allocator
 			retry_dur := int(time.diff(time.now(), data.next_sweep) / time.Second)
 			buf := make([]byte, 32, context.temp_allocator)
 			retry_str := strconv.itoa(buf, retry_dur)
-			res.headers["retry-after"] = retry_str
+			headers_set(&res.headers, "retry-after", retry_str)
 
 			if on, ok := data.opts.on_limit.(Rate_Limit_On_Limit); ok {
 				on.on_limit(req, res, on.user_data)
@@ -0,0 +1,437 @@
+package http
+
+import "core:bytes"
+import "core:mem"
+import "core:strings"
+
+// HPACK, header compression for HTTP/2 (RFC 7541).
+
+HPACK_Error :: enum {
+	None,
+	// The header block ended in the middle of a representation.
+	Truncated,
+	// An integer does not fit.
+	Invalid_Integer,
+	// An index that is not in the static or dynamic table.
+	Invalid_Index,
+	// Invalid Huffman encoded string, or one containing the EOS symbol.
+	Invalid_Huffman,
+	// A dynamic table size update that is bigger than the limit, or not at the start of a block.
+	Invalid_Table_Size,
+	// The decoded header list is bigger than the given maximum, the block was still decoded completely.
+	List_Too_Large,
+}
+
+@(private)
+HPACK_Field :: struct {
+	name:  string,
+	value: string,
+}
+
+@(private)
+HPACK_Decoder :: struct {
+	allocator: mem.Allocator,
+	// The dynamic table, the newest entry is at the end.
+	table:     [dynamic]HPACK_Field,
+	// The size of the dynamic table as defined in RFC 7541 4.1.
+	size:      int,
+	// The maximum size, set by the encoder using dynamic table size updates.
+	max_size:  int,
+	// The maximum size we advertised with SETTINGS_HEADER_TABLE_SIZE.
+	limit:     int,
+}
+
+// The additional size of each entry in the dynamic table, RFC 7541 4.1.
+@(private)
+HPACK_ENTRY_OVERHEAD :: 32
+
+@(private)
+hpack_decoder_init :: proc(d: ^HPACK_Decoder, limit: int, allocator := context.allocator) {
+	d.allocator = allocator
+	d.table = make([dynamic]HPACK_Field, allocator)
+	d.limit = limit
+	d.max_size = limit
+}
+
+@(private)
+hpack_decoder_destroy :: proc(d: ^HPACK_Decoder) {
+	for f in d.table {
+		delete(f.name, d.allocator)
+		delete(f.value, d.allocator)
+	}
+	delete(d.table)
+}
+
+// Decodes a complete header block into fields, the names and values are allocated using the given allocator.
+//
+// A header block has to be decoded completely, even if the headers are going to be rejected,
+// because decoding changes the state of the dynamic table that is shared with the encoder.
+//
+// When max_list_size is not 0, fields stop being added once the size of the list (RFC 7541 4.1) is over it,
+// and .List_Too_Large is returned after decoding, so a small block that references big table entries
+// over and over can't make us allocate more than that.
+@(private)
+hpack_decode :: proc(
+	d: ^HPACK_Decoder,
+	block: []byte,
+	fields: ^[dynamic]HPACK_Field,
+	max_list_size := 0,
+	allocator := context.allocator,
+) -> HPACK_Error {
+	block := block
+	at_start := true
+	list_size := 0
+	too_large := false
+
+	// Adds the size of the field to the list, returns whether it still fits.
+	fits :: proc(list_size: ^int, too_large: ^bool, max_list_size: int, field: HPACK_Field) -> bool {
+		list_size^ += len(field.name) + len(field.value) + HPACK_ENTRY_OVERHEAD
+		if max_list_size > 0 && list_size^ > max_list_size do too_large^ = true
+		return !too_large^
+	}
+
+	for len(block) > 0 {
+		b := block[0]
+
+		switch {
+		case b & 0x80 != 0: // Indexed header field, RFC 7541 6.1.
+			index := hpack_decode_int(&block, 7) or_return
+			field := hpack_lookup(d, index) or_return
+			if !fits(&list_size, &too_large, max_list_size, field) do break
+
+			append(fields, HPACK_Field{
+				name  = strings.clone(field.name, allocator),
+				value = strings.clone(field.value, allocator),
+			})
+
+		case b & 0xc0 == 0x40: // Literal header field with incremental indexing, RFC 7541 6.2.1.
+			field := hpack_decode_literal(d, &block, 6, allocator) or_return
+			hpack_table_add(d, field)
+
+			if !fits(&list_size, &too_large, max_list_size, field) {
+				delete(field.name, allocator)
+				delete(field.value, allocator)
+				break
+			}
+			append(fields, field)
+
+		case b & 0xe0 == 0x20: // Dynamic table size update, RFC 7541 6.3.
+			if !at_start do return .Invalid_Table_Size
+
+			size := hpack_decode_int(&block, 5) or_return
+			if size > d.limit do return .Invalid_Table_Size
+
+			d.max_size = size
+			hpack_table_evict(d, 0)
+			continue
+
+		case: // Literal header field without indexing or never indexed, RFC 7541 6.2.2 and 6.2.3.
+			field := hpack_decode_literal(d, &block, 4, allocator) or_return
+
+			if !fits(&list_size, &too_large, max_list_size, field) {
+				delete(field.name, allocator)
+				delete(field.value, allocator)
+				break
+			}
+			append(fields, field)
+		}
+
+		at_start = false
+	}
+
+	if too_large do return .List_Too_Large
+	return nil
+}
+
+// Encodes a header field, never adding it to the dynamic table, so there is no encoder state to keep.
+@(private)
+hpack_encode :: proc(buf: ^bytes.Buffer, name, value: string) {
+	name_index := 0
+	for field, i in HPACK_STATIC_TABLE {
+		if field.name != name do continue
+
+		if field.value == value {
+			// Indexed header field.
+			hpack_encode_int(buf, 0x80, 7, i + 1)
+			return
+		}
+
+		if name_index == 0 do name_index = i + 1
+	}
+
+	// Literal header field without indexing, indexed name or a new name.
+	hpack_encode_int(buf, 0x00, 4, name_index)
+	if name_index == 0 {
+		hpack_encode_string(buf, name)
+	}
+	hpack_encode_string(buf, value)
+}
+
+@(private)
+hpack_encode_int :: proc(buf: ^bytes.Buffer, pattern: byte, prefix: uint, value: int) {
+	max_prefix := (1 << prefix) - 1
+	if value < max_prefix {
+		bytes.buffer_write_byte(buf, pattern | byte(value))
+		return
+	}
+
+	bytes.buffer_write_byte(buf, pattern | byte(max_prefix))
+	value := value - max_prefix
+	for value >= 128 {
+		bytes.buffer_write_byte(buf, byte(value % 128 + 128))
+		value /= 128
+	}
+	bytes.buffer_write_byte(buf, byte(value))
+}
+
+// Strings are written without Huffman encoding, which is allowed but a bit bigger.
+@(private)
+hpack_encode_string :: proc(buf: ^bytes.Buffer, s: string) {
+	hpack_encode_int(buf, 0x00, 7, len(s))
+	bytes.buffer_write_string(buf, s)
+}
+
+@(private)
+hpack_decode_int :: proc(block: ^[]byte, prefix: uint) -> (value: int, err: HPACK_Error) {
+	if len(block^) == 0 do return 0, .Truncated
+
+	max_prefix := (1 << prefix) - 1
+	value = int(block^[0]) & max_prefix
+	block^ = block^[1:]
+	if value < max_prefix do return
+
+	shift: uint
+	for {
+		if len(block^) == 0 do return 0, .Truncated
+		if shift > 28 do return 0, .Invalid_Integer
+
+		b := block^[0]
+		block^ = block^[1:]
+
+		value += int(b & 0x7f) << shift
+		if b & 0x80 == 0 do return
+
+		shift += 7
+	}
+}
+
+@(private)
+hpack_decode_string :: proc(block: ^[]byte, allocator := context.allocator) -> (s: string, err: HPACK_Error) {
+	if len(block^) == 0 do return "", .Truncated
+
+	huffman := block^[0] & 0x80 != 0
+	length := hpack_decode_int(block, 7) or_return
+	if length > len(block^) do return "", .Truncated
+
+	raw := block^[:length]
+	block^ = block^[length:]
+
+	if !huffman {
+		return strings.clone(string(raw), allocator), nil
+	}
+
+	return hpack_huffman_decode(raw, allocator)
+}
+
+@(private)
+hpack_decode_literal :: proc(
+	d: ^HPACK_Decoder,
+	block: ^[]byte,
+	prefix: uint,
+	allocator := context.allocator,
+) -> (
+	field: HPACK_Field,
+	err: HPACK_Error,
+) {
+	index := hpack_decode_int(block, prefix) or_return
+	if index == 0 {
+		field.name = hpack_decode_string(block, allocator) or_return
+	} else {
+		indexed := hpack_lookup(d, index) or_return
+		field.name = strings.clone(indexed.name, allocator)
+	}
+
+	field.value = hpack_decode_string(block, allocator) or_return
+	return
+}
+
+@(private)
+hpack_lookup :: proc(d: ^HPACK_Decoder, index: int) -> (HPACK_Field, HPACK_Error) {
+	switch {
+	case index <= 0:
+		return {}, .Invalid_Index
+	case index <= len(HPACK_STATIC_TABLE):
+		return HPACK_STATIC_TABLE[index - 1], nil
+	case:
+		dyn := index - len(HPACK_STATIC_TABLE) - 1
+		if dyn >= len(d.table) do return {}, .Invalid_Index
+		return d.table[len(d.table) - 1 - dyn], nil
+	}
+}
+
+@(private)
+hpack_table_add :: proc(d: ^HPACK_Decoder, field: HPACK_Field) {
+	size := len(field.name) + len(field.value) + HPACK_ENTRY_OVERHEAD
+
+	// An entry bigger than the table empties the table, RFC 7541 4.4.
+	hpack_table_evict(d, size)
+	if size > d.max_size do return
+
+	append(&d.table, HPACK_Field{
+		name  = strings.clone(field.name, d.allocator),
+		value = strings.clone(field.value, d.allocator),
+	})
+	d.size += size
+}
+
+// Evicts the oldest entries until there is room for an entry of the given size.
+@(private)
+hpack_table_evict :: proc(d: ^HPACK_Decoder, room: int) {
+	for len(d.table) > 0 && d.size + room > d.max_size {
+		oldest := d.table[0]
+		d.size -= len(oldest.name) + len(oldest.value) + HPACK_ENTRY_OVERHEAD
+		delete(oldest.name, d.allocator)
+		delete(oldest.value, d.allocator)
+		ordered_remove(&d.table, 0)
+	}
+}
+
+// Decodes the canonical Huffman code of RFC 7541 Appendix B.
+@(private)
+hpack_huffman_decode :: proc(src: []byte, allocator := context.allocator) -> (s: string, err: HPACK_Error) {
+	b := strings.builder_make(0, len(src) * 8 / 5, allocator)
+	defer if err != nil do strings.builder_destroy(&b)
+
+	// The state of the code being decoded, see the 'puff' inflate implementation of zlib for the algorithm.
+	code, first, index, length: int
+	all_ones := true
+
+	for octet in src {
+		for shift := 7; shift >= 0; shift -= 1 {
+			bit := int(octet >> uint(shift)) & 1
+			if bit == 0 do all_ones = false
+
+			code |= bit
+			length += 1
+
+			count := int(HPACK_HUFFMAN_COUNTS[length])
+			if code - count < first {
+				sym := HPACK_HUFFMAN_SYMBOLS[index + (code - first)]
+				if sym == HPACK_HUFFMAN_EOS do return "", .Invalid_Huffman
+
+				strings.write_byte(&b, byte(sym))
+				code, first, index, length = 0, 0, 0, 0
+				all_ones = true
+				continue
+			}
+
+			if length == len(HPACK_HUFFMAN_COUNTS) - 1 do return "", .Invalid_Huffman
+
+			index += count
+			first += count
+			first <<= 1
+			code <<= 1
+		}
+	}
+
+	// Padding has to be the most significant bits of EOS (all ones), and strictly shorter than 8 bits.
+	if length > 7 || !all_ones do return "", .Invalid_Huffman
+
+	return strings.to_string(b), nil
+}
+
+@(private)
+HPACK_HUFFMAN_EOS :: 256
+
+// The amount of codes of each bit length.
+@(private)
+HPACK_HUFFMAN_COUNTS := [31]u16{
+	0, 0, 0, 0, 0, 10, 26, 32, 6, 0, 5, 3, 2, 6, 2, 3, 0, 0, 0, 3, 8, 13, 26, 29, 12, 4, 15, 19, 29, 0, 4,
+}
+
+// The symbols ordered by code length, then by symbol.
+@(private)
+HPACK_HUFFMAN_SYMBOLS := [257]u16{
+	48, 49, 50, 97, 99, 101, 105, 111, 115, 116, 32, 37, 45, 46, 47, 51,
+	52, 53, 54, 55, 56, 57, 61, 65, 95, 98, 100, 102, 103, 104, 108, 109,
+	110, 112, 114, 117, 58, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76,
+	77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 89, 106, 107, 113, 118,
+	119, 120, 121, 122, 38, 42, 44, 59, 88, 90, 33, 34, 40, 41, 63, 39,
+	43, 124, 35, 62, 0, 36, 64, 91, 93, 126, 94, 125, 60, 96, 123, 92,
+	195, 208, 128, 130, 131, 162, 184, 194, 224, 226, 153, 161, 167, 172, 176, 177,
+	179, 209, 216, 217, 227, 229, 230, 129, 132, 133, 134, 136, 146, 154, 156, 160,
+	163, 164, 169, 170, 173, 178, 181, 185, 186, 187, 189, 190, 196, 198, 228, 232,
+	233, 1, 135, 137, 138, 139, 140, 141, 143, 147, 149, 150, 151, 152, 155, 157,
+	158, 165, 166, 168, 174, 175, 180, 182, 183, 188, 191, 197, 231, 239, 9, 142,
+	144, 145, 148, 159, 171, 206, 215, 225, 236, 237, 199, 207, 234, 235, 192, 193,
+	200, 201, 202, 205, 210, 213, 218, 219, 238, 240, 242, 243, 255, 203, 204, 211,
+	212, 214, 221, 222, 223, 241, 244, 245, 246, 247, 248, 250, 251, 252, 253, 254,
+	2, 3, 4, 5, 6, 7, 8, 11, 12, 14, 15, 16, 17, 18, 19, 20,
+	21, 23, 24, 25, 26, 27, 28, 29, 30, 31, 127, 220, 249, 10, 13, 22,
+	256,
+}
+
+@(private)
+HPACK_STATIC_TABLE := [61]HPACK_Field{
+	{":authority", ""},
+	{":method", "GET"},
+	{":method", "POST"},
+	{":path", "/"},
+	{":path", "/index.html"},
+	{":scheme", "http"},
+	{":scheme", "https"},
+	{":status", "200"},
+	{":status", "204"},
+	{":status", "206"},
+	{":status", "304"},
+	{":status", "400"},
+	{":status", "404"},
+	{":status", "500"},
+	{"accept-charset", ""},
+	{"accept-encoding", "gzip, deflate"},
+	{"accept-language", ""},
+	{"accept-ranges", ""},
+	{"accept", ""},
+	{"access-control-allow-origin", ""},
+	{"age", ""},
+	{"allow", ""},
+	{"authorization", ""},
+	{"cache-control", ""},
+	{"content-disposition", ""},
+	{"content-encoding", ""},
+	{"content-language", ""},
+	{"content-length", ""},
+	{"content-location", ""},
+	{"content-range", ""},
+	{"content-type", ""},
+	{"cookie", ""},
+	{"date", ""},
+	{"etag", ""},
+	{"expect", ""},
+	{"expires", ""},
+	{"from", ""},
+	{"host", ""},
+	{"if-match", ""},
+	{"if-modified-since", ""},
+	{"if-none-match", ""},
+	{"if-range", ""},
+	{"if-unmodified-since", ""},
+	{"last-modified", ""},
+	{"link", ""},
+	{"location", ""},
+	{"max-forwards", ""},
+	{"proxy-authenticate", ""},
+	{"proxy-authorization", ""},
+	{"range", ""},
+	{"referer", ""},
+	{"refresh", ""},
+	{"retry-after", ""},
+	{"server", ""},
+	{"set-cookie", ""},
+	{"strict-transport-security", ""},
+	{"transfer-encoding", ""},
+	{"user-agent", ""},
+	{"vary", ""},
+	{"via", ""},
+	{"www-authenticate", ""},
+}
@@ -120,13 +120,140 @@ method_string :: proc(m: Method) -> string {
 	}
 }
 
+// A single header field, keys are lowercase.
+Header :: struct {
+	key:   string,
+	value: string,
+}
+
 // Headers are request or response headers.
 //
-// They are always parsed to lowercase because they are case-insensitive,
+// A key can occur multiple times (like multiple set-cookie headers), all values are kept,
+// in the order they were added/received.
+//
+// Keys are always parsed to lowercase because they are case-insensitive,
 // This allows you to just check the lowercase variant for existence/value.
 //
 // Thus, you should always add keys in lowercase.
-Headers :: map[string]string
+Headers :: struct {
+	// Use the headers_* procedures to change these.
+	entries: [dynamic]Header,
+}
+
+headers_init :: proc(h: ^Headers, allocator := context.allocator) {
+	h.entries = make([dynamic]Header, 0, 8, allocator)
+}
+
+// Frees the headers, keys and values are not freed.
+headers_destroy :: proc(h: ^Headers) {
+	delete(h.entries)
+}
+
+// Returns the first value of the key.
+headers_get :: proc(h: ^Headers, key: string) -> (value: string, ok: bool) {
+	for entry in h.entries {
+		if entry.key == key {
+			return entry.value, true
+		}
+	}
+	return "", false
+}
+
+// Returns all values of the key, in order.
+// The returned slice is allocated, the values are not.
+headers_get_all :: proc(h: ^Headers, key: string, allocator := context.allocator) -> []string {
+	values := make([dynamic]string, 0, 1, allocator)
+	for entry in h.entries {
+		if entry.key == key {
+			append(&values, entry.value)
+		}
+	}
+	return values[:]
+}
+
+headers_has :: proc(h: ^Headers, key: string) -> bool {
+	_, ok := headers_get(h, key)
+	return ok
+}
+
+// Adds a value for the key, keeping any existing values.
+headers_add :: proc(h: ^Headers, key, value: string) {
+	append(&h.entries, Header{key, value})
+}
+
+// Sets the value of the key, replacing any existing values.
+headers_set :: proc(h: ^Headers, key, value: string) {
+	for i := 0; i < len(h.entries); i += 1 {
+		if h.entries[i].key != key do continue
+
+		// Replace the first, remove the others.
+		h.entries[i].value = value
+		for j := len(h.entries) - 1; j > i; j -= 1 {
+			if h.entries[j].key == key {
+				ordered_remove(&h.entries, j)
+			}
+		}
+		return
+	}
+
+	headers_add(h, key, value)
+}
+
+// Removes all values of the key.
+headers_delete :: proc(h: ^Headers, key: string) -> (deleted: bool) {
+	for i := len(h.entries) - 1; i >= 0; i -= 1 {
+		if h.entries[i].key == key {
+			ordered_remove(&h.entries, i)
+			deleted = true
+		}
+	}
+	return
+}
+
+headers_clear :: proc(h: ^Headers) {
+	clear(&h.entries)
+}
+
+// Adds the header name to the Vary header, unless it is listed already, or the Vary header is "*".
+@(private)
+headers_add_vary :: proc(h: ^Headers, name: string) {
+	for vary in headers_get_all(h, "vary", context.temp_allocator) {
+		if header_has_token(vary, "*") || header_has_token(vary, name) do return
+	}
+	headers_add(h, "vary", name)
+}
+
+// Returns the headers with one entry per key, in order of first occurrence, for writing them out.
+//
+// RFC 9110 5.3: multiple values of a key are combined into one, separated by commas,
+// except for set-cookie, which can not be combined and keeps an entry per value.
+// The returned slice and the combined values are allocated.
+headers_combined :: proc(h: ^Headers, allocator := context.allocator) -> []Header {
+	combined := make([dynamic]Header, 0, len(h.entries), allocator)
+
+	outer: for entry, i in h.entries {
+		if entry.key == "set-cookie" {
+			append(&combined, entry)
+			continue
+		}
+
+		// Already combined with an earlier entry.
+		for prev in h.entries[:i] {
+			if prev.key == entry.key do continue outer
+		}
+
+		value := entry.value
+		for next in h.entries[i+1:] {
+			if next.key == entry.key {
+				value = strings.concatenate({value, ", ", next.value}, allocator)
+			}
+		}
+
+		append(&combined, Header{entry.key, value})
+	}
+
+	return combined[:]
+}
 
 header_parse :: proc(headers: ^Headers, line: string, allocator := context.allocator) -> (key: string, ok: bool) {
 	// Preceding spaces should not be allowed.
@@ -146,7 +273,7 @@ header_parse :: proc(headers: ^Headers, line: string, allocator := context.alloc
 
 	// RFC 7230 5.4: Server MUST respond with 400 to any request
 	// with multiple "Host" header fields.
-	if key == "host" && key in headers {
+	if key == "host" && headers_has(headers, key) {
 		return
 	}
 
@@ -156,12 +283,12 @@ header_parse :: proc(headers: ^Headers, line: string, allocator := context.alloc
 	// invalid value, then the message framing is invalid and the
 	// recipient MUST treat it as an unrecoverable error.
 	if key == "content-length" {
-		if curr_length, has_length_header := headers[key]; has_length_header {
+		if curr_length, has_length_header := headers_get(headers, key); has_length_header {
 			(curr_length == value) or_return
 		}
 	}
 
-	headers[key] = value
+	headers_add(headers, key, value)
 	ok = true
 	return
 }
@@ -0,0 +1,1238 @@
+package http
+
+import "core:bufio"
+import "core:bytes"
+import "core:encoding/base64"
+import "core:io"
+import "core:log"
+import "core:mem"
+import "core:strconv"
+import "core:strings"
+import "core:time"
+
+// HTTP/2 (RFC 9113).
+//
+// It is served over TLS when negotiated using ALPN ("h2"), and in cleartext (h2c) either
+// with prior knowledge (the client starts with the connection preface) or by upgrading an HTTP/1.1 request.
+//
+// Streams are multiplexed on the connection, but the handlers of a connection are called one after another,
+// each stream gets the same Request and Response as an HTTP/1.1 request would.
+// No frames are read while a handler runs, except when its response waits for flow control (see h2_response_data),
+// so a slow handler holds up the other streams of its connection, see Server_Opts.http2.
+
+// The connection preface every client starts with.
+H2_PREFACE :: "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"
+
+H2_Frame_Type :: enum u8 {
+	Data          = 0x0,
+	Headers       = 0x1,
+	Priority      = 0x2,
+	Rst_Stream    = 0x3,
+	Settings      = 0x4,
+	Push_Promise  = 0x5,
+	Ping          = 0x6,
+	Goaway        = 0x7,
+	Window_Update = 0x8,
+	Continuation  = 0x9,
+}
+
+H2_Error_Code :: enum u32 {
+	No_Error            = 0x0,
+	Protocol_Error      = 0x1,
+	Internal_Error      = 0x2,
+	Flow_Control_Error  = 0x3,
+	Settings_Timeout    = 0x4,
+	Stream_Closed       = 0x5,
+	Frame_Size_Error    = 0x6,
+	Refused_Stream      = 0x7,
+	Cancel              = 0x8,
+	Compression_Error   = 0x9,
+	Connect_Error       = 0xa,
+	Enhance_Your_Calm   = 0xb,
+	Inadequate_Security = 0xc,
+	HTTP_1_1_Required   = 0xd,
+}
+
+@(private)
+H2_FLAG_END_STREAM  :: 0x1
+@(private)
+H2_FLAG_ACK         :: 0x1
+@(private)
+H2_FLAG_END_HEADERS :: 0x4
+@(private)
+H2_FLAG_PADDED      :: 0x8
+@(private)
+H2_FLAG_PRIORITY    :: 0x20
+
+@(private)
+H2_SETTINGS_HEADER_TABLE_SIZE      :: 0x1
+@(private)
+H2_SETTINGS_ENABLE_PUSH            :: 0x2
+@(private)
+H2_SETTINGS_MAX_CONCURRENT_STREAMS :: 0x3
+@(private)
+H2_SETTINGS_INITIAL_WINDOW_SIZE    :: 0x4
+@(private)
+H2_SETTINGS_MAX_FRAME_SIZE         :: 0x5
+@(private)
+H2_SETTINGS_MAX_HEADER_LIST_SIZE   :: 0x6
+
+@(private)
+H2_FRAME_HEADER_SIZE :: 9
+@(private)
+H2_DEFAULT_WINDOW    :: 65_535
+@(private)
+H2_MAX_WINDOW        :: 1 << 31 - 1
+@(private)
+H2_MIN_FRAME_SIZE    :: 16_384
+@(private)
+H2_MAX_FRAME_SIZE    :: 1 << 24 - 1
+
+// The amount of streams a client can have open at the same time.
+@(private)
+H2_Max_Concurrent_Streams :: 100
+
+// Request bodies are buffered before the handler is called, this is the limit on that buffer.
+@(private)
+H2_Max_Request_Body_Bytes :: 32 << 20
+
+// The connection flow control window we give clients, which bounds the request bodies that are buffered
+// on a connection at the same time, the bytes of a body are given back once its stream has been handled.
+@(private)
+H2_Max_Buffered_Bytes :: 64 << 20
+
+@(private)
+H2_Frame :: struct {
+	type:    H2_Frame_Type,
+	flags:   u8,
+	stream:  u32,
+	payload: []byte,
+}
+
+@(private)
+H2_Settings :: struct {
+	header_table_size:   u32,
+	initial_window_size: u32,
+	max_frame_size:      u32,
+}
+
+@(private)
+H2_Stream :: struct {
+	id:          u32,
+	// Allocated with the connection allocator because a stream lives across multiple reads.
+	fields:      [dynamic]HPACK_Field,
+	body:        bytes.Buffer,
+	// Whether the client has ended the stream (END_STREAM), the request is then complete.
+	recv_closed: bool,
+	// Whether the stream has been reset, by the client, or by response_abort.
+	reset:       bool,
+	// Whether the stream is being handled, it is then kept around until the handler is done.
+	handling:    bool,
+	send_window: int,
+	// The bytes of the body that count against the connection window, given back when the stream is destroyed.
+	recv_bytes:  int,
+}
+
+@(private)
+H2_Conn :: struct {
+	allocator:        mem.Allocator,
+	decoder:          HPACK_Decoder,
+	peer:             H2_Settings,
+	streams:          map[u32]^H2_Stream,
+	// Streams that are fully received, waiting to be handled.
+	ready:            [dynamic]^H2_Stream,
+	last_stream_id:   u32,
+	send_window:      int,
+	// How many more bytes of DATA the client may send on the connection.
+	recv_window:      int,
+
+	// The header block that is being received in HEADERS and CONTINUATION frames.
+	header_block:     [dynamic]byte,
+	header_stream:    u32,
+	header_flags:     u8,
+
+	preface_received: bool,
+	settings_sent:    bool,
+	goaway_received:  bool,
+	goaway_sent:      bool,
+
+	read_buf:         [dynamic]byte,
+	// Frames are written into this buffer and sent in batches.
+	out:              bytes.Buffer,
+}
+
+@(private)
+h2_conn_init :: proc(h2: ^H2_Conn, allocator := context.allocator) {
+	h2.allocator = allocator
+	hpack_decoder_init(&h2.decoder, 4096, allocator)
+	h2.peer = H2_Settings {
+		header_table_size   = 4096,
+		initial_window_size = H2_DEFAULT_WINDOW,
+		max_frame_size      = H2_MIN_FRAME_SIZE,
+	}
+	h2.streams = make(map[u32]^H2_Stream, 8, allocator)
+	h2.ready = make([dynamic]^H2_Stream, allocator)
+	h2.send_window = H2_DEFAULT_WINDOW
+	h2.recv_window = H2_DEFAULT_WINDOW
+	h2.header_block = make([dynamic]byte, allocator)
+	h2.read_buf = make([dynamic]byte, allocator)
+	bytes.buffer_init_allocator(&h2.out, 0, 1024, allocator)
+}
+
+@(private)
+h2_conn_destroy :: proc(h2: ^H2_Conn) {
+	for _, stream in h2.streams {
+		h2_stream_destroy(h2, stream)
+	}
+	delete(h2.streams)
+	delete(h2.ready)
+	delete(h2.header_block)
+	delete(h2.read_buf)
+	bytes.buffer_destroy(&h2.out)
+	hpack_decoder_destroy(&h2.decoder)
+}
+
+@(private)
+h2_stream_new :: proc(h2: ^H2_Conn, id: u32) -> ^H2_Stream {
+	stream := new(H2_Stream, h2.allocator)
+	stream.id = id
+	stream.fields = make([dynamic]HPACK_Field, h2.allocator)
+	stream.send_window = int(h2.peer.initial_window_size)
+	bytes.buffer_init_allocator(&stream.body, 0, 0, h2.allocator)
+	h2.streams[id] = stream
+	return stream
+}
+
+@(private)
+h2_stream_destroy :: proc(h2: ^H2_Conn, stream: ^H2_Stream) {
+	for f in stream.fields {
+		delete(f.name, h2.allocator)
+		delete(f.value, h2.allocator)
+	}
+	delete(stream.fields)
+	bytes.buffer_destroy(&stream.body)
+
+	// The body is not buffered anymore, the client can send that much again.
+	if stream.recv_bytes > 0 {
+		h2_recv_release(h2, stream.recv_bytes)
+	}
+	free(stream, h2.allocator)
+}
+
+@(private)
+h2_stream_remove :: proc(h2: ^H2_Conn, stream: ^H2_Stream) {
+	delete_key(&h2.streams, stream.id)
+	for s, i in h2.ready {
+		if s == stream {
+			ordered_remove(&h2.ready, i)
+			break
+		}
+	}
+	h2_stream_destroy(h2, stream)
+}
+
+// Starts HTTP/2 on the connection, preface_received should be true if the HTTP/1.1 side already consumed it.
+@(private)
+connection_h2_start :: proc(c: ^Connection, preface_received: bool) -> ^H2_Conn {
+	c.h2 = new(H2_Conn, c.server.conn_allocator)
+	h2_conn_init(c.h2, c.server.conn_allocator)
+	c.h2.preface_received = preface_received
+	return c.h2
+}
+
+// Checks whether the request is an upgrade to cleartext HTTP/2 (RFC 7540 3.2), and if so, upgrades it.
+// The request is then handled as the first stream of the HTTP/2 connection.
+@(private)
+connection_h2_try_upgrade :: proc(c: ^Connection, req: ^Request, allocator: mem.Allocator) -> bool {
+	(c.ssl == nil && c.server.opts.http2) or_return
+
+	upgrade := headers_get(&req.headers, "upgrade") or_return
+	header_has_token(upgrade, "h2c") or_return
+
+	settings_str := headers_get(&req.headers, "http2-settings") or_return
+
+	// Only upgrade requests without a body, otherwise the body would need to be read before switching.
+	if length, has_length := headers_get(&req.headers, "content-length"); has_length && length != "0" {
+		return false
+	}
+	(!headers_has(&req.headers, "transfer-encoding")) or_return
+
+	// The settings are base64url encoded without padding.
+	settings_b64 := strings.builder_make(0, len(settings_str) + 3, allocator)
+	for ch in settings_str {
+		switch ch {
+		case '-': strings.write_byte(&settings_b64, '+')
+		case '_': strings.write_byte(&settings_b64, '/')
+		case:     strings.write_rune(&settings_b64, ch)
+		}
+	}
+	for strings.builder_len(settings_b64) % 4 != 0 {
+		strings.write_byte(&settings_b64, '=')
+	}
+	settings := base64.decode(strings.to_string(settings_b64), base64.DEC_TABLE, allocator)
+	(len(settings) % 6 == 0) or_return
+
+	RESPONSE :: "HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: h2c\r\n\r\n"
+	if _, err := io.write_string(io.to_writer(c.stream), RESPONSE); err != nil {
+		log.warnf("could not send h2c upgrade response: %s", err)
+		connection_close(c)
+		return true
+	}
+
+	h2 := connection_h2_start(c, false)
+	if code := h2_apply_settings(h2, settings); code != .No_Error {
+		h2_goaway(c, h2, code)
+		return true
+	}
+
+	// The upgraded request is stream 1, which is half-closed (remote) because it has no body.
+	rline := req.line.(Requestline)
+	stream := h2_stream_new(h2, 1)
+	stream.recv_closed = true
+	h2.last_stream_id = 1
+
+	add :: proc(h2: ^H2_Conn, stream: ^H2_Stream, name, value: string) {
+		append(&stream.fields, HPACK_Field{
+			name  = strings.clone(name, h2.allocator),
+			value = strings.clone(value, h2.allocator),
+		})
+	}
+
+	add(h2, stream, ":method", method_string(rline.method))
+	add(h2, stream, ":scheme", "http")
+	add(h2, stream, ":path", rline.target)
+	add(h2, stream, ":authority", headers_get(&req.headers, "host") or_else "")
+	for header in req.headers.entries {
+		if h2_connection_specific(header.key) do continue
+		switch header.key {
+		case "host", "http2-settings": continue
+		}
+		add(h2, stream, header.key, header.value)
+	}
+
+	append(&h2.ready, stream)
+	return true
+}
+
+// Serves the HTTP/2 connection until there is no more data to read,
+// after which the connection is either closing or should be given back to the event loop.
+@(private)
+connection_h2_handle :: proc(c: ^Connection, allocator: mem.Allocator) {
+	if c.state == .Closing || c.state == .Closed {
+		return
+	}
+
+	h2 := c.h2
+	if h2 == nil {
+		h2 = connection_h2_start(c, false)
+	}
+
+	// Once a frame starts arriving it has to be received within the header timeout, like the head of an HTTP/1.1 request,
+	// so a client that stops in the middle of one does not hold the worker.
+	// Between frames the connection is given back to the event loop, where the idle timeout applies.
+	defer connection_clear_deadline(c)
+
+	if !h2.settings_sent {
+		h2_send_settings(c, h2)
+		h2.settings_sent = true
+	}
+
+	if !h2.preface_received {
+		connection_set_deadline(c, c.server.opts.header_timeout)
+
+		preface: [len(H2_PREFACE)]byte
+		if err := connection_read_full(c, preface[:]); err != nil {
+			log.debugf("could not read HTTP/2 preface: %s", err)
+			connection_close(c)
+			return
+		}
+
+		if string(preface[:]) != H2_PREFACE {
+			h2_goaway(c, h2, .Protocol_Error)
+			return
+		}
+		h2.preface_received = true
+	}
+
+	// The connection was handed to us because it is readable, so at least one frame is read, blocking if needed.
+	// After that only the frames that are already buffered, connection_has_buffered does not see the bytes
+	// that are still in the socket, those make the event loop hand the connection back to a worker.
+	read_one := true
+
+	for {
+		// Handlers are not bound by the header timeout, waiting for flow control has its own, see h2_response_data.
+		connection_clear_deadline(c)
+
+		for len(h2.ready) > 0 {
+			stream := pop_front(&h2.ready)
+
+			c.state = .Active
+			stream.handling = true
+			alive := h2_handle_stream(c, h2, stream, allocator)
+			free_all(allocator)
+
+			delete_key(&h2.streams, stream.id)
+			h2_stream_destroy(h2, stream)
+
+			if !alive do return
+		}
+
+		if c.state == .Closing || c.state == .Closed {
+			return
+		}
+
+		// The client is going away, we are done when all streams have been handled.
+		if h2.goaway_received && len(h2.streams) == 0 {
+			h2_goaway(c, h2, .No_Error)
+			return
+		}
+
+		if !read_one && !connection_has_buffered(c) {
+			if h2_flush(c, h2) {
+				c.state = .Idle
+			}
+			return
+		}
+		read_one = false
+
+		// Sent before blocking on the read, the client could be waiting on them (like the SETTINGS ack).
+		if !connection_has_buffered(c) && !h2_flush(c, h2) {
+			return
+		}
+
+		connection_set_deadline(c, c.server.opts.header_timeout)
+		frame, ok := h2_read_frame(c, h2)
+		if !ok || !h2_process_frame(c, h2, frame) {
+			return
+		}
+	}
+}
+
+// Reads bytes from the connection, first taking the bytes that the HTTP/1.1 scanner already buffered.
+// The read deadline of the connection applies.
+@(private)
+connection_read_full :: proc(c: ^Connection, buf: []byte) -> io.Error {
+	n := 0
+	if c.scanner.end > c.scanner.start {
+		n = copy(buf, c.scanner.buf[c.scanner.start:c.scanner.end])
+		c.scanner.start += n
+	}
+
+	if n < len(buf) {
+		_, err := io.read_full(connection_reader(c), buf[n:])
+		return err
+	}
+
+	return nil
+}
+
+@(private)
+h2_read_frame :: proc(c: ^Connection, h2: ^H2_Conn) -> (f: H2_Frame, ok: bool) {
+	header: [H2_FRAME_HEADER_SIZE]byte
+	if err := connection_read_full(c, header[:]); err != nil {
+		log.debugf("could not read HTTP/2 frame: %s", err)
+		connection_close(c)
+		return
+	}
+
+	length := int(header[0]) << 16 | int(header[1]) << 8 | int(header[2])
+	f.type = H2_Frame_Type(header[3])
+	f.flags = header[4]
+	f.stream = h2_u32(header[5:]) & 0x7fffffff
+
+	// We never change SETTINGS_MAX_FRAME_SIZE, so this is the limit.
+	if length > H2_MIN_FRAME_SIZE {
+		h2_goaway(c, h2, .Frame_Size_Error)
+		return
+	}
+
+	resize(&h2.read_buf, length)
+	if err := connection_read_full(c, h2.read_buf[:]); err != nil {
+		log.debugf("could not read HTTP/2 frame payload: %s", err)
+		connection_close(c)
+		return
+	}
+
+	f.payload = h2.read_buf[:]
+	ok = true
+	return
+}
+
+// Processes a frame, returns false when it caused the connection to close.
+@(private)
+h2_process_frame :: proc(c: ^Connection, h2: ^H2_Conn, f: H2_Frame) -> bool {
+	// Only CONTINUATION frames of the same stream are allowed while a header block is being received.
+	if h2.header_stream != 0 && (f.type != .Continuation || f.stream != h2.header_stream) {
+		return h2_goaway(c, h2, .Protocol_Error)
+	}
+
+	#partial switch f.type {
+	case .Data:
+		return h2_on_data(c, h2, f)
+
+	case .Headers:
+		if f.stream == 0 || f.stream % 2 == 0 {
+			return h2_goaway(c, h2, .Protocol_Error)
+		}
+
+		payload, ok := h2_strip_padding(f)
+		if !ok do return h2_goaway(c, h2, .Protocol_Error)
+
+		if f.flags & H2_FLAG_PRIORITY != 0 {
+			if len(payload) < 5 do return h2_goaway(c, h2, .Frame_Size_Error)
+			payload = payload[5:]
+		}
+
+		clear(&h2.header_block)
+		append(&h2.header_block, ..payload)
+		h2.header_stream = f.stream
+		h2.header_flags = f.flags
+
+		if f.flags & H2_FLAG_END_HEADERS != 0 {
+			return h2_on_header_block(c, h2)
+		}
+
+	case .Continuation:
+		if h2.header_stream == 0 {
+			return h2_goaway(c, h2, .Protocol_Error)
+		}
+
+		append(&h2.header_block, ..f.payload)
+		if len(h2.header_block) > c.server.opts.limit_headers * 2 {
+			return h2_goaway(c, h2, .Enhance_Your_Calm)
+		}
+
+		if f.flags & H2_FLAG_END_HEADERS != 0 {
+			return h2_on_header_block(c, h2)
+		}
+
+	case .Priority:
+		if f.stream == 0 do return h2_goaway(c, h2, .Protocol_Error)
+		if len(f.payload) != 5 {
+			h2_rst_stream(h2, f.stream, .Frame_Size_Error)
+		}
+
+	case .Rst_Stream:
+		if f.stream == 0 do return h2_goaway(c, h2, .Protocol_Error)
+		if len(f.payload) != 4 do return h2_goaway(c, h2, .Frame_Size_Error)
+		if f.stream > h2.last_stream_id do return h2_goaway(c, h2, .Protocol_Error)
+
+		if stream, ok := h2.streams[f.stream]; ok {
+			stream.reset = true
+			if !stream.handling {
+				h2_stream_remove(h2, stream)
+			}
+		}
+
+	case .Settings:
+		if f.stream != 0 do return h2_goaway(c, h2, .Protocol_Error)
+
+		if f.flags & H2_FLAG_ACK != 0 {
+			if len(f.payload) != 0 do return h2_goaway(c, h2, .Frame_Size_Error)
+			return true
+		}
+
+		if len(f.payload) % 6 != 0 do return h2_goaway(c, h2, .Frame_Size_Error)
+
+		if code := h2_apply_settings(h2, f.payload); code != .No_Error {
+			return h2_goaway(c, h2, code)
+		}
+
+		h2_frame(h2, .Settings, H2_FLAG_ACK, 0, nil)
+
+	case .Push_Promise:
+		// Clients can't push.
+		return h2_goaway(c, h2, .Protocol_Error)
+
+	case .Ping:
+		if f.stream != 0 do return h2_goaway(c, h2, .Protocol_Error)
+		if len(f.payload) != 8 do return h2_goaway(c, h2, .Frame_Size_Error)
+
+		if f.flags & H2_FLAG_ACK == 0 {
+			h2_frame(h2, .Ping, H2_FLAG_ACK, 0, f.payload)
+			return h2_flush(c, h2)
+		}
+
+	case .Goaway:
+		if f.stream != 0 do return h2_goaway(c, h2, .Protocol_Error)
+		if len(f.payload) < 8 do return h2_goaway(c, h2, .Frame_Size_Error)
+
+		code := H2_Error_Code(h2_u32(f.payload[4:]))
+		log.debugf("HTTP/2 client is going away: %v", code)
+		h2.goaway_received = true
+
+	case .Window_Update:
+		if len(f.payload) != 4 do return h2_goaway(c, h2, .Frame_Size_Error)
+
+		increment := int(h2_u32(f.payload) & 0x7fffffff)
+		if f.stream == 0 {
+			if increment == 0 do return h2_goaway(c, h2, .Protocol_Error)
+
+			h2.send_window += increment
+			if h2.send_window > H2_MAX_WINDOW do return h2_goaway(c, h2, .Flow_Control_Error)
+			return true
+		}
+
+		stream, ok := h2.streams[f.stream]
+		if !ok do return true
+
+		if increment == 0 {
+			h2_rst_stream(h2, f.stream, .Protocol_Error)
+			return true
+		}
+
+		stream.send_window += increment
+		if stream.send_window > H2_MAX_WINDOW {
+			h2_rst_stream(h2, f.stream, .Flow_Control_Error)
+		}
+
+	case:
+		// Unknown frame types must be ignored.
+	}
+
+	return true
+}
+
+@(private)
+h2_on_data :: proc(c: ^Connection, h2: ^H2_Conn, f: H2_Frame) -> bool {
+	if f.stream == 0 do return h2_goaway(c, h2, .Protocol_Error)
+
+	// The whole frame counts towards flow control, including padding.
+	if len(f.payload) > h2.recv_window do return h2_goaway(c, h2, .Flow_Control_Error)
+	h2.recv_window -= len(f.payload)
+
+	payload, ok := h2_strip_padding(f)
+	if !ok do return h2_goaway(c, h2, .Protocol_Error)
+
+	stream, exists := h2.streams[f.stream]
+	if !exists || stream.recv_closed {
+		h2_recv_release(h2, len(f.payload))
+		if f.stream > h2.last_stream_id do return h2_goaway(c, h2, .Protocol_Error)
+		h2_rst_stream(h2, f.stream, .Stream_Closed)
+		return true
+	}
+
+	if bytes.buffer_length(&stream.body) + len(payload) > H2_Max_Request_Body_Bytes {
+		h2_recv_release(h2, len(f.payload))
+		h2_respond_status(c, h2, stream, .Payload_Too_Large)
+		h2_rst_stream(h2, stream.id, .No_Error)
+		h2_stream_remove(h2, stream)
+		return true
+	}
+
+	// Padding is not buffered, so it is given back right away, the body once the stream has been handled.
+	if padding := len(f.payload) - len(payload); padding > 0 {
+		h2_recv_release(h2, padding)
+	}
+
+	bytes.buffer_write(&stream.body, payload)
+	stream.recv_bytes += len(payload)
+
+	if f.flags & H2_FLAG_END_STREAM != 0 {
+		stream.recv_closed = true
+		append(&h2.ready, stream)
+	} else if len(f.payload) > 0 {
+		h2_window_update(h2, stream.id, len(f.payload))
+	}
+
+	return true
+}
+
+// Decodes a complete header block, and starts a new stream or adds trailers to an existing one.
+@(private)
+h2_on_header_block :: proc(c: ^Connection, h2: ^H2_Conn) -> bool {
+	id := h2.header_stream
+	end_stream := h2.header_flags & H2_FLAG_END_STREAM != 0
+	h2.header_stream = 0
+
+	fields := make([dynamic]HPACK_Field, h2.allocator)
+	defer {
+		for f in fields {
+			delete(f.name, h2.allocator)
+			delete(f.value, h2.allocator)
+		}
+		delete(fields)
+	}
+
+	// The limit is enforced while decoding, so a block that decodes into a huge list is never allocated.
+	too_large := false
+	switch err := hpack_decode(&h2.decoder, h2.header_block[:], &fields, c.server.opts.limit_headers, h2.allocator); err {
+	case .None:
+	case .List_Too_Large:
+		too_large = true
+	case:
+		log.debugf("HTTP/2 header block could not be decoded: %v", err)
+		return h2_goaway(c, h2, .Compression_Error)
+	}
+
+	// Trailers.
+	if stream, exists := h2.streams[id]; exists {
+		if stream.recv_closed || !end_stream {
+			h2_rst_stream(h2, id, .Protocol_Error)
+			return true
+		}
+
+		if too_large {
+			h2_respond_status(c, h2, stream, .Request_Header_Fields_Too_Large)
+			h2_stream_remove(h2, stream)
+			return true
+		}
+
+		for f in fields {
+			if strings.has_prefix(f.name, ":") || !header_allowed_trailer(f.name) do continue
+			append(&stream.fields, HPACK_Field{
+				name  = strings.clone(f.name, h2.allocator),
+				value = strings.clone(f.value, h2.allocator),
+			})
+		}
+
+		stream.recv_closed = true
+		append(&h2.ready, stream)
+		return true
+	}
+
+	// Stream identifiers of new streams have to be increasing.
+	if id <= h2.last_stream_id {
+		return h2_goaway(c, h2, .Protocol_Error)
+	}
+	h2.last_stream_id = id
+
+	// No new streams are handled after a GOAWAY.
+	if h2.goaway_sent || h2.goaway_received {
+		return true
+	}
+
+	if len(h2.streams) >= H2_Max_Concurrent_Streams {
+		h2_rst_stream(h2, id, .Refused_Stream)
+		return true
+	}
+
+	stream := h2_stream_new(h2, id)
+	stream.fields, fields = fields, stream.fields
+
+	if too_large {
+		h2_respond_status(c, h2, stream, .Request_Header_Fields_Too_Large)
+		h2_stream_remove(h2, stream)
+		return true
+	}
+
+	if end_stream {
+		stream.recv_closed = true
+		append(&h2.ready, stream)
+		return true
+	}
+
+	// The client waits for us to allow sending the body.
+	if c.server.opts.auto_expect_continue {
+		for f in stream.fields {
+			if f.name == "expect" && f.value == "100-continue" {
+				hbuf: bytes.Buffer
+				bytes.buffer_init_allocator(&hbuf, 0, 8, context.temp_allocator)
+				hpack_encode(&hbuf, ":status", "100")
+				h2_frame(h2, .Headers, H2_FLAG_END_HEADERS, id, bytes.buffer_to_bytes(&hbuf))
+				return h2_flush(c, h2)
+			}
+		}
+	}
+
+	return true
+}
+
+// Turns the stream into a Request, calls the handler, and sends the response.
+@(private)
+h2_handle_stream :: proc(c: ^Connection, h2: ^H2_Conn, stream: ^H2_Stream, allocator: mem.Allocator) -> bool {
+	req: Request
+	request_init(&req, allocator)
+	req.client = c.client
+	req.tls = c.ssl != nil
+	c.curr_req = &req
+
+	res: Response
+	response_init(&res, allocator)
+	res._conn = c
+	res._h2_stream = stream
+
+	method, path, scheme, authority: Maybe(string)
+	malformed := false
+	seen_regular := false
+
+	// RFC 9113 8.3.1 and 8.2.
+	for f in stream.fields {
+		if strings.has_prefix(f.name, ":") {
+			if seen_regular {
+				malformed = true
+				break
+			}
+
+			dest: ^Maybe(string)
+			switch f.name {
+			case ":method":    dest = &method
+			case ":path":      dest = &path
+			case ":scheme":    dest = &scheme
+			case ":authority": dest = &authority
+			case:
+				malformed = true
+			}
+
+			if dest == nil || dest^ != nil {
+				malformed = true
+				break
+			}
+			dest^ = f.value
+			continue
+		}
+
+		seen_regular = true
+
+		if f.name != strings.to_lower(f.name, allocator) || h2_connection_specific(f.name) {
+			malformed = true
+			break
+		}
+
+		if f.name == "te" && f.value != "trailers" {
+			malformed = true
+			break
+		}
+
+		// RFC 7540 8.1.2.5: cookies can be split into multiple fields, they are combined
+		// with a semicolon into one, like in HTTP/1.1.
+		name := strings.clone(f.name, allocator)
+		if existing, exists := headers_get(&req.headers, name); exists && name == "cookie" {
+			headers_set(&req.headers, name, strings.concatenate({existing, "; ", f.value}, allocator))
+		} else {
+			headers_add(&req.headers, name, strings.clone(f.value, allocator))
+		}
+	}
+
+	rline: Requestline
+	rline.version = Version{2, 0}
+
+	method_ok: bool
+	rline.method, method_ok = method_parse(method.? or_else "")
+	if !method_ok {
+		malformed = true
+	}
+
+	if rline.method != .Connect && (path == nil || path.? == "" || scheme == nil) {
+		malformed = true
+	}
+
+	body_len := bytes.buffer_length(&stream.body)
+	if length, has_length := headers_get(&req.headers, "content-length"); has_length {
+		if n, ok := strconv.parse_int(length, 10); !ok || n != body_len {
+			malformed = true
+		}
+	}
+
+	if malformed {
+		h2_rst_stream(h2, stream.id, .Protocol_Error)
+		return h2_flush(c, h2)
+	}
+
+	rline.target = path.? or_else ""
+	req.line = rline
+	req.url = url_parse(rline.target, allocator)
+
+	if a, ok := authority.?; ok && !headers_has(&req.headers, "host") {
+		headers_set(&req.headers, "host", a)
+	}
+
+	// Make the buffered body available like an HTTP/1.1 body with a content length.
+	buf := make([]byte, 32, allocator)
+	headers_set(&req.headers, "content-length", strconv.itoa(buf, body_len))
+
+	body_reader := new(bytes.Reader, allocator)
+	bytes.reader_init(body_reader, bytes.buffer_to_bytes(&stream.body))
+	req._body = new(bufio.Scanner, allocator)
+	bufio.scanner_init(req._body, io.to_reader(bytes.reader_to_stream(body_reader)), allocator)
+
+	if rline.method == .Options && rline.target == "*" {
+		res.status = .Ok
+	} else {
+		is_head := rline.method == .Head
+		res._head = is_head
+		if is_head && c.server.opts.redirect_head_to_get {
+			rline.method = .Get
+			req.line = rline
+		}
+
+		c.handler.handle(c.handler, &req, &res)
+
+		// Restored so the response is sent without a body.
+		if is_head && c.server.opts.redirect_head_to_get {
+			rline.method = .Head
+			req.line = rline
+		}
+	}
+
+	return h2_send_response(c, h2, stream, &req, &res)
+}
+
+@(private)
+h2_send_response :: proc(c: ^Connection, h2: ^H2_Conn, stream: ^H2_Stream, req: ^Request, res: ^Response) -> bool {
+	// A file body is sent in parts, so memory use does not depend on the size of the file.
+	if res._file != nil {
+		defer response_file_close(res)
+
+		err := response_file_stream(res)
+		if err == nil do err = response_end(res)
+		if err != nil {
+			log.debugf("could not send HTTP/2 response: %s", err)
+		}
+		return h2_conn_alive(c)
+	}
+
+	// The handler has started streaming the body, or trailers have been set which need to be sent after the body.
+	if res._headers_sent || len(res.trailers.entries) > 0 {
+		if err := response_end(res); err != nil {
+			log.debugf("could not send HTTP/2 response: %s", err)
+		}
+		return h2_conn_alive(c)
+	}
+
+	allocator := req.allocator
+	rline := req.line.(Requestline)
+
+	can_have_body := !status_informational(res.status) && res.status != .No_Content && res.status != .Not_Modified
+	send_body := can_have_body && rline.method != .Head
+
+	// Write the status code as the body, if there is no body set by the handlers.
+	if can_have_body && !status_success(res.status) && bytes.buffer_length(&res.body) == 0 {
+		bytes.buffer_write_string(&res.body, status_string(res.status))
+		headers_set(&res.headers, "content-type", mime_to_content_type(.Plain))
+	}
+
+	if can_have_body && !headers_has(&res.headers, "content-length") {
+		buf := make([]byte, 32, allocator)
+		headers_set(&res.headers, "content-length", strconv.itoa(buf, bytes.buffer_length(&res.body)))
+	}
+
+	body := bytes.buffer_to_bytes(&res.body)
+	if !send_body {
+		body = nil
+	}
+
+	h2_response_headers(c, h2, stream, res, len(body) == 0)
+	if len(body) > 0 {
+		h2_response_data(c, h2, stream, body, true)
+	}
+
+	h2_flush(c, h2)
+	return h2_conn_alive(c)
+}
+
+// Writes the HEADERS (and CONTINUATION) frames of the response.
+@(private)
+h2_response_headers :: proc(c: ^Connection, h2: ^H2_Conn, stream: ^H2_Stream, res: ^Response, end_stream: bool) -> bool {
+	if stream.reset do return false
+
+	allocator := c.curr_req.allocator
+
+	if res.status >= .Ok && res.status <= .Internal_Server_Error && !headers_has(&res.headers, "date") {
+		headers_set(&res.headers, "date", format_date_header(time.now(), allocator))
+	}
+
+	hbuf: bytes.Buffer
+	bytes.buffer_init_allocator(&hbuf, 0, 128, allocator)
+
+	status_buf := make([]byte, 8, allocator)
+	hpack_encode(&hbuf, ":status", strconv.itoa(status_buf, int(res.status)))
+
+	// RFC 7540 8.1.2: every value is its own field, HTTP/2 has no need to combine them.
+	for header in res.headers.entries {
+		if h2_connection_specific(header.key) do continue
+		hpack_encode(&hbuf, header.key, header.value)
+	}
+
+	for cookie in res.cookies {
+		hpack_encode(&hbuf, "set-cookie", strings.trim_prefix(cookie_string(cookie, allocator), "set-cookie: "))
+	}
+
+	h2_headers(h2, stream.id, bytes.buffer_to_bytes(&hbuf), end_stream)
+	return true
+}
+
+// Writes DATA frames, within the flow control windows.
+// Returns false if the stream was reset by the client, or the connection closed.
+@(private)
+h2_response_data :: proc(c: ^Connection, h2: ^H2_Conn, stream: ^H2_Stream, data: []byte, end_stream: bool) -> bool {
+	data := data
+	for {
+		// Wait for the client to open up the flow control windows.
+		// A client that does not is not reading the response, so the wait is bound like a write would be.
+		waiting := false
+		for len(data) > 0 && (h2.send_window <= 0 || stream.send_window <= 0) {
+			if stream.reset do return false
+
+			if !waiting {
+				connection_set_deadline(c, h2_window_timeout(c))
+				waiting = true
+			}
+
+			h2_flush(c, h2) or_return
+			frame := h2_read_frame(c, h2) or_return
+			h2_process_frame(c, h2, frame) or_return
+		}
+		if waiting {
+			connection_clear_deadline(c)
+		}
+
+		if stream.reset do return false
+
+		n := min(len(data), h2.send_window, stream.send_window, int(h2.peer.max_frame_size))
+		last := n == len(data)
+		flags: u8 = H2_FLAG_END_STREAM if last && end_stream else 0
+		h2_frame(h2, .Data, flags, stream.id, data[:n])
+
+		h2.send_window -= n
+		stream.send_window -= n
+		data = data[n:]
+
+		if last do return true
+	}
+}
+
+// How long a response waits for a WINDOW_UPDATE, the write timeout, or the idle timeout when that is disabled.
+@(private)
+h2_window_timeout :: proc(c: ^Connection) -> time.Duration {
+	if c.server.opts.write_timeout > 0 {
+		return c.server.opts.write_timeout
+	}
+	return c.server.opts.idle_timeout
+}
+
+// Ends a streamed response, with the trailers if there are any.
+@(private)
+h2_response_end :: proc(c: ^Connection, h2: ^H2_Conn, stream: ^H2_Stream, res: ^Response) -> bool {
+	// The stream was already ended with the headers.
+	if !res._send_body || stream.reset {
+		return h2_flush(c, h2)
+	}
+
+	if len(res.trailers.entries) == 0 {
+		h2_response_data(c, h2, stream, nil, true) or_return
+		return h2_flush(c, h2)
+	}
+
+	hbuf: bytes.Buffer
+	bytes.buffer_init_allocator(&hbuf, 0, 64, c.curr_req.allocator)
+	for trailer in res.trailers.entries {
+		hpack_encode(&hbuf, trailer.key, trailer.value)
+	}
+
+	h2_headers(h2, stream.id, bytes.buffer_to_bytes(&hbuf), true)
+	return h2_flush(c, h2)
+}
+
+@(private)
+h2_conn_alive :: proc(c: ^Connection) -> bool {
+	return c.state != .Closing && c.state != .Closed
+}
+
+// Sends a response with just a status, for errors that happen before a handler is called.
+@(private)
+h2_respond_status :: proc(c: ^Connection, h2: ^H2_Conn, stream: ^H2_Stream, status: Status) {
+	hbuf: bytes.Buffer
+	bytes.buffer_init_allocator(&hbuf, 0, 16, context.temp_allocator)
+
+	status_buf: [8]byte
+	hpack_encode(&hbuf, ":status", strconv.itoa(status_buf[:], int(status)))
+	h2_headers(h2, stream.id, bytes.buffer_to_bytes(&hbuf), true)
+}
+
+// Writes a header block, split into CONTINUATION frames if it is bigger than the maximum frame size.
+@(private)
+h2_headers :: proc(h2: ^H2_Conn, stream: u32, block: []byte, end_stream: bool) {
+	block := block
+	max_size := int(h2.peer.max_frame_size)
+
+	first := block[:min(len(block), max_size)]
+	block = block[len(first):]
+
+	flags: u8 = H2_FLAG_END_STREAM if end_stream else 0
+	if len(block) == 0 do flags |= H2_FLAG_END_HEADERS
+	h2_frame(h2, .Headers, flags, stream, first)
+
+	for len(block) > 0 {
+		part := block[:min(len(block), max_size)]
+		block = block[len(part):]
+
+		h2_frame(h2, .Continuation, H2_FLAG_END_HEADERS if len(block) == 0 else 0, stream, part)
+	}
+}
+
+@(private)
+h2_send_settings :: proc(c: ^Connection, h2: ^H2_Conn) {
+	settings: [18]byte
+	h2_put_setting(settings[0:], H2_SETTINGS_ENABLE_PUSH, 0)
+	h2_put_setting(settings[6:], H2_SETTINGS_MAX_CONCURRENT_STREAMS, H2_Max_Concurrent_Streams)
+	h2_put_setting(settings[12:], H2_SETTINGS_MAX_HEADER_LIST_SIZE, u32(c.server.opts.limit_headers))
+	h2_frame(h2, .Settings, 0, 0, settings[:])
+
+	// The connection window can only be changed with a WINDOW_UPDATE, SETTINGS_INITIAL_WINDOW_SIZE is for streams.
+	h2_window_update(h2, 0, H2_Max_Buffered_Bytes - H2_DEFAULT_WINDOW)
+	h2.recv_window += H2_Max_Buffered_Bytes - H2_DEFAULT_WINDOW
+}
+
+@(private)
+h2_put_setting :: proc(b: []byte, id: u16, value: u32) {
+	b[0] = u8(id >> 8)
+	b[1] = u8(id)
+	h2_put_u32(b[2:], value)
+}
+
+// Applies the settings of the client, RFC 9113 6.5.2.
+@(private)
+h2_apply_settings :: proc(h2: ^H2_Conn, payload: []byte) -> H2_Error_Code {
+	for i := 0; i + 6 <= len(payload); i += 6 {
+		id := u16(payload[i]) << 8 | u16(payload[i + 1])
+		value := h2_u32(payload[i + 2:])
+
+		switch id {
+		case H2_SETTINGS_HEADER_TABLE_SIZE:
+			// We don't use the dynamic table when encoding, so this does not matter.
+			h2.peer.header_table_size = value
+		case H2_SETTINGS_ENABLE_PUSH:
+			if value > 1 do return .Protocol_Error
+		case H2_SETTINGS_INITIAL_WINDOW_SIZE:
+			if value > H2_MAX_WINDOW do return .Flow_Control_Error
+
+			// The difference applies to every open stream.
+			delta := int(value) - int(h2.peer.initial_window_size)
+			for _, stream in h2.streams {
+				stream.send_window += delta
+				if stream.send_window > H2_MAX_WINDOW do return .Flow_Control_Error
+			}
+			h2.peer.initial_window_size = value
+		case H2_SETTINGS_MAX_FRAME_SIZE:
+			if value < H2_MIN_FRAME_SIZE || value > H2_MAX_FRAME_SIZE do return .Protocol_Error
+			h2.peer.max_frame_size = value
+		case H2_SETTINGS_MAX_CONCURRENT_STREAMS, H2_SETTINGS_MAX_HEADER_LIST_SIZE:
+			// We don't push or send requests, so we don't need these.
+		case:
+			// Unknown settings must be ignored.
+		}
+	}
+
+	return .No_Error
+}
+
+// Sends a GOAWAY frame and closes the connection, always returns false so it can be returned from frame processing.
+@(private)
+h2_goaway :: proc(c: ^Connection, h2: ^H2_Conn, code: H2_Error_Code) -> bool {
+	if code != .No_Error {
+		log.warnf("HTTP/2 connection error: %v", code)
+	}
+
+	if !h2.goaway_sent {
+		payload: [8]byte
+		h2_put_u32(payload[0:], h2.last_stream_id)
+		h2_put_u32(payload[4:], u32(code))
+		h2_frame(h2, .Goaway, 0, 0, payload[:])
+		h2.goaway_sent = true
+	}
+
+	if h2_flush(c, h2) {
+		connection_close(c)
+	}
+	return false
+}
+
+@(private)
+h2_rst_stream :: proc(h2: ^H2_Conn, stream: u32, code: H2_Error_Code) {
+	payload: [4]byte
+	h2_put_u32(payload[:], u32(code))
+	h2_frame(h2, .Rst_Stream, 0, stream, payload[:])
+}
+
+@(private)
+h2_window_update :: proc(h2: ^H2_Conn, stream: u32, increment: int) {
+	payload: [4]byte
+	h2_put_u32(payload[:], u32(increment))
+	h2_frame(h2, .Window_Update, 0, stream, payload[:])
+}
+
+// Gives bytes of DATA that are not buffered anymore back to the connection window.
+@(private)
+h2_recv_release :: proc(h2: ^H2_Conn, n: int) {
+	h2.recv_window += n
+	h2_window_update(h2, 0, n)
+}
+
+// Adds the frame to the output buffer, call h2_flush to send it.
+@(private)
+h2_frame :: proc(h2: ^H2_Conn, type: H2_Frame_Type, flags: u8, stream: u32, payload: []byte) {
+	header: [H2_FRAME_HEADER_SIZE]byte
+	header[0] = u8(len(payload) >> 16)
+	header[1] = u8(len(payload) >> 8)
+	header[2] = u8(len(payload))
+	header[3] = u8(type)
+	header[4] = flags
+	h2_put_u32(header[5:], stream & 0x7fffffff)
+
+	bytes.buffer_write(&h2.out, header[:])
+	bytes.buffer_write(&h2.out, payload)
+}
+
+// Sends the buffered frames, returns false (and closes the connection) if that fails.
+@(private)
+h2_flush :: proc(c: ^Connection, h2: ^H2_Conn) -> bool {
+	if bytes.buffer_length(&h2.out) == 0 do return true
+	defer bytes.buffer_reset(&h2.out)
+
+	if _, err := io.write(io.to_writer(c.stream), bytes.buffer_to_bytes(&h2.out)); err != nil {
+		log.warnf("could not send HTTP/2 frames: %s", err)
+		connection_close(c)
+		return false
+	}
+
+	return true
+}
+
+// Returns the payload of a DATA or HEADERS frame without its padding.
+@(private)
+h2_strip_padding :: proc(f: H2_Frame) -> (payload: []byte, ok: bool) {
+	payload = f.payload
+	if f.flags & H2_FLAG_PADDED == 0 do return payload, true
+
+	if len(payload) < 1 do return
+	pad := int(payload[0])
+	if pad >= len(payload) do return
+
+	return payload[1:len(payload) - pad], true
+}
+
+// Connection-specific header fields are not allowed in HTTP/2, RFC 9113 8.2.2.
+@(private)
+h2_connection_specific :: proc(name: string) -> bool {
+	switch name {
+	case "connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade":
+		return true
+	case:
+		return false
+	}
+}
+
+@(private)
+h2_u32 :: proc(b: []byte) -> u32 {
+	return u32(b[0]) << 24 | u32(b[1]) << 16 | u32(b[2]) << 8 | u32(b[3])
+}
+
+@(private)
+h2_put_u32 :: proc(b: []byte, v: u32) {
+	b[0] = u8(v >> 24)
+	b[1] = u8(v >> 16)
+	b[2] = u8(v >> 8)
+	b[3] = u8(v)
+}
+
+// Whether the comma separated header value contains the token (case-insensitive).
+header_has_token :: proc(value, token: string) -> bool {
+	value := value
+	for part in strings.split_iterator(&value, ",") {
+		if strings.equal_fold(strings.trim_space(part), token) {
+			return true
+		}
+	}
+	return false
+}
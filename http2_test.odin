@@ -0,0 +1,290 @@
+package http
+
+import "core:bytes"
+import "core:c"
+import "core:net"
+import "core:strings"
+import "core:testing"
+import "core:time"
+
+import "openssl"
+
+// Bigger than the initial flow control windows, so the server has to wait for the client to send more.
+@(private)
+H2_Test_Body_Size :: 200 << 10
+
+// Starts a server that responds to "/" with a body of H2_Test_Body_Size bytes, and to other paths with the path.
+// The tests speak HTTP/2 to it, writing and reading the frames themselves.
+@(private)
+h2_test_start :: proc(t: ^testing.T, s: ^Test_Server, opts: Server_Opts, tls: Maybe(Server_TLS_Opts) = nil) -> bool {
+	h := handler(proc(req: ^Request, res: ^Response) {
+		if req.url.path == "/" {
+			respond_plain(res, strings.repeat("a", H2_Test_Body_Size, req.allocator))
+		} else {
+			respond_plain(res, req.url.path)
+		}
+	})
+	return test_server_start(t, s, h, opts, tls)
+}
+
+// A connection of the test client, over TLS when ssl is set.
+@(private)
+H2_Test_Conn :: struct {
+	sock: net.TCP_Socket,
+	ctx:  ^openssl.SSL_CTX,
+	ssl:  ^openssl.SSL,
+}
+
+// Connects to the test server and sends the connection preface, with prior knowledge, or after negotiating h2 over TLS.
+@(private)
+h2_test_dial :: proc(t: ^testing.T, s: ^Test_Server, tls := false) -> (conn: H2_Test_Conn, ok: bool) {
+	sock, err := net.dial_tcp(s.endpoint)
+	if err != nil {
+		testing.errorf(t, "could not connect: %v", err)
+		return
+	}
+	conn.sock = sock
+
+	// Don't hang the tests when the server does not respond.
+	net.set_option(sock, .Receive_Timeout, 5 * time.Second)
+
+	if tls {
+		using openssl
+
+		conn.ctx = SSL_CTX_new(TLS_client_method())
+		SSL_CTX_load_verify_locations(conn.ctx, "testdata/localhost.pem", nil)
+		SSL_CTX_set_verify(conn.ctx, SSL_VERIFY_PEER, nil)
+
+		conn.ssl = SSL_new(conn.ctx)
+		SSL_set_fd(conn.ssl, c.int(sock))
+
+		alpn := "\x02h2"
+		SSL_set_alpn_protos(conn.ssl, raw_data(alpn), c.uint(len(alpn)))
+
+		if SSL_connect(conn.ssl) != 1 {
+			testing.error(t, "could not complete the TLS handshake")
+			h2_test_close(&conn)
+			return
+		}
+	}
+
+	if !h2_test_send(&conn, transmute([]byte)string(H2_PREFACE)) || !h2_test_write_frame(&conn, .Settings, 0, 0, nil) {
+		testing.error(t, "could not send the connection preface")
+		h2_test_close(&conn)
+		return
+	}
+	return conn, true
+}
+
+@(private)
+h2_test_close :: proc(conn: ^H2_Test_Conn) {
+	if conn.ssl != nil {
+		openssl.SSL_shutdown(conn.ssl)
+		openssl.SSL_free(conn.ssl)
+		openssl.SSL_CTX_free(conn.ctx)
+	}
+	net.close(conn.sock)
+}
+
+@(private)
+h2_test_send :: proc(conn: ^H2_Test_Conn, data: []byte) -> bool {
+	if conn.ssl != nil {
+		return openssl.SSL_write(conn.ssl, raw_data(data), c.int(len(data))) == c.int(len(data))
+	}
+
+	_, err := net.send_tcp(conn.sock, data)
+	return err == nil
+}
+
+@(private)
+h2_test_recv :: proc(conn: ^H2_Test_Conn, buf: []byte) -> bool {
+	n := 0
+	for n < len(buf) {
+		read: int
+		if conn.ssl != nil {
+			read = int(openssl.SSL_read(conn.ssl, raw_data(buf[n:]), c.int(len(buf) - n)))
+			if read <= 0 do return false
+		} else {
+			err: net.Network_Error
+			read, err = net.recv_tcp(conn.sock, buf[n:])
+			if err != nil || read == 0 do return false
+		}
+		n += read
+	}
+	return true
+}
+
+@(private)
+h2_test_write_frame :: proc(conn: ^H2_Test_Conn, type: H2_Frame_Type, flags: u8, stream: u32, payload: []byte) -> bool {
+	frame := make([]byte, H2_FRAME_HEADER_SIZE + len(payload), context.temp_allocator)
+	frame[0] = u8(len(payload) >> 16)
+	frame[1] = u8(len(payload) >> 8)
+	frame[2] = u8(len(payload))
+	frame[3] = u8(type)
+	frame[4] = flags
+	h2_put_u32(frame[5:], stream)
+	copy(frame[H2_FRAME_HEADER_SIZE:], payload)
+	return h2_test_send(conn, frame)
+}
+
+@(private)
+h2_test_read_frame :: proc(conn: ^H2_Test_Conn) -> (f: H2_Frame, ok: bool) {
+	header: [H2_FRAME_HEADER_SIZE]byte
+	h2_test_recv(conn, header[:]) or_return
+
+	f.type = H2_Frame_Type(header[3])
+	f.flags = header[4]
+	f.stream = h2_u32(header[5:]) & 0x7fffffff
+	f.payload = make([]byte, int(header[0]) << 16 | int(header[1]) << 8 | int(header[2]), context.temp_allocator)
+	h2_test_recv(conn, f.payload) or_return
+	return f, true
+}
+
+// Sends a GET request for the path on the stream.
+@(private)
+h2_test_request :: proc(conn: ^H2_Test_Conn, stream: u32, path: string) -> bool {
+	block: bytes.Buffer
+	bytes.buffer_init_allocator(&block, 0, 64, context.temp_allocator)
+	hpack_encode(&block, ":method", "GET")
+	hpack_encode(&block, ":scheme", "https" if conn.ssl != nil else "http")
+	hpack_encode(&block, ":path", path)
+	hpack_encode(&block, ":authority", "localhost")
+
+	return h2_test_write_frame(conn, .Headers, H2_FLAG_END_HEADERS | H2_FLAG_END_STREAM, stream, bytes.buffer_to_bytes(&block))
+}
+
+// Reads frames until the stream ends, returns the body that was received on it.
+@(private)
+h2_test_read_body :: proc(t: ^testing.T, conn: ^H2_Test_Conn, stream: u32) -> (body: string, ok: bool) {
+	b := strings.builder_make(context.temp_allocator)
+	for {
+		f, fok := h2_test_read_frame(conn)
+		if !fok {
+			testing.errorf(t, "the connection closed before stream %i ended", stream)
+			return
+		}
+		if f.type == .Goaway {
+			testing.errorf(t, "the server went away before stream %i ended", stream)
+			return
+		}
+		if f.stream != stream do continue
+
+		if f.type == .Data {
+			strings.write_bytes(&b, f.payload)
+		}
+		if (f.type == .Data || f.type == .Headers) && f.flags & H2_FLAG_END_STREAM != 0 {
+			return strings.to_string(b), true
+		}
+	}
+}
+
+// Sends requests on the same connection, the later ones after the server has handed the connection back to the event loop.
+@(private)
+h2_test_requests_on_one_connection :: proc(t: ^testing.T, tls: bool) {
+	s: Test_Server
+	if tls {
+		if !h2_test_start(t, &s, Default_Server_Opts, Server_TLS_Opts{certificate = TLS_Test_Default}) do return
+	} else {
+		if !h2_test_start(t, &s, Default_Server_Opts) do return
+	}
+	defer test_server_stop(&s)
+
+	conn, ok := h2_test_dial(t, &s, tls)
+	if !ok do return
+	defer h2_test_close(&conn)
+
+	paths := [?]string{"/first", "/second", "/third"}
+	for path, i in paths {
+		stream := u32(1 + 2 * i)
+		if !h2_test_request(&conn, stream, path) {
+			testing.errorf(t, "could not send the request for %s", path)
+			return
+		}
+
+		body, bok := h2_test_read_body(t, &conn, stream)
+		if !bok do return
+		testing.expect_value(t, body, path)
+
+		// Nothing is left for the server to read, it waits in the event loop for the next frames.
+		time.sleep(50 * time.Millisecond)
+	}
+
+	// Frames that are not requests are read too.
+	ping := [8]byte{1, 2, 3, 4, 5, 6, 7, 8}
+	if !h2_test_write_frame(&conn, .Ping, 0, 0, ping[:]) {
+		testing.error(t, "could not send a ping")
+		return
+	}
+
+	for {
+		f, fok := h2_test_read_frame(&conn)
+		if !fok {
+			testing.error(t, "the connection closed before the ping was answered")
+			return
+		}
+		if f.type != .Ping do continue
+
+		testing.expect(t, f.flags & H2_FLAG_ACK != 0, "the ping is answered with an ack")
+		testing.expect(t, bytes.equal(f.payload, ping[:]), "the ping ack has the payload of the ping")
+		break
+	}
+}
+
+@(test)
+test_h2c_requests_on_one_connection :: proc(t: ^testing.T) {
+	h2_test_requests_on_one_connection(t, false)
+}
+
+@(test)
+test_h2_tls_requests_on_one_connection :: proc(t: ^testing.T) {
+	h2_test_requests_on_one_connection(t, true)
+}
+
+@(test)
+test_h2_flow_control_wait_outlasts_header_timeout :: proc(t: ^testing.T) {
+	opts := Default_Server_Opts
+	opts.header_timeout = 200 * time.Millisecond
+
+	s: Test_Server
+	if !h2_test_start(t, &s, opts) do return
+	defer test_server_stop(&s)
+
+	conn, ok := h2_test_dial(t, &s)
+	if !ok do return
+	defer h2_test_close(&conn)
+
+	if !h2_test_request(&conn, 1, "/") {
+		testing.error(t, "could not send the request")
+		return
+	}
+
+	// The server sends what fits in the initial windows, and waits for a WINDOW_UPDATE.
+	received := 0
+	for received < H2_DEFAULT_WINDOW {
+		f, fok := h2_test_read_frame(&conn)
+		if !fok {
+			testing.errorf(t, "the connection closed after %i bytes of the initial window", received)
+			return
+		}
+		if f.type == .Data && f.stream == 1 {
+			received += len(f.payload)
+		}
+	}
+	testing.expect_value(t, received, H2_DEFAULT_WINDOW)
+
+	// A client that is slow to give more window is not subject to the header timeout.
+	time.sleep(3 * opts.header_timeout)
+
+	increment: [4]byte
+	h2_put_u32(increment[:], H2_Test_Body_Size)
+	ok = h2_test_write_frame(&conn, .Window_Update, 0, 0, increment[:])
+	ok &= h2_test_write_frame(&conn, .Window_Update, 0, 1, increment[:])
+	if !ok {
+		testing.error(t, "could not send the window updates")
+		return
+	}
+
+	rest, rok := h2_test_read_body(t, &conn, 1)
+	if !rok do return
+	testing.expect_value(t, received + len(rest), H2_Test_Body_Size)
+}
@@ -1,24 +1,389 @@
 package http
 
 import "core:path/filepath"
+import "core:strings"
+import "core:sync"
 
-Mime_Type :: enum { Plain, Json, Ico, Html }
+Mime_Type :: enum {
+	Plain,
+	Html,
+	Css,
+	Js,
+	Json,
+	Xml,
+	Csv,
+	Markdown,
+	Web_Manifest,
 
+	Ico,
+	Svg,
+	Png,
+	Jpeg,
+	Gif,
+	Webp,
+	Avif,
+	Bmp,
+
+	Woff,
+	Woff2,
+	Ttf,
+	Otf,
+
+	Mp3,
+	Ogg,
+	Wav,
+	Flac,
+	Mp4,
+	Webm,
+
+	Wasm,
+	Pdf,
+	Zip,
+	Gzip,
+	Tar,
+	Octet_Stream,
+}
+
+// Returns the mime type of the file extension of the path, the extension is matched case-insensitively.
+// Unknown extensions and types registered with mime_register give .Plain, see mime_lookup to get those.
 mime_from_extension :: proc(s: string) -> Mime_Type {
-	switch filepath.ext(s) {
-	case ".json": return .Json
-	case ".ico":  return .Ico
-	case ".html": return .Html
-	case:         return .Plain
-	}
+	m, _ := mime_from_extension_known(s)
+	return m
 }
 
+// Returns the content type for the mime type, text types have a charset parameter.
 mime_to_content_type :: proc(m: Mime_Type) -> string {
 	switch m {
-	case .Html:  return "text/html"
-	case .Ico:   return "application/vnd.microsoft.ico"
-	case .Json:  return "application/json"
-	case .Plain: return "text/plain"
-	case:        return "text/plain"
+	case .Plain:        return "text/plain; charset=utf-8"
+	case .Html:         return "text/html; charset=utf-8"
+	case .Css:          return "text/css; charset=utf-8"
+	// RFC 9239: text/javascript is the one to use, application/javascript is obsolete.
+	case .Js:           return "text/javascript; charset=utf-8"
+	// RFC 8259 11: JSON is always UTF-8, it has no charset parameter.
+	case .Json:         return "application/json"
+	case .Xml:          return "text/xml; charset=utf-8"
+	case .Csv:          return "text/csv; charset=utf-8"
+	case .Markdown:     return "text/markdown; charset=utf-8"
+	case .Web_Manifest: return "application/manifest+json"
+
+	case .Ico:          return "image/vnd.microsoft.icon"
+	case .Svg:          return "image/svg+xml"
+	case .Png:          return "image/png"
+	case .Jpeg:         return "image/jpeg"
+	case .Gif:          return "image/gif"
+	case .Webp:         return "image/webp"
+	case .Avif:         return "image/avif"
+	case .Bmp:          return "image/bmp"
+
+	case .Woff:         return "font/woff"
+	case .Woff2:        return "font/woff2"
+	case .Ttf:          return "font/ttf"
+	case .Otf:          return "font/otf"
+
+	case .Mp3:          return "audio/mpeg"
+	case .Ogg:          return "audio/ogg"
+	case .Wav:          return "audio/wav"
+	case .Flac:         return "audio/flac"
+	case .Mp4:          return "video/mp4"
+	case .Webm:         return "video/webm"
+
+	case .Wasm:         return "application/wasm"
+	case .Pdf:          return "application/pdf"
+	case .Zip:          return "application/zip"
+	case .Gzip:         return "application/gzip"
+	case .Tar:          return "application/x-tar"
+	case .Octet_Stream: return "application/octet-stream"
+	case:               return "text/plain; charset=utf-8"
+	}
+}
+
+@(private)
+mime_from_extension_known :: proc(s: string) -> (Mime_Type, bool) {
+	ext_buf: [16]byte
+	ext := filepath.ext(s)
+	if len(ext) > len(ext_buf) do return .Plain, false
+
+	// Lowercased without allocating, extensions of interest are short.
+	for i in 0..<len(ext) {
+		c := ext[i]
+		ext_buf[i] = c + 32 if c >= 'A' && c <= 'Z' else c
+	}
+	ext = string(ext_buf[:len(ext)])
+
+	switch ext {
+	case ".txt", ".text", ".log": return .Plain, true
+	case ".html", ".htm":         return .Html, true
+	case ".css":                  return .Css, true
+	case ".js", ".mjs", ".cjs":   return .Js, true
+	case ".json", ".map":         return .Json, true
+	case ".xml":                  return .Xml, true
+	case ".csv":                  return .Csv, true
+	case ".md", ".markdown":      return .Markdown, true
+	case ".webmanifest":          return .Web_Manifest, true
+
+	case ".ico":                  return .Ico, true
+	case ".svg":                  return .Svg, true
+	case ".png":                  return .Png, true
+	case ".jpg", ".jpeg", ".jfif": return .Jpeg, true
+	case ".gif":                  return .Gif, true
+	case ".webp":                 return .Webp, true
+	case ".avif":                 return .Avif, true
+	case ".bmp":                  return .Bmp, true
+
+	case ".woff":                 return .Woff, true
+	case ".woff2":                return .Woff2, true
+	case ".ttf":                  return .Ttf, true
+	case ".otf":                  return .Otf, true
+
+	case ".mp3":                  return .Mp3, true
+	case ".ogg", ".oga", ".opus": return .Ogg, true
+	case ".wav":                  return .Wav, true
+	case ".flac":                 return .Flac, true
+	case ".mp4", ".m4v":          return .Mp4, true
+	case ".webm":                 return .Webm, true
+
+	case ".wasm":                 return .Wasm, true
+	case ".pdf":                  return .Pdf, true
+	case ".zip":                  return .Zip, true
+	case ".gz":                   return .Gzip, true
+	case ".tar":                  return .Tar, true
+	case ".bin", ".exe":          return .Octet_Stream, true
+	case:                         return .Plain, false
+	}
+}
+
+@(private)
+mime_registry: struct {
+	mu:    sync.RW_Mutex,
+	// Lowercase extension, including the dot, to content type.
+	types: map[string]string,
+}
+
+// Registers the content type for the file extension (including the dot, like ".glb"), for mime_lookup and respond_file.
+// Registered types take precedence over the built-in ones, so they can be overridden too.
+//
+// The strings are copied with the given allocator, registering the same extension again replaces the content type.
+mime_register :: proc(ext: string, content_type: string, allocator := context.allocator) {
+	sync.rw_mutex_lock(&mime_registry.mu)
+	defer sync.rw_mutex_unlock(&mime_registry.mu)
+
+	if mime_registry.types == nil {
+		mime_registry.types = make(map[string]string, 16, allocator)
+	}
+
+	key := strings.to_lower(ext, allocator)
+	if key in mime_registry.types {
+		old_key, old_value := delete_key(&mime_registry.types, key)
+		delete(old_key, allocator)
+		delete(old_value, allocator)
+	}
+
+	mime_registry.types[key] = strings.clone(content_type, allocator)
+}
+
+// Returns the content type for the file extension of the path, from the registered types first, then the built-in ones.
+// Returns false when the extension is not known, the content could be sniffed instead, see mime_sniff.
+mime_lookup :: proc(path: string) -> (content_type: string, ok: bool) {
+	if ext := filepath.ext(path); ext != "" {
+		sync.rw_mutex_shared_lock(&mime_registry.mu)
+		defer sync.rw_mutex_shared_unlock(&mime_registry.mu)
+
+		if len(mime_registry.types) > 0 {
+			lower := strings.to_lower(ext, context.temp_allocator)
+			if registered, has := mime_registry.types[lower]; has {
+				return registered, true
+			}
+		}
+	}
+
+	m := mime_from_extension_known(path) or_return
+	return mime_to_content_type(m), true
+}
+
+// The amount of bytes mime_sniff looks at, more are ignored.
+Mime_Sniff_Length :: 512
+
+// Determines the content type of the data, following the "rules for identifying an unknown MIME type"
+// of the WHATWG MIME Sniffing Standard (https://mimesniff.spec.whatwg.org/#identifying-a-resource-with-an-unknown-mime-type).
+//
+// The content is assumed to come from a trusted source, so HTML and XML are detected (the "sniff-scriptable" flag).
+// When nothing matches, the result is text/plain when the data has no binary bytes, application/octet-stream otherwise.
+mime_sniff :: proc(data: []byte) -> string {
+	data := data[:min(len(data), Mime_Sniff_Length)]
+
+	// Scriptable types, which can have leading whitespace.
+	ws := 0
+	for ws < len(data) && mime_sniff_is_whitespace(data[ws]) do ws += 1
+	if content_type, ok := mime_sniff_scriptable(data[ws:]); ok {
+		return content_type
+	}
+
+	for p in mime_sniff_patterns {
+		if mime_sniff_match(data, p.pattern, p.mask) do return p.content_type
+	}
+
+	switch {
+	case mime_sniff_mp4(data):  return mime_to_content_type(.Mp4)
+	case mime_sniff_webm(data): return mime_to_content_type(.Webm)
+	}
+
+	for b in data {
+		if mime_sniff_is_binary(b) do return mime_to_content_type(.Octet_Stream)
+	}
+	return mime_to_content_type(.Plain)
+}
+
+@(private)
+mime_sniff_scriptable :: proc(data: []byte) -> (string, bool) {
+	for tag in mime_sniff_html_tags {
+		if len(data) <= len(tag) do continue
+		if !strings.equal_fold(string(data[:len(tag)]), tag) do continue
+
+		switch data[len(tag)] {
+		case ' ', '>': return mime_to_content_type(.Html), true
+		}
+	}
+
+	if len(data) >= 5 && string(data[:5]) == "<?xml" {
+		return mime_to_content_type(.Xml), true
+	}
+
+	return "", false
+}
+
+// Matched case-insensitively, and followed by a tag-terminating byte (space or >).
+@(private)
+mime_sniff_html_tags := [?]string{
+	"<!DOCTYPE HTML", "<HTML", "<HEAD", "<SCRIPT", "<IFRAME", "<H1", "<DIV", "<FONT",
+	"<TABLE", "<A", "<STYLE", "<TITLE", "<B", "<BODY", "<BR", "<P", "<!--",
+}
+
+@(private)
+Mime_Sniff_Pattern :: struct {
+	pattern:      string,
+	// Bytes of the data are and-ed with the mask before comparing, an empty mask compares as is.
+	mask:         string,
+	content_type: string,
+}
+
+@(private)
+mime_sniff_patterns := [?]Mime_Sniff_Pattern{
+	{"%PDF-", "", "application/pdf"},
+	{"%!PS-Adobe-", "", "application/postscript"},
+
+	// Byte order marks.
+	{"\xFE\xFF", "", "text/plain; charset=utf-16be"},
+	{"\xFF\xFE", "", "text/plain; charset=utf-16le"},
+	{"\xEF\xBB\xBF", "", "text/plain; charset=utf-8"},
+
+	// Images.
+	{"\x00\x00\x01\x00", "", "image/x-icon"},
+	{"\x00\x00\x02\x00", "", "image/x-icon"},
+	{"BM", "", "image/bmp"},
+	{"GIF87a", "", "image/gif"},
+	{"GIF89a", "", "image/gif"},
+	{"RIFF\x00\x00\x00\x00WEBPVP", "\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF\xFF\xFF", "image/webp"},
+	{"\x89PNG\r\n\x1A\n", "", "image/png"},
+	{"\xFF\xD8\xFF", "", "image/jpeg"},
+
+	// Audio and video, MP4 and WebM are matched by mime_sniff_mp4 and mime_sniff_webm.
+	{"FORM\x00\x00\x00\x00AIFF", "\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF", "audio/aiff"},
+	{"ID3", "", "audio/mpeg"},
+	{"OggS\x00", "", "application/ogg"},
+	{"MThd\x00\x00\x00\x06", "", "audio/midi"},
+	{"RIFF\x00\x00\x00\x00AVI ", "\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF", "video/avi"},
+	{"RIFF\x00\x00\x00\x00WAVE", "\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF", "audio/wave"},
+
+	// Fonts.
+	{
+		"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00LP",
+		"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xFF\xFF",
+		"application/vnd.ms-fontobject",
+	},
+	{"\x00\x01\x00\x00", "", "font/ttf"},
+	{"OTTO", "", "font/otf"},
+	{"ttcf", "", "font/collection"},
+	{"wOFF", "", "font/woff"},
+	{"wOF2", "", "font/woff2"},
+
+	// Archives.
+	{"\x1F\x8B\x08", "", "application/x-gzip"},
+	{"PK\x03\x04", "", "application/zip"},
+	{"Rar!\x1A\x07\x00", "", "application/x-rar-compressed"},
+	{"Rar!\x1A\x07\x01\x00", "", "application/x-rar-compressed"},
+
+	// Not in the standard, but unambiguous.
+	{"\x00asm", "", "application/wasm"},
+}
+
+@(private)
+mime_sniff_match :: proc(data: []byte, pattern, mask: string) -> bool {
+	if len(data) < len(pattern) do return false
+
+	for i in 0..<len(pattern) {
+		b := data[i]
+		if mask != "" do b &= mask[i]
+		if b != pattern[i] do return false
+	}
+	return true
+}
+
+// https://mimesniff.spec.whatwg.org/#signature-for-mp4
+@(private)
+mime_sniff_mp4 :: proc(data: []byte) -> bool {
+	if len(data) < 12 do return false
+
+	box_size := int(data[0]) << 24 | int(data[1]) << 16 | int(data[2]) << 8 | int(data[3])
+	if len(data) < box_size || box_size % 4 != 0 do return false
+
+	if string(data[4:8]) != "ftyp" do return false
+	if string(data[8:11]) == "mp4" do return true
+
+	// The compatible brands.
+	for i := 16; i + 3 <= box_size; i += 4 {
+		if string(data[i:i+3]) == "mp4" do return true
+	}
+	return false
+}
+
+// https://mimesniff.spec.whatwg.org/#signature-for-webm
+@(private)
+mime_sniff_webm :: proc(data: []byte) -> bool {
+	if len(data) < 4 || string(data[:4]) != "\x1A\x45\xDF\xA3" do return false
+
+	// Looks for the DocType element in the EBML header.
+	for i := 4; i + 1 < len(data) && i < 38; i += 1 {
+		if data[i] != 0x42 || data[i+1] != 0x82 do continue
+
+		i += 2
+		if i >= len(data) do return false
+
+		// The element size is a variable size integer, its length is given by the leading zero bits of the first byte.
+		size_len := 1
+		for mask := byte(0x80); size_len <= 8 && data[i] & mask == 0; mask >>= 1 {
+			size_len += 1
+		}
+		if size_len > 8 do return false
+
+		i += size_len
+		return i + 4 <= len(data) && string(data[i:i+4]) == "webm"
+	}
+	return false
+}
+
+@(private)
+mime_sniff_is_whitespace :: proc(b: byte) -> bool {
+	switch b {
+	case '\t', '\n', '\x0C', '\r', ' ': return true
+	case:                               return false
+	}
+}
+
+// https://mimesniff.spec.whatwg.org/#binary-data-byte
+@(private)
+mime_sniff_is_binary :: proc(b: byte) -> bool {
+	switch b {
+	case 0x00..=0x08, 0x0B, 0x0E..=0x1A, 0x1C..=0x1F: return true
+	case:                                             return false
 	}
 }
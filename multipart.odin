@@ -0,0 +1,444 @@
+package http
+
+import "core:bytes"
+import "core:io"
+import "core:math/rand"
+import "core:mem"
+import "core:os"
+import "core:path/filepath"
+import "core:strconv"
+import "core:strings"
+import "core:unicode"
+
+// multipart/form-data (RFC 7578), the encoding browsers use for forms with file uploads.
+//
+// The body is parsed while it is being read, small parts are kept in memory
+// and parts bigger than Multipart_Opts.max_memory are streamed into temporary files.
+
+Multipart_Opts :: struct {
+	// Parts bigger than this are written to a temporary file instead of being kept in memory.
+	// Defaults to 1MB.
+	max_memory:      int,
+	// The maximum size of a single part, -1 means no limit.
+	// Defaults to -1.
+	max_part_size:   int,
+	// The maximum size of the whole body, -1 means no limit.
+	// Defaults to 32MB.
+	max_total_size:  int,
+	// The maximum amount of parts.
+	// Defaults to 1000.
+	max_parts:       int,
+	// The maximum size of the headers of a single part.
+	// Defaults to 8000.
+	max_header_size: int,
+	// The directory temporary files are created in.
+	// Defaults to the TMPDIR (TEMP on Windows) environment variable, or /tmp.
+	temp_dir:        string,
+}
+
+Default_Multipart_Opts :: Multipart_Opts {
+	max_memory      = 1 << 20,
+	max_part_size   = -1,
+	max_total_size  = 32 << 20,
+	max_parts       = 1000,
+	max_header_size = 8000,
+}
+
+Multipart_Part :: struct {
+	headers:      Headers,
+	// The name of the form field, from the Content-Disposition header.
+	name:         string,
+	// The name of the uploaded file, without any directories, nil if the part is not a file.
+	// Empty when the client sent no name, or one that can't be used safely: "." or "..", or with control characters.
+	filename:     Maybe(string),
+	// Defaults to "text/plain" when the part has no Content-Type header.
+	content_type: string,
+	size:         int,
+	// The content of the part when it is kept in memory.
+	data:         []byte,
+	// The path of the temporary file the part was written to, empty when it is kept in memory.
+	// The file is removed by multipart_destroy, move or copy it if you want to keep it.
+	temp_path:    string,
+}
+
+Multipart_Form :: struct {
+	parts: [dynamic]Multipart_Part,
+}
+
+// Parses a multipart/form-data request body.
+//
+// Everything is allocated on the request's allocator, but the temporary files have to be removed by calling multipart_destroy.
+// Errors map to a status using body_error_status, exceeding any of the size limits is a .Too_Long error.
+request_multipart :: proc(req: ^Request, opts := Default_Multipart_Opts) -> (form: Multipart_Form, err: Body_Error) {
+	allocator := req.allocator
+
+	content_type := headers_get(&req.headers, "content-type") or_else ""
+	media := content_type
+	if semi := strings.index_byte(media, ';'); semi > -1 {
+		media = media[:semi]
+	}
+	if !strings.equal_fold(strings.trim_space(media), "multipart/form-data") {
+		return form, .Invalid_Multipart
+	}
+
+	// RFC 2046 5.1.1: boundaries are at most 70 characters.
+	boundary, has_boundary := header_param(content_type, "boundary", allocator)
+	if !has_boundary || len(boundary) == 0 || len(boundary) > 70 {
+		return form, .Invalid_Multipart
+	}
+
+	body := request_body_reader(req, opts.max_total_size) or_return
+
+	form.parts = make([dynamic]Multipart_Part, allocator)
+	defer if err != nil do multipart_destroy(&form)
+
+	mr: Multipart_Reader
+	mr.req = req
+	mr.r = body
+	mr.buf = make([dynamic]byte, 0, 4096, allocator)
+
+	// The first boundary does not have to be preceded by a line break,
+	// adding one makes every delimiter look the same.
+	append(&mr.buf, "\r\n")
+	delimiter := strings.concatenate({"\r\n--", boundary}, allocator)
+
+	// Skip the preamble.
+	for {
+		avail := mr.buf[mr.start:]
+		if i := bytes.index(avail, transmute([]byte)delimiter); i > -1 {
+			mr.start += i + len(delimiter)
+			break
+		}
+
+		mr.start += max(0, len(avail) - len(delimiter) + 1)
+		if !(multipart_fill(&mr) or_return) {
+			return form, .Invalid_Multipart
+		}
+	}
+
+	for {
+		// After a delimiter comes "--" when it is the last one, what follows that (the epilogue) is ignored,
+		// the body can also end right there, the line break after it is optional (RFC 2046 5.1.1).
+		for len(mr.buf) - mr.start < 2 {
+			if !(multipart_fill(&mr) or_return) do break
+		}
+		if strings.has_prefix(string(mr.buf[mr.start:]), "--") do break
+
+		// Otherwise a line break, which can be preceded by whitespace.
+		nl := multipart_find(&mr, "\r\n", 256) or_return
+		rest := strings.trim_right(string(mr.buf[mr.start:][:nl]), " \t")
+		mr.start += nl + 2
+
+		if rest != "" do return form, .Invalid_Multipart
+
+		if len(form.parts) >= opts.max_parts {
+			return form, .Too_Long
+		}
+
+		// Added right away, so a temporary file is cleaned up if something fails halfway.
+		append(&form.parts, Multipart_Part{})
+		part := &form.parts[len(form.parts) - 1]
+		headers_init(&part.headers, allocator)
+
+		header_size := 0
+		for {
+			nl := multipart_find(&mr, "\r\n", opts.max_header_size - header_size) or_return
+			line := string(mr.buf[mr.start:][:nl])
+			mr.start += nl + 2
+			header_size += nl + 2
+
+			if line == "" do break
+
+			// The line is a slice into the buffer, which is reused.
+			if _, ok := header_parse(&part.headers, strings.clone(line, allocator), allocator); !ok {
+				return form, .Invalid_Multipart
+			}
+		}
+
+		disposition := headers_get(&part.headers, "content-disposition") or_else ""
+		if !strings.has_prefix(strings.to_lower(disposition, allocator), "form-data") {
+			return form, .Invalid_Multipart
+		}
+
+		part.name, _ = header_param(disposition, "name", allocator)
+		if filename, has_filename := header_param(disposition, "filename", allocator); has_filename {
+			// Some clients send the full path, it is never safe to use directly.
+			if slash := strings.last_index_any(filename, "/\\"); slash > -1 {
+				filename = filename[slash + 1:]
+			}
+			// Names of directories, and control characters that could end up in paths, logs or headers, can't be used.
+			if filename == "." || filename == ".." || strings.index_proc(filename, unicode.is_control) > -1 {
+				filename = ""
+			}
+			part.filename = filename
+		}
+
+		part.content_type = headers_get(&part.headers, "content-type") or_else "text/plain"
+
+		multipart_read_part(&mr, part, delimiter, opts) or_return
+	}
+
+	return form, nil
+}
+
+// Removes the temporary files of the form.
+multipart_destroy :: proc(form: ^Multipart_Form) {
+	for _, i in form.parts {
+		part := &form.parts[i]
+		if part.temp_path == "" do continue
+
+		os.remove(part.temp_path)
+		part.temp_path = ""
+	}
+}
+
+// Returns the first part with the given field name.
+multipart_part :: proc(form: ^Multipart_Form, name: string) -> (^Multipart_Part, bool) {
+	for _, i in form.parts {
+		if form.parts[i].name == name {
+			return &form.parts[i], true
+		}
+	}
+	return nil, false
+}
+
+// Returns the value of the first field with the given name, fields that are written to a file are not returned.
+multipart_value :: proc(form: ^Multipart_Form, name: string) -> (string, bool) {
+	part := multipart_part(form, name) or_return
+	(part.temp_path == "") or_return
+	return string(part.data), true
+}
+
+@(private)
+Multipart_Reader :: struct {
+	req:   ^Request,
+	r:     io.Reader,
+	buf:   [dynamic]byte,
+	// Everything before start has been consumed.
+	start: int,
+	eof:   bool,
+}
+
+// Reads more of the body into the buffer, returns false when the body has ended.
+@(private)
+multipart_fill :: proc(mr: ^Multipart_Reader) -> (more: bool, err: Body_Error) {
+	if mr.eof do return false, nil
+
+	// Move what is left to the front, instead of growing the buffer.
+	if mr.start > 0 {
+		n := copy(mr.buf[:], mr.buf[mr.start:])
+		resize(&mr.buf, n)
+		mr.start = 0
+	}
+
+	for {
+		prev := len(mr.buf)
+		resize(&mr.buf, prev + 4096)
+
+		n, rerr := io.read(mr.r, mr.buf[prev:])
+		resize(&mr.buf, prev + n)
+
+		switch {
+		case rerr == .EOF:
+			mr.eof = true
+			return n > 0, nil
+		case rerr != nil:
+			if berr := request_body_error(mr.req); berr != nil {
+				return false, berr
+			}
+			return false, .Scan_Failed
+		case n > 0:
+			return true, nil
+		}
+	}
+}
+
+// Returns the index (relative to start) of the needle, reading more when it is not there yet.
+// It is an error when the needle is not within limit bytes.
+@(private)
+multipart_find :: proc(mr: ^Multipart_Reader, needle: string, limit: int) -> (i: int, err: Body_Error) {
+	for {
+		avail := mr.buf[mr.start:]
+		i = bytes.index(avail, transmute([]byte)needle)
+		if i > -1 && i <= limit {
+			return
+		}
+
+		if len(avail) > limit + len(needle) {
+			return -1, .Too_Long
+		}
+
+		if !(multipart_fill(mr) or_return) {
+			return -1, .Invalid_Multipart
+		}
+	}
+}
+
+// Reads the content of a part, up until the next delimiter.
+@(private)
+multipart_read_part :: proc(mr: ^Multipart_Reader, part: ^Multipart_Part, delimiter: string, opts: Multipart_Opts) -> Body_Error {
+	data: bytes.Buffer
+	bytes.buffer_init_allocator(&data, 0, 0, mr.req.allocator)
+
+	file := os.INVALID_HANDLE
+	defer if file != os.INVALID_HANDLE do os.close(file)
+
+	for {
+		avail := mr.buf[mr.start:]
+		if i := bytes.index(avail, transmute([]byte)delimiter); i > -1 {
+			multipart_part_write(part, &data, &file, avail[:i], opts, mr.req.allocator) or_return
+			mr.start += i + len(delimiter)
+			break
+		}
+
+		// Everything but the last bytes, which could be the start of the delimiter.
+		if safe := len(avail) - len(delimiter) + 1; safe > 0 {
+			multipart_part_write(part, &data, &file, avail[:safe], opts, mr.req.allocator) or_return
+			mr.start += safe
+		}
+
+		if !(multipart_fill(mr) or_return) {
+			return .Invalid_Multipart
+		}
+	}
+
+	if file == os.INVALID_HANDLE {
+		part.data = bytes.buffer_to_bytes(&data)
+	}
+
+	return nil
+}
+
+@(private)
+multipart_part_write :: proc(
+	part: ^Multipart_Part,
+	data: ^bytes.Buffer,
+	file: ^os.Handle,
+	b: []byte,
+	opts: Multipart_Opts,
+	allocator: mem.Allocator,
+) -> Body_Error {
+	part.size += len(b)
+	if opts.max_part_size > -1 && part.size > opts.max_part_size {
+		return .Too_Long
+	}
+
+	// Too big to keep in memory, move what we have to a temporary file and continue there.
+	if file^ == os.INVALID_HANDLE && part.size > opts.max_memory {
+		file^, part.temp_path = multipart_temp_file(opts, allocator) or_return
+
+		if _, errno := os.write(file^, bytes.buffer_to_bytes(data)); errno != os.ERROR_NONE {
+			return .Temp_File_Failed
+		}
+		bytes.buffer_destroy(data)
+	}
+
+	if file^ == os.INVALID_HANDLE {
+		bytes.buffer_write(data, b)
+		return nil
+	}
+
+	if _, errno := os.write(file^, b); errno != os.ERROR_NONE {
+		return .Temp_File_Failed
+	}
+
+	return nil
+}
+
+@(private)
+multipart_temp_file :: proc(opts: Multipart_Opts, allocator: mem.Allocator) -> (fd: os.Handle, path: string, err: Body_Error) {
+	dir := opts.temp_dir
+	if dir == "" {
+		when ODIN_OS == .Windows {
+			dir = os.get_env("TEMP", allocator)
+		} else {
+			dir = os.get_env("TMPDIR", allocator)
+		}
+	}
+	if dir == "" {
+		dir = "/tmp"
+	}
+
+	// Random names, retried a couple of times in the very unlikely case that it already exists.
+	for _ in 0 ..< 10 {
+		name_buf: [32]byte
+		name := strconv.append_u64(name_buf[:], rand.uint64(), 16)
+
+		path = filepath.join({dir, strings.concatenate({"odin-http-", name}, allocator)}, allocator)
+
+		errno: os.Errno
+		fd, errno = os.open(path, os.O_WRONLY | os.O_CREATE | os.O_EXCL, 0o600)
+		if errno == os.ERROR_NONE {
+			return
+		}
+	}
+
+	return os.INVALID_HANDLE, "", .Temp_File_Failed
+}
+
+// Returns the value of a parameter in a header value like `form-data; name="file"; filename="a.txt"`,
+// quoted values are unquoted.
+@(private)
+header_param :: proc(value: string, param: string, allocator := context.allocator) -> (string, bool) {
+	rest := value
+
+	// Skip the value before the parameters.
+	semi := strings.index_byte(rest, ';')
+	if semi < 0 do return "", false
+	rest = rest[semi + 1:]
+
+	for len(rest) > 0 {
+		rest = strings.trim_left_space(rest)
+
+		eq := strings.index_byte(rest, '=')
+		if eq < 0 do return "", false
+
+		key := strings.trim_space(rest[:eq])
+		rest = strings.trim_left_space(rest[eq + 1:])
+
+		val: string
+		if len(rest) > 0 && rest[0] == '"' {
+			b := strings.builder_make(0, len(rest), allocator)
+			closed := false
+			i := 1
+			for i < len(rest) {
+				ch := rest[i]
+				if ch == '\\' && i + 1 < len(rest) {
+					strings.write_byte(&b, rest[i + 1])
+					i += 2
+					continue
+				}
+
+				if ch == '"' {
+					closed = true
+					break
+				}
+
+				strings.write_byte(&b, ch)
+				i += 1
+			}
+			if !closed do return "", false
+
+			val = strings.to_string(b)
+			rest = rest[i + 1:]
+
+			semi = strings.index_byte(rest, ';')
+			rest = "" if semi < 0 else rest[semi + 1:]
+		} else {
+			semi = strings.index_byte(rest, ';')
+			if semi < 0 {
+				val = strings.trim_space(rest)
+				rest = ""
+			} else {
+				val = strings.trim_space(rest[:semi])
+				rest = rest[semi + 1:]
+			}
+		}
+
+		if strings.equal_fold(key, param) {
+			return val, true
+		}
+	}
+
+	return "", false
+}
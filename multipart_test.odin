@@ -0,0 +1,205 @@
+package http
+
+import "core:fmt"
+import "core:os"
+import "core:strings"
+import "core:testing"
+import "core:time"
+
+@(private)
+Multipart_Test_Boundary :: "odin-http-boundary"
+
+// Starts a server that parses multipart bodies, it responds with a line per part: the field name, the quoted filename
+// (or "-" when it is not a file), the size, whether the part was kept in "memory" or written to a "file", and the content.
+// Parts bigger than 16 bytes are written to a file, "/part-limit" allows parts of 32 bytes and "/total-limit" bodies of 128 bytes.
+@(private)
+multipart_test_start :: proc(t: ^testing.T, s: ^Test_Server) -> bool {
+	h := handler(proc(req: ^Request, res: ^Response) {
+		opts := Default_Multipart_Opts
+		opts.max_memory = 16
+		switch req.url.path {
+		case "/part-limit":  opts.max_part_size = 32
+		case "/total-limit": opts.max_total_size = 128
+		}
+
+		form, err := request_multipart(req, opts)
+		if err != nil {
+			respond_plain(res, fmt.tprint(err))
+			res.status = body_error_status(err)
+			return
+		}
+		defer multipart_destroy(&form)
+
+		b := strings.builder_make(context.temp_allocator)
+		for part in form.parts {
+			filename := "-"
+			if name, is_file := part.filename.?; is_file {
+				filename = fmt.tprintf("%q", name)
+			}
+
+			storage, content := "memory", string(part.data)
+			if part.temp_path != "" {
+				data, _ := os.read_entire_file(part.temp_path, context.temp_allocator)
+				storage, content = "file", string(data)
+			}
+
+			fmt.sbprintf(&b, "%s %s %i %s %s\n", part.name, filename, part.size, storage, content)
+		}
+		respond_plain(res, strings.to_string(b))
+	})
+	return test_server_start(t, s, h)
+}
+
+// A part with a form field.
+@(private)
+multipart_test_field :: proc(name, value: string) -> string {
+	return fmt.tprintf("Content-Disposition: form-data; name=\"%s\"\r\n\r\n%s", name, value)
+}
+
+// A part with an uploaded file.
+@(private)
+multipart_test_file :: proc(name, filename, content: string) -> string {
+	return fmt.tprintf(
+		"Content-Disposition: form-data; name=\"%s\"; filename=\"%s\"\r\nContent-Type: application/octet-stream\r\n\r\n%s",
+		name,
+		filename,
+		content,
+	)
+}
+
+// Puts the parts between delimiters, with a preamble and an epilogue that are ignored.
+@(private)
+multipart_test_body :: proc(parts: ..string) -> string {
+	b := strings.builder_make(context.temp_allocator)
+	strings.write_string(&b, "ignored preamble\r\n")
+	for part in parts {
+		strings.write_string(&b, "--" + Multipart_Test_Boundary + "\r\n")
+		strings.write_string(&b, part)
+		strings.write_string(&b, "\r\n")
+	}
+	strings.write_string(&b, "--" + Multipart_Test_Boundary + "--\r\nignored epilogue")
+	return strings.to_string(b)
+}
+
+// Sends the body in chunks of the given size, so the server gets delimiters and headers split across reads.
+@(private)
+multipart_test_post :: proc(t: ^testing.T, s: ^Test_Server, path, body: string, chunk: int) -> (response: string, ok: bool) {
+	chunks := make([dynamic]string, context.temp_allocator)
+	for rest := body; len(rest) > 0; {
+		n := min(chunk, len(rest))
+		append(&chunks, rest[:n])
+		rest = rest[n:]
+	}
+
+	pause := 2 * time.Millisecond if len(chunks) > 1 else 0
+	return server_test_post(t, s, len(body), chunks[:], pause, path, "multipart/form-data; boundary=" + Multipart_Test_Boundary)
+}
+
+@(test)
+test_multipart_split_across_reads :: proc(t: ^testing.T) {
+	s: Test_Server
+	if !multipart_test_start(t, &s) do return
+	defer test_server_stop(&s)
+
+	// Looks like a delimiter, but is not one.
+	upload := "almost\r\n--odin-http-\r\n--odin-http-boundarx"
+
+	body := multipart_test_body(
+		multipart_test_field("field", "value"),
+		multipart_test_file("upload", "upload.txt", upload),
+		multipart_test_file("small", "small.txt", "tiny"),
+		multipart_test_field("empty", ""),
+	)
+
+	expected := fmt.tprintf(
+		"field - 5 memory value\nupload \"upload.txt\" %i file %s\nsmall \"small.txt\" 4 memory tiny\nempty - 0 memory \n",
+		len(upload),
+		upload,
+	)
+
+	for chunk in ([]int{len(body), 1, 5, 7}) {
+		response, ok := multipart_test_post(t, &s, "/", body, chunk)
+		if !ok do return
+
+		testing.expectf(t, strings.has_prefix(response, "HTTP/1.1 200"), "chunks of %i: expected 200 OK, got %q", chunk, response)
+		testing.expectf(t, strings.has_suffix(response, expected), "chunks of %i: expected %q, got %q", chunk, expected, response)
+	}
+}
+
+@(test)
+test_multipart_limits :: proc(t: ^testing.T) {
+	s: Test_Server
+	if !multipart_test_start(t, &s) do return
+	defer test_server_stop(&s)
+
+	Case :: struct {
+		path:   string,
+		body:   string,
+		status: string,
+	}
+
+	cases := []Case{
+		// Exactly at the limit of a part, and over it, in memory and in a file.
+		{"/part-limit", multipart_test_body(multipart_test_file("f", "f.txt", strings.repeat("a", 32, context.temp_allocator))), "200"},
+		{"/part-limit", multipart_test_body(multipart_test_file("f", "f.txt", strings.repeat("a", 33, context.temp_allocator))), "413"},
+		{"/part-limit", multipart_test_body(multipart_test_field("f", "a"), multipart_test_field("g", strings.repeat("a", 64, context.temp_allocator))), "413"},
+		// The limit of the whole body, every part is small.
+		{"/total-limit", multipart_test_body(multipart_test_field("f", "a")), "200"},
+		{"/total-limit", multipart_test_body(multipart_test_field("f", "a"), multipart_test_field("g", "b"), multipart_test_field("h", "c")), "413"},
+		// Malformed bodies.
+		{"/", "no delimiters at all", "400"},
+		{"/", "--" + Multipart_Test_Boundary + "\r\nContent-Disposition: form-data; name=\"f\"\r\n\r\nnever ends", "400"},
+		{"/", "--" + Multipart_Test_Boundary + "\r\nContent-Type: text/plain\r\n\r\nno disposition\r\n--" + Multipart_Test_Boundary + "--", "400"},
+	}
+
+	for c in cases {
+		response, ok := multipart_test_post(t, &s, c.path, c.body, len(c.body))
+		if !ok do return
+
+		status := fmt.tprintf("HTTP/1.1 %s", c.status)
+		testing.expectf(t, strings.has_prefix(response, status), "%s %q: expected %s, got %q", c.path, c.body, c.status, response)
+	}
+
+	// The error is the one that maps to the status.
+	response, ok := multipart_test_post(t, &s, "/part-limit", cases[1].body, len(cases[1].body))
+	if !ok do return
+	testing.expect(t, strings.has_suffix(response, "Too_Long"), "a part over the limit is a .Too_Long error")
+
+	response, ok = multipart_test_post(t, &s, "/", cases[5].body, len(cases[5].body))
+	if !ok do return
+	testing.expect(t, strings.has_suffix(response, "Invalid_Multipart"), "a malformed body is an .Invalid_Multipart error")
+}
+
+@(test)
+test_multipart_filenames :: proc(t: ^testing.T) {
+	s: Test_Server
+	if !multipart_test_start(t, &s) do return
+	defer test_server_stop(&s)
+
+	Case :: struct {
+		filename: string,
+		expected: string,
+	}
+
+	cases := []Case{
+		{"report.pdf", "report.pdf"},
+		{"../../etc/passwd", "passwd"},
+		{"/home/user/report.pdf", "report.pdf"},
+		{"dir/..", ""},
+		{"..", ""},
+		{".", ""},
+		{"a\tb.txt", ""},
+		{"a\x01b.txt", ""},
+		{"a\x1bb.txt", ""},
+		{"", ""},
+	}
+
+	for c in cases {
+		body := multipart_test_body(multipart_test_file("f", c.filename, "x"))
+		response, ok := multipart_test_post(t, &s, "/", body, len(body))
+		if !ok do return
+
+		expected := fmt.tprintf("f %q 1 memory x\n", c.expected)
+		testing.expectf(t, strings.has_suffix(response, expected), "%q: expected %q, got %q", c.filename, expected, response)
+	}
+}
@@ -0,0 +1,65 @@
+// package nbio implements a minimal readiness based event loop for sockets.
+//
+// It is used by the server to wait on a large amount of (mostly idle) connections with a single thread,
+// handing them off to worker threads once there is data to read.
+//
+// Backends:
+// - Linux: io_uring (when the kernel supports it), falling back to epoll.
+// - Others: poll.
+package nbio
+
+import "core:net"
+import "core:time"
+
+Error :: enum {
+	None,
+	// The backend could not be set up, the platform or kernel does not support it.
+	Unsupported,
+	// Setting up the backend failed.
+	Init_Failed,
+	// Registering interest in a socket failed.
+	Watch_Failed,
+	// Waiting for events failed.
+	Wait_Failed,
+}
+
+// The event loop, fields are backend specific and should not be touched.
+Poller :: struct {
+	using _impl: _Poller,
+}
+
+// Initializes the poller, picking the best backend that is available.
+init :: proc(p: ^Poller, allocator := context.allocator) -> Error {
+	return _init(p, allocator)
+}
+
+// Frees the resources of the poller, the sockets that were watched are not closed.
+destroy :: proc(p: ^Poller) {
+	_destroy(p)
+}
+
+// Registers one-shot interest in the socket becoming readable (or closed by the peer).
+//
+// After the socket has been returned by `wait`, it will not be returned again until it is re-armed
+// by calling `watch` again. This makes it safe to hand the socket off to another thread.
+//
+// Can be called from any thread, also while another thread is blocked in `wait`.
+watch :: proc(p: ^Poller, socket: net.TCP_Socket) -> Error {
+	return _watch(p, socket)
+}
+
+// Removes the interest registered by `watch`, if there is any, events for the socket that are pending are dropped.
+//
+// Call this before closing a socket that might still be watched. The io_uring backend holds a reference to the socket
+// until its poll is removed, the socket would stay open, and its event could be reported for a new socket that got the same number.
+unwatch :: proc(p: ^Poller, socket: net.TCP_Socket) -> Error {
+	return _unwatch(p, socket)
+}
+
+// Blocks until at least one watched socket is readable, or the timeout expires.
+// The readable sockets are written into `ready`, and the amount is returned as `n`.
+//
+// A negative timeout waits indefinitely.
+wait :: proc(p: ^Poller, ready: []net.TCP_Socket, timeout: time.Duration) -> (n: int, err: Error) {
+	return _wait(p, ready, timeout)
+}
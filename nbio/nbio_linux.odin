@@ -0,0 +1,467 @@
+//+private
+package nbio
+
+import "core:c"
+import "core:intrinsics"
+import "core:log"
+import "core:mem"
+import "core:net"
+import "core:os"
+import "core:sync"
+import "core:time"
+
+foreign import libc "system:c"
+
+Backend :: enum {
+	IO_Uring,
+	Epoll,
+}
+
+_Poller :: struct {
+	backend:   Backend,
+	allocator: mem.Allocator,
+
+	// Epoll backend.
+	epoll_fd:  c.int,
+	events:    []Epoll_Event,
+
+	// IO_Uring backend.
+	ring:      IO_Uring,
+}
+
+_init :: proc(p: ^Poller, allocator := context.allocator) -> Error {
+	p.allocator = allocator
+
+	if err := ring_init(&p.ring, allocator); err == nil {
+		log.debug("nbio: using io_uring backend")
+		p.backend = .IO_Uring
+		return nil
+	}
+
+	p.epoll_fd = epoll_create1(EPOLL_CLOEXEC)
+	if p.epoll_fd < 0 {
+		log.errorf("nbio: epoll_create1 failed with errno %i", os.get_last_error())
+		return .Init_Failed
+	}
+
+	p.events = make([]Epoll_Event, MAX_EVENTS, allocator)
+
+	log.debug("nbio: using epoll backend")
+	p.backend = .Epoll
+	return nil
+}
+
+_destroy :: proc(p: ^Poller) {
+	switch p.backend {
+	case .IO_Uring:
+		ring_destroy(&p.ring)
+	case .Epoll:
+		close(p.epoll_fd)
+		delete(p.events, p.allocator)
+	}
+}
+
+_watch :: proc(p: ^Poller, socket: net.TCP_Socket) -> Error {
+	switch p.backend {
+	case .IO_Uring:
+		return ring_watch(&p.ring, socket)
+	case .Epoll:
+		ev := Epoll_Event {
+			events = EPOLLIN | EPOLLRDHUP | EPOLLONESHOT,
+			data   = u64(socket),
+		}
+
+		// A one-shot registration stays in the set after firing, so re-arm with a modify,
+		// and only add the socket when it was never added (or has been closed since).
+		if epoll_ctl(p.epoll_fd, EPOLL_CTL_MOD, c.int(socket), &ev) == 0 {
+			return nil
+		}
+
+		if errno := os.get_last_error(); errno != ENOENT {
+			log.errorf("nbio: epoll_ctl modify failed with errno %i", errno)
+			return .Watch_Failed
+		}
+
+		if epoll_ctl(p.epoll_fd, EPOLL_CTL_ADD, c.int(socket), &ev) != 0 {
+			log.errorf("nbio: epoll_ctl add failed with errno %i", os.get_last_error())
+			return .Watch_Failed
+		}
+
+		return nil
+	case:
+		panic("unreachable")
+	}
+}
+
+_unwatch :: proc(p: ^Poller, socket: net.TCP_Socket) -> Error {
+	switch p.backend {
+	case .IO_Uring:
+		return ring_unwatch(&p.ring, socket)
+	case .Epoll:
+		// Fails when the socket is not in the set, which is fine, it is not watched then.
+		epoll_ctl(p.epoll_fd, EPOLL_CTL_DEL, c.int(socket), nil)
+		return nil
+	case:
+		panic("unreachable")
+	}
+}
+
+_wait :: proc(p: ^Poller, ready: []net.TCP_Socket, timeout: time.Duration) -> (n: int, err: Error) {
+	switch p.backend {
+	case .IO_Uring:
+		return ring_wait(&p.ring, ready, timeout)
+	case .Epoll:
+		max := min(len(ready), len(p.events))
+		timeout_ms := c.int(-1) if timeout < 0 else c.int(timeout / time.Millisecond)
+
+		ret := epoll_wait(p.epoll_fd, raw_data(p.events), c.int(max), timeout_ms)
+		if ret < 0 {
+			if errno := os.get_last_error(); errno != EINTR {
+				log.errorf("nbio: epoll_wait failed with errno %i", errno)
+				return 0, .Wait_Failed
+			}
+			return 0, nil
+		}
+
+		for ev, i in p.events[:ret] {
+			ready[i] = net.TCP_Socket(ev.data)
+		}
+		return int(ret), nil
+	case:
+		panic("unreachable")
+	}
+}
+
+// The maximum amount of events retrieved in one call to wait.
+MAX_EVENTS :: 256
+
+// The amount of submission queue entries, we submit every entry right away so this doesn't need to be big.
+RING_ENTRIES :: 256
+
+ENOENT :: 2
+EINTR  :: 4
+ETIME  :: 62
+
+EPOLL_CLOEXEC :: 0o2000000
+EPOLL_CTL_ADD :: 1
+EPOLL_CTL_DEL :: 2
+EPOLL_CTL_MOD :: 3
+EPOLLIN       :: 0x001
+EPOLLRDHUP    :: 0x2000
+EPOLLONESHOT  :: 1 << 30
+
+// The kernel packs this struct on x86_64 only.
+when ODIN_ARCH == .amd64 {
+	Epoll_Event :: struct #packed {
+		events: u32,
+		data:   u64,
+	}
+} else {
+	Epoll_Event :: struct {
+		events: u32,
+		data:   u64,
+	}
+}
+
+PROT_READ     :: 0x1
+PROT_WRITE    :: 0x2
+MAP_SHARED    :: 0x01
+MAP_POPULATE  :: 0x8000
+MAP_FAILED    :: ~uintptr(0)
+
+@(default_calling_convention = "c")
+foreign libc {
+	epoll_create1 :: proc(flags: c.int) -> c.int ---
+	epoll_ctl     :: proc(epfd: c.int, op: c.int, fd: c.int, event: ^Epoll_Event) -> c.int ---
+	epoll_wait    :: proc(epfd: c.int, events: [^]Epoll_Event, maxevents: c.int, timeout: c.int) -> c.int ---
+	mmap          :: proc(addr: rawptr, length: c.size_t, prot: c.int, flags: c.int, fd: c.int, offset: i64) -> rawptr ---
+	munmap        :: proc(addr: rawptr, length: c.size_t) -> c.int ---
+	close         :: proc(fd: c.int) -> c.int ---
+}
+
+// These syscall numbers are the same on every architecture we support.
+SYS_io_uring_setup :: 425
+SYS_io_uring_enter :: 426
+
+IORING_OFF_SQ_RING :: 0
+IORING_OFF_SQES    :: 0x10000000
+
+IORING_FEAT_SINGLE_MMAP :: 1 << 0
+IORING_FEAT_NODROP      :: 1 << 1
+IORING_FEAT_EXT_ARG     :: 1 << 8
+
+IORING_ENTER_GETEVENTS :: 1 << 0
+IORING_ENTER_EXT_ARG   :: 1 << 3
+
+IORING_OP_POLL_ADD    :: 6
+IORING_OP_POLL_REMOVE :: 7
+
+// The user_data of submissions whose completions are not reported, like poll removals.
+RING_INTERNAL :: ~u64(0)
+
+POLLIN    :: 0x0001
+POLLRDHUP :: 0x2000
+
+IO_SQRing_Offsets :: struct {
+	head:         u32,
+	tail:         u32,
+	ring_mask:    u32,
+	ring_entries: u32,
+	flags:        u32,
+	dropped:      u32,
+	array:        u32,
+	resv1:        u32,
+	user_addr:    u64,
+}
+
+IO_CQRing_Offsets :: struct {
+	head:         u32,
+	tail:         u32,
+	ring_mask:    u32,
+	ring_entries: u32,
+	overflow:     u32,
+	cqes:         u32,
+	flags:        u32,
+	resv1:        u32,
+	user_addr:    u64,
+}
+
+IO_Uring_Params :: struct {
+	sq_entries:     u32,
+	cq_entries:     u32,
+	flags:          u32,
+	sq_thread_cpu:  u32,
+	sq_thread_idle: u32,
+	features:       u32,
+	wq_fd:          u32,
+	resv:           [3]u32,
+	sq_off:         IO_SQRing_Offsets,
+	cq_off:         IO_CQRing_Offsets,
+}
+#assert(size_of(IO_Uring_Params) == 120)
+
+IO_Uring_SQE :: struct {
+	opcode:        u8,
+	flags:         u8,
+	ioprio:        u16,
+	fd:            i32,
+	off:           u64,
+	addr:          u64,
+	len:           u32,
+	poll32_events: u32,
+	user_data:     u64,
+	buf_index:     u16,
+	personality:   u16,
+	splice_fd_in:  i32,
+	addr3:         u64,
+	_pad2:         u64,
+}
+#assert(size_of(IO_Uring_SQE) == 64)
+
+IO_Uring_CQE :: struct {
+	user_data: u64,
+	res:       i32,
+	flags:     u32,
+}
+#assert(size_of(IO_Uring_CQE) == 16)
+
+Kernel_Timespec :: struct {
+	sec:  i64,
+	nsec: i64,
+}
+
+IO_Uring_Getevents_Arg :: struct {
+	sigmask:    u64,
+	sigmask_sz: u32,
+	pad:        u32,
+	ts:         u64,
+}
+
+IO_Uring :: struct {
+	fd:         c.int,
+	// Guards the submission queue, watch can be called from multiple threads.
+	mu:         sync.Mutex,
+
+	ring:       rawptr,
+	ring_sz:    uint,
+	sqes:       [^]IO_Uring_SQE,
+	sqes_sz:    uint,
+
+	sq_head:    ^u32,
+	sq_tail:    ^u32,
+	sq_mask:    u32,
+	sq_entries: u32,
+	sq_array:   [^]u32,
+
+	cq_head:    ^u32,
+	cq_tail:    ^u32,
+	cq_mask:    u32,
+	cqes:       [^]IO_Uring_CQE,
+
+	// The generation of each socket, bumped by unwatch, guarded by mu.
+	// The user_data of a poll holds the socket and its generation, so the completion of a poll that was removed,
+	// or completed while being removed, is dropped instead of being reported for a new socket with the same number.
+	gens:       map[net.TCP_Socket]u32,
+}
+
+// Sets up an io_uring, returns .Unsupported if the kernel is missing it or features we need.
+ring_init :: proc(r: ^IO_Uring, allocator := context.allocator) -> Error {
+	params: IO_Uring_Params
+	fd := int(intrinsics.syscall(SYS_io_uring_setup, uintptr(RING_ENTRIES), uintptr(&params)))
+	if fd < 0 {
+		log.debugf("nbio: io_uring_setup failed with errno %i", -fd)
+		return .Unsupported
+	}
+	r.fd = c.int(fd)
+
+	// Single mmap is 5.4+, extended args (wait with a timeout) are 5.11+.
+	required :: IORING_FEAT_SINGLE_MMAP | IORING_FEAT_NODROP | IORING_FEAT_EXT_ARG
+	if params.features & required != required {
+		log.debugf("nbio: io_uring is missing required features, got %b", params.features)
+		close(r.fd)
+		return .Unsupported
+	}
+
+	sq_sz := uint(params.sq_off.array) + uint(params.sq_entries) * size_of(u32)
+	cq_sz := uint(params.cq_off.cqes) + uint(params.cq_entries) * size_of(IO_Uring_CQE)
+	r.ring_sz = max(sq_sz, cq_sz)
+
+	r.ring = mmap(nil, r.ring_sz, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_POPULATE, r.fd, IORING_OFF_SQ_RING)
+	if uintptr(r.ring) == MAP_FAILED {
+		log.errorf("nbio: mmap of io_uring rings failed with errno %i", os.get_last_error())
+		close(r.fd)
+		return .Init_Failed
+	}
+
+	r.sqes_sz = uint(params.sq_entries) * size_of(IO_Uring_SQE)
+	sqes := mmap(nil, r.sqes_sz, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_POPULATE, r.fd, IORING_OFF_SQES)
+	if uintptr(sqes) == MAP_FAILED {
+		log.errorf("nbio: mmap of io_uring submission entries failed with errno %i", os.get_last_error())
+		munmap(r.ring, r.ring_sz)
+		close(r.fd)
+		return .Init_Failed
+	}
+	r.sqes = ([^]IO_Uring_SQE)(sqes)
+
+	base := uintptr(r.ring)
+	r.sq_head    = (^u32)(base + uintptr(params.sq_off.head))
+	r.sq_tail    = (^u32)(base + uintptr(params.sq_off.tail))
+	r.sq_mask    = (^u32)(base + uintptr(params.sq_off.ring_mask))^
+	r.sq_entries = params.sq_entries
+	r.sq_array   = ([^]u32)(base + uintptr(params.sq_off.array))
+
+	r.cq_head = (^u32)(base + uintptr(params.cq_off.head))
+	r.cq_tail = (^u32)(base + uintptr(params.cq_off.tail))
+	r.cq_mask = (^u32)(base + uintptr(params.cq_off.ring_mask))^
+	r.cqes    = ([^]IO_Uring_CQE)(base + uintptr(params.cq_off.cqes))
+
+	r.gens = make(map[net.TCP_Socket]u32, 64, allocator)
+	return nil
+}
+
+ring_destroy :: proc(r: ^IO_Uring) {
+	munmap(r.sqes, r.sqes_sz)
+	munmap(r.ring, r.ring_sz)
+	close(r.fd)
+	delete(r.gens)
+}
+
+// Submits a one-shot poll for readability of the socket.
+ring_watch :: proc(r: ^IO_Uring, socket: net.TCP_Socket) -> Error {
+	sync.guard(&r.mu)
+
+	return ring_submit(r, {
+		opcode        = IORING_OP_POLL_ADD,
+		fd            = i32(socket),
+		poll32_events = POLLIN | POLLRDHUP,
+		user_data     = ring_user_data(socket, r.gens[socket]),
+	})
+}
+
+// Removes the poll of the socket, which releases the reference the ring holds to it.
+ring_unwatch :: proc(r: ^IO_Uring, socket: net.TCP_Socket) -> Error {
+	sync.guard(&r.mu)
+
+	gen := r.gens[socket]
+	r.gens[socket] = gen + 1
+
+	// When there is no poll, or it completed already, this fails with ENOENT, which is not reported.
+	return ring_submit(r, {
+		opcode    = IORING_OP_POLL_REMOVE,
+		addr      = ring_user_data(socket, gen),
+		user_data = RING_INTERNAL,
+	})
+}
+
+ring_user_data :: proc(socket: net.TCP_Socket, gen: u32) -> u64 {
+	return u64(gen) << 32 | u64(u32(socket))
+}
+
+// Queues the entry and submits it, the caller holds the lock.
+ring_submit :: proc(r: ^IO_Uring, entry: IO_Uring_SQE) -> Error {
+	head := sync.atomic_load_explicit(r.sq_head, .Acquire)
+	tail := r.sq_tail^
+	if tail - head >= r.sq_entries {
+		log.error("nbio: io_uring submission queue is full")
+		return .Watch_Failed
+	}
+
+	idx := tail & r.sq_mask
+	r.sqes[idx] = entry
+
+	r.sq_array[idx] = idx
+	sync.atomic_store_explicit(r.sq_tail, tail + 1, .Release)
+
+	ret := int(intrinsics.syscall(SYS_io_uring_enter, uintptr(r.fd), 1, 0, 0, 0, 0))
+	if ret < 0 {
+		log.errorf("nbio: io_uring_enter submit failed with errno %i", -ret)
+		return .Watch_Failed
+	}
+
+	return nil
+}
+
+ring_wait :: proc(r: ^IO_Uring, ready: []net.TCP_Socket, timeout: time.Duration) -> (n: int, err: Error) {
+	ts := Kernel_Timespec {
+		sec  = i64(timeout / time.Second),
+		nsec = i64(timeout % time.Second),
+	}
+
+	arg: IO_Uring_Getevents_Arg
+	if timeout >= 0 {
+		arg.ts = u64(uintptr(&ts))
+	}
+
+	flags :: IORING_ENTER_GETEVENTS | IORING_ENTER_EXT_ARG
+	ret := int(intrinsics.syscall(SYS_io_uring_enter, uintptr(r.fd), 0, 1, flags, uintptr(&arg), size_of(arg)))
+	if ret < 0 && -ret != ETIME && -ret != EINTR {
+		log.errorf("nbio: io_uring_enter wait failed with errno %i", -ret)
+		return 0, .Wait_Failed
+	}
+
+	sync.guard(&r.mu)
+
+	head := r.cq_head^
+	tail := sync.atomic_load_explicit(r.cq_tail, .Acquire)
+	for head != tail && n < len(ready) {
+		cqe := &r.cqes[head & r.cq_mask]
+		head += 1
+
+		if cqe.user_data == RING_INTERNAL do continue
+
+		// The poll has been removed by unwatch, the socket may be closed and its number in use by another one.
+		socket := net.TCP_Socket(u32(cqe.user_data))
+		if u32(cqe.user_data >> 32) != r.gens[socket] do continue
+
+		// A failed poll is still reported, reading the socket will surface the error to the caller.
+		if cqe.res < 0 {
+			log.debugf("nbio: poll on socket %i failed with errno %i", socket, -cqe.res)
+		}
+
+		ready[n] = socket
+		n += 1
+	}
+	sync.atomic_store_explicit(r.cq_head, head, .Release)
+
+	return
+}
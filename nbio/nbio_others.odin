@@ -0,0 +1,133 @@
+//+build !linux
+//+private
+package nbio
+
+import "core:c"
+import "core:log"
+import "core:mem"
+import "core:net"
+import "core:sync"
+import "core:time"
+
+when ODIN_OS == .Windows {
+	foreign import lib "system:Ws2_32.lib"
+
+	Poll_Fd :: struct {
+		fd:      uintptr,
+		events:  c.short,
+		revents: c.short,
+	}
+
+	POLLIN :: 0x0100 | 0x0200 // POLLRDNORM | POLLRDBAND
+
+	@(default_calling_convention = "stdcall")
+	foreign lib {
+		@(link_name = "WSAPoll")
+		poll :: proc(fds: [^]Poll_Fd, nfds: c.ulong, timeout: c.int) -> c.int ---
+	}
+} else {
+	when ODIN_OS == .Darwin {
+		foreign import lib "system:System.framework"
+	} else {
+		foreign import lib "system:c"
+	}
+
+	Poll_Fd :: struct {
+		fd:      c.int,
+		events:  c.short,
+		revents: c.short,
+	}
+
+	POLLIN :: 0x0001
+
+	@(default_calling_convention = "c")
+	foreign lib {
+		poll :: proc(fds: [^]Poll_Fd, nfds: c.uint, timeout: c.int) -> c.int ---
+	}
+}
+
+// poll has no way of adding sockets while blocked, so we cap the time we block,
+// sockets watched while waiting are picked up by the next call.
+MAX_POLL_WAIT :: 10 * time.Millisecond
+
+_Poller :: struct {
+	allocator: mem.Allocator,
+	mu:        sync.Mutex,
+	watching:  [dynamic]net.TCP_Socket,
+	fds:       [dynamic]Poll_Fd,
+}
+
+_init :: proc(p: ^Poller, allocator := context.allocator) -> Error {
+	p.allocator = allocator
+	p.watching = make([dynamic]net.TCP_Socket, allocator)
+	p.fds = make([dynamic]Poll_Fd, allocator)
+	log.debug("nbio: using poll backend")
+	return nil
+}
+
+_destroy :: proc(p: ^Poller) {
+	delete(p.watching)
+	delete(p.fds)
+}
+
+_watch :: proc(p: ^Poller, socket: net.TCP_Socket) -> Error {
+	sync.guard(&p.mu)
+	append(&p.watching, socket)
+	return nil
+}
+
+_unwatch :: proc(p: ^Poller, socket: net.TCP_Socket) -> Error {
+	sync.guard(&p.mu)
+	for w, i in p.watching {
+		if w == socket {
+			unordered_remove(&p.watching, i)
+			break
+		}
+	}
+	return nil
+}
+
+_wait :: proc(p: ^Poller, ready: []net.TCP_Socket, timeout: time.Duration) -> (n: int, err: Error) {
+	timeout := timeout
+	if timeout < 0 || timeout > MAX_POLL_WAIT {
+		timeout = MAX_POLL_WAIT
+	}
+
+	clear(&p.fds)
+	{
+		sync.guard(&p.mu)
+		for sock in p.watching {
+			append(&p.fds, Poll_Fd{fd = auto_cast sock, events = POLLIN})
+		}
+	}
+
+	if len(p.fds) == 0 {
+		time.sleep(timeout)
+		return
+	}
+
+	ret := poll(raw_data(p.fds), auto_cast len(p.fds), c.int(timeout / time.Millisecond))
+	if ret < 0 {
+		log.error("nbio: poll failed")
+		return 0, .Wait_Failed
+	}
+
+	sync.guard(&p.mu)
+	for fd in p.fds {
+		if fd.revents == 0 || n >= len(ready) do continue
+
+		// One-shot, remove it from the watched sockets.
+		// A socket that is not watched anymore was unwatched while polling, the event is dropped.
+		sock := net.TCP_Socket(fd.fd)
+		for w, i in p.watching {
+			if w == sock {
+				unordered_remove(&p.watching, i)
+				ready[n] = sock
+				n += 1
+				break
+			}
+		}
+	}
+
+	return
+}
@@ -22,16 +22,125 @@ when ODIN_OS == .Darwin {
 SSL_METHOD :: struct {}
 SSL_CTX :: struct {}
 SSL :: struct {}
+X509_VERIFY_PARAM :: struct {}
+X509_STORE_CTX :: struct {}
+
+SSL_FILETYPE_PEM :: 1
+
+SSL_VERIFY_NONE :: 0x00
+SSL_VERIFY_PEER :: 0x01
+
+TLS1_VERSION   :: 0x0301
+TLS1_1_VERSION :: 0x0302
+TLS1_2_VERSION :: 0x0303
+TLS1_3_VERSION :: 0x0304
+
+X509_V_OK                                    :: 0
+X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT         :: 2
+X509_V_ERR_CERT_NOT_YET_VALID                :: 9
+X509_V_ERR_CERT_HAS_EXPIRED                  :: 10
+X509_V_ERR_DEPTH_ZERO_SELF_SIGNED_CERT       :: 18
+X509_V_ERR_SELF_SIGNED_CERT_IN_CHAIN         :: 19
+X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT_LOCALLY :: 20
+X509_V_ERR_UNABLE_TO_VERIFY_LEAF_SIGNATURE   :: 21
+X509_V_ERR_CERT_REVOKED                      :: 23
+X509_V_ERR_CERT_UNTRUSTED                    :: 27
+X509_V_ERR_HOSTNAME_MISMATCH                 :: 62
+X509_V_ERR_IP_ADDRESS_MISMATCH               :: 64
+
+SSL_R_NO_PROTOCOLS_AVAILABLE          :: 191
+SSL_R_UNSUPPORTED_PROTOCOL            :: 258
+SSL_R_TLSV1_ALERT_PROTOCOL_VERSION    :: 1070
+
+SSL_ERROR_NONE        :: 0
+SSL_ERROR_SSL         :: 1
+SSL_ERROR_WANT_READ   :: 2
+SSL_ERROR_WANT_WRITE  :: 3
+SSL_ERROR_SYSCALL     :: 5
+SSL_ERROR_ZERO_RETURN :: 6
+
+SSL_TLSEXT_ERR_OK           :: 0
+SSL_TLSEXT_ERR_ALERT_FATAL  :: 2
+SSL_TLSEXT_ERR_NOACK        :: 3
+
+OPENSSL_NPN_NEGOTIATED :: 1
+
+TLSEXT_NAMETYPE_host_name :: 0
+
+SSL_CTRL_SET_TLSEXT_SERVERNAME_CB  :: 53
+SSL_CTRL_SET_TLSEXT_SERVERNAME_ARG :: 54
+SSL_CTRL_SET_TLSEXT_HOSTNAME       :: 55
+SSL_CTRL_SET_MIN_PROTO_VERSION     :: 123
+SSL_CTRL_SET_MAX_PROTO_VERSION     :: 124
+
+ALPN_Select_Proc :: #type proc "c" (ssl: ^SSL, out: ^[^]byte, outlen: ^u8, input: [^]byte, inlen: c.uint, arg: rawptr) -> c.int
+Servername_Proc  :: #type proc "c" (ssl: ^SSL, alert: ^c.int, arg: rawptr) -> c.int
+Verify_Proc      :: #type proc "c" (preverify_ok: c.int, ctx: ^X509_STORE_CTX) -> c.int
 
 foreign lib {
 	TLS_client_method :: proc() -> ^SSL_METHOD ---
+	TLS_server_method :: proc() -> ^SSL_METHOD ---
 	SSL_CTX_new :: proc(method: ^SSL_METHOD) -> ^SSL_CTX ---
+	SSL_CTX_use_certificate_chain_file :: proc(ctx: ^SSL_CTX, file: cstring) -> c.int ---
+	SSL_CTX_use_PrivateKey_file :: proc(ctx: ^SSL_CTX, file: cstring, type: c.int) -> c.int ---
+	SSL_CTX_check_private_key :: proc(ctx: ^SSL_CTX) -> c.int ---
+	SSL_CTX_set_alpn_select_cb :: proc(ctx: ^SSL_CTX, cb: ALPN_Select_Proc, arg: rawptr) ---
+	SSL_CTX_ctrl :: proc(ctx: ^SSL_CTX, cmd: c.int, larg: c.long, parg: rawptr) -> c.long ---
+	SSL_CTX_set_verify :: proc(ctx: ^SSL_CTX, mode: c.int, cb: Verify_Proc) ---
+	SSL_CTX_set_default_verify_paths :: proc(ctx: ^SSL_CTX) -> c.int ---
+	SSL_CTX_load_verify_locations :: proc(ctx: ^SSL_CTX, file: cstring, dir: cstring) -> c.int ---
+	SSL_CTX_callback_ctrl :: proc(ctx: ^SSL_CTX, cmd: c.int, fp: rawptr) -> c.long ---
+	SSL_select_next_proto :: proc(out: ^[^]byte, outlen: ^u8, server: [^]byte, server_len: c.uint, client: [^]byte, client_len: c.uint) -> c.int ---
 	SSL_new :: proc(ctx: ^SSL_CTX) -> ^SSL ---
 	SSL_set_fd :: proc(ssl: ^SSL, fd: c.int) -> c.int ---
+	SSL_set_SSL_CTX :: proc(ssl: ^SSL, ctx: ^SSL_CTX) -> ^SSL_CTX ---
+	SSL_get_servername :: proc(ssl: ^SSL, type: c.int) -> cstring ---
+	SSL_get0_alpn_selected :: proc(ssl: ^SSL, data: ^[^]byte, len: ^c.uint) ---
+	SSL_set_alpn_protos :: proc(ssl: ^SSL, protos: [^]byte, protos_len: c.uint) -> c.int ---
+	SSL_ctrl :: proc(ssl: ^SSL, cmd: c.int, larg: c.long, parg: rawptr) -> c.long ---
+	SSL_set1_host :: proc(ssl: ^SSL, hostname: cstring) -> c.int ---
+	SSL_get0_param :: proc(ssl: ^SSL) -> ^X509_VERIFY_PARAM ---
+	SSL_get_verify_result :: proc(ssl: ^SSL) -> c.long ---
+	X509_VERIFY_PARAM_set1_ip_asc :: proc(param: ^X509_VERIFY_PARAM, ipasc: cstring) -> c.int ---
+	ERR_peek_error :: proc() -> c.ulong ---
+	ERR_clear_error :: proc() ---
 	SSL_connect :: proc(ssl: ^SSL) -> c.int ---
+	SSL_accept :: proc(ssl: ^SSL) -> c.int ---
+	SSL_shutdown :: proc(ssl: ^SSL) -> c.int ---
+	SSL_pending :: proc(ssl: ^SSL) -> c.int ---
 	SSL_get_error :: proc(ssl: ^SSL, ret: c.int) -> c.int ---
 	SSL_read :: proc(ssl: ^SSL, buf: [^]byte, num: c.int) -> c.int ---
 	SSL_write :: proc(ssl: ^SSL, buf: [^]byte, num: c.int) -> c.int ---
 	SSL_free :: proc(ssl: ^SSL) ---
 	SSL_CTX_free :: proc(ctx: ^SSL_CTX) ---
 }
+
+// Macro in C: sets the callback that is called with the server name (SNI) the client requested.
+SSL_CTX_set_tlsext_servername_callback :: proc(ctx: ^SSL_CTX, cb: Servername_Proc) -> c.long {
+	return SSL_CTX_callback_ctrl(ctx, SSL_CTRL_SET_TLSEXT_SERVERNAME_CB, rawptr(cb))
+}
+
+// Macro in C: sets the argument given to the server name callback.
+SSL_CTX_set_tlsext_servername_arg :: proc(ctx: ^SSL_CTX, arg: rawptr) -> c.long {
+	return SSL_CTX_ctrl(ctx, SSL_CTRL_SET_TLSEXT_SERVERNAME_ARG, 0, arg)
+}
+
+// Macro in C: sets the server name (SNI) the client sends in the handshake.
+SSL_set_tlsext_host_name :: proc(ssl: ^SSL, name: cstring) -> c.long {
+	return SSL_ctrl(ssl, SSL_CTRL_SET_TLSEXT_HOSTNAME, TLSEXT_NAMETYPE_host_name, rawptr(name))
+}
+
+// Macro in C: sets the minimum protocol version, one of the TLS*_VERSION constants, or 0 for the lowest supported.
+SSL_CTX_set_min_proto_version :: proc(ctx: ^SSL_CTX, version: c.long) -> c.long {
+	return SSL_CTX_ctrl(ctx, SSL_CTRL_SET_MIN_PROTO_VERSION, version, nil)
+}
+
+// Macro in C: sets the maximum protocol version, one of the TLS*_VERSION constants, or 0 for the highest supported.
+SSL_CTX_set_max_proto_version :: proc(ctx: ^SSL_CTX, version: c.long) -> c.long {
+	return SSL_CTX_ctrl(ctx, SSL_CTRL_SET_MAX_PROTO_VERSION, version, nil)
+}
+
+// Macro in C: the reason part of an error code from the error queue.
+ERR_GET_REASON :: proc(err: c.ulong) -> c.int {
+	return c.int(err & 0xFFF)
+}
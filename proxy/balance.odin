@@ -0,0 +1,97 @@
+package proxy
+
+import "core:log"
+import "core:strings"
+import "core:sync"
+import "core:time"
+
+import client "../client"
+
+// Returns the upstream to forward the next request to, nil when none is available.
+@(private)
+upstream_pick :: proc(p: ^Proxy) -> ^Upstream {
+	n := len(p.upstreams)
+	if n == 0 do return nil
+
+	start := int(sync.atomic_add(&p._next, 1) % uint(n))
+	now := time.to_unix_nanoseconds(time.now())
+
+	best: ^Upstream
+	for i in 0..<n {
+		u := &p.upstreams[(start + i) % n]
+		if !sync.atomic_load(&u.healthy) || sync.atomic_load(&u._down_until) > now do continue
+
+		switch p.opts.balancer {
+		case .Round_Robin:
+			return u
+		case .Least_Connections:
+			if best == nil || sync.atomic_load(&u.active) < sync.atomic_load(&best.active) {
+				best = u
+			}
+		}
+	}
+	return best
+}
+
+// Counts a request that could not be forwarded, taking the upstream out of rotation after max_fails in a row.
+@(private)
+upstream_failed :: proc(p: ^Proxy, u: ^Upstream) {
+	if p.opts.max_fails <= 0 do return
+
+	fails := sync.atomic_add(&u._fails, 1) + 1
+	if fails < p.opts.max_fails do return
+
+	sync.atomic_store(&u._fails, 0)
+	until := time.time_add(time.now(), p.opts.fail_timeout)
+	sync.atomic_store(&u._down_until, time.to_unix_nanoseconds(until))
+	log.warnf("proxy: %i requests in a row to %s failed, taking it out of rotation for %v", fails, u.url, p.opts.fail_timeout)
+}
+
+@(private)
+upstream_succeeded :: proc(u: ^Upstream) {
+	if sync.atomic_load(&u._fails) != 0 {
+		sync.atomic_store(&u._fails, 0)
+	}
+}
+
+// Checks the health of every upstream each health_interval, until the proxy is destroyed.
+@(private)
+health_loop :: proc(p: ^Proxy) {
+	context.allocator = context.temp_allocator
+
+	for {
+		for _, i in p.upstreams {
+			health_check(p, &p.upstreams[i])
+		}
+		free_all(context.temp_allocator)
+
+		sync.mutex_lock(&p._mu)
+		if !p._stopping {
+			sync.cond_wait_with_timeout(&p._cond, &p._mu, p.opts.health_interval)
+		}
+		stopping := p._stopping
+		sync.mutex_unlock(&p._mu)
+
+		if stopping do return
+	}
+}
+
+@(private)
+health_check :: proc(p: ^Proxy, u: ^Upstream) {
+	healthy := false
+	if res, err := client.client_get(&p._client, strings.concatenate({u.url, p.opts.health_path})); err == nil {
+		healthy = res.status >= .Ok && res.status < .Bad_Request
+		client.response_destroy(&res)
+	}
+
+	if sync.atomic_exchange(&u.healthy, healthy) == healthy do return
+
+	if healthy {
+		// Passing a check also ends a time out of rotation because of failed requests.
+		sync.atomic_store(&u._fails, 0)
+		sync.atomic_store(&u._down_until, 0)
+		log.infof("proxy: upstream %s is healthy again", u.url)
+	} else {
+		log.warnf("proxy: upstream %s failed its health check, taking it out of rotation", u.url)
+	}
+}
@@ -0,0 +1,421 @@
+// package proxy implements a reverse proxy handler, forwarding requests to a set of upstream servers.
+//
+// Request and response bodies are streamed, hop-by-hop headers are removed, X-Forwarded-* and Forwarded (RFC 7239)
+// headers are added, connections to the upstreams are kept alive and reused through a client.Client,
+// and upgrades (like WebSockets) are tunneled between the client and the upstream.
+//
+// Upgrades are only supported over HTTP/1.1. A tunnel keeps the thread of its handler until it is closed,
+// which is not counted as a worker of the server, see http.response_hijack.
+package proxy
+
+import "core:io"
+import "core:log"
+import "core:mem"
+import "core:net"
+import "core:strconv"
+import "core:strings"
+import "core:sync"
+import "core:thread"
+import "core:time"
+
+import http ".."
+import client "../client"
+
+Balancer :: enum {
+	// The upstreams take turns.
+	Round_Robin,
+	// The upstream with the least requests in flight, ties are broken in round robin order.
+	Least_Connections,
+}
+
+Proxy_Opts :: struct {
+	balancer:        Balancer,
+	// Used for the connections to the upstreams.
+	// Keep the idle_timeout below the keep-alive timeout of the upstreams, so they don't close a connection as it is reused.
+	client_opts:     client.Client_Opts,
+	// Removed from the request path before it is forwarded, like "/api" when the handler is added to the route "/api/*rest".
+	// Only whole segments are removed, with "/api" the path "/apiary" is forwarded as is.
+	// The prefix of the route group the handler is added to is always removed.
+	strip_prefix:    string,
+	// Whether the Host header of the request is forwarded, otherwise it is set to the host of the upstream.
+	preserve_host:   bool,
+	// Whether the X-Forwarded-* and Forwarded headers of the request are kept and appended to,
+	// only enable this when the server is behind another proxy that sets them, clients can set them to anything.
+	trust_forwarded: bool,
+	// The path that is requested on each upstream every health_interval, empty to not do health checks.
+	// An upstream that fails to connect, or responds with anything other than 2xx or 3xx, is taken out of rotation
+	// until it passes a check again.
+	health_path:     string,
+	health_interval: time.Duration,
+	// After this many requests in a row could not be forwarded to an upstream, it is taken out of rotation
+	// for fail_timeout. 0 to never take upstreams out of rotation because of failed requests.
+	max_fails:       int,
+	fail_timeout:    time.Duration,
+}
+
+Default_Proxy_Opts :: Proxy_Opts {
+	balancer        = .Round_Robin,
+	client_opts     = {
+		max_idle_per_host     = 32,
+		idle_timeout          = 30 * time.Second,
+		max_decompressed_size = client.Default_Max_Decompressed_Size,
+	},
+	health_interval = 10 * time.Second,
+	max_fails       = 3,
+	fail_timeout    = 10 * time.Second,
+}
+
+// An upstream server, the fields are updated by the proxy and should only be read (atomically).
+Upstream :: struct {
+	// Scheme and host, like "http://localhost:8081", requests are forwarded to this followed by the request path.
+	url:         string,
+	// The amount of requests (and tunnels) in flight.
+	active:      int,
+	// Whether the last health check passed, always true without health checks.
+	healthy:     bool,
+	// Requests in a row that could not be forwarded.
+	_fails:      int,
+	// Unix nanoseconds until which the upstream is out of rotation because of failed requests.
+	_down_until: i64,
+}
+
+// A Proxy can be used by multiple threads at the same time.
+Proxy :: struct {
+	opts:      Proxy_Opts,
+	upstreams: []Upstream,
+	allocator: mem.Allocator,
+
+	_client:   client.Client,
+	// Incremented for every pick, the upstream to start at.
+	_next:     uint,
+	_health:   ^thread.Thread,
+	_mu:       sync.Mutex,
+	_cond:     sync.Cond,
+	_stopping: bool,
+}
+
+// Initializes the proxy for the upstreams, given as a scheme and host, like "http://10.0.0.2:8080" or "https://api.internal".
+// Health checks are started on a separate thread when opts.health_path is set.
+proxy_init :: proc(p: ^Proxy, upstreams: []string, opts := Default_Proxy_Opts, allocator := context.allocator) {
+	p.opts = opts
+	p.allocator = allocator
+	client.client_init(&p._client, opts.client_opts, allocator)
+
+	p.upstreams = make([]Upstream, len(upstreams), allocator)
+	for url, i in upstreams {
+		p.upstreams[i] = {
+			url     = strings.clone(strings.trim_right(url, "/"), allocator),
+			healthy = true,
+		}
+	}
+
+	if opts.health_path != "" && opts.health_interval > 0 {
+		p._health = thread.create_and_start_with_poly_data(p, health_loop, context)
+	}
+}
+
+// Stops the health checks, closes the idle connections to the upstreams and frees the proxy.
+// Requests that are still being forwarded must be done before this is called.
+proxy_destroy :: proc(p: ^Proxy) {
+	if p._health != nil {
+		sync.mutex_lock(&p._mu)
+		p._stopping = true
+		sync.cond_broadcast(&p._cond)
+		sync.mutex_unlock(&p._mu)
+
+		thread.join(p._health)
+		thread.destroy(p._health)
+		p._health = nil
+	}
+
+	client.client_destroy(&p._client)
+
+	for u in p.upstreams {
+		delete(u.url, p.allocator)
+	}
+	delete(p.upstreams, p.allocator)
+}
+
+// Returns a handler that forwards every request to one of the upstreams of the proxy.
+//
+// When no upstream is available the response is 503 Service Unavailable,
+// when the upstream can not be reached, or it fails before responding, it is 502 Bad Gateway.
+proxy_handler :: proc(p: ^Proxy) -> http.Handler {
+	h: http.Handler
+	h.user_data = p
+
+	h.handle = proc(h: ^http.Handler, req: ^http.Request, res: ^http.Response) {
+		p := (^Proxy)(h.user_data)
+
+		// Everything allocated here is only needed for this request.
+		context.allocator = context.temp_allocator
+
+		forward(p, req, res)
+	}
+
+	return h
+}
+
+// Headers that only apply to a single connection, they are not forwarded (RFC 9110 7.6.1).
+@(private)
+hop_by_hop := [?]string{
+	"connection",
+	"keep-alive",
+	"proxy-connection",
+	"proxy-authenticate",
+	"proxy-authorization",
+	"te",
+	"trailer",
+	"transfer-encoding",
+	"upgrade",
+}
+
+@(private)
+forward :: proc(p: ^Proxy, req: ^http.Request, res: ^http.Response) {
+	rline := req.line.(http.Requestline)
+
+	u := upstream_pick(p)
+	if u == nil {
+		log.warn("proxy: no upstream available")
+		res.status = .Service_Unavailable
+		return
+	}
+
+	sync.atomic_add(&u.active, 1)
+	defer sync.atomic_sub(&u.active, 1)
+
+	upgrade := is_upgrade(req)
+
+	out: client.Request
+	client.request_init(&out, rline.method)
+	defer client.request_destroy(&out)
+
+	copy_end_to_end(&out.headers, &req.headers)
+
+	// The server answered 100 Continue already, if the client asked for it.
+	http.headers_delete(&out.headers, "expect")
+
+	if !p.opts.preserve_host {
+		http.headers_delete(&out.headers, "host")
+	}
+
+	// Otherwise the client asks for a compressed body, which the client of the proxy might not accept.
+	if !http.headers_has(&out.headers, "accept-encoding") {
+		http.headers_set(&out.headers, "accept-encoding", "identity")
+	}
+
+	if upgrade {
+		http.headers_set(&out.headers, "connection", "upgrade")
+		http.headers_set(&out.headers, "upgrade", http.headers_get(&req.headers, "upgrade") or_else "")
+	}
+
+	forwarded_headers(p, req, &out.headers)
+
+	if http.headers_chunked(&req.headers) || http.headers_has(&req.headers, "content-length") {
+		body, err := http.request_body_reader(req)
+		if err != nil {
+			res.status = http.body_error_status(err)
+			return
+		}
+
+		// Sent chunked when the length is not known up front.
+		length := -1
+		if !http.headers_chunked(&req.headers) {
+			length, _ = strconv.parse_int(http.headers_get(&req.headers, "content-length") or_else "", 10)
+		}
+		client.with_reader(&out, body, length)
+	}
+
+	cres, err := client.client_request(&p._client, upstream_target(p, u, req), &out)
+	if err != nil {
+		if rerr, ok := err.(client.Request_Error); ok && rerr == .Body_Stream_Failed {
+			res.status = http.body_error_status(http.request_body_error(req))
+			if res.status == .Ok do res.status = .Bad_Request
+			return
+		}
+
+		log.warnf("proxy: forwarding %s %s to %s failed: %v", http.method_string(rline.method), req.url.path, u.url, err)
+		upstream_failed(p, u)
+		res.status = .Bad_Gateway
+		return
+	}
+	defer client.response_destroy(&cres)
+
+	upstream_succeeded(u)
+
+	if upgrade && cres.status == .Switching_Protocols {
+		tunnel(res, &cres)
+		return
+	}
+
+	res.status = cres.status
+	copy_end_to_end(&res.headers, &cres.headers)
+
+	// Announced trailers are forwarded, their values are set once the body has been read.
+	trailers: []string
+	if http.headers_chunked(&cres.headers) {
+		names := strings.join(http.headers_get_all(&cres.headers, "trailer", context.temp_allocator), ",")
+		announced := make([dynamic]string)
+		for name in strings.split_iterator(&names, ",") {
+			key := strings.to_lower(strings.trim_space(name))
+			if key == "" || !http.response_trailer_set(res, key, "") do continue
+			append(&announced, key)
+		}
+		trailers = announced[:]
+	}
+
+	body, berr := client.response_body_reader(&cres)
+	if berr != nil {
+		log.warnf("proxy: invalid response body from %s: %v", u.url, berr)
+		http.headers_clear(&res.headers)
+		http.headers_clear(&res.trailers)
+		res.status = .Bad_Gateway
+		return
+	}
+
+	if http.response_headers_send(res) != nil {
+		return
+	}
+
+	buf: [16 << 10]byte
+	for {
+		n, rerr := io.read(body, buf[:])
+		if n > 0 {
+			if _, werr := http.response_write(res, buf[:n]); werr != nil {
+				// The client is gone.
+				return
+			}
+		}
+
+		if rerr == .EOF do break
+		if rerr != nil {
+			// Ending the response normally would make a truncated body look complete.
+			log.warnf("proxy: reading the response body from %s failed: %v", u.url, client.response_body_error(&cres))
+			http.response_abort(res)
+			return
+		}
+	}
+
+	// The trailers were added to the headers of the upstream response at the end of the body.
+	for name in trailers {
+		if value, ok := http.headers_get(&cres.headers, name); ok {
+			http.response_trailer_set(res, name, strings.clone(value, req.allocator))
+		} else {
+			http.headers_delete(&res.trailers, name)
+		}
+	}
+}
+
+// Whether the request asks to switch protocols, see tunnel.
+@(private)
+is_upgrade :: proc(req: ^http.Request) -> bool {
+	rline := req.line.(http.Requestline)
+	if rline.version != {1, 1} do return false
+
+	connection := strings.join(http.headers_get_all(&req.headers, "connection", context.temp_allocator), ",")
+	return http.header_has_token(connection, "upgrade") && http.headers_has(&req.headers, "upgrade")
+}
+
+// Replaces the headers in dst with the end-to-end headers of src,
+// leaving out the hop-by-hop headers and the headers that the connection header lists.
+@(private)
+copy_end_to_end :: proc(dst, src: ^http.Headers) {
+	connection := strings.join(http.headers_get_all(src, "connection", context.temp_allocator), ",")
+
+	is_hop_by_hop :: proc(key, connection: string) -> bool {
+		for name in hop_by_hop {
+			if key == name do return true
+		}
+		return http.header_has_token(connection, key)
+	}
+
+	for header in src.entries {
+		if !is_hop_by_hop(header.key, connection) {
+			http.headers_delete(dst, header.key)
+		}
+	}
+
+	for header in src.entries {
+		if !is_hop_by_hop(header.key, connection) {
+			http.headers_add(dst, header.key, header.value)
+		}
+	}
+}
+
+// Adds the X-Forwarded-For, X-Forwarded-Host, X-Forwarded-Proto and Forwarded (RFC 7239) headers,
+// so the upstream knows who the request is from.
+@(private)
+forwarded_headers :: proc(p: ^Proxy, req: ^http.Request, headers: ^http.Headers) {
+	if !p.opts.trust_forwarded {
+		http.headers_delete(headers, "x-forwarded-for")
+		http.headers_delete(headers, "x-forwarded-host")
+		http.headers_delete(headers, "x-forwarded-proto")
+		http.headers_delete(headers, "forwarded")
+	}
+
+	ip := net.address_to_string(req.client.address)
+	host := http.headers_get(&req.headers, "host") or_else ""
+	proto := req.tls ? "https" : "http"
+
+	if xff := strings.join(http.headers_get_all(headers, "x-forwarded-for", context.temp_allocator), ", "); xff != "" {
+		http.headers_set(headers, "x-forwarded-for", strings.concatenate({xff, ", ", ip}))
+	} else {
+		http.headers_set(headers, "x-forwarded-for", ip)
+	}
+
+	// The first proxy knows the original values.
+	if host != "" && !http.headers_has(headers, "x-forwarded-host") {
+		http.headers_set(headers, "x-forwarded-host", host)
+	}
+	if !http.headers_has(headers, "x-forwarded-proto") {
+		http.headers_set(headers, "x-forwarded-proto", proto)
+	}
+
+	b := strings.builder_make()
+	if prev := strings.join(http.headers_get_all(headers, "forwarded", context.temp_allocator), ", "); prev != "" {
+		strings.write_string(&b, prev)
+		strings.write_string(&b, ", ")
+	}
+
+	// IPv6 addresses contain colons, they have to be bracketed and quoted.
+	strings.write_string(&b, "for=")
+	if _, is_ip6 := req.client.address.(net.IP6_Address); is_ip6 {
+		strings.write_string(&b, "\"[")
+		strings.write_string(&b, ip)
+		strings.write_string(&b, "]\"")
+	} else {
+		strings.write_string(&b, ip)
+	}
+
+	strings.write_string(&b, ";proto=")
+	strings.write_string(&b, proto)
+
+	// A host with quotes or backslashes would have to be escaped, those are not valid hosts anyway.
+	if host != "" && !strings.contains_any(host, "\"\\") {
+		strings.write_string(&b, ";host=\"")
+		strings.write_string(&b, host)
+		strings.write_byte(&b, '"')
+	}
+
+	http.headers_set(headers, "forwarded", strings.to_string(b))
+}
+
+// Returns the url to forward the request to, the upstream followed by the request path and query.
+@(private)
+upstream_target :: proc(p: ^Proxy, u: ^Upstream, req: ^http.Request) -> string {
+	rline := req.line.(http.Requestline)
+
+	path := req.url.path
+	path = strings.trim_prefix(path, req.route_prefix)
+	path = http.path_trim_prefix(path, p.opts.strip_prefix)
+
+	query: string
+	if q := strings.index_byte(rline.target, '?'); q >= 0 {
+		query = rline.target[q:]
+	}
+
+	if !strings.has_prefix(path, "/") {
+		return strings.concatenate({u.url, "/", path, query})
+	}
+	return strings.concatenate({u.url, path, query})
+}
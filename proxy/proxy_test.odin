@@ -0,0 +1,375 @@
+package proxy
+
+import "core:bytes"
+import "core:fmt"
+import "core:io"
+import "core:strings"
+import "core:sync"
+import "core:testing"
+import "core:time"
+
+import http ".."
+import client "../client"
+
+// The tests run a proxy server in front of upstream servers that stand in for real services,
+// and an upstream on this port, where nothing listens, which can't be reached.
+@(private)
+Test_Dead_Port :: 18289
+
+@(private)
+Test_Upstream :: struct {
+	using test_server: http.Test_Server,
+	name:    string,
+	// What /health answers, 204 No Content when true, 503 Service Unavailable otherwise.
+	healthy: bool,
+}
+
+// Starts an upstream that answers:
+// - /health with its health.
+// - /echo with the request body, streamed back as it is read.
+// - Anything else with the request headers, a line each.
+// Every response has an x-upstream header with its name.
+@(private)
+test_upstream_start :: proc(t: ^testing.T, u: ^Test_Upstream, name: string) -> bool {
+	u.name = name
+	u.healthy = true
+	h: http.Handler
+	h.user_data = u
+	h.handle = proc(h: ^http.Handler, req: ^http.Request, res: ^http.Response) {
+		u := (^Test_Upstream)(h.user_data)
+
+		http.headers_set(&res.headers, "x-upstream", u.name)
+		// Hop-by-hop, the proxy must not pass it on.
+		http.headers_set(&res.headers, "proxy-authenticate", "Basic")
+		http.headers_set(&res.headers, "x-end-to-end", "1")
+
+		switch req.url.path {
+		case "/health":
+			res.status = sync.atomic_load(&u.healthy) ? .No_Content : .Service_Unavailable
+
+		case "/echo":
+			http.headers_set(&res.headers, "x-request-chunked", http.headers_chunked(&req.headers) ? "yes" : "no")
+
+			body, err := http.request_body_reader(req)
+			if err != nil {
+				res.status = http.body_error_status(err)
+				return
+			}
+
+			res.status = .Ok
+			buf: [4096]byte
+			for {
+				n, rerr := io.read(body, buf[:])
+				if n > 0 {
+					if _, werr := http.response_write(res, buf[:n]); werr != nil do return
+				}
+				if rerr != nil do break
+			}
+
+		case:
+			b := strings.builder_make(context.temp_allocator)
+			for header in req.headers.entries {
+				fmt.sbprintf(&b, "%s: %s\n", header.key, header.value)
+			}
+			http.respond_plain(res, strings.to_string(b))
+		}
+	}
+
+	return http.test_server_start(t, &u.test_server, h)
+}
+
+// Starts two upstreams and a proxy server in front of them.
+@(private)
+Test_Setup :: struct {
+	upstreams: [2]Test_Upstream,
+	proxy:     Proxy,
+	front:     http.Test_Server,
+	client:    client.Client,
+}
+
+@(private)
+test_setup :: proc(t: ^testing.T, s: ^Test_Setup, opts := Default_Proxy_Opts) -> bool {
+	test_upstream_start(t, &s.upstreams[0], "a") or_return
+	test_upstream_start(t, &s.upstreams[1], "b") or_return
+
+	proxy_init(&s.proxy, {
+		http.test_server_url(&s.upstreams[0], ""),
+		http.test_server_url(&s.upstreams[1], ""),
+	}, opts)
+
+	http.test_server_start(t, &s.front, proxy_handler(&s.proxy)) or_return
+
+	client.client_init(&s.client)
+	return true
+}
+
+@(private)
+test_teardown :: proc(s: ^Test_Setup) {
+	client.client_destroy(&s.client)
+	http.test_server_stop(&s.front)
+	proxy_destroy(&s.proxy)
+	http.test_server_stop(&s.upstreams[0])
+	http.test_server_stop(&s.upstreams[1])
+}
+
+// Sends the request through the proxy, returns the status, headers and body of the response.
+@(private)
+test_request :: proc(t: ^testing.T, s: ^Test_Setup, path: string, req: ^client.Request) -> (res: client.Response, body: string, ok: bool) {
+	err: client.Error
+	res, err = client.client_request(&s.client, http.test_server_url(&s.front, path), req, context.temp_allocator)
+	if err != nil {
+		testing.errorf(t, "request to %s failed: %v", path, err)
+		return
+	}
+
+	raw, _, berr := client.response_body_raw(&res, -1, context.temp_allocator)
+	if berr != nil {
+		testing.errorf(t, "reading the body of %s failed: %v", path, berr)
+		client.response_destroy(&res)
+		return
+	}
+	return res, raw, true
+}
+
+@(test)
+test_proxy_strips_hop_by_hop_headers :: proc(t: ^testing.T) {
+	s: Test_Setup
+	if !test_setup(t, &s) do return
+	defer test_teardown(&s)
+
+	req: client.Request
+	client.request_init(&req, .Get, context.temp_allocator)
+	http.headers_set(&req.headers, "connection", "x-listed-hop")
+	http.headers_set(&req.headers, "x-listed-hop", "1")
+	http.headers_set(&req.headers, "keep-alive", "timeout=5")
+	http.headers_set(&req.headers, "proxy-authorization", "Basic dXNlcjpwYXNz")
+	http.headers_set(&req.headers, "x-end-to-end", "1")
+
+	res, body, ok := test_request(t, &s, "/headers", &req)
+	if !ok do return
+	defer client.response_destroy(&res)
+
+	testing.expect_value(t, res.status, http.Status.Ok)
+
+	// What the upstream received.
+	testing.expect(t, !strings.contains(body, "x-listed-hop"), "headers listed in connection are hop-by-hop")
+	testing.expect(t, !strings.contains(body, "keep-alive:"), "keep-alive is hop-by-hop")
+	testing.expect(t, !strings.contains(body, "proxy-authorization:"), "proxy-authorization is hop-by-hop")
+	testing.expect(t, strings.contains(body, "x-end-to-end: 1\n"), "end-to-end headers are forwarded")
+
+	// What the client received.
+	testing.expect(t, !http.headers_has(&res.headers, "proxy-authenticate"), "proxy-authenticate is hop-by-hop")
+	testing.expect_value(t, http.headers_get(&res.headers, "x-end-to-end") or_else "", "1")
+}
+
+@(test)
+test_proxy_adds_forwarded_headers :: proc(t: ^testing.T) {
+	s: Test_Setup
+	if !test_setup(t, &s) do return
+	defer test_teardown(&s)
+
+	req: client.Request
+	client.request_init(&req, .Get, context.temp_allocator)
+	// Not trusted by default, the client could say anything.
+	http.headers_set(&req.headers, "x-forwarded-for", "6.6.6.6")
+	http.headers_set(&req.headers, "forwarded", "for=6.6.6.6")
+
+	res, body, ok := test_request(t, &s, "/headers", &req)
+	if !ok do return
+	defer client.response_destroy(&res)
+
+	host := fmt.tprintf("127.0.0.1:%i", s.front.endpoint.port)
+
+	testing.expect(t, !strings.contains(body, "6.6.6.6"), "forwarded headers of the client are not trusted")
+	testing.expect(t, strings.contains(body, "x-forwarded-for: 127.0.0.1\n"), "x-forwarded-for is the client address")
+	testing.expect(t, strings.contains(body, "x-forwarded-proto: http\n"), "x-forwarded-proto is the scheme of the request")
+	testing.expect(t, strings.contains(body, fmt.tprintf("x-forwarded-host: %s\n", host)), "x-forwarded-host is the host of the request")
+	testing.expect(t, strings.contains(body, fmt.tprintf("forwarded: for=127.0.0.1;proto=http;host=\"%s\"\n", host)), "forwarded has all three")
+}
+
+@(test)
+test_proxy_streams_bodies :: proc(t: ^testing.T) {
+	s: Test_Setup
+	if !test_setup(t, &s) do return
+	defer test_teardown(&s)
+
+	// Bigger than the buffers on the way, so it has to be passed on in parts.
+	sent := make([]byte, 256 << 10, context.temp_allocator)
+	for _, i in sent {
+		sent[i] = byte('a' + i % 26)
+	}
+
+	r: bytes.Reader
+	bytes.reader_init(&r, sent)
+
+	req: client.Request
+	client.request_init(&req, .Post, context.temp_allocator)
+	client.with_reader(&req, bytes.reader_to_stream(&r))
+
+	res, body, ok := test_request(t, &s, "/echo", &req)
+	if !ok do return
+	defer client.response_destroy(&res)
+
+	testing.expect_value(t, res.status, http.Status.Ok)
+	// The proxy does not know the length either, it does not read the whole body before forwarding it.
+	testing.expect_value(t, http.headers_get(&res.headers, "x-request-chunked") or_else "", "yes")
+	testing.expect(t, http.headers_chunked(&res.headers), "the response is streamed back without a content-length")
+	testing.expect_value(t, len(body), len(sent))
+	testing.expect(t, body == string(sent), "the body arrives unchanged")
+}
+
+@(test)
+test_proxy_round_robin :: proc(t: ^testing.T) {
+	s: Test_Setup
+	if !test_setup(t, &s) do return
+	defer test_teardown(&s)
+
+	prev: string
+	counts := make(map[string]int, 2, context.temp_allocator)
+	for i in 0..<6 {
+		req: client.Request
+		client.request_init(&req, .Get, context.temp_allocator)
+
+		res, _, ok := test_request(t, &s, "/", &req)
+		if !ok do return
+
+		name := strings.clone(http.headers_get(&res.headers, "x-upstream") or_else "", context.temp_allocator)
+		client.response_destroy(&res)
+
+		if i > 0 && name == prev {
+			testing.errorf(t, "request %i went to upstream %q again", i, name)
+		}
+		prev = name
+		counts[name] += 1
+	}
+
+	testing.expect_value(t, counts["a"], 3)
+	testing.expect_value(t, counts["b"], 3)
+}
+
+@(test)
+test_proxy_pick_round_robin :: proc(t: ^testing.T) {
+	p: Proxy
+	p.opts = Default_Proxy_Opts
+	p.upstreams = []Upstream{{url = "a", healthy = true}, {url = "b", healthy = true}, {url = "c", healthy = true}}
+
+	expected := [?]string{"a", "b", "c", "a"}
+	for name in expected {
+		testing.expect_value(t, upstream_pick(&p).url, name)
+	}
+
+	// Upstreams that are out of rotation are skipped.
+	p.upstreams[1].healthy = false
+	p.upstreams[2]._down_until = time.to_unix_nanoseconds(time.time_add(time.now(), time.Hour))
+	for _ in 0..<3 {
+		testing.expect_value(t, upstream_pick(&p).url, "a")
+	}
+
+	p.upstreams[0].healthy = false
+	testing.expect(t, upstream_pick(&p) == nil, "no upstream is available")
+}
+
+@(test)
+test_proxy_pick_least_connections :: proc(t: ^testing.T) {
+	p: Proxy
+	p.opts = Default_Proxy_Opts
+	p.opts.balancer = .Least_Connections
+	p.upstreams = []Upstream{{url = "a", healthy = true, active = 2}, {url = "b", healthy = true, active = 0}, {url = "c", healthy = true, active = 1}}
+
+	for _ in 0..<3 {
+		testing.expect_value(t, upstream_pick(&p).url, "b")
+	}
+
+	p.upstreams[1].active = 5
+	testing.expect_value(t, upstream_pick(&p).url, "c")
+
+	p.upstreams[2].healthy = false
+	testing.expect_value(t, upstream_pick(&p).url, "a")
+
+	// Ties take turns.
+	p.upstreams[0].active = 0
+	p.upstreams[1].active = 0
+	first := upstream_pick(&p).url
+	second := upstream_pick(&p).url
+	testing.expect(t, first != second, "ties are broken in round robin order")
+}
+
+@(test)
+test_proxy_health_checks :: proc(t: ^testing.T) {
+	healthy, failing: Test_Upstream
+	if !test_upstream_start(t, &healthy, "healthy") do return
+	defer http.test_server_stop(&healthy)
+	if !test_upstream_start(t, &failing, "failing") do return
+	defer http.test_server_stop(&failing)
+	sync.atomic_store(&failing.healthy, false)
+
+	opts := Default_Proxy_Opts
+	opts.health_path = "/health"
+	// No health check thread, the checks are done by the test.
+	opts.health_interval = 0
+
+	p: Proxy
+	proxy_init(&p, {
+		http.test_server_url(&healthy, ""),
+		http.test_server_url(&failing, ""),
+		fmt.tprintf("http://127.0.0.1:%i", Test_Dead_Port),
+	}, opts)
+	defer proxy_destroy(&p)
+
+	for _, i in p.upstreams {
+		health_check(&p, &p.upstreams[i])
+	}
+
+	testing.expect(t, p.upstreams[0].healthy, "an upstream answering 2xx is healthy")
+	testing.expect(t, !p.upstreams[1].healthy, "an upstream answering 5xx is out of rotation")
+	testing.expect(t, !p.upstreams[2].healthy, "an upstream that can't be reached is out of rotation")
+
+	for _ in 0..<3 {
+		testing.expect_value(t, upstream_pick(&p), &p.upstreams[0])
+	}
+
+	// Back in rotation once it passes a check again.
+	sync.atomic_store(&failing.healthy, true)
+	health_check(&p, &p.upstreams[1])
+	testing.expect(t, p.upstreams[1].healthy, "an upstream that recovered is back in rotation")
+}
+
+@(test)
+test_proxy_strip_prefix_whole_segments :: proc(t: ^testing.T) {
+	context.allocator = context.temp_allocator
+
+	p: Proxy
+	p.opts = Default_Proxy_Opts
+	p.opts.strip_prefix = "/api"
+	u := Upstream{url = "http://upstream"}
+
+	Case :: struct {
+		target, expected: string,
+	}
+
+	cases := []Case{
+		{"/api", "http://upstream/"},
+		{"/api/", "http://upstream/"},
+		{"/api/users?page=2", "http://upstream/users?page=2"},
+		{"/apiary/x", "http://upstream/apiary/x"},
+		{"/apix", "http://upstream/apix"},
+		{"/other/api/x", "http://upstream/other/api/x"},
+	}
+
+	for c in cases {
+		req: http.Request
+		http.request_init(&req)
+		req.line = http.Requestline{method = .Get, target = c.target, version = {1, 1}}
+		req.url = http.url_parse(c.target)
+
+		testing.expect_value(t, upstream_target(&p, &u, &req), c.expected)
+	}
+
+	// A trailing slash on the prefix does not change what it matches.
+	p.opts.strip_prefix = "/api/"
+	req: http.Request
+	http.request_init(&req)
+	req.line = http.Requestline{method = .Get, target = "/apiary/x", version = {1, 1}}
+	req.url = http.url_parse("/apiary/x")
+	testing.expect_value(t, upstream_target(&p, &u, &req), "http://upstream/apiary/x")
+}
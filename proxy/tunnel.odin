@@ -0,0 +1,103 @@
+package proxy
+
+import "core:bytes"
+import "core:log"
+import "core:net"
+import "core:time"
+
+import http ".."
+import client "../client"
+import nbio "../nbio"
+
+// How often an idle tunnel checks whether the server is shutting down.
+@(private)
+Tunnel_Shutdown_Interval :: time.Second
+
+// Takes over the connection of the response, sends the 101 Switching Protocols of the upstream,
+// and passes data between the client and the upstream until either of them closes the connection.
+//
+// This runs on the thread that handled the upgrade request, which leaves the worker pool when the connection is hijacked,
+// so open tunnels don't take workers away from other requests.
+@(private)
+tunnel :: proc(res: ^http.Response, cres: ^client.Response) {
+	conn, ok := http.response_hijack(res)
+	if !ok {
+		log.warn("proxy: can not tunnel an upgrade over this connection")
+		res.status = .Bad_Gateway
+		return
+	}
+	defer http.connection_close(conn)
+
+	client.response_hijack(cres)
+
+	// The headers of the upstream are part of the handshake (like sec-websocket-accept), they are sent as is.
+	head: bytes.Buffer
+	bytes.buffer_init_allocator(&head, 0, 160)
+	bytes.buffer_write_string(&head, "HTTP/1.1 101 Switching Protocols\r\n")
+	for header in cres.headers.entries {
+		bytes.buffer_write_string(&head, header.key)
+		bytes.buffer_write_string(&head, ": ")
+		bytes.buffer_write_string(&head, header.value)
+		bytes.buffer_write_string(&head, "\r\n")
+	}
+	bytes.buffer_write_string(&head, "\r\n")
+
+	if err := http.connection_write(conn, bytes.buffer_to_bytes(&head)); err != nil {
+		log.debugf("proxy: could not send the upgrade response: %v", err)
+		return
+	}
+
+	poller: nbio.Poller
+	if err := nbio.init(&poller); err != nil {
+		log.errorf("proxy: could not set up a poller for the tunnel: %v", err)
+		return
+	}
+	defer nbio.destroy(&poller)
+
+	down := conn.socket
+	up := client.response_conn_socket(cres)
+
+	buf: [16 << 10]byte
+	ready: [2]net.TCP_Socket
+	down_watched, up_watched: bool
+	for !conn.server.shutting_down {
+		// Data that has been read from a socket already does not make it readable again, it is passed on first.
+		if !down_watched && !http.connection_has_buffered(conn) {
+			if nbio.watch(&poller, down) != nil do return
+			down_watched = true
+		}
+		if !up_watched && !client.response_conn_has_buffered(cres) {
+			if nbio.watch(&poller, up) != nil do return
+			up_watched = true
+		}
+
+		from_down, from_up := !down_watched, !up_watched
+		if down_watched && up_watched {
+			n, err := nbio.wait(&poller, ready[:], Tunnel_Shutdown_Interval)
+			if err != nil {
+				log.errorf("proxy: waiting on the tunnel failed: %v", err)
+				return
+			}
+
+			for socket in ready[:n] {
+				if socket == down {
+					from_down, down_watched = true, false
+				} else if socket == up {
+					from_up, up_watched = true, false
+				}
+			}
+		}
+
+		if from_down {
+			n, err := http.connection_read(conn, buf[:])
+			if n > 0 && client.response_conn_write(cres, buf[:n]) != nil do return
+			if err != nil do return
+		}
+
+		if from_up {
+			n, err := client.response_conn_read(cres, buf[:])
+			if n > 0 && http.connection_write(conn, buf[:n]) != nil do return
+			if err != nil do return
+		}
+	}
+}
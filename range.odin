@@ -0,0 +1,280 @@
+package http
+
+import "core:bytes"
+import "core:math/rand"
+import "core:os"
+import "core:slice"
+import "core:strconv"
+import "core:strings"
+import "core:time"
+
+// A range of bytes, from start up to but not including end.
+Byte_Range :: struct {
+	start: int,
+	end:   int,
+}
+
+Range_Result :: enum {
+	// There is no (usable) range, the full representation is sent.
+	Full,
+	// The ranges are satisfiable, a 206 Partial Content is sent.
+	Partial,
+	// None of the ranges are satisfiable, a 416 Range Not Satisfiable is sent.
+	Unsatisfiable,
+}
+
+// More ranges than this in one request are ignored and the full representation is sent,
+// many (small or overlapping) ranges are more work than sending everything.
+Max_Ranges :: 32
+
+// Parses the value of a Range header for a representation of the given size, RFC 9110 14.1.2.
+//
+// Ranges that start after the end of the representation are left out, when none are left the result is .Unsatisfiable.
+// Positions too big for an int are past the end of any representation, they don't overflow.
+// Overlapping and adjacent ranges are merged and the ranges are sorted, as RFC 9110 14.2 allows,
+// so the ranges never add up to more than the size, no matter how often a client asks for the same bytes.
+// A header that is invalid, uses a unit other than bytes, or has more than Max_Ranges ranges gives .Full,
+// the header is then ignored, as RFC 9110 14.2 allows.
+range_parse :: proc(value: string, size: int, allocator := context.allocator) -> (ranges: []Byte_Range, result: Range_Result) {
+	eq := strings.index_byte(value, '=')
+	if eq == -1 || !strings.equal_fold(strings.trim_space(value[:eq]), "bytes") {
+		return nil, .Full
+	}
+
+	specs := value[eq+1:]
+	satisfiable := make([dynamic]Byte_Range, 0, 1, allocator)
+	count := 0
+
+	for spec in strings.split_iterator(&specs, ",") {
+		spec := strings.trim_space(spec)
+		// RFC 9110 5.6.1: empty list elements are allowed and ignored.
+		if spec == "" do continue
+
+		count += 1
+		if count > Max_Ranges {
+			delete(satisfiable)
+			return nil, .Full
+		}
+
+		dash := strings.index_byte(spec, '-')
+		if dash == -1 {
+			delete(satisfiable)
+			return nil, .Full
+		}
+
+		first, last := spec[:dash], spec[dash+1:]
+		r: Byte_Range
+
+		switch {
+		case first == "":
+			// A suffix range, the last n bytes.
+			n, ok := range_parse_int(last)
+			if !ok {
+				delete(satisfiable)
+				return nil, .Full
+			}
+			// An empty representation has no last bytes to send.
+			if n == 0 || size == 0 do continue
+
+			r = {max(size - n, 0), size}
+		case:
+			start, ok := range_parse_int(first)
+			if !ok {
+				delete(satisfiable)
+				return nil, .Full
+			}
+
+			end := size
+			if last != "" {
+				l, lok := range_parse_int(last)
+				if !lok || l < start {
+					delete(satisfiable)
+					return nil, .Full
+				}
+				end = min(l + 1, size)
+			}
+
+			if start >= size do continue
+			r = {start, end}
+		}
+
+		append(&satisfiable, r)
+	}
+
+	if count == 0 {
+		delete(satisfiable)
+		return nil, .Full
+	}
+
+	if len(satisfiable) == 0 {
+		delete(satisfiable)
+		return nil, .Unsatisfiable
+	}
+
+	return range_coalesce(satisfiable[:]), .Partial
+}
+
+// Sorts the ranges and merges the ones that overlap or touch, returns the merged ranges at the start of the slice.
+@(private)
+range_coalesce :: proc(ranges: []Byte_Range) -> []Byte_Range {
+	slice.sort_by(ranges, proc(a, b: Byte_Range) -> bool {
+		return a.start < b.start
+	})
+
+	n := 1
+	for r in ranges[1:] {
+		last := &ranges[n-1]
+		if r.start <= last.end {
+			last.end = max(last.end, r.end)
+			continue
+		}
+
+		ranges[n] = r
+		n += 1
+	}
+	return ranges[:n]
+}
+
+// Positions with more digits than this would overflow an int, they are past the end of any representation.
+@(private)
+Range_Max_Digits :: 18
+
+// What positions with more than Range_Max_Digits digits are parsed as, one more than it still fits an int.
+@(private)
+Range_Past_End :: 999_999_999_999_999_999
+
+@(private)
+range_parse_int :: proc(s: string) -> (n: int, ok: bool) {
+	if s == "" do return
+	for c in s {
+		if c < '0' || c > '9' do return
+	}
+	// Leading zeroes don't make it bigger.
+	if len(strings.trim_left(s, "0")) > Range_Max_Digits do return Range_Past_End, true
+	return strconv.parse_int(s, 10)
+}
+
+// Where the content of a ranged response comes from.
+@(private)
+Range_Source :: union {
+	[]byte,
+	os.Handle,
+}
+
+// Decides how to respond to the Range header of the request the response is for.
+// Ranges only apply to GET requests (and HEAD requests handled as GET), RFC 9110 14.2.
+@(private)
+response_ranges :: proc(r: ^Response, size: int, allocator := context.allocator) -> (ranges: []Byte_Range, result: Range_Result) {
+	if r._conn == nil || r._conn.curr_req == nil do return nil, .Full
+
+	req := r._conn.curr_req
+	rline, has_line := req.line.(Requestline)
+	if !has_line || rline.method != .Get do return nil, .Full
+
+	value, has_range := headers_get(&req.headers, "range")
+	if !has_range do return nil, .Full
+
+	// The representation changed since the client got the part it has, the full representation is sent.
+	if !request_if_range(req, r) do return nil, .Full
+
+	return range_parse(value, size, allocator)
+}
+
+// Sets the body to the content, or the requested ranges of it.
+// The validators are set and the preconditions of the request are evaluated first, see response_preconditions.
+@(private)
+respond_content :: proc(
+	r: ^Response,
+	content_type: string,
+	size: int,
+	src: Range_Source,
+	etag: string,
+	last_modified: time.Time,
+	allocator := context.allocator,
+) {
+	r.status = .Ok
+	headers_set(&r.headers, "accept-ranges", "bytes")
+
+	if !response_preconditions(r, etag, last_modified) do return
+
+	ranges, result := response_ranges(r, size, allocator)
+	switch result {
+	case .Full:
+		headers_set(&r.headers, "content-type", content_type)
+		range_source_body(r, src, {0, size}, allocator)
+
+	case .Unsatisfiable:
+		// RFC 9110 15.5.17: the content-range has the current length of the representation.
+		r.status = .Range_Not_Satisfiable
+		headers_set(&r.headers, "content-range", strings.concatenate({"bytes */", itoa(size, allocator)}, allocator))
+
+	case .Partial:
+		r.status = .Partial_Content
+
+		if len(ranges) == 1 {
+			headers_set(&r.headers, "content-type", content_type)
+			headers_set(&r.headers, "content-range", content_range(ranges[0], size, allocator))
+			range_source_body(r, src, ranges[0], allocator)
+			return
+		}
+
+		// RFC 9110 14.6: multiple ranges are sent as a multipart/byteranges body, every part has its own content-range.
+		boundary_buf: [16]byte
+		boundary := strconv.append_u64(boundary_buf[:], rand.uint64(), 16)
+		headers_set(&r.headers, "content-type", strings.concatenate({"multipart/byteranges; boundary=", boundary}, allocator))
+
+		parts := make([]Response_File_Part, len(ranges), allocator)
+		for rng, i in ranges {
+			parts[i] = {
+				head   = strings.concatenate({
+					"\r\n--", boundary,
+					"\r\ncontent-type: ", content_type,
+					"\r\ncontent-range: ", content_range(rng, size, allocator),
+					"\r\n\r\n",
+				}, allocator),
+				offset = rng.start,
+				length = rng.end - rng.start,
+			}
+		}
+		close_delimiter := strings.concatenate({"\r\n--", boundary, "--\r\n"}, allocator)
+
+		// Like a single range, a file is not read but the parts are sent from it when the response is.
+		if fd, is_file := src.(os.Handle); is_file {
+			file := Response_File{handle = fd, parts = parts, close_delimiter = close_delimiter}
+			response_file_set(r, file)
+			headers_set(&r.headers, "content-length", itoa(response_file_length(file), allocator))
+			return
+		}
+
+		content := src.([]byte)
+		for part in parts {
+			bytes.buffer_write_string(&r.body, part.head)
+			bytes.buffer_write(&r.body, content[part.offset:][:part.length])
+		}
+		bytes.buffer_write_string(&r.body, close_delimiter)
+	}
+}
+
+// Sets the range of the source as the body, a file is not read but sent when the response is, see respond_file.
+@(private)
+range_source_body :: proc(r: ^Response, src: Range_Source, rng: Byte_Range, allocator := context.allocator) {
+	if fd, is_file := src.(os.Handle); is_file {
+		response_file_set(r, {handle = fd, offset = rng.start, length = rng.end - rng.start})
+		headers_set(&r.headers, "content-length", itoa(rng.end - rng.start, allocator))
+		return
+	}
+
+	bytes.buffer_write(&r.body, src.([]byte)[rng.start:rng.end])
+}
+
+// Formats the content-range header value of a range, RFC 9110 14.4.
+@(private)
+content_range :: proc(r: Byte_Range, size: int, allocator := context.allocator) -> string {
+	return strings.concatenate({"bytes ", itoa(r.start, allocator), "-", itoa(r.end - 1, allocator), "/", itoa(size, allocator)}, allocator)
+}
+
+@(private)
+itoa :: proc(n: int, allocator := context.allocator) -> string {
+	buf := make([]byte, 32, allocator)
+	return strconv.itoa(buf, n)
+}
@@ -0,0 +1,67 @@
+package http
+
+import "core:slice"
+import "core:strings"
+import "core:testing"
+
+@(test)
+test_range_parse :: proc(t: ^testing.T) {
+	Case :: struct {
+		value:  string,
+		size:   int,
+		result: Range_Result,
+		ranges: []Byte_Range,
+	}
+
+	cases := []Case{
+		{"bytes=0-99", 1000, .Partial, {{0, 100}}},
+		{"bytes=900-", 1000, .Partial, {{900, 1000}}},
+		{"bytes=-100", 1000, .Partial, {{900, 1000}}},
+		{"bytes=-2000", 1000, .Partial, {{0, 1000}}},
+		{"bytes=0-99999", 1000, .Partial, {{0, 1000}}},
+		{"Bytes = 0-0", 1000, .Partial, {{0, 1}}},
+		{"bytes=, 0-1 ,", 1000, .Partial, {{0, 2}}},
+		// Sorted, and merged when they overlap or touch.
+		{"bytes=500-599, 0-0, 2-3, 1-1", 1000, .Partial, {{0, 4}, {500, 600}}},
+		{"bytes=0-500, 0-500, 100-", 1000, .Partial, {{0, 1000}}},
+		// Ranges past the end are left out.
+		{"bytes=1000-", 1000, .Unsatisfiable, nil},
+		{"bytes=2000-3000, 0-9", 1000, .Partial, {{0, 10}}},
+		{"bytes=-0", 1000, .Unsatisfiable, nil},
+		{"bytes=0-", 0, .Unsatisfiable, nil},
+		{"bytes=-5", 0, .Unsatisfiable, nil},
+		// Invalid headers are ignored.
+		{"items=0-1", 1000, .Full, nil},
+		{"bytes", 1000, .Full, nil},
+		{"bytes=5-1", 1000, .Full, nil},
+		{"bytes=abc", 1000, .Full, nil},
+		{"bytes=1-a", 1000, .Full, nil},
+		{"bytes=+1-2", 1000, .Full, nil},
+		{"bytes=1_0-20", 1000, .Full, nil},
+		{"bytes=-", 1000, .Full, nil},
+		// Positions too big for an int are past the end.
+		{"bytes=99999999999999999999-", 1000, .Unsatisfiable, nil},
+		{"bytes=99999999999999999999-99999999999999999999", 1000, .Unsatisfiable, nil},
+		{"bytes=0-99999999999999999999", 1000, .Partial, {{0, 1000}}},
+		{"bytes=-99999999999999999999", 1000, .Partial, {{0, 1000}}},
+		{"bytes=9223372036854775808-", 1000, .Unsatisfiable, nil},
+		{"bytes=0000000000000000000000005-9", 1000, .Partial, {{5, 10}}},
+	}
+
+	for c in cases {
+		ranges, result := range_parse(c.value, c.size, context.temp_allocator)
+		testing.expectf(t, result == c.result, "%q: expected %v, got %v", c.value, c.result, result)
+		testing.expectf(t, slice.equal(ranges, c.ranges), "%q: expected %v, got %v", c.value, c.ranges, ranges)
+	}
+
+	// Too many ranges are more work than sending everything.
+	many := strings.concatenate({"bytes=", strings.repeat("0-0,", Max_Ranges + 1, context.temp_allocator)}, context.temp_allocator)
+	_, result := range_parse(many, 1000, context.temp_allocator)
+	testing.expect_value(t, result, Range_Result.Full)
+
+	most := strings.concatenate({"bytes=", strings.repeat("0-0,", Max_Ranges, context.temp_allocator)}, context.temp_allocator)
+	ranges: []Byte_Range
+	ranges, result = range_parse(most, 1000, context.temp_allocator)
+	testing.expect_value(t, result, Range_Result.Partial)
+	testing.expect(t, slice.equal(ranges, []Byte_Range{{0, 1}}), "the same range is sent once")
+}
@@ -2,6 +2,7 @@ package http
 
 import "core:bufio"
 import "core:bytes"
+import "core:io"
 import "core:log"
 import "core:mem"
 import "core:net"
@@ -14,25 +15,38 @@ Request :: struct {
 	headers:    Headers,
 	url:        URL,
 	client:     net.Endpoint,
+	// Whether the request came in over a TLS connection.
+	tls:        bool,
 
 	// Route params/captures.
 	url_params: []string,
+	// Prefix of the route group the matched route was added to, the path within the group is
+	// `url.path[len(route_prefix):]`.
+	route_prefix: string,
+	// Names of the url_params, when the request matched a route in the route tree, see request_param.
+	_url_param_names: []string,
+
+	// The session of the client, set by middleware_session, nil without it.
+	session:    ^Session,
 
 	// Allocator that is freed after the request.
 	allocator:  mem.Allocator,
-	_body:      bufio.Scanner,
+	_body:      ^bufio.Scanner,
 	_body_err:  Body_Error,
+	// Whether the body has been (or is being) read, using request_body or request_body_reader.
+	_body_started: bool,
+	_body_reader:  ^Body_Reader,
 }
 
 request_init :: proc(r: ^Request, allocator: mem.Allocator = context.allocator) {
-	r.headers = make(Headers, 3, allocator)
+	headers_init(&r.headers, allocator)
 	r.allocator = allocator
 }
 
 server_headers_validate :: proc(headers: ^Headers) -> bool {
 	// RFC 7230 5.4: A server MUST respond with a 400 (Bad Request) status code to any
 	// HTTP/1.1 request message that lacks a Host header field.
-	("host" in headers) or_return
+	headers_has(headers, "host") or_return
 
     return headers_validate(headers)
 }
@@ -43,8 +57,8 @@ headers_validate :: proc(headers: ^Headers) -> bool {
 	// the final encoding, the message body length cannot be determined
 	// reliably; the server MUST respond with the 400 (Bad Request)
 	// status code and then close the connection.
-	if enc_header, ok := headers["transfer-encoding"]; ok {
-		strings.has_suffix(enc_header, "chunked") or_return
+	if headers_has(headers, "transfer-encoding") {
+		headers_chunked(headers) or_return
 	}
 
 	// RFC 7230 3.3.3: If a message is received with both a Transfer-Encoding and a
@@ -52,14 +66,37 @@ headers_validate :: proc(headers: ^Headers) -> bool {
 	// Content-Length.  Such a message might indicate an attempt to
 	// perform request smuggling (Section 9.5) or response splitting
 	// (Section 9.4) and ought to be handled as an error.
-	if "transfer-encoding" in headers && "content-length" in headers {
-		delete(headers["content-length"])
-		delete_key(headers, "content-length")
+	if headers_has(headers, "transfer-encoding") && headers_has(headers, "content-length") {
+		headers_delete(headers, "content-length")
 	}
 
 	return true
 }
 
+// Whether chunked is the final transfer coding, multiple transfer-encoding headers are read as one list.
+@(private)
+headers_chunked :: proc(headers: ^Headers) -> bool {
+	last: Maybe(string)
+	for entry in headers.entries {
+		if entry.key == "transfer-encoding" {
+			last = entry.value
+		}
+	}
+
+	enc, ok := last.?
+	return ok && strings.has_suffix(enc, "chunked")
+}
+
+// Removes the chunked coding from the transfer-encoding, after the body has been decoded.
+@(private)
+headers_remove_chunked :: proc(headers: ^Headers, allocator := context.allocator) {
+	encodings := headers_get_all(headers, "transfer-encoding", allocator)
+	defer delete(encodings, allocator)
+
+	encoding := encodings[0] if len(encodings) == 1 else strings.join(encodings, ", ", allocator)
+	headers_set(headers, "transfer-encoding", strings.trim_suffix(encoding, "chunked"))
+}
+
 Body_Error :: enum {
 	None,
 	No_Length,
@@ -68,6 +105,12 @@ Body_Error :: enum {
 	Scan_Failed,
 	Invalid_Chunk_Size,
 	Invalid_Trailer_Header,
+	// The multipart body is malformed, or it is not a multipart/form-data request.
+	Invalid_Multipart,
+	// A temporary file for a multipart upload could not be created or written to.
+	Temp_File_Failed,
+	// The content encoding of the body is not supported, or the body could not be decoded with it.
+	Invalid_Encoding,
 }
 
 // Any non-special body, could have been a chunked body that has been read in fully automatically.
@@ -89,6 +132,9 @@ body_error_status :: proc(e: Body_Error) -> Status {
 	switch e {
 	case .Too_Long:                             return .Payload_Too_Large
 	case .Scan_Failed, .Invalid_Trailer_Header: return .Bad_Request
+	case .Invalid_Multipart:                    return .Bad_Request
+	case .Temp_File_Failed:                     return .Internal_Server_Error
+	case .Invalid_Encoding:                     return .Unsupported_Media_Type
 	case .Invalid_Length, .Invalid_Chunk_Size:  return .Unprocessable_Content
 	case .No_Length:                            return .Length_Required
 	case .None:                                 return .Ok
@@ -115,60 +161,393 @@ body_destroy :: proc(body: Body_Type, was_allocation: bool) {
 // Free using body_destroy().
 request_body :: proc(req: ^Request, max_length: int = -1) -> (body: Body_Type, was_allocation: bool, err: Body_Error) {
 	defer req._body_err = err
-	assert(req._body_err == nil)
-    return parse_body(&req.headers, &req._body, max_length, req.allocator)
+	assert(!req._body_started, "the request body can only be read once")
+	req._body_started = true
+    return parse_body(&req.headers, req._body, max_length, req.allocator)
+}
+
+// Reads a body as it arrives, see request_body_reader.
+// Meant for internal use, you should use `http.request_body_reader`.
+Body_Reader :: struct {
+	// Trailers are added to these headers.
+	headers:     ^Headers,
+	scanner:     ^bufio.Scanner,
+	// Where the error is stored when reading fails.
+	err:         ^Body_Error,
+	allocator:   mem.Allocator,
+	chunked:     bool,
+	// Without a length, the body is everything until the connection is closed, only allowed for responses.
+	until_close: bool,
+	// The bytes left in the body, or in the current chunk when chunked.
+	remaining:   int,
+	read:        int,
+	max_length:  int,
+	done:        bool,
+}
+
+// Returns a reader over the request body, it is read from the connection as you read from the reader,
+// instead of reading it into memory first like request_body does.
+// This allows piping uploads into a file or hash without buffering them.
+//
+// Content-Length and chunked bodies are supported, chunked bodies are decoded as they are read,
+// trailer headers are added to the request headers once the reader returns .EOF.
+//
+// When reading goes wrong, the reader returns an io.Error and the details can be retrieved using request_body_error.
+// Reading more than max_length (when it is not -1) is a .Too_Long error.
+//
+// Can only be called once, and not together with request_body.
+request_body_reader :: proc(req: ^Request, max_length := -1) -> (r: io.Reader, err: Body_Error) {
+	defer req._body_err = err
+	assert(!req._body_started, "the request body can only be read once")
+	req._body_started = true
+
+	br := new(Body_Reader, req.allocator)
+	body_reader_init(br, &req.headers, req._body, &req._body_err, max_length, false, req.allocator) or_return
+
+	req._body_reader = br
+	return body_reader_to_reader(br), .None
+}
+
+// Sets up the reader for the body that follows the headers, based on the content-length or chunked transfer encoding.
+// With until_close, a body without either is read until the connection is closed (RFC 7230 3.3.3, for responses),
+// otherwise that is a .No_Length error.
+//
+// Meant for internal use, you should use `http.request_body_reader`.
+body_reader_init :: proc(
+	br: ^Body_Reader,
+	headers: ^Headers,
+	scanner: ^bufio.Scanner,
+	err: ^Body_Error,
+	max_length := -1,
+	until_close := false,
+	allocator := context.allocator,
+) -> Body_Error {
+	br^ = {
+		headers    = headers,
+		scanner    = scanner,
+		err        = err,
+		allocator  = allocator,
+		max_length = max_length,
+	}
+
+	if headers_chunked(headers) {
+		br.chunked = true
+		return .None
+	}
+
+	length, has_length := headers_get(headers, "content-length")
+	if !has_length {
+		if !until_close do return .No_Length
+
+		br.until_close = true
+		br.remaining = max(int)
+		return .None
+	}
+
+	ilen, ok := strconv.parse_int(length, 10)
+	if !ok || ilen < 0 {
+		return .Invalid_Length
+	}
+
+	if max_length > -1 && ilen > max_length {
+		return .Too_Long
+	}
+
+	br.remaining = ilen
+	br.done = ilen == 0
+	return .None
+}
+
+// Meant for internal use, you should use `http.request_body_reader`.
+body_reader_to_reader :: proc(br: ^Body_Reader) -> io.Reader {
+	s: io.Stream
+	s.data = br
+	s.procedure = _body_reader_stream_proc
+	return io.to_reader(s)
+}
+
+// Returns the error that occurred while reading the body with the reader from request_body_reader.
+request_body_error :: proc(req: ^Request) -> Body_Error {
+	return req._body_err
+}
+
+// Returns the value of the cookie with the name, from the Cookie header of the request.
+// When the client sent it more than once, like for different paths, the first (most specific) one is returned.
+request_cookie :: proc(req: ^Request, name: string) -> (value: string, ok: bool) {
+	for header in headers_get_all(&req.headers, "cookie", context.temp_allocator) {
+		header := header
+		for pair in strings.split_iterator(&header, ";") {
+			pair := strings.trim_space(pair)
+			eq := strings.index_byte(pair, '=')
+			if eq < 0 || pair[:eq] != name do continue
+
+			value = pair[eq+1:]
+			// RFC 6265 4.1.1: a value can be in double quotes.
+			if len(value) >= 2 && value[0] == '"' && value[len(value)-1] == '"' {
+				value = value[1:len(value)-1]
+			}
+			return value, true
+		}
+	}
+	return "", false
+}
+
+@(private)
+_body_reader_stream_proc :: proc(
+	stream_data: rawptr,
+	mode: io.Stream_Mode,
+	p: []byte,
+	offset: i64,
+	whence: io.Seek_From,
+) -> (
+	n: i64,
+	err: io.Error,
+) {
+	#partial switch mode {
+	case .Query:
+		return io.query_utility(io.Stream_Mode_Set{.Query, .Read})
+	case .Read:
+		read: int
+		read, err = body_reader_read((^Body_Reader)(stream_data), p)
+		n = i64(read)
+	case:
+		err = .Empty
+	}
+	return
+}
+
+// Meant for internal use, you should use `http.request_body_reader`.
+body_reader_read :: proc(br: ^Body_Reader, p: []byte) -> (n: int, err: io.Error) {
+	if br.err^ != nil do return 0, .Unknown
+	if br.done do return 0, .EOF
+	if len(p) == 0 do return 0, nil
+
+	fail :: proc(br: ^Body_Reader, err: Body_Error) -> io.Error {
+		br.err^ = err
+		br.done = true
+		return .Unknown if err != .Scan_Failed else .Unexpected_EOF
+	}
+
+	scanner := br.scanner
+
+	if br.chunked && br.remaining == 0 {
+		if berr := body_reader_next_chunk(br); berr != nil {
+			return 0, fail(br, berr)
+		}
+
+		if br.done do return 0, .EOF
+	}
+
+	n, err = scanner_read(scanner, p[:min(len(p), br.remaining)])
+	br.remaining -= n
+	br.read += n
+
+	if br.until_close {
+		if br.max_length > -1 && br.read > br.max_length {
+			return n, fail(br, .Too_Long)
+		}
+
+		// The connection being closed is the end of the body.
+		if err == .EOF || err == .Unexpected_EOF {
+			br.done = true
+			return n, .EOF if n == 0 else nil
+		}
+	}
+
+	if err == .EOF && br.remaining > 0 {
+		return n, fail(br, .Scan_Failed)
+	} else if err != nil && err != .EOF {
+		return n, fail(br, .Scan_Failed)
+	}
+	err = nil
+
+	if br.remaining == 0 {
+		if !br.chunked {
+			br.done = true
+			return
+		}
+
+		// Read the CRLF after the chunk data.
+		if !bufio.scanner_scan(scanner) || bufio.scanner_text(scanner) != "" {
+			return n, fail(br, .Scan_Failed)
+		}
+	}
+
+	return
+}
+
+// Reads the size line of the next chunk, RFC 7230 4.1.
+// When it is the last chunk, the trailer is read and the reader is done.
+@(private)
+body_reader_next_chunk :: proc(br: ^Body_Reader) -> Body_Error {
+	scanner := br.scanner
+	headers := br.headers
+
+	if !bufio.scanner_scan(scanner) {
+		return .Scan_Failed
+	}
+
+	size_line := bufio.scanner_text(scanner)
+
+	// If there is a semicolon, discard everything after it,
+	// that would be chunk extensions which we currently have no interest in.
+	if semi := strings.index_byte(size_line, ';'); semi > -1 {
+		size_line = size_line[:semi]
+	}
+
+	size, ok := strconv.parse_int(strings.trim_space(size_line), 16)
+	if !ok || size < 0 {
+		return .Invalid_Chunk_Size
+	}
+
+	if size > 0 {
+		if br.max_length > -1 && br.read + size > br.max_length {
+			return .Too_Long
+		}
+
+		br.remaining = size
+		return nil
+	}
+
+	// Last chunk, read the trailer until the empty line.
+	for {
+		if !bufio.scanner_scan(scanner) {
+			return .Scan_Failed
+		}
+
+		line := bufio.scanner_text(scanner)
+		if line == "" {
+			break
+		}
+
+		// The line is a slice into the scanner's buffer, which is reused by the next scan.
+		key, ok := header_parse(headers, strings.clone(line, br.allocator), br.allocator)
+		if !ok {
+			return .Invalid_Trailer_Header
+		}
+
+		// A recipient MUST ignore (or consider as an error) any fields that are forbidden to be sent in a trailer.
+		// Only the trailer that was just added is removed, the header with the same key is kept.
+		if !header_allowed_trailer(key) {
+			pop(&headers.entries)
+		}
+	}
+
+	headers_delete(headers, "trailer")
+	headers_remove_chunked(headers, br.allocator)
+
+	br.done = true
+	return nil
+}
+
+// Reads the rest of a body that the handler did not (fully) read, so the connection can be reused.
+// Returns false if the connection can not be reused, because the body is too big to discard or it could not be read.
+@(private)
+request_body_discard :: proc(req: ^Request) -> bool {
+	if !req._body_started {
+		if _, err := request_body_reader(req); err != nil {
+			return err == .No_Length
+		}
+	}
+
+	br := req._body_reader
+	if br == nil {
+		return req._body_err == nil || req._body_err == .No_Length
+	}
+
+	buf: [4096]byte
+	discarded := 0
+	for !br.done {
+		n, err := body_reader_read(br, buf[:])
+		if err != nil && err != .EOF {
+			return false
+		}
+
+		discarded += n
+		if discarded > Max_Post_Handler_Discard_Bytes {
+			return false
+		}
+	}
+
+	return true
+}
+
+// Reads from the scanner's buffer first, and from the underlying reader when it is empty.
+// This way bytes can be read without the scanner's token size limits, and without losing buffered data.
+@(private)
+scanner_read :: proc(s: ^bufio.Scanner, p: []byte) -> (n: int, err: io.Error) {
+	if s.end > s.start {
+		n = copy(p, s.buf[s.start:s.end])
+		s.start += n
+		return
+	}
+
+	return io.read(s.r, p)
 }
 
 // Meant for internal use, you should use `http.request_body`.
 parse_body :: proc(headers: ^Headers, _body: ^bufio.Scanner, max_length := -1, allocator := context.allocator) -> (body: Body_Type, was_allocation: bool, err: Body_Error) {
-	if enc_header, ok := headers["transfer-encoding"]; ok && strings.has_suffix(enc_header, "chunked") {
+	plain: Body_Plain
+	plain, was_allocation = parse_body_plain(headers, _body, max_length, allocator) or_return
+	body = plain
+
+	// Automatically decode url encoded bodies.
+	if typ, ok := headers_get(headers, "content-type"); ok && typ == "application/x-www-form-urlencoded" {
+		defer if was_allocation do delete(plain)
+		body = body_url_decode(plain, allocator)
+	}
+
+	return
+}
+
+// Reads the body based on the content-length or chunked transfer encoding, without decoding it any further.
+// Meant for internal use, you should use `http.request_body`.
+parse_body_plain :: proc(headers: ^Headers, _body: ^bufio.Scanner, max_length := -1, allocator := context.allocator) -> (body: Body_Plain, was_allocation: bool, err: Body_Error) {
+	if headers_chunked(headers) {
         was_allocation = true
 		body = request_body_chunked(headers, _body, max_length, allocator) or_return
 	} else {
 		body = request_body_length(headers, _body, max_length) or_return
 	}
+	return
+}
 
-	// Automatically decode url encoded bodies.
-	if typ, ok := headers["content-type"]; ok && typ == "application/x-www-form-urlencoded" {
-		plain := body.(Body_Plain)
-        defer if was_allocation do delete(plain)
-
-		keyvalues := strings.split(plain, "&", allocator)
-        defer delete(keyvalues, allocator)
-
-		queries := make(Body_Url_Encoded, len(keyvalues), allocator)
-		for keyvalue in keyvalues {
-			seperator := strings.index(keyvalue, "=")
-			if seperator == -1 { 	// The keyvalue has no value.
-				queries[keyvalue] = ""
-				continue
-			}
-
-            key, key_decoded_ok := net.percent_decode(keyvalue[:seperator], allocator)
-            if !key_decoded_ok {
-                log.warnf("url encoded body key %q could not be decoded", keyvalue[:seperator])
-                continue
-            }
+// Decodes an application/x-www-form-urlencoded body.
+// Meant for internal use, you should use `http.request_body`.
+body_url_decode :: proc(plain: string, allocator := context.allocator) -> Body_Url_Encoded {
+	keyvalues := strings.split(plain, "&", allocator)
+	defer delete(keyvalues, allocator)
+
+	queries := make(Body_Url_Encoded, len(keyvalues), allocator)
+	for keyvalue in keyvalues {
+		seperator := strings.index(keyvalue, "=")
+		if seperator == -1 { 	// The keyvalue has no value.
+			queries[keyvalue] = ""
+			continue
+		}
 
-			val, val_decoded_ok := net.percent_decode(keyvalue[seperator + 1:], allocator)
-            if !val_decoded_ok {
-                log.warnf("url encoded body value %q for key %q could not be decoded", keyvalue[seperator+1:], key)
-                continue
-            }
+		key, key_decoded_ok := net.percent_decode(keyvalue[:seperator], allocator)
+		if !key_decoded_ok {
+			log.warnf("url encoded body key %q could not be decoded", keyvalue[:seperator])
+			continue
+		}
 
-			queries[key] = val
+		val, val_decoded_ok := net.percent_decode(keyvalue[seperator + 1:], allocator)
+		if !val_decoded_ok {
+			log.warnf("url encoded body value %q for key %q could not be decoded", keyvalue[seperator+1:], key)
+			continue
 		}
 
-		body = queries
+		queries[key] = val
 	}
 
-	return
+	return queries
 }
 
 // "Decodes" a request body based on the content length header.
 // Meant for internal usage, you should use `http.request_body`.
 request_body_length :: proc(headers: ^Headers, _body: ^bufio.Scanner, max_length: int) -> (string, Body_Error) {
-	len, ok := headers["content-length"]
+	len, ok := headers_get(headers, "content-length")
 	if !ok {
 		return "", .No_Length
 	}
@@ -304,18 +683,15 @@ request_body_chunked :: proc(headers: ^Headers, _body: ^bufio.Scanner, max_lengt
 		}
 
 		// A recipient MUST ignore (or consider as an error) any fields that are forbidden to be sent in a trailer.
+		// Only the trailer that was just added is removed, the header with the same key is kept.
 		if !header_allowed_trailer(key) {
-			delete(headers[key])
-			delete_key(headers, key)
+			delete(key)
+			pop(&headers.entries)
 		}
 	}
 
-	if "trailer" in headers {
-		delete(headers["trailer"])
-		delete_key(headers, "trailer")
-	}
-
-	headers["transfer-encoding"] = strings.trim_suffix(headers["transfer-encoding"], "chunked")
+	headers_delete(headers, "trailer")
+	headers_remove_chunked(headers, allocator)
 
 	return bytes.buffer_to_string(&body_buff), .None
 }
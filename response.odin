@@ -1,7 +1,6 @@
 package http
 
 import "core:bytes"
-import "core:net"
 import "core:os"
 import "core:io"
 import "core:path/filepath"
@@ -15,22 +14,77 @@ Response :: struct {
 	headers: Headers,
 	cookies: [dynamic]Cookie,
 	body:    bytes.Buffer,
+	// Sent after a chunked body, see response_trailer_set.
+	trailers: Headers,
+
+	// The connection this is a response on, set by the server.
+	_conn:         ^Connection,
+	// The HTTP/2 stream this is a response on, nil for HTTP/1.1.
+	_h2_stream:    ^H2_Stream,
+	_headers_sent: bool,
+	_send_body:    bool,
+	_chunked:      bool,
+	// Whether this answers a HEAD request, also while the handler sees it as a GET, see Server_Opts.redirect_head_to_get.
+	_head:         bool,
+	// Set by middleware_compress, compresses the body when it is streamed.
+	_compress:     ^Compressor,
+	// Set when the streamed body is done, so the compressor writes its end.
+	_ending:       bool,
+	// A file that is sent as the body instead of the body buffer, see respond_file.
+	_file:         Maybe(Response_File),
+}
+
+// A part of an open file, sent as the body of a response without reading it into memory.
+// The response owns the handle, it is closed when the response is sent.
+@(private)
+Response_File :: struct {
+	handle:          os.Handle,
+	offset:          int,
+	length:          int,
+	// When set, the body is multipart/byteranges and offset and length are not used:
+	// every part is its head followed by its range of the file, after the parts comes the close delimiter.
+	parts:           []Response_File_Part,
+	close_delimiter: string,
+}
+
+// A part of a multipart/byteranges file body, see respond_content.
+@(private)
+Response_File_Part :: struct {
+	// The delimiter and header fields of the part.
+	head:   string,
+	offset: int,
+	length: int,
+}
+
+@(private)
+Sendfile_Result :: enum {
+	Ok,
+	// The file or socket does not support sendfile, the rest is read and written instead.
+	Unsupported,
+	Failed,
 }
 
 response_init :: proc(r: ^Response, allocator := context.allocator) {
 	r.status = .NotFound
-	r.headers = make(Headers, 3, allocator)
-	r.headers["server"] = "Odin"
+	headers_init(&r.headers, allocator)
+	headers_set(&r.headers, "server", "Odin")
+	headers_init(&r.trailers, allocator)
 	bytes.buffer_init_allocator(&r.body, 0, 0, allocator)
 }
 
+// The amount of bytes written using response_write that are buffered before they are sent.
+@(private)
+Response_Stream_Buffer_Size :: 16 << 10
+
 // Sends the response over the connection.
-response_send :: proc(using r: ^Response, conn: ^Connection, allocator := context.allocator) -> net.Network_Error {
+response_send :: proc(using r: ^Response, conn: ^Connection, allocator := context.allocator) -> io.Error {
 	res: bytes.Buffer
 	// Responses are on average at least 100 bytes, so lets start there, but add the body's length.
 	initial_buf_cap := response_needs_content_length(r, conn) ? 100 + bytes.buffer_length(&body) : 100
 	bytes.buffer_init_allocator(&res, 0, initial_buf_cap, allocator)
 
+	defer response_file_close(r)
+
 	will_close := response_must_close(conn.curr_req, r)
 	defer if will_close do connection_close(conn)
 
@@ -38,74 +92,441 @@ response_send :: proc(using r: ^Response, conn: ^Connection, allocator := contex
 	// the entire request message body or close the connection after sending
 	// its response, since otherwise the remaining data on a persistent
 	// connection would be misinterpreted as the next request.
-	if !will_close {
+	// An informational response (100 Continue) is sent before the handler reads the body.
+	if !will_close && !status_informational(status) {
+		// Read what is left of the body, if the handler did not read all of it.
+		if !request_body_discard(conn.curr_req) {
+			headers_set(&headers, "connection", "close")
+			will_close = true
+		}
+
 		switch conn.curr_req._body_err {
 		case .Scan_Failed, .Invalid_Length, .Invalid_Chunk_Size, .Too_Long, .Invalid_Trailer_Header:
 			// Any read error should close the connection.
 			status = body_error_status(conn.curr_req._body_err)
-			headers["connection"] = "close"
+			headers_set(&headers, "connection", "close")
+			will_close = true
+		case .No_Length, .None, .Invalid_Multipart, .Temp_File_Failed, .Invalid_Encoding:
+			// no-op, request had no body, read succeeded, or the error did not break the framing.
+		}
+
+		// RFC 9110 15.5.9: the body did not arrive within the read timeout.
+		if conn.timed_out {
+			status = .Request_Timeout
+			headers_set(&headers, "connection", "close")
 			will_close = true
-		case .No_Length, .None: // no-op, request had no body or read succeeded.
-		case:
-			// No error means the body was not read by a handler.
-			_, _, err := request_body(conn.curr_req, Max_Post_Handler_Discard_Bytes)
-			switch err {
-			case .Scan_Failed, .Invalid_Length, .Invalid_Trailer_Header, .Too_Long, .Invalid_Chunk_Size:
-				// Any read error should close the connection.
-				status = body_error_status(conn.curr_req._body_err)
-				headers["connection"] = "close"
-				will_close = true
-			case .No_Length, .None: // no-op, request had no body or read succeeded.
-			case:
-				assert(err != nil, "always expect error from request_body")
-			}
 		}
 	}
 
-	bytes.buffer_write_string(&res, "HTTP/1.1 ")
-	bytes.buffer_write_string(&res, status_string(status))
-	bytes.buffer_write_string(&res, "\r\n")
+	// The status was replaced because of the request, the file is not the response anymore.
+	if _file != nil && !status_success(status) {
+		response_file_close(r)
+		headers_delete(&headers, "content-length")
+	}
 
-	// Per RFC 9910 6.6.1 a Date header must be added in 2xx, 3xx, 4xx responses.
-	if status >= .Ok && status <= .Internal_Server_Error && "date" not_in headers {
-		headers["date"] = format_date_header(time.now(), allocator)
+	// The handler has started streaming the body, or trailers have been set which need a chunked body.
+	// A file body that is compressed is streamed too.
+	if _headers_sent || len(trailers.entries) > 0 || (_file != nil && _compress != nil) {
+		response_file_stream(r) or_return
+		return response_end(r)
 	}
 
 	// Write the status code as the body, if there is no body set by the handlers.
 	if response_can_have_body(r, conn) && !status_success(status) && bytes.buffer_length(&body) == 0 {
 		bytes.buffer_write_string(&body, status_string(status))
-		headers["content-type"] = mime_to_content_type(.Plain)
+		headers_set(&headers, "content-type", mime_to_content_type(.Plain))
 	}
 
-	if "content-length" not_in headers && response_needs_content_length(r, conn) {
+	if !headers_has(&headers, "content-length") && response_needs_content_length(r, conn) {
 		buf := make([]byte, 32, allocator)
-		headers["content-length"] = strconv.itoa(buf, bytes.buffer_length(&body))
+		headers_set(&headers, "content-length", strconv.itoa(buf, bytes.buffer_length(&body)))
+	}
+
+	response_write_head(r, &res, allocator)
+
+	if response_can_have_body(r, conn) {
+		if _file != nil {
+			io.write(io.to_writer(conn.stream), bytes.buffer_to_bytes(&res)) or_return
+			return response_file_send(r, conn)
+		}
+
+		bytes.buffer_write(&res, bytes.buffer_to_bytes(&body))
+	}
+
+	_, err := io.write(io.to_writer(conn.stream), bytes.buffer_to_bytes(&res))
+
+	return err
+}
+
+// Sets the file body of the response, closing the previous one.
+@(private)
+response_file_set :: proc(r: ^Response, file: Response_File) {
+	response_file_close(r)
+	r._file = file
+}
+
+@(private)
+response_file_close :: proc(r: ^Response) {
+	if file, ok := r._file.?; ok {
+		os.close(file.handle)
+		r._file = nil
+	}
+}
+
+// The length of the body that is sent for the file, including the heads of the parts.
+@(private)
+response_file_length :: proc(file: Response_File) -> int {
+	if len(file.parts) == 0 do return file.length
+
+	n := len(file.close_delimiter)
+	for part in file.parts {
+		n += len(part.head) + part.length
+	}
+	return n
+}
+
+// Sends the file body of an HTTP/1.1 response, after the head has been sent.
+// Over plain TCP the kernel copies the file to the socket (sendfile(2) on Linux), with TLS it is read and written in parts,
+// either way memory use does not depend on the size of the file.
+@(private)
+response_file_send :: proc(r: ^Response, conn: ^Connection) -> io.Error {
+	file := r._file.?
+	if len(file.parts) == 0 {
+		return response_file_send_range(conn, file.handle, file.offset, file.length)
+	}
+
+	w := io.to_writer(conn.stream)
+	for part in file.parts {
+		io.write_string(w, part.head) or_return
+		response_file_send_range(conn, file.handle, part.offset, part.length) or_return
+	}
+	_, err := io.write_string(w, file.close_delimiter)
+	return err
+}
+
+@(private)
+response_file_send_range :: proc(conn: ^Connection, handle: os.Handle, start, length: int) -> io.Error {
+	offset, end := start, start + length
+
+	if conn.ssl == nil {
+		sent, result := sendfile(conn.socket, handle, start, length)
+		switch result {
+		case .Ok:          return nil
+		case .Failed:      return .Unexpected_EOF
+		case .Unsupported: offset += sent
+		}
+	}
+
+	buf: [Response_Stream_Buffer_Size]byte
+	w := io.to_writer(conn.stream)
+	for offset < end {
+		n, err := os.read_at(handle, buf[:min(len(buf), end - offset)], i64(offset))
+		if err != os.ERROR_NONE || n <= 0 do return .Unexpected_EOF
+
+		io.write(w, buf[:n]) or_return
+		offset += n
+	}
+
+	return nil
+}
+
+// Writes the file body using response_write, so it is compressed, sent as HTTP/2 DATA frames, or followed by trailers.
+// Does nothing when there is no file body.
+@(private)
+response_file_stream :: proc(r: ^Response) -> io.Error {
+	file, has_file := r._file.?
+	if !has_file do return nil
+
+	response_headers_send(r) or_return
+	if !r._send_body do return nil
+
+	if len(file.parts) == 0 {
+		return response_file_stream_range(r, file.handle, file.offset, file.length)
 	}
 
-	for header, value in headers {
-		bytes.buffer_write_string(&res, header)
-		bytes.buffer_write_string(&res, ": ")
+	for part in file.parts {
+		response_write(r, transmute([]byte)part.head) or_return
+		response_file_stream_range(r, file.handle, part.offset, part.length) or_return
+	}
+	_, err := response_write(r, transmute([]byte)file.close_delimiter)
+	return err
+}
 
-		// Escape newlines in headers, if we don't, an attacker can find an endpoint
-		// that returns a header with user input, and inject headers into the response.
-		esc_value, _ := strings.replace_all(value, "\n", "\\n", allocator)
-		bytes.buffer_write_string(&res, esc_value)
+@(private)
+response_file_stream_range :: proc(r: ^Response, handle: os.Handle, start, length: int) -> io.Error {
+	buf: [Response_Stream_Buffer_Size]byte
+	offset, end := start, start + length
+	for offset < end {
+		n, err := os.read_at(handle, buf[:min(len(buf), end - offset)], i64(offset))
+		if err != os.ERROR_NONE || n <= 0 do return .Unexpected_EOF
+
+		response_write(r, buf[:n]) or_return
+		offset += n
+	}
+
+	return nil
+}
 
-		bytes.buffer_write_string(&res, "\r\n")
+// Writes the status line and headers.
+@(private)
+response_write_head :: proc(using r: ^Response, res: ^bytes.Buffer, allocator := context.allocator) {
+	bytes.buffer_write_string(res, "HTTP/1.1 ")
+	bytes.buffer_write_string(res, status_string(status))
+	bytes.buffer_write_string(res, "\r\n")
+
+	// Per RFC 9910 6.6.1 a Date header must be added in 2xx, 3xx, 4xx responses.
+	if status >= .Ok && status <= .Internal_Server_Error && !headers_has(&headers, "date") {
+		headers_set(&headers, "date", format_date_header(time.now(), allocator))
+	}
+
+	for header in headers_combined(&headers, allocator) {
+		response_write_header(res, header.key, header.value, allocator)
 	}
 
 	for cookie in cookies {
-		bytes.buffer_write_string(&res, cookie_string(cookie))
-		bytes.buffer_write_string(&res, "\r\n")
+		bytes.buffer_write_string(res, cookie_string(cookie, allocator))
+		bytes.buffer_write_string(res, "\r\n")
 	}
 
 	// Empty line denotes end of headers and start of body.
-	bytes.buffer_write_string(&res, "\r\n")
+	bytes.buffer_write_string(res, "\r\n")
+}
+
+@(private)
+response_write_header :: proc(res: ^bytes.Buffer, header, value: string, allocator := context.allocator) {
+	bytes.buffer_write_string(res, header)
+	bytes.buffer_write_string(res, ": ")
+
+	// Escape newlines in headers, if we don't, an attacker can find an endpoint
+	// that returns a header with user input, and inject headers into the response.
+	esc_value, _ := strings.replace_all(value, "\n", "\\n", allocator)
+	bytes.buffer_write_string(res, esc_value)
 
-	if response_can_have_body(r, conn) do bytes.buffer_write(&res, bytes.buffer_to_bytes(&body))
+	bytes.buffer_write_string(res, "\r\n")
+}
+
+// Sends the status line and headers, after which the body can be streamed using response_write and response_flush.
+// This makes it possible to send bodies that are too big to keep in memory, or that are generated over time.
+//
+// When there is no content-length header, the body is sent with "Transfer-Encoding: chunked" (or as HTTP/2 DATA frames).
+// Headers, cookies and the status can not be changed after this.
+//
+// This is called by response_write and response_flush if the headers have not been sent yet.
+response_headers_send :: proc(r: ^Response) -> io.Error {
+	if r._headers_sent do return nil
+	r._headers_sent = true
+
+	conn := r._conn
+	assert(conn != nil, "response is not attached to a connection, only responses given to handlers can be streamed")
+
+	r._send_body = response_can_have_body(r, conn)
+
+	if r._compress != nil {
+		compress_stream_start(r)
+	}
+
+	// Let the client know which trailers to expect.
+	if len(r.trailers.entries) > 0 {
+		names := strings.builder_make(context.temp_allocator)
+		for trailer in headers_combined(&r.trailers, context.temp_allocator) {
+			if strings.builder_len(names) > 0 do strings.write_string(&names, ", ")
+			strings.write_string(&names, trailer.key)
+		}
+		headers_set(&r.headers, "trailer", strings.to_string(names))
+	}
+
+	if r._h2_stream != nil {
+		if !h2_response_headers(conn, conn.h2, r._h2_stream, r, !r._send_body) || !h2_flush(conn, conn.h2) {
+			return .Unexpected_EOF
+		}
+		return nil
+	}
+
+	// Without a known length, the body is sent in chunks, the end is marked by an empty chunk.
+	if r._send_body && !headers_has(&r.headers, "content-length") {
+		headers_set(&r.headers, "transfer-encoding", "chunked")
+		r._chunked = true
+	}
+
+	res: bytes.Buffer
+	bytes.buffer_init_allocator(&res, 0, 100, context.temp_allocator)
+	response_write_head(r, &res, context.temp_allocator)
+
+	_, err := io.write(io.to_writer(conn.stream), bytes.buffer_to_bytes(&res))
+	return err
+}
+
+// Writes to the body of the response, sending the headers first if they haven't been sent yet.
+//
+// The data is buffered, and sent when the buffer is full or when response_flush is called.
+// When the response is done, the rest of the body is sent after the handler returns.
+response_write :: proc(r: ^Response, data: []byte) -> (n: int, err: io.Error) {
+	response_headers_send(r) or_return
+
+	bytes.buffer_write(&r.body, data)
+	if bytes.buffer_length(&r.body) >= Response_Stream_Buffer_Size {
+		response_flush(r) or_return
+	}
+
+	return len(data), nil
+}
+
+response_write_string :: proc(r: ^Response, data: string) -> (n: int, err: io.Error) {
+	return response_write(r, transmute([]byte)data)
+}
+
+// Returns a writer that writes to the response using response_write, and flushes on io.flush.
+response_writer :: proc(r: ^Response) -> io.Writer {
+	s: io.Stream
+	s.data = r
+	s.procedure = _response_stream_proc
+	return io.to_writer(s)
+}
+
+@(private)
+_response_stream_proc :: proc(
+	stream_data: rawptr,
+	mode: io.Stream_Mode,
+	p: []byte,
+	offset: i64,
+	whence: io.Seek_From,
+) -> (
+	n: i64,
+	err: io.Error,
+) {
+	r := (^Response)(stream_data)
+
+	#partial switch mode {
+	case .Query:
+		return io.query_utility(io.Stream_Mode_Set{.Query, .Write, .Flush})
+	case .Write:
+		written: int
+		written, err = response_write(r, p)
+		n = i64(written)
+	case .Flush:
+		err = response_flush(r)
+	case:
+		err = .Empty
+	}
+	return
+}
+
+// Sends everything that has been written with response_write so far.
+// Useful for long-polling and server-sent events, where the client should receive data right away.
+response_flush :: proc(r: ^Response) -> io.Error {
+	response_headers_send(r) or_return
+	defer bytes.buffer_reset(&r.body)
+
+	data := bytes.buffer_to_bytes(&r.body)
+	if !r._send_body do return nil
+
+	if r._compress != nil {
+		data = compress_stream_part(r._compress, data, r._ending)
+	}
+	if len(data) == 0 do return nil
+
+	conn := r._conn
+	if r._h2_stream != nil {
+		if !h2_response_data(conn, conn.h2, r._h2_stream, data, false) || !h2_flush(conn, conn.h2) {
+			return .Unexpected_EOF
+		}
+		return nil
+	}
+
+	w := io.to_writer(conn.stream)
+	if !r._chunked {
+		io.write(w, data) or_return
+		return nil
+	}
+
+	// RFC 7230 4.1: chunk = chunk-size CRLF chunk-data CRLF
+	size_buf: [18]byte
+	size := strconv.append_int(size_buf[:16], i64(len(data)), 16)
+	io.write_string(w, size) or_return
+	io.write_string(w, "\r\n") or_return
+	io.write(w, data) or_return
+	io.write_string(w, "\r\n") or_return
+	return nil
+}
+
+// Takes over the connection of the response, for a protocol the server does not speak,
+// like tunneling a connection after a 101 Switching Protocols, see websocket_upgrade for WebSockets.
+//
+// Nothing is sent, the handler is responsible for the connection from now on, the server won't respond or handle
+// any more requests on it. Use connection_read and connection_write, and close it with connection_close.
+// When the server shuts down, the connection is shut down after Server_Opts.shutdown_timeout, reads and writes then fail.
+//
+// The handler can keep using the connection on its thread, a new worker takes its place in the pool,
+// and the thread exits when the handler returns.
+//
+// Returns false for HTTP/2 connections, which multiplex requests, and when the response has already started.
+response_hijack :: proc(r: ^Response) -> (c: ^Connection, ok: bool) {
+	c = r._conn
+	if c == nil || r._h2_stream != nil || r._headers_sent do return nil, false
+
+	connection_hijack(c)
+	r._headers_sent = true
+	return c, true
+}
+
+// Stops the response without finishing it, for when a streamed body can not be completed,
+// like when the upstream of a proxy fails halfway.
+// The client sees an incomplete response, instead of one that looks complete but is not.
+//
+// The HTTP/1.1 connection is closed, an HTTP/2 stream is reset.
+response_abort :: proc(r: ^Response) {
+	conn := r._conn
+	if conn == nil do return
+
+	if r._h2_stream != nil {
+		if !r._h2_stream.reset {
+			r._h2_stream.reset = true
+			h2_rst_stream(conn.h2, r._h2_stream.id, .Internal_Error)
+			h2_flush(conn, conn.h2)
+		}
+		return
+	}
+
+	connection_close(conn)
+}
+
+// Sets a trailer, a header that is sent after the body, like a checksum of a streamed body.
+// Trailers should be set before the headers are sent, so they can be announced in the trailer header,
+// their values can change until the response is done.
+//
+// Returns false if the header is not allowed as a trailer, see header_allowed_trailer.
+response_trailer_set :: proc(r: ^Response, key, value: string) -> bool {
+	header_allowed_trailer(key) or_return
+	headers_set(&r.trailers, key, value)
+	return true
+}
+
+// Sends the rest of a streamed body, followed by the trailers.
+@(private)
+response_end :: proc(r: ^Response) -> io.Error {
+	r._ending = true
+	response_flush(r) or_return
+
+	conn := r._conn
+	if r._h2_stream != nil {
+		if !h2_response_end(conn, conn.h2, r._h2_stream, r) {
+			return .Unexpected_EOF
+		}
+		return nil
+	}
 
-	_, err := net.send_tcp(conn.socket, bytes.buffer_to_bytes(&res))
+	if !r._chunked do return nil
 
+	// RFC 7230 4.1.2: last-chunk trailer-part CRLF
+	res: bytes.Buffer
+	bytes.buffer_init_allocator(&res, 0, 32, context.temp_allocator)
+	bytes.buffer_write_string(&res, "0\r\n")
+	for trailer in headers_combined(&r.trailers, context.temp_allocator) {
+		response_write_header(&res, trailer.key, trailer.value, context.temp_allocator)
+	}
+	bytes.buffer_write_string(&res, "\r\n")
+
+	_, err := io.write(io.to_writer(conn.stream), bytes.buffer_to_bytes(&res))
 	return err
 }
 
@@ -113,35 +534,67 @@ response_send :: proc(using r: ^Response, conn: ^Connection, allocator := contex
 respond_html :: proc(using r: ^Response, html: string) {
 	status = .Ok
 	bytes.buffer_write_string(&body, html)
-	headers["content-type"] = mime_to_content_type(Mime_Type.Html)
+	headers_set(&headers, "content-type", mime_to_content_type(Mime_Type.Html))
 }
 
 // Sets the response to one that sends the given plain text.
 respond_plain :: proc(using r: ^Response, text: string) {
 	status = .Ok
 	bytes.buffer_write_string(&body, text)
-	headers["content-type"] = mime_to_content_type(Mime_Type.Plain)
+	headers_set(&headers, "content-type", mime_to_content_type(Mime_Type.Plain))
 }
 
 // Sets the response to one that sends the contents of the file at the given path.
-// Content-Type header is set based on the file extension, see the MimeType enum for known file extensions.
+// Content-Type header is set based on the file extension, see mime_lookup,
+// when the extension is not known the start of the file is sniffed, see mime_sniff.
+//
+// The Range header of the request is honored, only the requested ranges of the file are read.
+// The file is not read into memory, it is sent when the handler returns, using sendfile(2) on Linux when possible.
+// The ETag and Last-Modified headers are set, and conditional requests are answered with a 304 or 412.
 respond_file :: proc(using r: ^Response, path: string, allocator := context.allocator) {
-	bs, ok := os.read_entire_file(path, allocator)
-	if !ok {
+	content_type, _ := mime_lookup(path)
+	respond_file_typed(r, path, content_type, allocator)
+}
+
+// Like respond_file, with the given content type instead of the one of the file extension, empty to sniff it.
+@(private)
+respond_file_typed :: proc(using r: ^Response, path, content_type: string, allocator := context.allocator) {
+	fd, err := os.open(path)
+	if err != os.ERROR_NONE {
+		status = .NotFound
+		return
+	}
+	defer {
+		// Unless it is the body now, it is then closed after the response is sent.
+		if file, is_body := _file.?; !is_body || file.handle != fd do os.close(fd)
+	}
+
+	fi, serr := os.fstat(fd, allocator)
+	defer os.file_info_delete(fi, allocator)
+	if serr != os.ERROR_NONE || fi.is_dir {
 		status = .NotFound
 		return
 	}
 
-	respond_file_content(r, path, bs)
+	content_type := content_type
+	if content_type == "" {
+		sniff: [Mime_Sniff_Length]byte
+		n, _ := os.read_at(fd, sniff[:], 0)
+		content_type = mime_sniff(sniff[:max(n, 0)])
+	}
+
+	etag := etag_file(fi.size, fi.modification_time, allocator)
+	respond_content(r, content_type, int(fi.size), fd, etag, fi.modification_time, allocator)
 }
 
+// Sets the response to one that sends the given content, as the file at the given path.
+// The Content-Type is based on the file extension, or sniffed from the content when it is not known.
+// The Range header of the request is honored, and an ETag based on the content is set.
 respond_file_content :: proc(using r: ^Response, path: string, content: []byte) {
-	mime := mime_from_extension(path)
-	content_type := mime_to_content_type(mime)
+	content_type, known := mime_lookup(path)
+	if !known do content_type = mime_sniff(content)
 
-	status = .Ok
-	headers["content-type"] = content_type
-	bytes.buffer_write(&body, content)
+	respond_content(r, content_type, len(content), content, etag_content(content, context.temp_allocator), {})
 }
 
 // Sets the response to one that, based on the request path, returns a file.
@@ -150,7 +603,7 @@ respond_file_content :: proc(using r: ^Response, path: string, content: []byte)
 // request: The request path.
 //
 // Path traversal is detected and cleaned up.
-// The Content-Type is set based on the file extension, see the MimeType enum for known file extensions.
+// The Content-Type is set based on the file extension, see respond_file.
 respond_dir :: proc(using r: ^Response, base, target, request: string, allocator := context.allocator) {
 	if !strings.has_prefix(request, base) {
 		status = .NotFound
@@ -176,7 +629,7 @@ respond_json :: proc(using r: ^Response, v: any, opt: json.Marshal_Options = {})
 	json.marshal_to_writer(io.to_writer(stream), v, &opt) or_return
 
 	status = .Ok
-	headers["content-type"] = mime_to_content_type(Mime_Type.Json)
+	headers_set(&headers, "content-type", mime_to_content_type(Mime_Type.Json))
 
 	return nil
 }
@@ -346,7 +799,8 @@ cookie_parse :: proc(value: string, allocator := context.allocator) -> (cookie:
 // (Successful) response to a CONNECT request.
 @(private)
 response_needs_content_length :: proc(r: ^Response, conn: ^Connection) -> bool {
-	if status_informational(r.status) || r.status == .No_Content {
+	// RFC 9110 15.4.5: a 304 has no content, a content-length would have to be that of the 200 response.
+	if status_informational(r.status) || r.status == .No_Content || r.status == .Not_Modified {
 		return false
 	}
 
@@ -364,6 +818,7 @@ response_needs_content_length :: proc(r: ^Response, conn: ^Connection) -> bool {
 @(private)
 response_can_have_body :: proc(r: ^Response, conn: ^Connection) -> bool {
 	response_needs_content_length(r, conn) or_return
+	if r._head do return false
 
 	if rline, ok := conn.curr_req.line.(Requestline); ok {
 		return rline.method != .Head
@@ -378,9 +833,9 @@ response_can_have_body :: proc(r: ^Response, conn: ^Connection) -> bool {
 // If we are responding with a close connection header, make sure we close.
 @(private)
 response_must_close :: proc(req: ^Request, res: ^Response) -> bool {
-	if req, req_has := req.headers["connection"]; req_has && req == "close" {
+	if req, req_has := headers_get(&req.headers, "connection"); req_has && req == "close" {
 		return true
-	} else if res, res_has := res.headers["connection"]; res_has && res == "close" {
+	} else if res, res_has := headers_get(&res.headers, "connection"); res_has && res == "close" {
 		return true
 	}
 
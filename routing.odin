@@ -1,5 +1,6 @@
 package http
 
+import "core:fmt"
 import "core:log"
 import "core:net"
 import "core:strings"
@@ -21,6 +22,17 @@ url_parse :: proc(raw: string, allocator := context.allocator) -> URL {
 	return url
 }
 
+// Removes the prefix when it is made up of whole segments of the path,
+// "/api" is removed from "/api" and "/api/users", but not from "/apiary".
+path_trim_prefix :: proc(path, prefix: string) -> string {
+	prefix := strings.trim_suffix(prefix, "/")
+	if prefix == "" || !strings.has_prefix(path, prefix) do return path
+
+	rest := path[len(prefix):]
+	if rest != "" && rest[0] != '/' do return path
+	return rest
+}
+
 Route :: struct {
 	handler: Handler,
 	pattern: string,
@@ -28,13 +40,56 @@ Route :: struct {
 
 Router :: struct {
 	allocator: runtime.Allocator,
+	// Lua pattern routes, tried in order when no path in the tree matched.
 	routes:    map[Method][dynamic]Route,
 	all:       [dynamic]Route,
+
+	// Called when no route matches the path, the status is 404 Not Found by default.
+	not_found:          Maybe(Handler),
+	// Called when the path only matches routes of other methods, the status is 405 Method Not Allowed
+	// by default and the Allow header lists the methods that the path does have routes for.
+	method_not_allowed: Maybe(Handler),
+
+	_tree:     ^Route_Node,
+	// Copies of the registered paths, the tree's segments are slices into these.
+	_paths:    [dynamic]string,
+	_groups:   [dynamic]^Route_Group,
+	// Handlers that are wrapped by group middleware.
+	_handlers: [dynamic]^Handler,
+}
+
+// Routes that share a path prefix and middleware, created using router_group or group_group.
+Route_Group :: struct {
+	router:     ^Router,
+	parent:     ^Route_Group,
+	// The full prefix, including the prefixes of the parent groups.
+	prefix:     string,
+	middleware: [dynamic]Handler,
+}
+
+// A node in the route tree, each node is a segment of a path (the parts between slashes).
+@(private)
+Route_Node :: struct {
+	// The static segment, or the name of the parameter/wildcard.
+	segment:  string,
+	static:   [dynamic]^Route_Node,
+	param:    ^Route_Node,
+	wildcard: ^Route_Node,
+	// Handlers of the route that ends at this node, by method.
+	handlers: [Method]Maybe(Handler),
+	// Prefixes of the groups the handlers were added to, by method.
+	prefixes: [Method]string,
+	// The path of the route that ends at this node, as registered.
+	path:     string,
 }
 
 router_init :: proc(router: ^Router, allocator := context.allocator) {
 	router.allocator = allocator
 	router.routes = make(map[Method][dynamic]Route, 0, allocator)
+	router._tree = new(Route_Node, allocator)
+	router._paths = make([dynamic]string, allocator)
+	router._groups = make([dynamic]^Route_Group, allocator)
+	router._handlers = make([dynamic]^Handler, allocator)
 }
 
 router_destroy :: proc(router: ^Router) {
@@ -52,9 +107,36 @@ router_destroy :: proc(router: ^Router) {
 	}
 
 	delete(router.routes)
+
+	route_node_destroy(router._tree, router.allocator)
+
+	for path in router._paths {
+		delete(path, router.allocator)
+	}
+	delete(router._paths)
+
+	for group in router._groups {
+		delete(group.prefix, router.allocator)
+		delete(group.middleware)
+		free(group, router.allocator)
+	}
+	delete(router._groups)
+
+	for handler in router._handlers {
+		free(handler, router.allocator)
+	}
+	delete(router._handlers)
 }
 
 // Returns a handler that matches against the given routes.
+//
+// The route tree is tried first, then the Lua pattern routes of the method,
+// and then the Lua pattern routes added with route_all.
+// A HEAD request without a HEAD route is handled by the GET route of the path, which it is identical to,
+// the server leaves out the body.
+//
+// If nothing matches, but the path does match routes of other methods, the response is a
+// 405 Method Not Allowed with an Allow header, or, for OPTIONS requests, a 204 No Content with the Allow header.
 router_handler :: proc(router: ^Router) -> Handler {
 	h: Handler
 	h.user_data = router
@@ -63,113 +145,246 @@ router_handler :: proc(router: ^Router) -> Handler {
 		router := (^Router)(handler.user_data)
 		rline := req.line.(Requestline)
 
+		if route_tree_try(router, rline.method, req, res) {
+			return
+		}
+
 		if routes_try(router.routes[rline.method], req, res) {
 			return
 		}
 
+		// RFC 9110 9.3.2: HEAD is identical to GET, without the body, the server already sends it without one.
+		if rline.method == .Head {
+			if route_tree_try(router, .Get, req, res) || routes_try(router.routes[.Get], req, res) {
+				return
+			}
+		}
+
 		if routes_try(router.all, req, res) {
 			return
 		}
 
-		log.infof("no route matched %s %s", method_string(rline.method), rline.target)
+		allowed := router_allowed(router, req.url.path)
+		if allowed == {} {
+			log.infof("no route matched %s %s", method_string(rline.method), rline.target)
+
+			res.status = .NotFound
+			if nf, ok := router.not_found.?; ok {
+				nf.handle(&nf, req, res)
+			}
+			return
+		}
+
+		// RFC 7231 4.3.2: HEAD is identical to GET (and redirected to it by default).
+		if .Get in allowed do allowed += {.Head}
+		allowed += {.Options}
+
+		headers_set(&res.headers, "allow", router_allow_header(allowed))
+
+		// RFC 7231 4.3.7: OPTIONS requests get the communication options of the path, which is the Allow header.
+		if rline.method == .Options {
+			res.status = .No_Content
+			return
+		}
+
+		res.status = .Method_Not_Allowed
+		if mna, ok := router.method_not_allowed.?; ok {
+			mna.handle(&mna, req, res)
+		}
 	}
 
 	return h
 }
 
-route_get :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Get,
-		Route{
-			handler = handler,
-			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
-		},
-	)
+route_get :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Get, path, handler)
 }
 
-route_post :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Post,
-		Route{
-			handler = handler,
-			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
-		},
-	)
+route_post :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Post, path, handler)
 }
 
 // NOTE: this does not get called when `Server_Opts.redirect_head_to_get` is set to true.
-route_head :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Head,
-		Route{
-			handler = handler,
-			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
-		},
-	)
+route_head :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Head, path, handler)
 }
 
-route_put :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Put,
-		Route{
-			handler = handler,
-			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
-		},
-	)
+route_put :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Put, path, handler)
 }
 
-route_patch :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Patch,
-		Route{
-			handler = handler,
-			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
-		},
-	)
+route_patch :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Patch, path, handler)
 }
 
-route_trace :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Trace,
-		Route{
-			handler = handler,
-			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
-		},
-	)
+route_trace :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Trace, path, handler)
 }
 
-route_delete :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Delete,
-		Route{
-			handler = handler,
-			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
-		},
-	)
+route_delete :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Delete, path, handler)
 }
 
-route_connect :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Connect,
-		Route{
-			handler = handler,
-			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
-		},
-	)
+route_connect :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Connect, path, handler)
+}
+
+route_options :: proc(router: ^Router, path: string, handler: Handler) {
+	route_add(router, .Options, path, handler)
+}
+
+// Adds a route to the tree, the path is matched segment by segment (the parts between slashes):
+// - "users" matches exactly that segment,
+// - ":id" matches any non-empty segment, its value is retrieved using `request_param(req, "id")`,
+// - "*rest" matches the rest of the path (slashes included), it can only be the last segment.
+//
+// Static segments are preferred over parameters, which are preferred over wildcards,
+// so the order in which routes are added does not matter.
+//
+// Panics if the route conflicts with one that was added before, this is the case when the same
+// method and path are added twice, or when parameters at the same position have different names.
+// Also panics if the path looks like a Lua pattern, those are added using route_pattern.
+route_add :: proc(router: ^Router, method: Method, path: string, handler: Handler) {
+	route_tree_add(router, method, path, handler, "")
+}
+
+@(private)
+route_tree_add :: proc(router: ^Router, method: Method, path: string, handler: Handler, prefix: string) {
+	if err := route_tree_insert(router, method, path, handler, prefix); err != "" {
+		log.panic(err)
+	}
+}
+
+// Adds the route to the tree, returns why it conflicts when it can't be added, allocated using the temp allocator.
+@(private)
+route_tree_insert :: proc(router: ^Router, method: Method, path: string, handler: Handler, prefix: string) -> (err: string) {
+	// Before the route tree, route_get and friends took Lua patterns, which the tree would silently
+	// match as literal segments, so a pattern would never match anything.
+	if route_is_pattern(path) {
+		return fmt.tprintf("route %q: looks like a Lua pattern, paths are matched segment by segment now, use route_pattern for patterns", path)
+	}
+
+	path := strings.clone(path, router.allocator)
+	append(&router._paths, path)
+
+	segments := strings.split(strings.trim_prefix(path, "/"), "/", context.temp_allocator)
+
+	node := router._tree
+	for segment, i in segments {
+		switch {
+		case strings.has_prefix(segment, ":"):
+			name := segment[1:]
+			if name == "" {
+				return fmt.tprintf("route %q: parameter without a name", path)
+			}
+
+			if node.param == nil {
+				node.param = new(Route_Node, router.allocator)
+				node.param.segment = name
+			} else if node.param.segment != name {
+				return fmt.tprintf("route %q: parameter %q conflicts with %q of route %q", path, segment, node.param.segment, route_node_any_path(node.param))
+			}
+
+			node = node.param
+
+		case strings.has_prefix(segment, "*"):
+			name := segment[1:]
+			if name == "" {
+				return fmt.tprintf("route %q: wildcard without a name", path)
+			}
+
+			if i != len(segments)-1 {
+				return fmt.tprintf("route %q: a wildcard has to be the last segment", path)
+			}
+
+			if node.wildcard == nil {
+				node.wildcard = new(Route_Node, router.allocator)
+				node.wildcard.segment = name
+			} else if node.wildcard.segment != name {
+				return fmt.tprintf("route %q: wildcard %q conflicts with %q of route %q", path, segment, node.wildcard.segment, node.wildcard.path)
+			}
+
+			node = node.wildcard
+
+		case:
+			next: ^Route_Node
+			for child in node.static {
+				if child.segment == segment {
+					next = child
+					break
+				}
+			}
+
+			if next == nil {
+				next = new(Route_Node, router.allocator)
+				next.segment = segment
+				if node.static == nil {
+					node.static = make([dynamic]^Route_Node, router.allocator)
+				}
+				append(&node.static, next)
+			}
+
+			node = next
+		}
+	}
+
+	if _, exists := node.handlers[method].?; exists {
+		return fmt.tprintf("route %s %q conflicts with route %q", method_string(method), path, node.path)
+	}
+
+	node.handlers[method] = handler
+	node.prefixes[method] = prefix
+	node.path = path
+	return ""
 }
 
-route_options :: proc(router: ^Router, pattern: string, handler: Handler) {
-	route_add(
-		router,
-		.Options,
+// Whether the path uses syntax that is specific to Lua patterns: anchors, captures, sets, character classes
+// and repeated dots. These characters can be part of a URL, but not of the routes that are used in practice.
+@(private)
+route_is_pattern :: proc(path: string) -> bool {
+	if strings.has_prefix(path, "^") || strings.has_suffix(path, "$") {
+		return true
+	}
+
+	if strings.contains_any(path, "()[]") {
+		return true
+	}
+
+	for i in 0 ..< len(path) {
+		switch path[i] {
+		case '%':
+			// A character class like %d or %w, or an escape like %., unless it is percent-encoding.
+			if i+2 >= len(path) || !route_is_hex(path[i+1]) || !route_is_hex(path[i+2]) {
+				return true
+			}
+		case '.':
+			if i+1 < len(path) && strings.index_byte("+*-?", path[i+1]) != -1 {
+				return true
+			}
+		}
+	}
+
+	return false
+}
+
+@(private)
+route_is_hex :: proc(c: byte) -> bool {
+	return (c >= '0' && c <= '9') || (c >= 'a' && c <= 'f') || (c >= 'A' && c <= 'F')
+}
+
+// Adds a route that matches the path against a Lua pattern, these are tried in order,
+// after the route tree did not have a match.
+//
+// Route matching is implemented using an implementation of Lua patterns, see the docs on them here:
+// https://www.lua.org/pil/20.2.html
+// The captures are available, in order, in `req.url_params`.
+route_pattern :: proc(router: ^Router, method: Method, pattern: string, handler: Handler) {
+	if method not_in router.routes {
+		router.routes[method] = make([dynamic]Route, router.allocator)
+	}
+
+	append(
+		&router.routes[method],
 		Route{
 			handler = handler,
 			pattern = strings.concatenate([]string{"^", pattern, "$"}, router.allocator),
@@ -177,7 +392,7 @@ route_options :: proc(router: ^Router, pattern: string, handler: Handler) {
 	)
 }
 
-// Adds a catch-all fallback route (all methods, ran if no other routes match).
+// Adds a catch-all fallback route using a Lua pattern (all methods, ran if no other routes match).
 route_all :: proc(router: ^Router, pattern: string, handler: Handler) {
 	if router.all == nil {
 		router.all = make([dynamic]Route, 0, 1, router.allocator)
@@ -192,13 +407,277 @@ route_all :: proc(router: ^Router, pattern: string, handler: Handler) {
 	)
 }
 
+// Creates a group of routes under the prefix, for example "/api/v1".
+// Routes added to the group get the prefix in front of their path, and run through the group's middleware.
+router_group :: proc(router: ^Router, prefix: string) -> ^Route_Group {
+	return route_group_new(router, nil, prefix)
+}
+
+// Creates a group inside another group, its prefix comes after the parent's prefix,
+// and the parent's middleware runs before its own.
+group_group :: proc(parent: ^Route_Group, prefix: string) -> ^Route_Group {
+	return route_group_new(parent.router, parent, prefix)
+}
+
+// Adds middleware to the group, it runs (in the order it is added) for routes added to the group,
+// or its sub-groups, after this call.
+//
+// The next handler of the middleware is set for each route, so it should be created without one:
+// `http.group_use(api, http.middleware_logger(nil))`.
+group_use :: proc(group: ^Route_Group, middleware: Handler) {
+	append(&group.middleware, middleware)
+}
+
+group_get :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Get, path, handler)
+}
+
+group_post :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Post, path, handler)
+}
+
+// NOTE: this does not get called when `Server_Opts.redirect_head_to_get` is set to true.
+group_head :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Head, path, handler)
+}
+
+group_put :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Put, path, handler)
+}
+
+group_patch :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Patch, path, handler)
+}
+
+group_trace :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Trace, path, handler)
+}
+
+group_delete :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Delete, path, handler)
+}
+
+group_connect :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Connect, path, handler)
+}
+
+group_options :: proc(group: ^Route_Group, path: string, handler: Handler) {
+	group_add(group, .Options, path, handler)
+}
+
+// Adds a route to the group, see route_add for the path syntax.
+// The path "/" refers to the prefix itself, so "/api" and not "/api/".
+group_add :: proc(group: ^Route_Group, method: Method, path: string, handler: Handler) {
+	full: string
+	if path == "/" || path == "" {
+		full = group.prefix
+	} else {
+		full = strings.concatenate({group.prefix, path}, context.temp_allocator)
+	}
+
+	route_tree_add(group.router, method, full, route_group_wrap(group, handler), group.prefix)
+}
+
 @(private)
-route_add :: proc(router: ^Router, method: Method, route: Route) {
-	if method not_in router.routes {
-		router.routes[method] = make([dynamic]Route, router.allocator)
+route_group_new :: proc(router: ^Router, parent: ^Route_Group, prefix: string) -> ^Route_Group {
+	parent_prefix := parent.prefix if parent != nil else ""
+
+	group := new(Route_Group, router.allocator)
+	group.router = router
+	group.parent = parent
+	group.prefix = strings.concatenate({parent_prefix, strings.trim_suffix(prefix, "/")}, router.allocator)
+	group.middleware = make([dynamic]Handler, router.allocator)
+
+	append(&router._groups, group)
+	return group
+}
+
+// Wraps the handler in the middleware of the group and its parents, the outermost group's
+// first middleware is the first to be called.
+@(private)
+route_group_wrap :: proc(group: ^Route_Group, handler: Handler) -> Handler {
+	router := group.router
+
+	result := handler
+	for g := group; g != nil; g = g.parent {
+		for i := len(g.middleware)-1; i >= 0; i -= 1 {
+			inner := new_clone(result, router.allocator)
+			append(&router._handlers, inner)
+
+			result = g.middleware[i]
+			result.next = inner
+		}
 	}
 
-	append(&router.routes[method], route)
+	return result
+}
+
+// Returns the value of the ":name" or "*name" segment of the matched route.
+request_param :: proc(req: ^Request, name: string) -> (value: string, ok: bool) {
+	for param_name, i in req._url_param_names {
+		if param_name == name {
+			return req.url_params[i], true
+		}
+	}
+
+	return "", false
+}
+
+@(private)
+route_tree_try :: proc(router: ^Router, method: Method, req: ^Request, res: ^Response) -> bool {
+	path := req.url.path
+	if path == "" do path = "/"
+
+	names  := make([dynamic]string, req.allocator)
+	values := make([dynamic]string, req.allocator)
+
+	node := route_match(router._tree, path, method, &names, &values)
+	if node == nil {
+		delete(names)
+		delete(values)
+		return false
+	}
+
+	req.url_params = values[:]
+	req._url_param_names = names[:]
+	req.route_prefix = node.prefixes[method]
+
+	rh := node.handlers[method].?
+	rh.handle(&rh, req, res)
+	return true
+}
+
+// Finds the node matching the rest of the path (which is empty, or starts with a slash),
+// that has a handler for the method, or any handler if the method is nil.
+// Backtracks when a more specific segment did not lead to a match.
+@(private)
+route_match :: proc(node: ^Route_Node, path: string, method: Maybe(Method), names, values: ^[dynamic]string) -> ^Route_Node {
+	if path == "" {
+		return node if route_node_handles(node, method) else nil
+	}
+
+	rest := path[1:]
+	segment, next := rest, ""
+	if end := strings.index_byte(rest, '/'); end != -1 {
+		segment, next = rest[:end], rest[end:]
+	}
+
+	for child in node.static {
+		if child.segment == segment {
+			if found := route_match(child, next, method, names, values); found != nil {
+				return found
+			}
+		}
+	}
+
+	if node.param != nil && segment != "" {
+		append(names, node.param.segment)
+		append(values, segment)
+
+		if found := route_match(node.param, next, method, names, values); found != nil {
+			return found
+		}
+
+		pop(names)
+		pop(values)
+	}
+
+	if node.wildcard != nil && route_node_handles(node.wildcard, method) {
+		append(names, node.wildcard.segment)
+		append(values, rest)
+		return node.wildcard
+	}
+
+	return nil
+}
+
+@(private)
+route_node_handles :: proc(node: ^Route_Node, method: Maybe(Method)) -> bool {
+	if m, ok := method.?; ok {
+		return node.handlers[m] != nil
+	}
+
+	for handler in node.handlers {
+		if handler != nil do return true
+	}
+	return false
+}
+
+// Returns the path of a route that goes through the node, for use in conflict messages.
+@(private)
+route_node_any_path :: proc(node: ^Route_Node) -> string {
+	if node.path != "" do return node.path
+	for child in node.static {
+		if path := route_node_any_path(child); path != "" do return path
+	}
+	if node.param != nil do return route_node_any_path(node.param)
+	if node.wildcard != nil do return node.wildcard.path
+	return ""
+}
+
+@(private)
+route_node_destroy :: proc(node: ^Route_Node, allocator: runtime.Allocator) {
+	if node == nil do return
+
+	for child in node.static {
+		route_node_destroy(child, allocator)
+	}
+	delete(node.static)
+
+	route_node_destroy(node.param, allocator)
+	route_node_destroy(node.wildcard, allocator)
+	free(node, allocator)
+}
+
+// Returns the methods that have a route matching the path, either in the tree or as a Lua pattern.
+@(private)
+router_allowed :: proc(router: ^Router, path: string) -> (allowed: bit_set[Method]) {
+	path := path
+	if path == "" do path = "/"
+
+	names  := make([dynamic]string, context.temp_allocator)
+	values := make([dynamic]string, context.temp_allocator)
+
+	for method in Method {
+		clear(&names)
+		clear(&values)
+		if route_match(router._tree, path, method, &names, &values) != nil {
+			allowed += {method}
+			continue
+		}
+
+		if routes_match(router.routes[method], path) {
+			allowed += {method}
+		}
+	}
+
+	return
+}
+
+@(private)
+router_allow_header :: proc(allowed: bit_set[Method]) -> string {
+	b := strings.builder_make(context.temp_allocator)
+	for method in Method {
+		if method not_in allowed do continue
+
+		if strings.builder_len(b) > 0 {
+			strings.write_string(&b, ", ")
+		}
+		strings.write_string(&b, method_string(method))
+	}
+	return strings.to_string(b)
+}
+
+@(private)
+routes_match :: proc(routes: [dynamic]Route, path: string) -> bool {
+	for route in routes {
+		n, err := match.find_aux(path, route.pattern, 0, true, &routes_try_captures)
+		if err == .OK && n > 0 {
+			return true
+		}
+	}
+
+	return false
 }
 
 @(private)
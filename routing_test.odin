@@ -0,0 +1,360 @@
+package http
+
+import "core:bytes"
+import "core:fmt"
+import "core:strings"
+import "core:testing"
+
+// Returns a handler that responds with the name, followed by the parameters of the route, like "user id=1".
+@(private)
+routing_test_handler :: proc(name: string) -> Handler {
+	h: Handler
+	h.user_data = new_clone(name, context.temp_allocator)
+	h.handle = proc(h: ^Handler, req: ^Request, res: ^Response) {
+		b := strings.builder_make(context.temp_allocator)
+		strings.write_string(&b, (^string)(h.user_data)^)
+		for name, i in req._url_param_names {
+			fmt.sbprintf(&b, " %s=%s", name, req.url_params[i])
+		}
+		respond_plain(res, strings.to_string(b))
+	}
+	return h
+}
+
+// Calls the handler of the router like the server would.
+@(private)
+routing_test_request :: proc(router: ^Router, method: Method, target: string) -> (res: Response) {
+	// The captures of Lua pattern routes are allocated using the context allocator.
+	context.allocator = context.temp_allocator
+
+	h := router_handler(router)
+
+	req: Request
+	request_init(&req, context.temp_allocator)
+	req.line = Requestline{method = method, target = target, version = {1, 1}}
+	req.url = url_parse(target, context.temp_allocator)
+
+	response_init(&res, context.temp_allocator)
+	h.handle(&h, &req, &res)
+	return
+}
+
+// Expects the response body, or the status when it is not 200 OK.
+@(private)
+routing_test_expect :: proc(t: ^testing.T, router: ^Router, method: Method, target: string, expected: string, loc := #caller_location) {
+	res := routing_test_request(router, method, target)
+	got := string(res.body.buf[:]) if res.status == .Ok else fmt.tprint(res.status)
+	testing.expectf(t, got == expected, "%s %s: expected %q, got %q", method_string(method), target, expected, got, loc = loc)
+}
+
+@(test)
+test_routing_precedence :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	// Added from least to most specific, the order does not matter.
+	route_get(&router, "/users/*rest", routing_test_handler("wildcard"))
+	route_get(&router, "/users/:id", routing_test_handler("param"))
+	route_get(&router, "/users/new", routing_test_handler("static"))
+	route_get(&router, "/users/:id/posts", routing_test_handler("posts"))
+
+	routing_test_expect(t, &router, .Get, "/users/new", "static")
+	routing_test_expect(t, &router, .Get, "/users/42", "param id=42")
+	routing_test_expect(t, &router, .Get, "/users/42/posts", "posts id=42")
+	routing_test_expect(t, &router, .Get, "/users/new/posts", "posts id=new")
+	routing_test_expect(t, &router, .Get, "/users/42/likes", "wildcard rest=42/likes")
+	routing_test_expect(t, &router, .Get, "/users/42/posts/1", "wildcard rest=42/posts/1")
+	routing_test_expect(t, &router, .Get, "/other", "NotFound")
+}
+
+@(test)
+test_routing_backtracking :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	route_get(&router, "/files/special/info", routing_test_handler("info"))
+	route_get(&router, "/files/:name/raw", routing_test_handler("raw"))
+	route_get(&router, "/files/*path", routing_test_handler("any"))
+	route_post(&router, "/forms/static", routing_test_handler("static"))
+	route_get(&router, "/forms/:form", routing_test_handler("form"))
+
+	// The static segment leads nowhere, the parameter does.
+	routing_test_expect(t, &router, .Get, "/files/special/raw", "raw name=special")
+	routing_test_expect(t, &router, .Get, "/files/special/info", "info")
+
+	// Neither does the parameter, the wildcard gets the rest, without the parameter that was tried.
+	routing_test_expect(t, &router, .Get, "/files/special/other", "any path=special/other")
+	routing_test_expect(t, &router, .Get, "/files/a/raw/b", "any path=a/raw/b")
+
+	// The static segment only has a route for another method.
+	routing_test_expect(t, &router, .Get, "/forms/static", "form form=static")
+	routing_test_expect(t, &router, .Post, "/forms/static", "static")
+}
+
+@(test)
+test_routing_conflicts :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	h := routing_test_handler("first")
+	testing.expect_value(t, route_tree_insert(&router, .Get, "/users/:id", h, ""), "")
+	testing.expect_value(t, route_tree_insert(&router, .Get, "/files/*path", h, ""), "")
+
+	// Another method, or a more specific route, is not a conflict.
+	testing.expect_value(t, route_tree_insert(&router, .Post, "/users/:id", h, ""), "")
+	testing.expect_value(t, route_tree_insert(&router, .Get, "/users/:id/posts", h, ""), "")
+	testing.expect_value(t, route_tree_insert(&router, .Get, "/files/readme", h, ""), "")
+
+	conflicts := []string{
+		"/users/:id",
+		"/users/:name",
+		"/users/:name/likes",
+		"/files/*rest",
+		"/files/*path/more",
+		"/users/:",
+		"/files/*",
+		"/users/%d+",
+		"^/users$",
+	}
+
+	conflicting := routing_test_handler("conflicting")
+	for path in conflicts {
+		err := route_tree_insert(&router, .Get, path, conflicting, "")
+		testing.expectf(t, err != "", "%q: expected a conflict", path)
+	}
+
+	// Still routed to the first one.
+	routing_test_expect(t, &router, .Get, "/users/1", "first id=1")
+	routing_test_expect(t, &router, .Get, "/files/a", "first path=a")
+}
+
+@(test)
+test_routing_trailing_slashes :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	route_get(&router, "/users", routing_test_handler("users"))
+	route_get(&router, "/docs/", routing_test_handler("docs"))
+	route_get(&router, "/static/*path", routing_test_handler("static"))
+
+	routing_test_expect(t, &router, .Get, "/users", "users")
+	routing_test_expect(t, &router, .Get, "/users/", "NotFound")
+
+	routing_test_expect(t, &router, .Get, "/docs/", "docs")
+	routing_test_expect(t, &router, .Get, "/docs", "NotFound")
+
+	// A wildcard matches an empty rest, but not a missing one.
+	routing_test_expect(t, &router, .Get, "/static/", "static path=")
+	routing_test_expect(t, &router, .Get, "/static/a/", "static path=a/")
+	routing_test_expect(t, &router, .Get, "/static", "NotFound")
+
+	// A parameter does not match an empty segment.
+	route_get(&router, "/items/:id", routing_test_handler("item"))
+	routing_test_expect(t, &router, .Get, "/items/1", "item id=1")
+	routing_test_expect(t, &router, .Get, "/items/", "NotFound")
+}
+
+@(test)
+test_routing_root :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	route_get(&router, "/*rest", routing_test_handler("any"))
+
+	// Without a route for the root, the wildcard matches it.
+	routing_test_expect(t, &router, .Get, "/", "any rest=")
+	routing_test_expect(t, &router, .Get, "/a/b", "any rest=a/b")
+
+	route_get(&router, "/", routing_test_handler("root"))
+	routing_test_expect(t, &router, .Get, "/", "root")
+	routing_test_expect(t, &router, .Get, "/a", "any rest=a")
+}
+
+// Returns middleware that writes the name to the body before calling the next handler.
+@(private)
+routing_test_middleware :: proc(name: string) -> Handler {
+	h: Handler
+	h.user_data = new_clone(name, context.temp_allocator)
+	h.handle = proc(h: ^Handler, req: ^Request, res: ^Response) {
+		bytes.buffer_write_string(&res.body, (^string)(h.user_data)^)
+		bytes.buffer_write_string(&res.body, " ")
+
+		next := h.next.(^Handler)
+		next.handle(next, req, res)
+	}
+	return h
+}
+
+@(test)
+test_routing_group_prefixes :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	api := router_group(&router, "/api")
+	v1 := group_group(api, "/v1/")
+
+	group_get(api, "/status", routing_test_handler("status"))
+	group_get(v1, "/users/:id", routing_test_handler("user"))
+	group_get(v1, "/", routing_test_handler("v1"))
+
+	routing_test_expect(t, &router, .Get, "/api/status", "status")
+	routing_test_expect(t, &router, .Get, "/api/v1/users/1", "user id=1")
+	routing_test_expect(t, &router, .Get, "/api/v1", "v1")
+	routing_test_expect(t, &router, .Get, "/api/v1/", "NotFound")
+	routing_test_expect(t, &router, .Get, "/v1/users/1", "NotFound")
+	routing_test_expect(t, &router, .Get, "/api/users/1", "NotFound")
+
+	// The prefix of the group the matched route was added to, for handlers that serve a subtree.
+	prefix := handler(proc(req: ^Request, res: ^Response) {
+		respond_plain(res, req.route_prefix)
+	})
+	route_get(&router, "/prefix", prefix)
+	group_get(api, "/prefix", prefix)
+	group_get(v1, "/prefix/*rest", prefix)
+
+	routing_test_expect(t, &router, .Get, "/prefix", "")
+	routing_test_expect(t, &router, .Get, "/api/prefix", "/api")
+	routing_test_expect(t, &router, .Get, "/api/v1/prefix/a/b", "/api/v1")
+}
+
+@(test)
+test_routing_group_middleware :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	api := router_group(&router, "/api")
+
+	// Middleware only applies to routes that are added after it.
+	group_get(api, "/early", routing_test_handler("early"))
+
+	group_use(api, routing_test_middleware("api1"))
+	group_use(api, routing_test_middleware("api2"))
+
+	v1 := group_group(api, "/v1")
+	group_use(v1, routing_test_middleware("v1"))
+
+	group_get(api, "/route", routing_test_handler("route"))
+	group_get(v1, "/route", routing_test_handler("route"))
+
+	group_use(api, routing_test_middleware("late"))
+	group_get(v1, "/later", routing_test_handler("later"))
+
+	// Ungrouped routes don't run through any of it.
+	route_get(&router, "/plain", routing_test_handler("plain"))
+
+	routing_test_expect(t, &router, .Get, "/api/early", "early")
+	routing_test_expect(t, &router, .Get, "/api/route", "api1 api2 route")
+	// The parent's middleware runs first, in the order it was added.
+	routing_test_expect(t, &router, .Get, "/api/v1/route", "api1 api2 v1 route")
+	routing_test_expect(t, &router, .Get, "/api/v1/later", "api1 api2 late v1 later")
+	routing_test_expect(t, &router, .Get, "/plain", "plain")
+}
+
+@(test)
+test_routing_method_not_allowed :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	route_get(&router, "/users/:id", routing_test_handler("user"))
+	route_delete(&router, "/users/:id", routing_test_handler("delete"))
+	route_pattern(&router, .Put, "/users/(%d+)", routing_test_handler("put"))
+	route_post(&router, "/submit", routing_test_handler("submit"))
+
+	Case :: struct {
+		method: Method,
+		target: string,
+		allow:  string,
+	}
+
+	cases := []Case{
+		// HEAD comes with GET, OPTIONS is always allowed, Lua pattern routes count too.
+		{.Post, "/users/1", "GET, HEAD, PUT, DELETE, OPTIONS"},
+		{.Post, "/users/abc", "GET, HEAD, DELETE, OPTIONS"},
+		{.Get, "/submit", "POST, OPTIONS"},
+		{.Head, "/submit", "POST, OPTIONS"},
+	}
+
+	for c in cases {
+		res := routing_test_request(&router, c.method, c.target)
+		testing.expectf(t, res.status == .Method_Not_Allowed, "%s %s: expected 405, got %v", method_string(c.method), c.target, res.status)
+
+		allow := headers_get(&res.headers, "allow") or_else ""
+		testing.expectf(t, allow == c.allow, "%s %s: expected Allow %q, got %q", method_string(c.method), c.target, c.allow, allow)
+	}
+
+	// Nothing at all is routed to the path.
+	res := routing_test_request(&router, .Post, "/other")
+	testing.expect_value(t, res.status, Status.NotFound)
+	testing.expect(t, !headers_has(&res.headers, "allow"), "a path without routes has no Allow header")
+}
+
+@(test)
+test_routing_head_and_options :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	route_get(&router, "/users/:id", routing_test_handler("user"))
+	route_pattern(&router, .Get, "/pattern/(%w+)", routing_test_handler("pattern"))
+	route_get(&router, "/both", routing_test_handler("get"))
+	route_head(&router, "/both", routing_test_handler("head"))
+	route_post(&router, "/custom", routing_test_handler("post"))
+	route_options(&router, "/custom", routing_test_handler("options"))
+
+	// A HEAD request is handled by the GET route, unless there is a HEAD route.
+	routing_test_expect(t, &router, .Head, "/users/1", "user id=1")
+	routing_test_expect(t, &router, .Head, "/pattern/abc", "pattern")
+	routing_test_expect(t, &router, .Head, "/both", "head")
+	routing_test_expect(t, &router, .Get, "/both", "get")
+
+	// OPTIONS is answered with the methods of the path, unless there is an OPTIONS route.
+	res := routing_test_request(&router, .Options, "/users/1")
+	testing.expect_value(t, res.status, Status.No_Content)
+	testing.expect_value(t, headers_get(&res.headers, "allow") or_else "", "GET, HEAD, OPTIONS")
+	testing.expect(t, len(res.body.buf) == 0, "the automatic OPTIONS response has no body")
+
+	routing_test_expect(t, &router, .Options, "/custom", "options")
+	routing_test_expect(t, &router, .Options, "/other", "NotFound")
+}
+
+@(test)
+test_routing_custom_handlers :: proc(t: ^testing.T) {
+	router: Router
+	router_init(&router)
+	defer router_destroy(&router)
+
+	route_get(&router, "/users/:id", routing_test_handler("user"))
+
+	router.not_found = handler(proc(req: ^Request, res: ^Response) {
+		bytes.buffer_write_string(&res.body, "custom not found")
+	})
+	router.method_not_allowed = handler(proc(req: ^Request, res: ^Response) {
+		bytes.buffer_write_string(&res.body, "custom method not allowed")
+	})
+
+	// The status and the Allow header are set before the custom handler is called.
+	res := routing_test_request(&router, .Get, "/other")
+	testing.expect_value(t, res.status, Status.NotFound)
+	testing.expect_value(t, string(res.body.buf[:]), "custom not found")
+
+	res = routing_test_request(&router, .Post, "/users/1")
+	testing.expect_value(t, res.status, Status.Method_Not_Allowed)
+	testing.expect_value(t, headers_get(&res.headers, "allow") or_else "", "GET, HEAD, OPTIONS")
+	testing.expect_value(t, string(res.body.buf[:]), "custom method not allowed")
+
+	// The automatic OPTIONS response is not a method that is not allowed.
+	res = routing_test_request(&router, .Options, "/users/1")
+	testing.expect_value(t, res.status, Status.No_Content)
+	testing.expect(t, len(res.body.buf) == 0, "the custom handler is not called for OPTIONS")
+
+	// Matched routes don't reach either of them.
+	routing_test_expect(t, &router, .Get, "/users/1", "user id=1")
+}
@@ -0,0 +1,48 @@
+//+private
+package http
+
+import "core:c"
+import "core:net"
+import "core:os"
+
+foreign import libc "system:c"
+
+@(default_calling_convention = "c")
+foreign libc {
+	@(link_name = "sendfile")
+	_sendfile :: proc(out_fd: c.int, in_fd: c.int, offset: ^i64, count: c.size_t) -> c.ssize_t ---
+}
+
+EINTR  :: 4
+EINVAL :: 22
+ENOSYS :: 38
+
+// Linux transfers at most this many bytes in one call.
+Sendfile_Max :: 0x7ffff000
+
+// Sends length bytes of the file, starting at offset, to the socket, without copying them through user space.
+// Blocks like a write to the socket does, so the socket's send timeout applies.
+sendfile :: proc(sock: net.TCP_Socket, fd: os.Handle, offset, length: int) -> (sent: int, result: Sendfile_Result) {
+	off := i64(offset)
+	for sent < length {
+		n := _sendfile(c.int(sock), c.int(fd), &off, c.size_t(min(length - sent, Sendfile_Max)))
+		if n < 0 {
+			switch os.get_last_error() {
+			case EINTR:
+				continue
+			case EINVAL, ENOSYS:
+				// The file system (or a file like /proc/*) can not be used with sendfile, it is read instead.
+				return sent, .Unsupported
+			case:
+				return sent, .Failed
+			}
+		}
+
+		// The file got shorter while it is sent.
+		if n == 0 do return sent, .Failed
+
+		sent += int(n)
+	}
+
+	return sent, .Ok
+}
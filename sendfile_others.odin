@@ -0,0 +1,11 @@
+//+build !linux
+//+private
+package http
+
+import "core:net"
+import "core:os"
+
+// Files are read and written in parts on other targets.
+sendfile :: proc(sock: net.TCP_Socket, fd: os.Handle, offset, length: int) -> (sent: int, result: Sendfile_Result) {
+	return 0, .Unsupported
+}
@@ -13,6 +13,9 @@ import "core:runtime"
 import "core:c/libc"
 import "core:os"
 
+import "nbio"
+import "openssl"
+
 Server_Opts :: struct {
 	// Whether the server should accept every request that sends a "Expect: 100-continue" header automatically.
 	// Defaults to true.
@@ -33,6 +36,71 @@ Server_Opts :: struct {
 	// The HTTP spec does not specify any limits but in practice it is safer.
 	// defaults to 8000.
 	limit_headers:        int,
+	// The amount of worker threads that handle requests, connections are multiplexed over these.
+	//
+	// Workers do blocking I/O: a worker is occupied from the first bytes of a request until its response is written,
+	// so a client that sends or reads slowly holds it for that long, bounded by the header, body and write timeouts.
+	// Hijacked connections, like WebSockets and proxy tunnels, are not counted: their handler keeps its thread
+	// and a new worker takes its place in the pool, see response_hijack.
+	//
+	// Defaults to 0, which uses 4 threads per CPU core, with a minimum of 8.
+	thread_count:         int,
+	// Whether HTTP/2 is served, negotiated using ALPN over TLS, and in cleartext using prior knowledge or an upgrade.
+	//
+	// The streams of a connection are handled one after another, on one worker, so they block each other:
+	// a slow handler delays the other requests on its connection, and frames for them (like RST_STREAM and PING)
+	// are only read once it returns, or while its response waits for the client's flow control window.
+	// Clients that make slow requests at the same time should use separate connections, or HTTP/1.1.
+	//
+	// Defaults to true.
+	http2:                bool,
+	// The maximum duration for receiving the request line and headers (and the TLS handshake),
+	// counted from when the first bytes of the request are received.
+	// A request that takes longer is responded to with a 408 Request Timeout, and the connection is closed.
+	// This protects against slowloris attacks, where a client holds a worker by sending its request very slowly.
+	// Defaults to 10 seconds, 0 disables it.
+	header_timeout:       time.Duration,
+	// The maximum duration for receiving the whole request, including the body.
+	// A request that takes longer is responded to with a 408 Request Timeout, and the connection is closed.
+	// Defaults to 0, which disables it, so big uploads are not cut off, the body_timeout still applies.
+	read_timeout:         time.Duration,
+	// The maximum duration a single read of the request body may wait for data, so a client that stops sending
+	// its body (or sends it very slowly) does not hold a worker, while big uploads that keep coming in are not cut off.
+	// A read that waits longer is responded to with a 408 Request Timeout, and the connection is closed.
+	// Defaults to 30 seconds, 0 disables it.
+	body_timeout:         time.Duration,
+	// The maximum duration a single write of the response may take, which is when the client is not reading it.
+	// The connection is closed without a response when it expires.
+	// Defaults to 30 seconds, 0 disables it.
+	write_timeout:        time.Duration,
+	// How long a keep-alive connection may wait for its next request, it is closed without a response after.
+	// Defaults to 60 seconds, 0 disables it.
+	idle_timeout:         time.Duration,
+	// The maximum amount of connections that are open at the same time,
+	// what happens to connections over this limit is decided by the connection_limit_policy.
+	// Defaults to 0, which means no limit.
+	max_connections:      int,
+	// What to do with new connections when max_connections is reached.
+	// Defaults to .Delay.
+	connection_limit_policy: Connection_Limit_Policy,
+	// The maximum amount of connections that are open at the same time from the same client IP address,
+	// so that one client can not use up every thread and file descriptor.
+	// Connections over this limit are rejected with a 503, delaying them would hold back the other clients.
+	// Defaults to 0, which means no limit.
+	max_connections_per_ip: int,
+	// How long server_shutdown waits for hijacked connections (like WebSockets and proxy tunnels) to be closed
+	// by their handlers. After that their sockets are shut down, which makes the reads and writes of the handlers fail,
+	// so they notice and close the connection.
+	// Defaults to 5 seconds.
+	shutdown_timeout:     time.Duration,
+}
+
+Connection_Limit_Policy :: enum {
+	// Stops accepting connections until one closes, new connections wait in the backlog of the operating system.
+	Delay,
+	// Accepts the connection, responds with a 503 Service Unavailable and closes it.
+	// TLS connections are closed without a response, because that would need a handshake.
+	Reject,
 }
 
 Default_Server_Opts :: Server_Opts {
@@ -40,14 +108,62 @@ Default_Server_Opts :: Server_Opts {
 	redirect_head_to_get = true,
 	limit_request_line   = 8000,
 	limit_headers        = 8000,
+	thread_count         = 0,
+	http2                = true,
+	header_timeout       = 10 * time.Second,
+	read_timeout         = 0,
+	body_timeout         = 30 * time.Second,
+	write_timeout        = 30 * time.Second,
+	idle_timeout         = 60 * time.Second,
+	max_connections      = 0,
+	connection_limit_policy = .Delay,
+	max_connections_per_ip = 0,
+	shutdown_timeout     = 5 * time.Second,
 }
 
 Server :: struct {
 	opts:           Server_Opts,
 	tcp_sock:       net.TCP_Socket,
+	// Set when the server was started with TLS options.
+	tls:            ^Server_TLS,
 	conn_allocator: mem.Allocator,
+	handler:        ^Handler,
+	poller:         nbio.Poller,
+	// The context the workers are started with.
+	worker_context: runtime.Context,
+
+	// Guards workers and retired.
+	workers_mu:     sync.Mutex,
+	workers:        [dynamic]^thread.Thread,
+	// Workers that left the pool because their connection was hijacked, and have exited, waiting to be joined.
+	retired:        [dynamic]^thread.Thread,
+
+	// Guards conns and closing.
+	conns_mu:       sync.Mutex,
 	conns:          map[net.TCP_Socket]^Connection,
+	// The amount of open connections per client IP address, only kept when max_connections_per_ip is set.
+	conns_per_ip:   map[IP_Key]int,
+	// Set when max_connections is reached and the server socket is not watched, guarded by conns_mu.
+	accept_paused:  bool,
+	// Connections that have been shut down, waiting for Conn_Close_Delay to pass before closing.
+	closing:        [dynamic]^Connection,
+
+	// Connections that have data to read, waiting for a worker to pick them up.
+	queue_mu:       sync.Mutex,
+	queue_cond:     sync.Cond,
+	queue:          [dynamic]^Connection,
+
+	// When the event loop checks for connections that have been idle for longer than the idle timeout.
+	next_idle_check: time.Time,
+
 	shutting_down:  bool,
+	closed:         bool,
+}
+
+Server_Error :: union #shared_nil {
+	net.Network_Error,
+	TLS_Error,
+	nbio.Error,
 }
 
 Default_Endpoint := net.Endpoint {
@@ -60,76 +176,521 @@ listen_and_serve :: proc(
 	h: ^Handler,
 	endpoint: net.Endpoint = Default_Endpoint,
 	opts: Server_Opts = Default_Server_Opts,
-) -> (err: net.Network_Error) {
+) -> (err: Server_Error) {
 	server_listen(s, endpoint, opts) or_return
 	return server_serve(s, h)
 }
 
+// Serves HTTPS using the certificate and private key at the given paths.
+// Use server_listen with Server_TLS_Opts for SNI and ALPN configuration.
+listen_and_serve_tls :: proc(
+	s: ^Server,
+	h: ^Handler,
+	cert_file: string,
+	key_file: string,
+	endpoint: net.Endpoint = Default_Endpoint,
+	opts: Server_Opts = Default_Server_Opts,
+) -> (err: Server_Error) {
+	tls := Server_TLS_Opts {
+		certificate = {cert_file = cert_file, key_file = key_file},
+	}
+	server_listen(s, endpoint, opts, tls) or_return
+	return server_serve(s, h)
+}
+
+// Starts listening on the endpoint.
+// When tls is given, every connection is accepted with a TLS handshake using the configured certificates.
 server_listen :: proc(
 	s: ^Server,
 	endpoint: net.Endpoint = Default_Endpoint,
 	opts: Server_Opts = Default_Server_Opts,
-) -> (err: net.Network_Error) {
+	tls: Maybe(Server_TLS_Opts) = nil,
+) -> (err: Server_Error) {
 	s.opts = opts
-	s.tcp_sock, err = net.listen_tcp(endpoint)
+
+	if tls_opts, ok := tls.?; ok {
+		if len(tls_opts.alpn) == 0 && opts.http2 {
+			tls_opts.alpn = Default_ALPN_H2
+		}
+
+		s.tls = new(Server_TLS)
+		if err = server_tls_init(s.tls, tls_opts); err != nil {
+			free(s.tls)
+			s.tls = nil
+			return
+		}
+	}
+
+	s.tcp_sock = net.listen_tcp(endpoint) or_return
+
+	// The event loop accepts until there are no more pending connections, so accepting can't block.
+	net.set_blocking(s.tcp_sock, false) or_return
 	return
 }
 
-server_serve :: proc(using s: ^Server, handler: ^Handler) -> net.Network_Error {
+// Serves the connections of the listening server.
+//
+// The calling thread runs an event loop that waits for connections to become readable,
+// once they are, they are handed off to a pool of worker threads that handle the requests with blocking I/O.
+// When the worker is done and there is no more data, the connection is handed back to the event loop.
+//
+// This means an idle (keep-alive) connection does not occupy a thread, a connection that is sending a request,
+// or receiving a response, does, see Server_Opts.thread_count.
+server_serve :: proc(using s: ^Server, handler: ^Handler) -> Server_Error {
 	// Save allocator so we can free connections later.
 	conn_allocator = context.allocator
+	s.handler = handler
 
-	for {
-		// Don't accept more connections when we are shutting down,
-		// but we don't want to return yet, this way users can wait for this
-		// proc to return as well as the shutdown proc.
-		if shutting_down {
-			time.sleep(SHUTDOWN_INTERVAL)
+	if err := nbio.init(&poller, conn_allocator); err != nil {
+		log.errorf("could not initialize the event loop: %s", err)
+		server_serve_abort(s)
+		return err
+	}
+
+	if err := nbio.watch(&poller, tcp_sock); err != nil {
+		log.errorf("could not watch the server socket: %s", err)
+		nbio.destroy(&poller)
+		server_serve_abort(s)
+		return err
+	}
+
+	thread_count := opts.thread_count
+	if thread_count <= 0 {
+		thread_count = max(os.processor_core_count() * 4, 8)
+	}
+	worker_context = context
+	workers = make([dynamic]^thread.Thread, 0, thread_count, conn_allocator)
+	retired = make([dynamic]^thread.Thread, conn_allocator)
+	for _ in 0..<thread_count {
+		server_start_worker(s)
+	}
+	log.infof("serving with %i worker threads", thread_count)
+
+	ready: [256]net.TCP_Socket
+	for !sync.atomic_load(&closed) {
+		n, err := nbio.wait(&poller, ready[:], SHUTDOWN_INTERVAL)
+		if err != nil {
+			log.errorf("event loop error: %s", err)
 			continue
 		}
 
-		c := new(Connection, conn_allocator)
-		c.state = .Pending
-		c.server = s
-		c.handler = handler
-		c.socket_mu = sync.Mutex{}
-		sync.mutex_lock(&c.socket_mu)
-
-		// Each connection has its own thread. This has to be the case because
-		// sockets are blocking in the 'net' package of Odin.
-		//
-		// We use the mutex so we can start a thread before accepting the connection,
-		// this way, the first request of a connection does not have to wait for
-		// the thread to spin up.
-		c.thread = thread.create_and_start_with_poly_data(
-			c,
-			proc(c: ^Connection) {
-				// Will block until main thread unlocks the mutex, indicating a connection is ready.
-				sync.mutex_lock(&c.socket_mu)
+		for sock in ready[:n] {
+			if sock == tcp_sock {
+				server_accept(s)
+			} else {
+				server_on_readable(s, sock)
+			}
+		}
 
-				c.state = .New
-				if err := conn_handle_reqs(c); err != nil {
-					log.errorf("connection error: %s", err)
-				}
-			},
-			context,
-		)
+		server_close_pending(s)
+		server_close_idle(s)
+		server_join_retired(s)
+	}
+
+	// Wake up the workers so they notice we are closed.
+	sync.mutex_lock(&queue_mu)
+	sync.cond_broadcast(&queue_cond)
+	sync.mutex_unlock(&queue_mu)
+
+	// Taken one at a time, a worker that is still running a hijacked handler can start a replacement, or retire, meanwhile.
+	for {
+		sync.mutex_lock(&workers_mu)
+		w, ok := pop_safe(&workers)
+		if !ok {
+			w, ok = pop_safe(&retired)
+		}
+		sync.mutex_unlock(&workers_mu)
+
+		if !ok do break
+		thread.join(w)
+		thread.destroy(w)
+	}
+
+	delete(workers)
+	delete(retired)
+	delete(queue)
+	delete(closing)
+	delete(conns)
+	delete(conns_per_ip)
+	nbio.destroy(&poller)
+
+	if tls != nil {
+		server_tls_destroy(tls)
+		free(tls)
+	}
+	return nil
+}
+
+// Releases what server_listen set up, when serving could not start.
+@(private)
+server_serve_abort :: proc(using s: ^Server) {
+	net.close(tcp_sock)
+	sync.atomic_store(&closed, true)
+
+	if tls != nil {
+		server_tls_destroy(tls)
+		free(tls)
+		tls = nil
+	}
+}
 
-		socket, client, err := net.accept_tcp(s.tcp_sock)
+// Accepts every pending connection and hands them to the event loop.
+@(private)
+server_accept :: proc(using s: ^Server) {
+	if shutting_down do return
+
+	// Re-arm the server socket when we have accepted all pending connections,
+	// it is re-armed when a connection closes if accepting is paused because of the connection limit.
+	defer if !accept_paused {
+		if err := nbio.watch(&poller, tcp_sock); err != nil {
+			log.errorf("could not watch the server socket: %s", err)
+		}
+	}
+
+	for {
+		sync.mutex_lock(&conns_mu)
+		full := opts.max_connections > 0 && len(conns) >= opts.max_connections
+		if full && opts.connection_limit_policy == .Delay {
+			accept_paused = true
+		}
+		sync.mutex_unlock(&conns_mu)
+
+		if accept_paused {
+			log.warnf("reached the maximum of %i connections, pausing accepting new connections", opts.max_connections)
+			return
+		}
+
+		socket, client, err := net.accept_tcp(tcp_sock)
 		if err != nil {
-			free(c.thread)
-			free(c)
-			return err
+			if aerr, ok := err.(net.Accept_Error); ok && aerr == .Would_Block {
+				return
+			}
+
+			log.errorf("accept error: %s", err)
+			return
+		}
+
+		// Handlers use blocking reads, some platforms inherit the non-blocking flag of the server socket.
+		if err := net.set_blocking(socket, true); err != nil {
+			log.errorf("could not make connection blocking: %s", err)
+			net.close(socket)
+			continue
 		}
 
+		if full {
+			log.warnf("reached the maximum of %i connections, rejecting connection from %v", opts.max_connections, client.address)
+			server_reject(s, socket)
+			continue
+		}
+
+		if opts.max_connections_per_ip > 0 {
+			key := ip_key(client.address)
+
+			sync.mutex_lock(&conns_mu)
+			allowed := conns_per_ip[key] < opts.max_connections_per_ip
+			if allowed do conns_per_ip[key] += 1
+			sync.mutex_unlock(&conns_mu)
+
+			if !allowed {
+				log.warnf("%v reached the maximum of %i connections per address, rejecting", client.address, opts.max_connections_per_ip)
+				server_reject(s, socket)
+				continue
+			}
+		}
+
+		c := new(Connection, conn_allocator)
+		c.state = .New
+		c.server = s
+		c.handler = s.handler
 		c.socket = socket
 		c.client = client
+		c.stream = tcp_stream(socket)
+
+		if tls != nil {
+			c.ssl = openssl.SSL_new(tls.ctx)
+			openssl.SSL_set_fd(c.ssl, i32(socket))
+			c.stream = ssl_tcp_stream(c.ssl)
+		}
+
+		if opts.write_timeout > 0 {
+			if err := net.set_option(socket, .Send_Timeout, opts.write_timeout); err != nil {
+				log.warnf("could not set the write timeout of connection %i: %s", socket, err)
+			}
+		}
 
-		log.infof("new connection with %v, got %i open connections", client.address, len(conns))
+		bufio.scanner_init(&c.scanner, connection_reader(c), conn_allocator)
 
+		sync.mutex_lock(&conns_mu)
 		conns[socket] = c
+		open := len(conns)
+		sync.mutex_unlock(&conns_mu)
+
+		log.infof("new connection with %v, got %i open connections", client.address, open)
+
+		server_watch(s, c)
+	}
+}
+
+// Responds to a connection over the connection limits with a 503 Service Unavailable, and closes it.
+@(private)
+server_reject :: proc(s: ^Server, socket: net.TCP_Socket) {
+	// RFC 9110 15.6.4: the server is currently unable to handle the request due to a temporary overload.
+	// The response is static and small, so it fits in the send buffer and doesn't block.
+	Response_Unavailable :: "HTTP/1.1 503 Service Unavailable\r\nconnection: close\r\ncontent-length: 0\r\nretry-after: 1\r\n\r\n"
+
+	if s.tls == nil {
+		net.send_tcp(socket, transmute([]byte)string(Response_Unavailable))
+		net.shutdown(socket, net.Shutdown_Manner.Send)
+	}
+
+	net.close(socket)
+}
+
+// A client IP address, IPv4 addresses are stored as IPv4-mapped IPv6 addresses.
+@(private)
+IP_Key :: [16]byte
+
+@(private)
+ip_key :: proc(address: net.Address) -> (key: IP_Key) {
+	switch a in address {
+	case net.IP4_Address:
+		key[10] = 0xff
+		key[11] = 0xff
+		copy(key[12:], a[:])
+	case net.IP6_Address:
+		key = transmute(IP_Key)a
+	}
+	return
+}
+
+// Hands the connection to the event loop, it is queued for a worker when it becomes readable.
+@(private)
+server_watch :: proc(using s: ^Server, c: ^Connection) {
+	sync.mutex_lock(&conns_mu)
+	c.watching = true
+	c.idle_since = time.now()
+	sync.mutex_unlock(&conns_mu)
+
+	if err := nbio.watch(&poller, c.socket); err != nil {
+		log.errorf("could not watch connection %i: %s", c.socket, err)
+
+		sync.mutex_lock(&conns_mu)
+		c.watching = false
+		sync.mutex_unlock(&conns_mu)
+
+		connection_close(c)
+	}
+}
+
+@(private)
+server_on_readable :: proc(using s: ^Server, sock: net.TCP_Socket) {
+	sync.mutex_lock(&conns_mu)
+	c, ok := conns[sock]
+	// The event could be stale, the connection could have been closed,
+	// or is being closed by a graceful shutdown.
+	if !ok || !c.watching {
+		sync.mutex_unlock(&conns_mu)
+		return
+	}
+	c.watching = false
+	sync.mutex_unlock(&conns_mu)
+
+	sync.mutex_lock(&queue_mu)
+	append(&queue, c)
+	sync.cond_signal(&queue_cond)
+	sync.mutex_unlock(&queue_mu)
+}
+
+// Blocks until there is a connection ready to be handled, returns false when the server has closed.
+@(private)
+server_dequeue :: proc(using s: ^Server) -> (c: ^Connection, ok: bool) {
+	sync.mutex_lock(&queue_mu)
+	defer sync.mutex_unlock(&queue_mu)
+
+	for len(queue) == 0 {
+		if sync.atomic_load(&closed) do return
+		sync.cond_wait(&queue_cond, &queue_mu)
+	}
+
+	return pop_front(&queue), true
+}
+
+// Starts a worker thread and adds it to the pool.
+@(private)
+server_start_worker :: proc(s: ^Server) {
+	t := thread.create(proc(t: ^thread.Thread) {
+		server_worker((^Server)(t.data), t)
+	})
+	t.data = s
+	t.init_context = s.worker_context
+
+	sync.mutex_lock(&s.workers_mu)
+	append(&s.workers, t)
+	sync.mutex_unlock(&s.workers_mu)
+
+	thread.start(t)
+}
+
+// Set on a worker that handles a hijacked connection, a new worker took its place in the pool.
+// The worker exits once the handler returns, see connection_hijack.
+@(private)
+@(thread_local)
+worker_replaced: bool
+
+// Takes the connection over from the server, for response_hijack and websocket_upgrade.
+//
+// The handler keeps using its worker's thread for as long as it needs, so a new worker is started in its place,
+// hijacked connections don't take workers away from the other connections.
+@(private)
+connection_hijack :: proc(c: ^Connection) {
+	// Long lived, the request's read timeout does not apply.
+	connection_clear_deadline(c)
+	c.state = .Hijacked
+
+	if !worker_replaced {
+		worker_replaced = true
+		server_start_worker(c.server)
+	}
+}
+
+// Takes the worker out of the pool, it is joined by the event loop once it has exited, see server_join_retired.
+@(private)
+server_retire_worker :: proc(s: ^Server, t: ^thread.Thread) {
+	sync.mutex_lock(&s.workers_mu)
+	defer sync.mutex_unlock(&s.workers_mu)
+
+	// It is not in the pool when server_serve is already joining it.
+	for w, i in s.workers {
+		if w == t {
+			unordered_remove(&s.workers, i)
+			append(&s.retired, t)
+			return
+		}
+	}
+}
+
+// Joins and frees the workers that have retired.
+@(private)
+server_join_retired :: proc(s: ^Server) {
+	for {
+		sync.mutex_lock(&s.workers_mu)
+		w, ok := pop_safe(&s.retired)
+		sync.mutex_unlock(&s.workers_mu)
+
+		if !ok do return
+		thread.join(w)
+		thread.destroy(w)
+	}
+}
+
+@(private)
+server_worker :: proc(s: ^Server, t: ^thread.Thread) {
+	// Every request is allocated in this arena, and it is freed after each request.
+	arena: virtual.Arena
+	if err := virtual.arena_init_growing(&arena); err != nil {
+		panic("could not create memory arena")
+	}
+	defer virtual.arena_destroy(&arena)
+
+	allocator := virtual.arena_allocator(&arena)
+	context.temp_allocator = allocator
+
+	for c in server_dequeue(s) {
+		if c.ssl != nil && !c.tls_accepted {
+			// The handshake reads from the socket directly, so the timeout is set on the socket here.
+			connection_set_deadline(c, s.opts.header_timeout)
+			accepted := connection_tls_accept(c)
+			connection_clear_deadline(c)
+
+			if !accepted {
+				connection_close(c)
+				continue
+			}
+
+			// The client might not have sent its request yet.
+			if !connection_has_buffered(c) {
+				server_watch(s, c)
+				continue
+			}
+		}
+
+		conn_handle_reqs(c, allocator)
+
+		// The handler that hijacked the connection has returned, this worker's place in the pool has been taken.
+		if worker_replaced {
+			server_retire_worker(s, t)
+			return
+		}
+
+		if c.state == .Hijacked || c.state == .Closing || c.state == .Closed {
+			continue
+		}
+
+		if s.shutting_down {
+			connection_close(c)
+			continue
+		}
+
+		server_watch(s, c)
+	}
+}
+
+// Closes the connections whose close delay has passed.
+@(private)
+server_close_pending :: proc(using s: ^Server) {
+	now := time.now()
+
+	sync.mutex_lock(&conns_mu)
+	defer sync.mutex_unlock(&conns_mu)
+
+	for i := 0; i < len(closing); {
+		c := closing[i]
+		if time.diff(c.close_at, now) < 0 {
+			i += 1
+			continue
+		}
+
+		unordered_remove(&closing, i)
+
+		if c.ssl != nil do openssl.SSL_free(c.ssl)
+
+		// The event loop could still be watching it, which keeps the socket open on some backends,
+		// and would report it readable when its number is reused by a new connection.
+		nbio.unwatch(&poller, c.socket)
+		net.close(c.socket)
+		c.state = .Closed
+		log.infof("closed connection: %i", c.socket)
+
+		server_on_connection_close(s, c)
+	}
+}
+
+// Closes the connections that have been waiting in the event loop for longer than the idle timeout.
+@(private)
+server_close_idle :: proc(using s: ^Server) {
+	if opts.idle_timeout <= 0 do return
+
+	now := time.now()
+	if time.diff(next_idle_check, now) < 0 do return
+	next_idle_check = time.time_add(now, SHUTDOWN_INTERVAL)
+
+	to_close := make([dynamic]^Connection, context.temp_allocator)
+
+	sync.mutex_lock(&conns_mu)
+	for _, c in conns {
+		if c.watching && time.diff(c.idle_since, now) > opts.idle_timeout {
+			// Taken out of the event loop, so a late readable event doesn't hand it to a worker.
+			c.watching = false
+			append(&to_close, c)
+		}
+	}
+	sync.mutex_unlock(&conns_mu)
 
-		sync.mutex_unlock(&c.socket_mu)
+	for c in to_close {
+		log.debugf("closing connection %i, it has been idle for longer than %v", c.socket, opts.idle_timeout)
+		// Its poll is still pending, cancel it before the socket is closed so its number can't be reported for a new connection.
+		nbio.unwatch(&poller, c.socket)
+		connection_close(c)
 	}
 }
 
@@ -142,38 +703,69 @@ SHUTDOWN_INTERVAL :: time.Millisecond * 100
 // Some error logs will be generated but all active connections are finished
 // before closing them and all connections and threads are freed.
 //
-// 1. Stops 'server_start' from accepting new connections.
+// 1. Stops 'server_serve' from accepting new connections.
 // 2. Close and free non-active connections.
-// 3. Repeat 2 every SHUTDOWN_INTERVAL until no more connections are open.
+// 3. Repeat 2 every SHUTDOWN_INTERVAL until no more connections are open,
+//    hijacked connections that are still open after the shutdown_timeout have their sockets shut down.
 // 4. Close the main socket.
-// 5. Signal 'server_start' it can return.
+// 5. Signal 'server_serve' it can return.
 server_shutdown :: proc(using s: ^Server) {
 	shutting_down = true
-	defer shutting_down = false // causes 'server_start' to return.
-	defer delete(conns)
+	defer sync.atomic_store(&closed, true) // causes 'server_serve' to return.
+
+	start := time.now()
+
+	to_close := make([dynamic]^Connection)
+	defer delete(to_close)
 
 	for {
+		sync.mutex_lock(&conns_mu)
 		for sock, conn in conns {
 			#partial switch conn.state {
 			case .Active:
 				log.infof("shutdown: connection %i still active", sock)
-			case .New, .Idle, .Pending:
+			case .New, .Idle:
+				// Connections that are not in the event loop are queued for a worker,
+				// the worker closes it after handling the request.
+				if !conn.watching {
+					log.debugf("shutdown: connection %i is queued", sock)
+					continue
+				}
+
 				log.infof("shutdown: closing connection %i", sock)
-				thread.run_with_poly_data(conn, connection_close, context)
+				conn.watching = false
+				append(&to_close, conn)
+			case .Hijacked:
+				if time.since(start) < opts.shutdown_timeout {
+					log.infof("shutdown: connection %i is hijacked, waiting for it to be closed", sock)
+				} else if !conn._shut_down {
+					// Not closed here, the handler is still using it, it closes the connection once its reads fail.
+					log.infof("shutdown: connection %i is hijacked and still open, shutting its socket down", sock)
+					net.shutdown(conn.socket, net.Shutdown_Manner.Both)
+					conn._shut_down = true
+				}
 			case .Closing:
 				log.debugf("shutdown: connection %i is closing", sock)
 			case .Closed:
 				assert(false, "closed connections are not in this map")
 			}
 		}
+		open := len(conns)
+		sync.mutex_unlock(&conns_mu)
+
+		for conn in to_close {
+			connection_close(conn)
+		}
+		clear(&to_close)
 
-		if len(conns) == 0 {
+		if open == 0 {
 			break
 		}
 
 		time.sleep(SHUTDOWN_INTERVAL)
 	}
 
+	nbio.unwatch(&poller, tcp_sock)
 	net.close(tcp_sock)
 	log.info("shutdown: done")
 }
@@ -183,6 +775,7 @@ server_shutdown_force :: proc(s: ^Server) {
 	log.info("forcing shutdown")
 
 	for _, conn in s.conns {
+		nbio.unwatch(&s.poller, conn.socket)
 		net.close(conn.socket)
 	}
 
@@ -214,11 +807,36 @@ server_shutdown_on_interrupt :: proc(using s: ^Server) {
 	})
 }
 
+// Frees the connection, the caller should hold the conns_mu lock.
 @(private)
 server_on_connection_close :: proc(using s: ^Server, c: ^Connection) {
-	free(c.thread, conn_allocator)
+	delete_key(&conns, c.socket)
+
+	if opts.max_connections_per_ip > 0 {
+		key := ip_key(c.client.address)
+		if conns_per_ip[key] <= 1 {
+			delete_key(&conns_per_ip, key)
+		} else {
+			conns_per_ip[key] -= 1
+		}
+	}
+
+	// There is room for a new connection again, start accepting.
+	if accept_paused && !shutting_down && len(conns) < opts.max_connections {
+		accept_paused = false
+		log.info("below the maximum connections again, resuming accepting new connections")
+
+		if err := nbio.watch(&poller, tcp_sock); err != nil {
+			log.errorf("could not watch the server socket: %s", err)
+		}
+	}
+
+	bufio.scanner_destroy(&c.scanner)
+	if c.h2 != nil {
+		h2_conn_destroy(c.h2)
+		free(c.h2, conn_allocator)
+	}
 	free(c, conn_allocator)
-	delete_key(&s.conns, c.socket)
 }
 
 
@@ -234,96 +852,283 @@ Max_Post_Handler_Discard_Bytes :: 256 << 10
 Conn_Close_Delay :: time.Millisecond * 500
 
 Connection_State :: enum {
-	Pending, // Pending a client to attach.
 	New,     // Got client, waiting to service first request.
 	Active,  // Servicing request.
 	Idle,    // Waiting for next request.
+	Hijacked, // Taken over by a handler, like for a WebSocket, the server does not handle it anymore.
 	Closing, // Going to close, cleaning up.
 	Closed,  // Fully closed.
 }
 
 Connection :: struct {
-	server:    ^Server,
-	socket_mu: sync.Mutex,
-	socket:    net.TCP_Socket,
-	client:    net.Endpoint,
-	curr_req:  ^Request,
-	handler:   ^Handler,
-	state:     Connection_State,
-	thread:    ^thread.Thread,
+	server:       ^Server,
+	socket:       net.TCP_Socket,
+	// Stream over the socket, or the TLS connection if the server is using TLS.
+	stream:       io.Stream,
+	ssl:          ^openssl.SSL,
+	tls_accepted: bool,
+	// The protocol negotiated with ALPN, empty when not using TLS or if the client did not use ALPN.
+	protocol:     string,
+	// Set when the connection is speaking HTTP/2.
+	h2:           ^H2_Conn,
+	client:       net.Endpoint,
+	curr_req:     ^Request,
+	handler:      ^Handler,
+	state:        Connection_State,
+	// Kept for the lifetime of the connection, so pipelined requests that have been read ahead are not lost.
+	scanner:      bufio.Scanner,
+	// Whether the connection is waiting for data in the event loop, guarded by the server's conns_mu.
+	watching:     bool,
+	// When the connection was handed to the event loop, used for the idle timeout.
+	idle_since:   time.Time,
+	close_at:     time.Time,
+	// Reads fail after this time, zero for no deadline, see connection_set_deadline.
+	read_deadline: time.Time,
+	// Reads that wait for data longer than this fail, 0 for no limit, see connection_set_read_idle.
+	read_idle:     time.Duration,
+	// Whether a read failed because the read deadline passed.
+	timed_out:     bool,
+	// Whether a receive timeout is currently set on the socket.
+	_recv_timeout: bool,
+	// Whether the socket of the hijacked connection was shut down by server_shutdown.
+	_shut_down:    bool,
+}
+
+// Sets a deadline for reading from the connection, reads that would block past it fail, and set timed_out.
+// Does nothing when the timeout is 0.
+@(private)
+connection_set_deadline :: proc(c: ^Connection, timeout: time.Duration) {
+	if timeout <= 0 do return
+
+	c.read_deadline = time.time_add(time.now(), timeout)
+	connection_apply_deadline(c)
+}
+
+// Limits how long a single read may wait for data, on top of the deadline, reads that wait longer fail, and set timed_out.
+// Does nothing when the timeout is 0.
+@(private)
+connection_set_read_idle :: proc(c: ^Connection, timeout: time.Duration) {
+	if timeout <= 0 do return
+
+	c.read_idle = timeout
+	connection_apply_deadline(c)
+}
+
+// Clears the deadline and the read idle timeout.
+@(private)
+connection_clear_deadline :: proc(c: ^Connection) {
+	c.read_deadline = {}
+	c.read_idle = 0
+	connection_apply_deadline(c)
+}
+
+// Sets the receive timeout of the socket to the time left until the deadline, or the read idle timeout if that is sooner.
+// This works with blocking sockets, because the timeout is updated before every read.
+@(private)
+connection_apply_deadline :: proc(c: ^Connection) -> bool {
+	timeout := c.read_idle
+	if c.read_deadline != {} {
+		remaining := time.diff(time.now(), c.read_deadline)
+		if remaining <= 0 {
+			c.timed_out = true
+			return false
+		}
+
+		if timeout <= 0 || remaining < timeout {
+			timeout = remaining
+		}
+	}
+
+	if timeout <= 0 {
+		if c._recv_timeout {
+			net.set_option(c.socket, .Receive_Timeout, time.Duration(0))
+			c._recv_timeout = false
+		}
+		return true
+	}
+
+	// A timeout of 0 means no timeout at all to the socket.
+	timeout = max(timeout, time.Millisecond)
+
+	if err := net.set_option(c.socket, .Receive_Timeout, timeout); err != nil {
+		log.warnf("could not set the read timeout of connection %i: %s", c.socket, err)
+	}
+	c._recv_timeout = true
+	return true
+}
+
+// Returns a reader over the connection's stream that enforces the read deadline.
+@(private)
+connection_reader :: proc(c: ^Connection) -> io.Reader {
+	s: io.Stream
+	s.data = c
+	s.procedure = _connection_stream_proc
+	return io.to_reader(s)
+}
+
+@(private)
+_connection_stream_proc :: proc(
+	stream_data: rawptr,
+	mode: io.Stream_Mode,
+	p: []byte,
+	offset: i64,
+	whence: io.Seek_From,
+) -> (
+	n: i64,
+	err: io.Error,
+) {
+	c := (^Connection)(stream_data)
+
+	#partial switch mode {
+	case .Query:
+		return io.query_utility(io.Stream_Mode_Set{.Query, .Read})
+	case .Read:
+		if !connection_apply_deadline(c) {
+			return 0, .Unexpected_EOF
+		}
+
+		start := time.now()
+		read, rerr := io.read(io.to_reader(c.stream), p)
+		n, err = i64(read), rerr
+
+		if err != nil {
+			now := time.now()
+			if c.read_deadline != {} && time.diff(now, c.read_deadline) <= 0 {
+				c.timed_out = true
+			}
+			if c.read_idle > 0 && time.diff(start, now) >= c.read_idle {
+				c.timed_out = true
+			}
+		}
+	case:
+		err = .Empty
+	}
+	return
+}
+
+// Reads from a connection taken over with response_hijack,
+// bytes that the server already read from the socket (after the request) are returned first.
+connection_read :: proc(c: ^Connection, p: []byte) -> (n: int, err: io.Error) {
+	return scanner_read(&c.scanner, p)
+}
+
+// Writes all of p to a connection taken over with response_hijack.
+connection_write :: proc(c: ^Connection, p: []byte) -> io.Error {
+	_, err := io.write(io.to_writer(c.stream), p)
+	return err
+}
+
+// Whether there is data that has already been read from the socket, but not consumed.
+// The event loop won't report these connections as readable, so they have to be handled right away.
+// The same goes for other ways of waiting for the socket to be readable, after response_hijack.
+connection_has_buffered :: proc(c: ^Connection) -> bool {
+	if c.scanner.end > c.scanner.start {
+		return true
+	}
+
+	return c.ssl != nil && openssl.SSL_pending(c.ssl) > 0
 }
 
 // RFC 7230 6.6.
+//
+// The connection is not closed right away, the send side is shut down and
+// the event loop closes it after Conn_Close_Delay.
 connection_close :: proc(c: ^Connection) {
-	if c.state == .Closed {
+	if c.state == .Closing || c.state == .Closed {
 		log.infof("connection %i already closed", c.socket)
 		return
 	}
 
 	log.infof("closing connection: %i", c.socket)
-	defer log.infof("closed connection: %i", c.socket)
 
 	c.state = .Closing
 
-	// Close read side of the connection, then wait a little bit, allowing the client
+	// Let the client know we are not handling any more streams.
+	if c.h2 != nil && !c.h2.goaway_sent {
+		payload: [8]byte
+		h2_put_u32(payload[0:], c.h2.last_stream_id)
+		h2_frame(c.h2, .Goaway, 0, 0, payload[:])
+		c.h2.goaway_sent = true
+		h2_flush(c, c.h2)
+	}
+
+	// Let the client know we are done with the TLS connection.
+	if c.ssl != nil && c.tls_accepted {
+		openssl.SSL_shutdown(c.ssl)
+	}
+
+	// Close send side of the connection, then wait a little bit, allowing the client
 	// to process the closing and receive any remaining data.
 	net.shutdown(c.socket, net.Shutdown_Manner.Send)
 
-	// This will block the whole thread, but we have one thread per connection anyway,
-	// so should not matter as long as this is done after sending everything.
-	time.sleep(Conn_Close_Delay)
-	net.close(c.socket)
+	c.close_at = time.time_add(time.now(), Conn_Close_Delay)
 
-	c.state = .Closed
-
-	server_on_connection_close(c.server, c)
+	sync.mutex_lock(&c.server.conns_mu)
+	append(&c.server.closing, c)
+	sync.mutex_unlock(&c.server.conns_mu)
 }
 
-// Calls handler for each of the requests coming in.
-// Everything is allocated in a memory arena for that request, and freed at the end of the request.
+// Calls handler for each of the requests that are available on the connection.
+// Everything is allocated in the given memory arena, and freed at the end of each request.
 // If you need to keep data from either the Request or Response, you need to clone it.
-conn_handle_reqs :: proc(c: ^Connection) -> net.Network_Error {
-	// Make sure the connection is closed when we are returning.
-	defer if c.state != .Closing && c.state != .Closed {
-		connection_close(c)
+//
+// Returns when there is no more data buffered, the connection is then either closing or idle,
+// an idle connection should be handed back to the event loop.
+conn_handle_reqs :: proc(c: ^Connection, allocator: mem.Allocator) {
+	if c.h2 != nil || (c.protocol == "h2" && c.server.opts.http2) {
+		connection_h2_handle(c, allocator)
+		return
 	}
 
-	stream := tcp_stream(c.socket)
-	stream_reader := io.to_reader(stream)
-
-	arena: virtual.Arena
-	if err := virtual.arena_init_growing(&arena); err != nil {
-		panic("could not create memory arena")
-	}
-	allocator := virtual.arena_allocator(&arena)
-	context.temp_allocator = allocator
-
 	Requests: for {
 		defer free_all(allocator)
 
-		// PERF: we shouldn't create a new scanner everytime.
-		scanner: bufio.Scanner
-		bufio.scanner_init(&scanner, stream_reader, allocator)
+		scanner := &c.scanner
 		scanner.max_token_size = c.server.opts.limit_request_line
 
 		res: Response
 		response_init(&res, allocator)
+		res._conn = c
 
 		req: Request
 		request_init(&req, allocator)
 		c.curr_req = &req
 		req.client = c.client
+		req.tls = c.ssl != nil
+
+		// The connection was readable, so the request has started arriving, the headers have to follow in time.
+		start := time.now()
+		connection_set_deadline(c, c.server.opts.header_timeout)
 
 		// In the interest of robustness, a server that is expecting to receive
 		// and parse a request-line SHOULD ignore at least one empty line (CRLF)
 		// received prior to the request-line.
-		rline_str, ok := scanner_scan_or_bad_req(&scanner, &res, c, .URI_Too_Long, allocator)
+		rline_str, ok := scanner_scan_or_bad_req(scanner, &res, c, .URI_Too_Long, allocator)
 		if !ok do break
 		if rline_str == "" {
-			rline_str, ok = scanner_scan_or_bad_req(&scanner, &res, c, .URI_Too_Long, allocator)
+			rline_str, ok = scanner_scan_or_bad_req(scanner, &res, c, .URI_Too_Long, allocator)
 			if !ok do break
 		}
 
+		// A client with prior knowledge of HTTP/2 support starts with the connection preface.
+		if rline_str == "PRI * HTTP/2.0" && c.server.opts.http2 && c.state == .New {
+			for expected in ([]string{"", "SM", ""}) {
+				line, ok := scanner_scan_or_bad_req(scanner, &res, c, .Bad_Request, allocator)
+				if !ok do break Requests
+
+				if line != expected {
+					headers_set(&res.headers, "connection", "close")
+					response_send_or_log(&res, c, .Bad_Request, allocator)
+					break Requests
+				}
+			}
+
+			connection_clear_deadline(c)
+			connection_h2_start(c, true)
+			connection_h2_handle(c, allocator)
+			return
+		}
+
 		c.state = .Active
 
 		// Recipients of an invalid request-line SHOULD respond with either a
@@ -332,11 +1137,11 @@ conn_handle_reqs :: proc(c: ^Connection) -> net.Network_Error {
 		rline, err := requestline_parse(rline_str, allocator)
 		switch err {
 		case .Method_Not_Implemented:
-			res.headers["connection"] = "close"
+			headers_set(&res.headers, "connection", "close")
 			response_send_or_log(&res, c, .Not_Implemented, allocator)
 			break Requests
 		case .Invalid_Version_Format, .Not_Enough_Fields:
-			res.headers["connection"] = "close"
+			headers_set(&res.headers, "connection", "close")
 			response_send_or_log(&res, c, .Bad_Request, allocator)
 			break Requests
 		case .None:
@@ -346,7 +1151,7 @@ conn_handle_reqs :: proc(c: ^Connection) -> net.Network_Error {
 
 		// Might need to support more versions later.
 		if rline.version.major != 1 || rline.version.minor < 1 {
-			res.headers["connection"] = "close"
+			headers_set(&res.headers, "connection", "close")
 			response_send_or_log(&res, c, .HTTP_Version_Not_Supported, allocator)
 			break
 		}
@@ -356,7 +1161,7 @@ conn_handle_reqs :: proc(c: ^Connection) -> net.Network_Error {
 		scanner.max_token_size = c.server.opts.limit_headers
 		// Keep parsing the request as line delimited headers until we get to an empty line.
 		for line in scanner_scan_or_bad_req(
-			&scanner,
+			scanner,
 			&res,
 			c,
 			.Request_Header_Fields_Too_Large,
@@ -368,29 +1173,48 @@ conn_handle_reqs :: proc(c: ^Connection) -> net.Network_Error {
 			}
 
 			if _, ok := header_parse(&req.headers, line); !ok {
-				res.headers["connection"] = "close"
+				headers_set(&res.headers, "connection", "close")
 				response_send_or_log(&res, c, .Bad_Request, allocator)
 				break Requests
 			}
 
 			scanner.max_token_size -= len(line)
 			if scanner.max_token_size <= 0 {
-				res.headers["connection"] = "close"
+				headers_set(&res.headers, "connection", "close")
 				response_send_or_log(&res, c, .Request_Header_Fields_Too_Large, allocator)
 				break Requests
 			}
 		}
 
+		if c.state == .Closing || c.state == .Closed {
+			break
+		}
+
 		if !server_headers_validate(&req.headers) {
-			res.headers["connection"] = "close"
+			headers_set(&res.headers, "connection", "close")
 			response_send_or_log(&res, c, .Bad_Request, allocator)
 			break
 		}
 
 		scanner.max_token_size = bufio.DEFAULT_MAX_SCAN_TOKEN_SIZE
 
+		// From here on the body is read, which is limited by the read timeout, counted from the start of the request,
+		// and every read of it by the body timeout.
+		connection_clear_deadline(c)
+		if c.server.opts.read_timeout > 0 {
+			c.read_deadline = time.time_add(start, c.server.opts.read_timeout)
+		}
+		connection_set_read_idle(c, c.server.opts.body_timeout)
+
+		// The request is handled as the first stream of an HTTP/2 connection.
+		if connection_h2_try_upgrade(c, &req, allocator) {
+			connection_clear_deadline(c)
+			connection_h2_handle(c, allocator)
+			return
+		}
+
 		// Automatically respond with a continue status when the client has the Expect: 100-continue header.
-		if expect, ok := req.headers["expect"];
+		if expect, ok := headers_get(&req.headers, "expect");
 		   ok && expect == "100-continue" && c.server.opts.auto_expect_continue {
 
 			res.status = .Continue
@@ -414,28 +1238,52 @@ conn_handle_reqs :: proc(c: ^Connection) -> net.Network_Error {
 			// says a HEAD is identical to a GET but just without writing the body,
 			// handlers shouldn't have to worry about it.
 			is_head := rline.method == .Head
+			res._head = is_head
 			if is_head && c.server.opts.redirect_head_to_get {
 				rline.method = .Get
+				req.line = rline
 			}
 
 			c.handler.handle(c.handler, &req, &res)
 
+			// Restored so the response is sent without a body.
 			if is_head && c.server.opts.redirect_head_to_get {
 				rline.method = .Head
+				req.line = rline
 			}
 		}
 
+		// The handler took over the connection.
+		if c.state == .Hijacked {
+			return
+		}
+
+		// The handler aborted the response, see response_abort.
+		if c.state == .Closing || c.state == .Closed {
+			break
+		}
+
 		if err := response_send(&res, c, allocator); err != nil {
-			log.warnf("could not send response: %s", err)
+			// Most likely the client is gone or the write timeout expired, either way the connection is unusable.
+			log.debugf("could not send response: %s", err)
+			connection_close(c)
+			break
 		}
 
+		connection_clear_deadline(c)
+
 		if c.state == .Closing || c.state == .Closed {
 			break
 		}
 
 		c.state = .Idle
+
+		// Handle pipelined requests that were already read right away,
+		// otherwise wait for the event loop to tell us there is more.
+		if !connection_has_buffered(c) {
+			break
+		}
 	}
-	return nil
 }
 
 @(private)
@@ -456,6 +1304,29 @@ scanner_scan_or_bad_req :: proc(
 ) -> (string, bool) {
 	if !bufio.scanner_scan(s) {
 		err := bufio.scanner_error(s)
+
+		// RFC 9110 15.5.9: a server sends a 408 when it did not receive a complete request in time,
+		// and it should send "close" in the connection header field.
+		if conn.timed_out {
+			log.debugf("connection %i timed out reading the request", conn.socket)
+			connection_clear_deadline(conn)
+			headers_set(&res.headers, "connection", "close")
+			response_send_or_log(res, conn, .Request_Timeout, allocator)
+			return "", false
+		}
+
+		// The client closed the connection, there is nobody to respond to.
+		eof := err == nil
+		if ierr, ok := err.(io.Error); ok && ierr == .Unexpected_EOF {
+			eof = true
+		}
+
+		if eof {
+			log.debugf("connection %i closed by client", conn.socket)
+			connection_close(conn)
+			return "", false
+		}
+
 		log.warnf("request scanner error: %s", err)
 
 		res.status = .Bad_Request
@@ -467,7 +1338,7 @@ scanner_scan_or_bad_req :: proc(
 			}
 		}
 
-		res.headers["connection"] = "close"
+		headers_set(&res.headers, "connection", "close")
 		response_send_or_log(res, conn, res.status, allocator)
 		return "", false
 	}
@@ -0,0 +1,225 @@
+package http
+
+import "core:fmt"
+import "core:net"
+import "core:strings"
+import "core:testing"
+import "core:thread"
+import "core:time"
+
+// A server that serves on its own thread, for tests that talk to a real server.
+// It listens on the loopback address, on a port picked by the operating system, so tests that run at the same time don't clash.
+Test_Server :: struct {
+	server:   Server,
+	handler:  Handler,
+	// Where the server listens, with the port that was picked.
+	endpoint: net.Endpoint,
+	thread:   ^thread.Thread,
+}
+
+// Starts serving the handler on a new thread, stop it with test_server_stop.
+// The handler is copied into the Test_Server, which the server keeps a pointer to, so s should not move.
+test_server_start :: proc(
+	t: ^testing.T,
+	s: ^Test_Server,
+	h: Handler,
+	opts := Default_Server_Opts,
+	tls: Maybe(Server_TLS_Opts) = nil,
+	loc := #caller_location,
+) -> bool {
+	if err := server_listen(&s.server, net.Endpoint{address = net.IP4_Loopback, port = 0}, opts, tls); err != nil {
+		testing.errorf(t, "could not listen: %v", err, loc = loc)
+		return false
+	}
+
+	endpoint, err := net.bound_endpoint(s.server.tcp_sock)
+	if err != nil {
+		testing.errorf(t, "could not get the port the server listens on: %v", err, loc = loc)
+		server_serve_abort(&s.server)
+		return false
+	}
+
+	s.endpoint = endpoint
+	s.handler = h
+	s.thread = thread.create_and_start_with_poly_data(s, proc(s: ^Test_Server) {
+		server_serve(&s.server, &s.handler)
+	}, context)
+	return true
+}
+
+test_server_stop :: proc(s: ^Test_Server) {
+	server_shutdown(&s.server)
+	thread.join(s.thread)
+	thread.destroy(s.thread)
+}
+
+// The URL of the path on the test server, allocated using the temp allocator.
+test_server_url :: proc(s: ^Test_Server, path := "/") -> string {
+	return fmt.tprintf("http://127.0.0.1:%i%s", s.endpoint.port, path)
+}
+
+// Sends a request over a new connection, returns what the server responded with before closing the connection.
+@(private)
+server_test_get :: proc(t: ^testing.T, s: ^Test_Server, path: string) -> (response: string, ok: bool) {
+	sock, err := net.dial_tcp(s.endpoint)
+	if err != nil {
+		testing.errorf(t, "could not connect: %v", err)
+		return
+	}
+	defer net.close(sock)
+
+	// Don't hang the tests when the server does not respond.
+	net.set_option(sock, .Receive_Timeout, 5 * time.Second)
+
+	request := fmt.tprintf("GET %s HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n", path)
+	if _, serr := net.send_tcp(sock, transmute([]byte)request); serr != nil {
+		testing.errorf(t, "could not send the request: %v", serr)
+		return
+	}
+
+	b := strings.builder_make(context.temp_allocator)
+	buf: [1024]byte
+	for {
+		n, rerr := net.recv_tcp(sock, buf[:])
+		if rerr != nil || n == 0 do break
+		strings.write_bytes(&b, buf[:n])
+	}
+	return strings.to_string(b), true
+}
+
+@(test)
+test_server_hijacked_connections_release_their_worker :: proc(t: ^testing.T) {
+	opts := Default_Server_Opts
+	opts.thread_count = 1
+
+	h := handler(proc(req: ^Request, res: ^Response) {
+		if req.url.path != "/hijack" {
+			respond_plain(res, "not hijacked")
+			return
+		}
+
+		conn, ok := response_hijack(res)
+		if !ok do return
+
+		// Holds on to the connection until the client closes it.
+		buf: [64]byte
+		for {
+			if _, err := connection_read(conn, buf[:]); err != nil do break
+		}
+		connection_close(conn)
+	})
+
+	s: Test_Server
+	if !test_server_start(t, &s, h, opts) do return
+	defer test_server_stop(&s)
+
+	// More hijacked connections than workers, closed before the server is stopped so their handlers return.
+	hijacked: [3]net.TCP_Socket
+	opened := 0
+	defer {
+		for sock in hijacked[:opened] do net.close(sock)
+	}
+
+	for _, i in hijacked {
+		sock, err := net.dial_tcp(s.endpoint)
+		if err != nil {
+			testing.errorf(t, "could not connect: %v", err)
+			return
+		}
+		hijacked[i] = sock
+		opened += 1
+
+		request := "GET /hijack HTTP/1.1\r\nhost: localhost\r\n\r\n"
+		net.send_tcp(sock, transmute([]byte)request)
+
+		// Handled one after another, when the previous handler still held the only worker this one would never be.
+		time.sleep(50 * time.Millisecond)
+	}
+
+	response, ok := server_test_get(t, &s, "/")
+	if !ok do return
+	testing.expect(t, strings.has_prefix(response, "HTTP/1.1 200"), "a request is handled while hijacked connections are open")
+	testing.expect(t, strings.has_suffix(response, "not hijacked"), "a request is handled while hijacked connections are open")
+}
+
+// Sends a request with a body of the given length in parts, pausing after each, returns the response.
+@(private)
+server_test_post :: proc(
+	t: ^testing.T,
+	s: ^Test_Server,
+	length: int,
+	parts: []string,
+	pause: time.Duration,
+	path := "/",
+	content_type := "text/plain",
+) -> (response: string, ok: bool) {
+	sock, err := net.dial_tcp(s.endpoint)
+	if err != nil {
+		testing.errorf(t, "could not connect: %v", err)
+		return
+	}
+	defer net.close(sock)
+
+	// Don't hang the tests when the server does not respond.
+	net.set_option(sock, .Receive_Timeout, 5 * time.Second)
+
+	head := fmt.tprintf(
+		"POST %s HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\ncontent-type: %s\r\ncontent-length: %i\r\n\r\n",
+		path,
+		content_type,
+		length,
+	)
+	if _, serr := net.send_tcp(sock, transmute([]byte)head); serr != nil {
+		testing.errorf(t, "could not send the request: %v", serr)
+		return
+	}
+
+	for part in parts {
+		if _, serr := net.send_tcp(sock, transmute([]byte)part); serr != nil {
+			testing.errorf(t, "could not send the body: %v", serr)
+			return
+		}
+		time.sleep(pause)
+	}
+
+	b := strings.builder_make(context.temp_allocator)
+	buf: [1024]byte
+	for {
+		n, rerr := net.recv_tcp(sock, buf[:])
+		if rerr != nil || n == 0 do break
+		strings.write_bytes(&b, buf[:n])
+	}
+	return strings.to_string(b), true
+}
+
+@(test)
+test_server_body_timeout :: proc(t: ^testing.T) {
+	opts := Default_Server_Opts
+	opts.body_timeout = 300 * time.Millisecond
+
+	// Echoes the body back.
+	h := handler(proc(req: ^Request, res: ^Response) {
+		body, _, err := request_body(req, 1024)
+		if err != nil {
+			res.status = body_error_status(err)
+			return
+		}
+		respond_plain(res, body.(Body_Plain) or_else "")
+	})
+
+	s: Test_Server
+	if !test_server_start(t, &s, h, opts) do return
+	defer test_server_stop(&s)
+
+	// Every part arrives within the body timeout, all of them take longer than it, which is fine.
+	parts := []string{"aa", "bb", "cc", "dd", "ee"}
+	response, ok := server_test_post(t, &s, 10, parts, 100 * time.Millisecond)
+	if !ok do return
+	testing.expect(t, strings.has_prefix(response, "HTTP/1.1 200"), "a body that keeps coming in is read")
+	testing.expect(t, strings.has_suffix(response, "aabbccddee"), "a body that keeps coming in is read")
+
+	// The client stops sending halfway through the body.
+	response, ok = server_test_post(t, &s, 10, {"aa"}, 0)
+	if !ok do return
+	testing.expect(t, strings.has_prefix(response, "HTTP/1.1 408"), "a body that stops coming in times out")
+}
@@ -0,0 +1,326 @@
+package http
+
+import "core:crypto"
+import "core:crypto/sha2"
+import "core:log"
+import "core:mem"
+import "core:strings"
+import "core:time"
+
+// Server-side sessions.
+//
+// middleware_session loads the session of the client before the next handler, using the id in the session cookie,
+// and saves it to the store after it. Handlers use session_get, session_set and session_delete on the request,
+// session_regenerate when the user logs in, and session_destroy when they log out.
+//
+// The cookie only holds the session id, signed with HMAC-SHA256, the values are kept in the store.
+// A session is only stored (and the cookie only sent) once a value is set, visitors that never get a value
+// don't fill up the store.
+
+Session :: struct {
+	// The id of the session, empty when it has not been stored yet, or after session_regenerate or session_destroy,
+	// a new id is then created after the handler.
+	id:          string,
+	// Use session_get, session_set and session_delete to access these.
+	values:      map[string]string,
+	// When the session expires, zero for a session that has not been stored yet.
+	expires:     time.Time,
+	// The id to remove from the store after the handler, see session_regenerate.
+	_old_id:     string,
+	_changed:    bool,
+	// Whether the request came with a valid session cookie.
+	_had_cookie: bool,
+}
+
+// Where sessions are kept between requests, see Memory_Session_Store and File_Session_Store.
+// A store is used by multiple threads at the same time.
+//
+// Embed it as the first field of a custom store with `using`, the procedures get that store.
+Session_Store :: struct {
+	// Returns the values of the session and when it expires, ok is false when there is no such session, or it expired.
+	// The values are allocated with the given allocator.
+	load:    proc(store: ^Session_Store, id: string, allocator: mem.Allocator) -> (values: map[string]string, expires: time.Time, ok: bool),
+	// Saves the values of the session, replacing what was saved for the id before, the values have to be copied.
+	save:    proc(store: ^Session_Store, id: string, values: map[string]string, expires: time.Time) -> bool,
+	// Removes the session.
+	destroy: proc(store: ^Session_Store, id: string),
+}
+
+Session_Opts :: struct {
+	// The key the session cookie is signed with, at least 32 random bytes, keep it out of source control.
+	secret:   []byte,
+	store:    ^Session_Store,
+	// The session cookie, its value and max age are set by the middleware.
+	cookie:   Cookie,
+	// How long a session lasts.
+	lifetime: time.Duration,
+	// Whether the lifetime starts over on each request, so the session expires after being unused for lifetime,
+	// otherwise it expires lifetime after it was created.
+	sliding:  bool,
+}
+
+Default_Session_Opts :: Session_Opts {
+	cookie   = {name = "session", path = "/", http_only = true, secure = true, same_site = .Lax},
+	lifetime = 24 * time.Hour,
+	sliding  = true,
+}
+
+// Attaches the session of the client to the request, see Session.
+//
+// The cookie is set after the next handler returns, so a handler that streams the response (see response_write)
+// has to be done with the session before it starts writing.
+//
+// The next handler can be nil when the middleware is added to a route group, the group sets it.
+middleware_session :: proc(next: Maybe(^Handler), opts: ^Session_Opts) -> Handler {
+	assert(len(opts.secret) > 0, "middleware_session needs a secret to sign the cookie with")
+	assert(opts.store != nil, "middleware_session needs a store")
+
+	h: Handler
+	h.user_data = opts
+	h.next = next
+
+	h.handle = proc(h: ^Handler, req: ^Request, res: ^Response) {
+		opts := (^Session_Opts)(h.user_data)
+
+		next, has_next := h.next.(^Handler)
+		if !has_next {
+			log.warn("middleware_session does not have a next handler")
+			return
+		}
+
+		s := new(Session, req.allocator)
+		req.session = s
+
+		if cookie, ok := request_cookie(req, opts.cookie.name); ok {
+			if id, valid := session_cookie_verify(opts.secret, cookie); valid {
+				s._had_cookie = true
+
+				values, expires, found := opts.store.load(opts.store, id, req.allocator)
+				if found && time.diff(time.now(), expires) > 0 {
+					s.id = id
+					s.values = values
+					s.expires = expires
+				}
+			}
+		}
+
+		if s.id == "" {
+			s.values = make(map[string]string, 8, req.allocator)
+		}
+
+		next.handle(next, req, res)
+
+		session_finish(opts, req, res)
+	}
+
+	return h
+}
+
+// Returns the value of the key in the session of the request.
+session_get :: proc(req: ^Request, key: string) -> (value: string, ok: bool) {
+	assert(req.session != nil, "middleware_session does not handle this request")
+	value, ok = req.session.values[key]
+	return
+}
+
+// Sets the value of the key in the session of the request, the key and value are copied.
+session_set :: proc(req: ^Request, key, value: string) {
+	assert(req.session != nil, "middleware_session does not handle this request")
+	s := req.session
+
+	if key in s.values {
+		s.values[key] = strings.clone(value, req.allocator)
+	} else {
+		s.values[strings.clone(key, req.allocator)] = strings.clone(value, req.allocator)
+	}
+	s._changed = true
+}
+
+// Removes the key from the session of the request.
+session_delete :: proc(req: ^Request, key: string) {
+	assert(req.session != nil, "middleware_session does not handle this request")
+	s := req.session
+
+	if key in s.values {
+		delete_key(&s.values, key)
+		s._changed = true
+	}
+}
+
+// Gives the session a new id, keeping its values, and removes the old id from the store.
+// Call it when the user logs in or their privileges change, so an id that leaked or was
+// planted before (session fixation) can't be used to act as them.
+session_regenerate :: proc(req: ^Request) {
+	assert(req.session != nil, "middleware_session does not handle this request")
+	s := req.session
+
+	if s.id != "" {
+		if s._old_id == "" do s._old_id = s.id
+		s.id = ""
+	}
+	s._changed = true
+}
+
+// Removes the session from the store and the cookie from the client, like when the user logs out.
+// Values set afterwards start a new session.
+session_destroy :: proc(req: ^Request) {
+	assert(req.session != nil, "middleware_session does not handle this request")
+	s := req.session
+
+	if s.id != "" {
+		if s._old_id == "" do s._old_id = s.id
+		s.id = ""
+	}
+	clear(&s.values)
+	s._changed = true
+}
+
+// Saves the session after the handler, and sets or removes the cookie.
+@(private)
+session_finish :: proc(opts: ^Session_Opts, req: ^Request, res: ^Response) {
+	s := req.session
+	store := opts.store
+	now := time.now()
+
+	if s._old_id != "" {
+		store.destroy(store, s._old_id)
+	}
+
+	cookie := opts.cookie
+	switch {
+	case s.id == "" && len(s.values) == 0:
+		// Nothing to store, the cookie of a destroyed or expired session is removed.
+		if !s._had_cookie do return
+		cookie.value = ""
+		cookie.max_age_secs = 0
+
+	case s.id == "":
+		s.id = session_id_new(req.allocator)
+		s.expires = time.time_add(now, opts.lifetime)
+		if !store.save(store, s.id, s.values, s.expires) {
+			log.errorf("could not save session")
+			return
+		}
+
+		cookie.value = session_cookie_sign(opts.secret, s.id, req.allocator)
+		cookie.max_age_secs = int(opts.lifetime / time.Second)
+
+	case opts.sliding:
+		s.expires = time.time_add(now, opts.lifetime)
+		if !store.save(store, s.id, s.values, s.expires) {
+			log.errorf("could not save session")
+			return
+		}
+
+		cookie.value = session_cookie_sign(opts.secret, s.id, req.allocator)
+		cookie.max_age_secs = int(opts.lifetime / time.Second)
+
+	case:
+		if s._changed && !store.save(store, s.id, s.values, s.expires) {
+			log.errorf("could not save session")
+		}
+		return
+	}
+
+	if res._headers_sent {
+		log.warn("the session changed after the response was sent, the session cookie could not be set")
+		return
+	}
+
+	append(&res.cookies, cookie)
+}
+
+// The length of a session id, 32 random bytes in hex.
+@(private)
+Session_Id_Length :: 64
+
+@(private)
+session_id_new :: proc(allocator := context.allocator) -> string {
+	random: [Session_Id_Length / 2]byte
+	crypto.rand_bytes(random[:])
+	return session_hex(random[:], allocator)
+}
+
+// Whether the id is one that session_id_new could have created, it is used as a file name by File_Session_Store.
+@(private)
+session_id_valid :: proc(id: string) -> bool {
+	if len(id) != Session_Id_Length do return false
+	for c in transmute([]byte)id {
+		if !(c >= '0' && c <= '9') && !(c >= 'a' && c <= 'f') do return false
+	}
+	return true
+}
+
+// Returns the cookie value for the id, the id followed by a dot and its signature.
+@(private)
+session_cookie_sign :: proc(secret: []byte, id: string, allocator := context.allocator) -> string {
+	mac := hmac_sha256(secret, transmute([]byte)id)
+	return strings.concatenate({id, ".", session_hex(mac[:], context.temp_allocator)}, allocator)
+}
+
+// Returns the id in the cookie value, ok is false when the signature does not match.
+@(private)
+session_cookie_verify :: proc(secret: []byte, value: string) -> (id: string, ok: bool) {
+	dot := strings.index_byte(value, '.')
+	if dot < 0 do return
+
+	id = value[:dot]
+	if !session_id_valid(id) do return "", false
+
+	mac := hmac_sha256(secret, transmute([]byte)id)
+	expected := session_hex(mac[:], context.temp_allocator)
+
+	// Compared in constant time, so the signature can't be guessed byte by byte from how long the comparison takes.
+	given := value[dot+1:]
+	if len(given) != len(expected) do return "", false
+	if crypto.compare_constant_time(transmute([]byte)given, transmute([]byte)expected) != 1 do return "", false
+
+	return id, true
+}
+
+@(private)
+session_hex :: proc(data: []byte, allocator := context.allocator) -> string {
+	Digits :: "0123456789abcdef"
+
+	buf := make([]byte, len(data) * 2, allocator)
+	for b, i in data {
+		buf[i*2]   = Digits[b >> 4]
+		buf[i*2+1] = Digits[b & 0xf]
+	}
+	return string(buf)
+}
+
+// RFC 2104: HMAC with SHA-256.
+@(private)
+hmac_sha256 :: proc(key, data: []byte) -> (mac: [sha2.DIGEST_SIZE_256]byte) {
+	Block_Size :: 64
+
+	// Keys longer than a block are hashed first.
+	k: [Block_Size]byte
+	if len(key) > Block_Size {
+		sum := sha2.hash_bytes_256(key)
+		copy(k[:], sum[:])
+	} else {
+		copy(k[:], key)
+	}
+
+	ipad, opad: [Block_Size]byte
+	for b, i in k {
+		ipad[i] = b ~ 0x36
+		opad[i] = b ~ 0x5c
+	}
+
+	inner: [sha2.DIGEST_SIZE_256]byte
+	ctx: sha2.Sha256_Context
+	sha2.init(&ctx)
+	sha2.update(&ctx, ipad[:])
+	sha2.update(&ctx, data)
+	sha2.final(&ctx, inner[:])
+
+	ctx = {}
+	sha2.init(&ctx)
+	sha2.update(&ctx, opad[:])
+	sha2.update(&ctx, inner[:])
+	sha2.final(&ctx, mac[:])
+	return
+}
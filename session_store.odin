@@ -0,0 +1,294 @@
+package http
+
+import "core:log"
+import "core:math/rand"
+import "core:mem"
+import "core:net"
+import "core:os"
+import "core:path/filepath"
+import "core:strconv"
+import "core:strings"
+import "core:sync"
+import "core:time"
+
+// How often the stores remove expired sessions, checked when a session is saved.
+@(private)
+Session_Sweep_Interval :: time.Minute
+
+// Keeps sessions in memory, they are lost when the server stops.
+Memory_Session_Store :: struct {
+	using store: Session_Store,
+	allocator:   mem.Allocator,
+	_sessions:   map[string]Memory_Session,
+	_next_sweep: time.Time,
+	_mu:         sync.Mutex,
+}
+
+@(private)
+Memory_Session :: struct {
+	values:  map[string]string,
+	expires: time.Time,
+}
+
+memory_session_store_init :: proc(s: ^Memory_Session_Store, allocator := context.allocator) {
+	s.allocator = allocator
+	s._sessions = make(map[string]Memory_Session, 16, allocator)
+	s._next_sweep = time.time_add(time.now(), Session_Sweep_Interval)
+
+	s.load = proc(store: ^Session_Store, id: string, allocator: mem.Allocator) -> (values: map[string]string, expires: time.Time, ok: bool) {
+		s := (^Memory_Session_Store)(store)
+		sync.guard(&s._mu)
+
+		session, found := s._sessions[id]
+		if !found do return
+
+		if time.diff(time.now(), session.expires) <= 0 {
+			memory_session_remove(s, id)
+			return
+		}
+
+		values = make(map[string]string, len(session.values), allocator)
+		for k, v in session.values {
+			values[strings.clone(k, allocator)] = strings.clone(v, allocator)
+		}
+		return values, session.expires, true
+	}
+
+	s.save = proc(store: ^Session_Store, id: string, values: map[string]string, expires: time.Time) -> bool {
+		s := (^Memory_Session_Store)(store)
+		context.allocator = s.allocator
+		sync.guard(&s._mu)
+
+		if time.since(s._next_sweep) > 0 {
+			memory_session_sweep(s)
+			s._next_sweep = time.time_add(time.now(), Session_Sweep_Interval)
+		}
+
+		session := Memory_Session{
+			values  = make(map[string]string, len(values)),
+			expires = expires,
+		}
+		for k, v in values {
+			session.values[strings.clone(k)] = strings.clone(v)
+		}
+
+		if id in s._sessions {
+			memory_session_free(s._sessions[id])
+			s._sessions[id] = session
+		} else {
+			s._sessions[strings.clone(id)] = session
+		}
+		return true
+	}
+
+	s.destroy = proc(store: ^Session_Store, id: string) {
+		s := (^Memory_Session_Store)(store)
+		sync.guard(&s._mu)
+		memory_session_remove(s, id)
+	}
+}
+
+memory_session_store_destroy :: proc(s: ^Memory_Session_Store) {
+	context.allocator = s.allocator
+	for id, session in s._sessions {
+		memory_session_free(session)
+		delete(id)
+	}
+	delete(s._sessions)
+}
+
+// Removes the session, the store's mutex has to be locked.
+@(private)
+memory_session_remove :: proc(s: ^Memory_Session_Store, id: string) {
+	context.allocator = s.allocator
+	if id not_in s._sessions do return
+
+	key, session := delete_key(&s._sessions, id)
+	memory_session_free(session)
+	delete(key)
+}
+
+// Removes the expired sessions, the store's mutex has to be locked.
+@(private)
+memory_session_sweep :: proc(s: ^Memory_Session_Store) {
+	now := time.now()
+
+	expired := make([dynamic]string, context.temp_allocator)
+	for id, session in s._sessions {
+		if time.diff(now, session.expires) <= 0 do append(&expired, id)
+	}
+
+	for id in expired {
+		memory_session_remove(s, id)
+	}
+}
+
+@(private)
+memory_session_free :: proc(session: Memory_Session, allocator := context.allocator) {
+	for k, v in session.values {
+		delete(k, allocator)
+		delete(v, allocator)
+	}
+	delete(session.values)
+}
+
+// Keeps sessions in files, one per session, so they survive restarts of the server.
+//
+// A file holds when the session expires, followed by a line per value, with the key and value percent encoded.
+// Files are written to a temporary file first, and then renamed, so a file is never read half written.
+File_Session_Store :: struct {
+	using store: Session_Store,
+	// The directory the session files are in.
+	dir:         string,
+	allocator:   mem.Allocator,
+	_next_sweep: time.Time,
+	_mu:         sync.Mutex,
+}
+
+// Initializes the store for the directory, it is created when it does not exist.
+file_session_store_init :: proc(s: ^File_Session_Store, dir: string, allocator := context.allocator) -> bool {
+	s.dir = strings.clone(dir, allocator)
+	s.allocator = allocator
+	s._next_sweep = time.time_add(time.now(), Session_Sweep_Interval)
+
+	if !os.is_dir(dir) {
+		if err := os.make_directory(dir, 0o700); err != os.ERROR_NONE {
+			log.errorf("could not create session directory %q: %v", dir, err)
+			return false
+		}
+	}
+
+	s.load = proc(store: ^Session_Store, id: string, allocator: mem.Allocator) -> (values: map[string]string, expires: time.Time, ok: bool) {
+		s := (^File_Session_Store)(store)
+		if !session_id_valid(id) do return
+
+		path := filepath.join({s.dir, id}, context.temp_allocator)
+		values, expires = file_session_read(path, allocator) or_return
+
+		if time.diff(time.now(), expires) <= 0 {
+			os.remove(path)
+			return {}, {}, false
+		}
+		return values, expires, true
+	}
+
+	s.save = proc(store: ^Session_Store, id: string, values: map[string]string, expires: time.Time) -> bool {
+		s := (^File_Session_Store)(store)
+		if !session_id_valid(id) do return false
+
+		sync.mutex_lock(&s._mu)
+		sweep := time.since(s._next_sweep) > 0
+		if sweep do s._next_sweep = time.time_add(time.now(), Session_Sweep_Interval)
+		sync.mutex_unlock(&s._mu)
+
+		if sweep do file_session_sweep(s)
+
+		b := strings.builder_make(context.temp_allocator)
+		strings.write_i64(&b, time.to_unix_nanoseconds(expires))
+		strings.write_byte(&b, '\n')
+		for k, v in values {
+			strings.write_string(&b, net.percent_encode(k, context.temp_allocator))
+			strings.write_byte(&b, '=')
+			strings.write_string(&b, net.percent_encode(v, context.temp_allocator))
+			strings.write_byte(&b, '\n')
+		}
+
+		path := filepath.join({s.dir, id}, context.temp_allocator)
+
+		// Random, concurrent saves of the same session don't write to the same temporary file.
+		name_buf: [32]byte
+		tmp := strings.concatenate({path, ".", strconv.append_u64(name_buf[:], rand.uint64(), 16), ".tmp"}, context.temp_allocator)
+
+		fd, err := os.open(tmp, os.O_WRONLY | os.O_CREATE | os.O_TRUNC, 0o600)
+		if err != os.ERROR_NONE {
+			log.errorf("could not create session file %q: %v", tmp, err)
+			return false
+		}
+
+		_, werr := os.write(fd, transmute([]byte)strings.to_string(b))
+		os.close(fd)
+		if werr != os.ERROR_NONE {
+			log.errorf("could not write session file %q: %v", tmp, werr)
+			os.remove(tmp)
+			return false
+		}
+
+		if rerr := os.rename(tmp, path); rerr != os.ERROR_NONE {
+			log.errorf("could not move session file %q into place: %v", tmp, rerr)
+			os.remove(tmp)
+			return false
+		}
+		return true
+	}
+
+	s.destroy = proc(store: ^Session_Store, id: string) {
+		s := (^File_Session_Store)(store)
+		if !session_id_valid(id) do return
+
+		os.remove(filepath.join({s.dir, id}, context.temp_allocator))
+	}
+
+	return true
+}
+
+file_session_store_destroy :: proc(s: ^File_Session_Store) {
+	delete(s.dir, s.allocator)
+}
+
+// Reads the session file, ok is false when it does not exist or is malformed.
+@(private)
+file_session_read :: proc(path: string, allocator := context.allocator) -> (values: map[string]string, expires: time.Time, ok: bool) {
+	data := os.read_entire_file(path, context.temp_allocator) or_return
+	content := string(data)
+
+	first := strings.split_iterator(&content, "\n") or_return
+	nanos := strconv.parse_i64_of_base(first, 10) or_return
+	expires = time.unix(0, nanos)
+
+	values = make(map[string]string, 8, allocator)
+	for line in strings.split_iterator(&content, "\n") {
+		if line == "" do continue
+
+		eq := strings.index_byte(line, '=')
+		if eq < 0 do return values, expires, false
+
+		key, key_ok := net.percent_decode(line[:eq], allocator)
+		value, value_ok := net.percent_decode(line[eq+1:], allocator)
+		if !key_ok || !value_ok do return values, expires, false
+
+		values[key] = value
+	}
+
+	return values, expires, true
+}
+
+// Removes the files of expired sessions, and temporary files left behind by a crash.
+@(private)
+file_session_sweep :: proc(s: ^File_Session_Store) {
+	fd, err := os.open(s.dir)
+	if err != os.ERROR_NONE do return
+	defer os.close(fd)
+
+	entries, rerr := os.read_dir(fd, -1, context.temp_allocator)
+	if rerr != os.ERROR_NONE do return
+
+	now := time.now()
+	for entry in entries {
+		if entry.is_dir do continue
+
+		if strings.has_suffix(entry.name, ".tmp") {
+			// Older than the sweep interval, no save is still writing it.
+			if time.diff(entry.modification_time, now) > Session_Sweep_Interval {
+				os.remove(entry.fullpath)
+			}
+			continue
+		}
+
+		if !session_id_valid(entry.name) do continue
+
+		_, expires, ok := file_session_read(entry.fullpath, context.temp_allocator)
+		if !ok || time.diff(now, expires) <= 0 {
+			os.remove(entry.fullpath)
+		}
+	}
+}
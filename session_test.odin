@@ -0,0 +1,376 @@
+package http
+
+import "core:mem"
+import "core:os"
+import "core:strings"
+import "core:testing"
+import "core:time"
+
+@(private)
+Session_Test_Secret :: "a secret for the tests that is longer than 32 bytes"
+// The file store tests keep their sessions here.
+@(private)
+Session_Test_Dir :: "session_test_data"
+
+// What the handler behind the middleware does with the session.
+@(private)
+Session_Test_Action :: #type proc(req: ^Request, res: ^Response)
+
+// Calls the session middleware like the server would, with the cookie value sent by the client when it is not empty.
+@(private)
+session_test_request :: proc(opts: ^Session_Opts, cookie: string, action: Session_Test_Action = nil) -> (res: Response) {
+	action := action
+
+	next: Handler
+	next.user_data = &action
+	next.handle = proc(h: ^Handler, req: ^Request, res: ^Response) {
+		res.status = .Ok
+		if do_action := (^Session_Test_Action)(h.user_data)^; do_action != nil {
+			do_action(req, res)
+		}
+	}
+	h := middleware_session(&next, opts)
+
+	req: Request
+	request_init(&req, context.temp_allocator)
+	req.line = Requestline{method = .Get, target = "/", version = {1, 1}}
+	req.url = url_parse("/", context.temp_allocator)
+	if cookie != "" {
+		headers_set(&req.headers, "cookie", strings.concatenate({opts.cookie.name, "=", cookie}, context.temp_allocator))
+	}
+
+	response_init(&res, context.temp_allocator)
+	h.handle(&h, &req, &res)
+	return
+}
+
+// Returns the session cookie the response sets.
+@(private)
+session_test_cookie :: proc(opts: ^Session_Opts, res: ^Response) -> (cookie: Cookie, ok: bool) {
+	for c in res.cookies {
+		if c.name == opts.cookie.name do return c, true
+	}
+	return
+}
+
+// Returns the session id in the value of a session cookie.
+@(private)
+session_test_cookie_id :: proc(value: string) -> string {
+	dot := strings.index_byte(value, '.')
+	return value[:dot] if dot >= 0 else value
+}
+
+@(private)
+session_test_opts :: proc(store: ^Session_Store) -> Session_Opts {
+	opts := Default_Session_Opts
+	opts.secret = transmute([]byte)string(Session_Test_Secret)
+	opts.store = store
+	return opts
+}
+
+@(private)
+session_test_bytes :: proc(b: byte, n: int) -> []byte {
+	data := make([]byte, n, context.temp_allocator)
+	for _, i in data do data[i] = b
+	return data
+}
+
+@(test)
+test_session_hmac_sha256 :: proc(t: ^testing.T) {
+	Case :: struct {
+		key, data: []byte,
+		mac:       string,
+	}
+
+	key_4 := make([]byte, 25, context.temp_allocator)
+	for _, i in key_4 do key_4[i] = byte(i + 1)
+
+	// RFC 4231 4.2 to 4.8, without 4.6, which is truncated.
+	cases := []Case{
+		{session_test_bytes(0x0b, 20), transmute([]byte)string("Hi There"), "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"},
+		{transmute([]byte)string("Jefe"), transmute([]byte)string("what do ya want for nothing?"), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"},
+		{session_test_bytes(0xaa, 20), session_test_bytes(0xdd, 50), "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"},
+		{key_4, session_test_bytes(0xcd, 50), "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b"},
+		{session_test_bytes(0xaa, 131), transmute([]byte)string("Test Using Larger Than Block-Size Key - Hash Key First"), "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"},
+		{
+			session_test_bytes(0xaa, 131),
+			transmute([]byte)string("This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm."),
+			"9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+		},
+	}
+
+	for c, i in cases {
+		mac := hmac_sha256(c.key, c.data)
+		got := session_hex(mac[:], context.temp_allocator)
+		testing.expectf(t, got == c.mac, "test case %i: got %s, expected %s", i + 1, got, c.mac)
+	}
+}
+
+@(test)
+test_session_cookie_rejects_tampering :: proc(t: ^testing.T) {
+	secret := transmute([]byte)string(Session_Test_Secret)
+
+	id := session_id_new(context.temp_allocator)
+	value := session_cookie_sign(secret, id, context.temp_allocator)
+
+	verified, ok := session_cookie_verify(secret, value)
+	testing.expect(t, ok, "a signed cookie is valid")
+	testing.expect_value(t, verified, id)
+
+	// Changes the hex digit at the index to another one.
+	tamper :: proc(s: string, i: int) -> string {
+		b := transmute([]byte)strings.clone(s, context.temp_allocator)
+		b[i] = b[i] == '0' ? '1' : '0'
+		return string(b)
+	}
+
+	rejected := []string{
+		tamper(value, len(value) - 1),
+		tamper(value, len(id) + 1),
+		tamper(value, 0),
+		tamper(value, len(id) - 1),
+		id,
+		strings.concatenate({id, "."}, context.temp_allocator),
+		strings.concatenate({value, "0"}, context.temp_allocator),
+		value[:len(value) - 1],
+		session_cookie_sign(transmute([]byte)string("another secret, also longer than 32 bytes"), id, context.temp_allocator),
+		"",
+		".",
+	}
+
+	for r in rejected {
+		if _, valid := session_cookie_verify(secret, r); valid {
+			testing.errorf(t, "%q is accepted", r)
+		}
+	}
+}
+
+@(test)
+test_session_invalid_ids_never_reach_the_store :: proc(t: ^testing.T) {
+	secret := transmute([]byte)string(Session_Test_Secret)
+
+	Recording_Store :: struct {
+		using store: Session_Store,
+		loaded:      [dynamic]string,
+	}
+
+	store: Recording_Store
+	store.loaded = make([dynamic]string, context.temp_allocator)
+	store.load = proc(store: ^Session_Store, id: string, allocator: mem.Allocator) -> (values: map[string]string, expires: time.Time, ok: bool) {
+		s := (^Recording_Store)(store)
+		append(&s.loaded, strings.clone(id, context.temp_allocator))
+		return
+	}
+	store.save = proc(store: ^Session_Store, id: string, values: map[string]string, expires: time.Time) -> bool {
+		return true
+	}
+	store.destroy = proc(store: ^Session_Store, id: string) {}
+
+	opts := session_test_opts(&store.store)
+
+	// Correctly signed, so only the check of the id itself keeps them out.
+	ids := []string{
+		"../../../etc/passwd",
+		"..",
+		"",
+		strings.repeat("a", Session_Id_Length - 1, context.temp_allocator),
+		strings.repeat("a", Session_Id_Length + 1, context.temp_allocator),
+		strings.repeat("A", Session_Id_Length, context.temp_allocator),
+		strings.concatenate({strings.repeat("a", Session_Id_Length - 3, context.temp_allocator), "/.."}, context.temp_allocator),
+		strings.concatenate({strings.repeat("a", Session_Id_Length - 1, context.temp_allocator), "g"}, context.temp_allocator),
+	}
+
+	for id in ids {
+		session_test_request(&opts, session_cookie_sign(secret, id, context.temp_allocator))
+	}
+	testing.expectf(t, len(store.loaded) == 0, "the store was asked for %v", store.loaded[:])
+
+	// The file store checks too, as it uses the id as a file name.
+	fs: File_Session_Store
+	if !file_session_store_init(&fs, Session_Test_Dir) {
+		testing.error(t, "could not create the session directory")
+		return
+	}
+	defer {
+		file_session_store_destroy(&fs)
+		os.remove_directory(Session_Test_Dir)
+	}
+
+	values := make(map[string]string, 1, context.temp_allocator)
+	values["user"] = "1"
+	expires := time.time_add(time.now(), time.Hour)
+
+	for id in ids {
+		testing.expectf(t, !fs.save(&fs.store, id, values, expires), "%q is saved", id)
+		_, _, found := fs.load(&fs.store, id, context.temp_allocator)
+		testing.expectf(t, !found, "%q is loaded", id)
+	}
+	testing.expect(t, !fs.save(&fs.store, "../session_test_escape", values, expires), "a path is not an id")
+	testing.expect(t, !os.exists("session_test_escape"), "a session file is written outside the directory")
+}
+
+@(test)
+test_session_regenerate :: proc(t: ^testing.T) {
+	store: Memory_Session_Store
+	memory_session_store_init(&store)
+	defer memory_session_store_destroy(&store)
+	opts := session_test_opts(&store.store)
+
+	res := session_test_request(&opts, "", proc(req: ^Request, res: ^Response) {
+		session_set(req, "user", "1")
+	})
+	first, ok := session_test_cookie(&opts, &res)
+	if !ok {
+		testing.error(t, "setting a value sets the cookie")
+		return
+	}
+	old_id := session_test_cookie_id(first.value)
+
+	res = session_test_request(&opts, first.value, proc(req: ^Request, res: ^Response) {
+		session_regenerate(req)
+	})
+	second, has_second := session_test_cookie(&opts, &res)
+	if !has_second {
+		testing.error(t, "regenerating sets a new cookie")
+		return
+	}
+	new_id := session_test_cookie_id(second.value)
+
+	testing.expect(t, new_id != old_id, "the session has a new id")
+	testing.expect(t, session_id_valid(new_id), "the new id is valid")
+
+	_, _, old_found := store.load(&store.store, old_id, context.temp_allocator)
+	testing.expect(t, !old_found, "the old id is removed from the store")
+
+	values, _, new_found := store.load(&store.store, new_id, context.temp_allocator)
+	testing.expect(t, new_found, "the session is stored under the new id")
+	testing.expect_value(t, values["user"], "1")
+
+	// The old cookie does not give access to the session anymore.
+	res = session_test_request(&opts, first.value, proc(req: ^Request, res: ^Response) {
+		respond_plain(res, session_get(req, "user") or_else "")
+	})
+	testing.expect_value(t, string(res.body.buf[:]), "")
+}
+
+@(test)
+test_session_expired_drops_cookie :: proc(t: ^testing.T) {
+	store: Memory_Session_Store
+	memory_session_store_init(&store)
+	defer memory_session_store_destroy(&store)
+	opts := session_test_opts(&store.store)
+
+	id := session_id_new(context.temp_allocator)
+	values := make(map[string]string, 1, context.temp_allocator)
+	values["user"] = "1"
+	store.save(&store.store, id, values, time.time_add(time.now(), -time.Second))
+
+	res := session_test_request(&opts, session_cookie_sign(opts.secret, id, context.temp_allocator), proc(req: ^Request, res: ^Response) {
+		respond_plain(res, session_get(req, "user") or_else "")
+	})
+	testing.expect_value(t, string(res.body.buf[:]), "")
+
+	cookie, ok := session_test_cookie(&opts, &res)
+	testing.expect(t, ok, "the cookie of an expired session is removed")
+	testing.expect_value(t, cookie.value, "")
+	testing.expect_value(t, cookie.max_age_secs.? or_else -1, 0)
+}
+
+@(test)
+test_session_sliding_and_fixed_expiry :: proc(t: ^testing.T) {
+	for sliding in ([]bool{true, false}) {
+		store: Memory_Session_Store
+		memory_session_store_init(&store)
+		defer memory_session_store_destroy(&store)
+		opts := session_test_opts(&store.store)
+		opts.sliding = sliding
+
+		res := session_test_request(&opts, "", proc(req: ^Request, res: ^Response) {
+			session_set(req, "user", "1")
+		})
+		cookie, ok := session_test_cookie(&opts, &res)
+		if !ok {
+			testing.errorf(t, "sliding=%v: setting a value sets the cookie", sliding)
+			continue
+		}
+		id := session_test_cookie_id(cookie.value)
+
+		_, created, _ := store.load(&store.store, id, context.temp_allocator)
+		time.sleep(5 * time.Millisecond)
+
+		// A request that does not change the session.
+		res = session_test_request(&opts, cookie.value)
+		_, resent := session_test_cookie(&opts, &res)
+		_, expires, _ := store.load(&store.store, id, context.temp_allocator)
+
+		if sliding {
+			testing.expect(t, resent, "a sliding session sends the cookie again, with the full max age")
+			testing.expect(t, time.diff(created, expires) > 0, "a sliding session is extended on every request")
+		} else {
+			testing.expect(t, !resent, "a fixed session does not send the cookie again")
+			testing.expect_value(t, expires, created)
+		}
+
+		// A request that changes the session keeps the expiry of a fixed session.
+		res = session_test_request(&opts, cookie.value, proc(req: ^Request, res: ^Response) {
+			session_set(req, "user", "2")
+		})
+		values: map[string]string
+		values, expires, _ = store.load(&store.store, id, context.temp_allocator)
+		testing.expect_value(t, values["user"], "2")
+		if !sliding {
+			testing.expect_value(t, expires, created)
+		}
+	}
+}
+
+@(test)
+test_session_file_store_round_trip :: proc(t: ^testing.T) {
+	fs: File_Session_Store
+	if !file_session_store_init(&fs, Session_Test_Dir + "_round_trip") {
+		testing.error(t, "could not create the session directory")
+		return
+	}
+	defer {
+		os.remove_directory(fs.dir)
+		file_session_store_destroy(&fs)
+	}
+
+	values := make(map[string]string, 8, context.temp_allocator)
+	values["user"] = "1"
+	values["with space"] = "a value with spaces"
+	values["k=v"] = "a=b=c"
+	values["new\nline"] = "first\nsecond\r\n"
+	values["100%"] = "%41 stays %41"
+	values["ünïcödé"] = "✓"
+	values["empty"] = ""
+
+	id := session_id_new(context.temp_allocator)
+	expires := time.time_add(time.now(), time.Hour)
+	if !fs.save(&fs.store, id, values, expires) {
+		testing.error(t, "could not save the session")
+		return
+	}
+
+	loaded, loaded_expires, ok := fs.load(&fs.store, id, context.temp_allocator)
+	if !ok {
+		testing.error(t, "could not load the session")
+		return
+	}
+
+	testing.expect_value(t, loaded_expires, expires)
+	testing.expect_value(t, len(loaded), len(values))
+	for k, v in values {
+		got, found := loaded[k]
+		testing.expectf(t, found && got == v, "%q is %q, expected %q", k, got, v)
+	}
+
+	fs.destroy(&fs.store, id)
+	_, _, ok = fs.load(&fs.store, id, context.temp_allocator)
+	testing.expect(t, !ok, "a destroyed session is gone")
+
+	// An expired session is not loaded, and its file is removed.
+	fs.save(&fs.store, id, values, time.time_add(time.now(), -time.Second))
+	_, _, ok = fs.load(&fs.store, id, context.temp_allocator)
+	testing.expect(t, !ok, "an expired session is not loaded")
+}
@@ -0,0 +1,350 @@
+package http
+
+import "core:log"
+import "core:net"
+import "core:os"
+import "core:path/filepath"
+import "core:slice"
+import "core:strings"
+
+Static_Dotfiles :: enum {
+	// Files and directories starting with a dot are not found, and left out of directory listings.
+	Hide,
+	// Requests for files and directories starting with a dot are answered with 403 Forbidden.
+	Forbid,
+	// Files and directories starting with a dot are served like any other.
+	Allow,
+}
+
+Static_Symlinks :: enum {
+	// Symbolic links are followed when they point to something inside the root directory.
+	Within_Root,
+	// Symbolic links are followed, wherever they point to.
+	Follow,
+	// Paths that go through a symbolic link are not found.
+	Deny,
+}
+
+Static_Cache_Control :: struct {
+	// The file extension, including the dot, like ".css", or "*" for files that no other rule matches.
+	ext:   string,
+	// The value of the Cache-Control header, like "public, max-age=31536000, immutable".
+	value: string,
+}
+
+Static_Opts :: struct {
+	// Removed from the request path before it is looked up in the root directory,
+	// like "/static" when the handler is added to the route "/static/*path".
+	// Only whole segments are removed, with "/static" the path "/staticfoo" is looked up as is.
+	// The prefix of the route group the handler is added to is always removed.
+	strip_prefix:  string,
+	// The file that is served for a directory, empty to not serve index files.
+	index:         string,
+	// Whether a directory without an index file is answered with an HTML listing of its contents,
+	// otherwise it is 403 Forbidden.
+	listing:       bool,
+	// Whether a ".br" or ".gz" file next to the requested file is served instead,
+	// when the client accepts that encoding. The content type stays that of the requested file.
+	precompressed: bool,
+	// Cache-Control headers per file extension.
+	cache_control: []Static_Cache_Control,
+	dotfiles:      Static_Dotfiles,
+	symlinks:      Static_Symlinks,
+}
+
+Default_Static_Opts := Static_Opts {
+	index         = "index.html",
+	listing       = false,
+	precompressed = true,
+	dotfiles      = .Hide,
+	symlinks      = .Within_Root,
+}
+
+@(private)
+Static_Data :: struct {
+	// The absolute path of the root directory, with symbolic links resolved.
+	root: string,
+	opts: ^Static_Opts,
+}
+
+// Returns a handler that serves the files inside the root directory, using respond_file,
+// so Range requests and conditional requests are supported.
+//
+// A request for a directory is redirected to the path with a trailing slash, where it is answered
+// with the index file, or a listing, see Static_Opts.
+//
+// Path traversal is prevented by checking the percent decoded path for ".." segments,
+// so encoded forms like "%2e%2e/", "..%2f" and "%2E%2E%5C" are rejected too.
+// The path is never decoded a second time, so "%252e%252e" is a file named "%2e%2e".
+static_handler :: proc(root: string, opts: ^Static_Opts = nil, allocator := context.allocator) -> Handler {
+	context.allocator = allocator
+
+	data := new(Static_Data)
+	data.opts = opts != nil ? opts : &Default_Static_Opts
+
+	real, err := os.absolute_path_from_relative(root)
+	if err != os.ERROR_NONE {
+		log.errorf("could not resolve static root %q: %v", root, err)
+		real = filepath.clean(root)
+	}
+	data.root = real
+
+	h: Handler
+	h.user_data = data
+
+	h.handle = proc(h: ^Handler, req: ^Request, res: ^Response) {
+		data := (^Static_Data)(h.user_data)
+		opts := data.opts
+
+		// Everything allocated here is only needed for this request.
+		context.allocator = context.temp_allocator
+
+		rline := req.line.(Requestline)
+		if rline.method != .Get && rline.method != .Head {
+			headers_set(&res.headers, "allow", "GET, HEAD")
+			res.status = .Method_Not_Allowed
+			return
+		}
+
+		rel, status := static_path(req, opts)
+		if status != .Ok {
+			res.status = status
+			return
+		}
+
+		path := filepath.join({data.root, rel})
+		if !static_link_allowed(data, path) {
+			res.status = .NotFound
+			return
+		}
+
+		fi, err := os.stat(path)
+		if err != os.ERROR_NONE {
+			res.status = .NotFound
+			return
+		}
+
+		if !fi.is_dir {
+			// "file.txt/" is not a directory.
+			if strings.has_suffix(rel, "/") {
+				res.status = .NotFound
+				return
+			}
+
+			static_serve_file(data, req, res, path)
+			return
+		}
+
+		// Relative links in the index or listing are resolved against the directory only with a trailing slash.
+		if !strings.has_suffix(req.url.path, "/") {
+			location := strings.concatenate({req.url.path, "/"})
+			if q := strings.index_byte(rline.target, '?'); q >= 0 {
+				location = strings.concatenate({location, rline.target[q:]})
+			}
+
+			headers_set(&res.headers, "location", location)
+			res.status = .Moved_Permanently
+			return
+		}
+
+		if opts.index != "" {
+			index := filepath.join({path, opts.index})
+			if ifi, ierr := os.stat(index); ierr == os.ERROR_NONE && !ifi.is_dir && static_link_allowed(data, index) {
+				static_serve_file(data, req, res, index)
+				return
+			}
+		}
+
+		if !opts.listing {
+			res.status = .Forbidden
+			return
+		}
+
+		static_listing(res, path, req.url.path, opts)
+	}
+
+	return h
+}
+
+// Returns the path of the file to serve, relative to the root, or the status to respond with if it can't be served.
+@(private)
+static_path :: proc(req: ^Request, opts: ^Static_Opts) -> (rel: string, status: Status) {
+	path := req.url.path
+	path = strings.trim_prefix(path, req.route_prefix)
+	path = path_trim_prefix(path, opts.strip_prefix)
+
+	decoded, ok := net.percent_decode(path)
+	if !ok do return "", .Bad_Request
+
+	// Checked after decoding, an encoded NUL or backslash is as dangerous as a literal one.
+	if strings.contains_any(decoded, "\x00\\") do return "", .NotFound
+
+	segments := strings.split(decoded, "/")
+	for segment in segments {
+		if segment == ".." do return "", .NotFound
+		if segment == "." || len(segment) == 0 || segment[0] != '.' do continue
+
+		switch opts.dotfiles {
+		case .Hide:   return "", .NotFound
+		case .Forbid: return "", .Forbidden
+		case .Allow:
+		}
+	}
+
+	return decoded, .Ok
+}
+
+// Checks the symbolic link policy for the path, which has to be absolute and clean.
+@(private)
+static_link_allowed :: proc(data: ^Static_Data, path: string) -> bool {
+	if data.opts.symlinks == .Follow do return true
+
+	real, err := os.absolute_path_from_relative(path)
+	if err != os.ERROR_NONE do return false
+
+	switch data.opts.symlinks {
+	case .Deny:
+		// The root itself is resolved already, so any difference comes from a link inside it.
+		return real == path
+	case .Within_Root:
+		if real == data.root do return true
+		return strings.has_prefix(real, data.root) && (strings.has_suffix(data.root, "/") || real[len(data.root)] == '/')
+	case .Follow:
+	}
+	return true
+}
+
+@(private)
+static_serve_file :: proc(data: ^Static_Data, req: ^Request, res: ^Response, path: string) {
+	opts := data.opts
+	content_type, known := mime_lookup(path)
+	if !known {
+		// Sniffed from the file itself, not from a precompressed one.
+		if fd, err := os.open(path); err == os.ERROR_NONE {
+			sniff: [Mime_Sniff_Length]byte
+			n, _ := os.read(fd, sniff[:])
+			os.close(fd)
+			content_type = mime_sniff(sniff[:max(n, 0)])
+		}
+	}
+	serve := path
+
+	if opts.precompressed {
+		Precompressed :: struct {
+			ext:    string,
+			coding: string,
+		}
+
+		// Preferred in this order when the client accepts both equally.
+		siblings := [?]Precompressed{{".br", "br"}, {".gz", "gzip"}}
+
+		has_sibling := false
+		best_q := 0.0
+		best_coding: string
+		for sibling in siblings {
+			sibling_path := strings.concatenate({path, sibling.ext})
+			sfi, err := os.stat(sibling_path)
+			if err != os.ERROR_NONE || sfi.is_dir || !static_link_allowed(data, sibling_path) do continue
+
+			has_sibling = true
+			if q := accept_encoding_weight(req, sibling.coding); q > best_q {
+				best_q, best_coding, serve = q, sibling.coding, sibling_path
+			}
+		}
+
+		// RFC 9110 12.5.5: the response depends on the Accept-Encoding of the request.
+		if has_sibling {
+			headers_add_vary(&res.headers, "accept-encoding")
+		}
+
+		if best_coding != "" {
+			headers_set(&res.headers, "content-encoding", best_coding)
+		}
+	}
+
+	ext := filepath.ext(path)
+	for rule in opts.cache_control {
+		if rule.ext == ext || rule.ext == "*" {
+			headers_set(&res.headers, "cache-control", rule.value)
+			if rule.ext == ext do break
+		}
+	}
+
+	respond_file_typed(res, serve, content_type)
+
+	// Nothing is sent, so it is not encoded either.
+	if res.status == .NotFound || res.status == .Internal_Server_Error {
+		headers_delete(&res.headers, "content-encoding")
+	}
+}
+
+// Responds with an HTML page listing the files in the directory, directories first.
+@(private)
+static_listing :: proc(res: ^Response, dir, url_path: string, opts: ^Static_Opts) {
+	fd, err := os.open(dir)
+	if err != os.ERROR_NONE {
+		res.status = .NotFound
+		return
+	}
+	defer os.close(fd)
+
+	entries, rerr := os.read_dir(fd, -1)
+	if rerr != os.ERROR_NONE {
+		res.status = .Internal_Server_Error
+		return
+	}
+
+	slice.sort_by(entries, proc(a, b: os.File_Info) -> bool {
+		if a.is_dir != b.is_dir do return a.is_dir
+		return a.name < b.name
+	})
+
+	title := html_escape(url_path)
+
+	b := strings.builder_make()
+	strings.write_string(&b, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Index of ")
+	strings.write_string(&b, title)
+	strings.write_string(&b, "</title>\n</head>\n<body>\n<h1>Index of ")
+	strings.write_string(&b, title)
+	strings.write_string(&b, "</h1>\n<ul>\n")
+
+	if url_path != "/" {
+		strings.write_string(&b, "<li><a href=\"../\">../</a></li>\n")
+	}
+
+	for entry in entries {
+		if opts.dotfiles != .Allow && strings.has_prefix(entry.name, ".") do continue
+
+		href := net.percent_encode(entry.name)
+		name := html_escape(entry.name)
+
+		strings.write_string(&b, "<li><a href=\"")
+		strings.write_string(&b, href)
+		if entry.is_dir do strings.write_byte(&b, '/')
+		strings.write_string(&b, "\">")
+		strings.write_string(&b, name)
+		if entry.is_dir do strings.write_byte(&b, '/')
+		strings.write_string(&b, "</a></li>\n")
+	}
+
+	strings.write_string(&b, "</ul>\n</body>\n</html>\n")
+
+	respond_html(res, strings.to_string(b))
+}
+
+// Escapes the characters that have a meaning in HTML text and attribute values.
+@(private)
+html_escape :: proc(s: string, allocator := context.allocator) -> string {
+	b := strings.builder_make(0, len(s), allocator)
+	for c in s {
+		switch c {
+		case '&':  strings.write_string(&b, "&amp;")
+		case '<':  strings.write_string(&b, "&lt;")
+		case '>':  strings.write_string(&b, "&gt;")
+		case '"':  strings.write_string(&b, "&quot;")
+		case '\'': strings.write_string(&b, "&#39;")
+		case:      strings.write_rune(&b, c)
+		}
+	}
+	return strings.to_string(b)
+}
@@ -0,0 +1,263 @@
+package http
+
+import "core:os"
+import "core:path/filepath"
+import "core:strings"
+import "core:testing"
+
+// Every test serves its own directory inside Static_Test_Dir, so tests that run at the same time don't clash.
+// Next to the root of that directory is a file that must never be served.
+@(private)
+Static_Test_Dir :: "static_test_data"
+@(private)
+Static_Test_Secret :: "not for the internet"
+
+when ODIN_OS != .Windows {
+	foreign import static_test_libc "system:c"
+
+	@(default_calling_convention = "c")
+	foreign static_test_libc {
+		@(link_name = "symlink")
+		static_test_symlink :: proc(target, link_path: cstring) -> i32 ---
+	}
+}
+
+// Creates Static_Test_Dir/name/root, with "public.txt", ".hidden.txt" and an empty "sub" directory inside it.
+// Where symbolic links are supported, "inside.txt" links to "public.txt" and "outside.txt" to the secret.
+@(private)
+static_test_setup :: proc(t: ^testing.T, name: string) -> (dir, root: string) {
+	dir = filepath.join({Static_Test_Dir, name}, context.temp_allocator)
+	root = filepath.join({dir, "root"}, context.temp_allocator)
+
+	os.make_directory(Static_Test_Dir, 0o755)
+	os.make_directory(dir, 0o755)
+	os.make_directory(root, 0o755)
+	os.make_directory(filepath.join({root, "sub"}, context.temp_allocator), 0o755)
+
+	ok := os.write_entire_file(filepath.join({dir, "secret.txt"}, context.temp_allocator), transmute([]byte)string(Static_Test_Secret))
+	ok &= os.write_entire_file(filepath.join({root, "public.txt"}, context.temp_allocator), transmute([]byte)string("public"))
+	ok &= os.write_entire_file(filepath.join({root, ".hidden.txt"}, context.temp_allocator), transmute([]byte)string("hidden"))
+	testing.expect(t, ok, "could not write the test files")
+
+	when ODIN_OS != .Windows {
+		inside := strings.clone_to_cstring(filepath.join({root, "inside.txt"}, context.temp_allocator), context.temp_allocator)
+		outside := strings.clone_to_cstring(filepath.join({root, "outside.txt"}, context.temp_allocator), context.temp_allocator)
+		testing.expect(t, static_test_symlink("public.txt", inside) == 0, "could not create the symbolic links")
+		testing.expect(t, static_test_symlink("../secret.txt", outside) == 0, "could not create the symbolic links")
+	}
+	return
+}
+
+@(private)
+static_test_teardown :: proc(dir: string) {
+	root := filepath.join({dir, "root"}, context.temp_allocator)
+	for file in ([]string{"public.txt", ".hidden.txt", "inside.txt", "outside.txt"}) {
+		os.remove(filepath.join({root, file}, context.temp_allocator))
+	}
+	os.remove(filepath.join({dir, "secret.txt"}, context.temp_allocator))
+	os.remove_directory(filepath.join({root, "sub"}, context.temp_allocator))
+	os.remove_directory(root)
+	os.remove_directory(dir)
+	// Fails while other tests still use it, the last one removes it.
+	os.remove_directory(Static_Test_Dir)
+}
+
+// Calls the handler with a GET request for the target, like the server would.
+@(private)
+static_test_request :: proc(h: ^Handler, target: string) -> (res: Response) {
+	req: Request
+	request_init(&req, context.temp_allocator)
+	req.line = Requestline{method = .Get, target = target, version = {1, 1}}
+	req.url = url_parse(target, context.temp_allocator)
+
+	response_init(&res, context.temp_allocator)
+	h.handle(h, &req, &res)
+	return
+}
+
+@(test)
+test_static_serves_files_inside_root :: proc(t: ^testing.T) {
+	dir, root := static_test_setup(t, "serves")
+	defer static_test_teardown(dir)
+
+	h := static_handler(root)
+	defer free(h.user_data)
+
+	res := static_test_request(&h, "/public.txt")
+	defer response_file_close(&res)
+
+	testing.expect_value(t, res.status, Status.Ok)
+	_, has_file := res._file.?
+	testing.expect(t, has_file, "the file should be the body")
+}
+
+@(test)
+test_static_rejects_encoded_traversal :: proc(t: ^testing.T) {
+	dir, root := static_test_setup(t, "traversal")
+	defer static_test_teardown(dir)
+
+	h := static_handler(root)
+	defer free(h.user_data)
+
+	targets := []string{
+		"/../secret.txt",
+		"/%2e%2e/secret.txt",
+		"/%2E%2E/secret.txt",
+		"/..%2fsecret.txt",
+		"/..%2Fsecret.txt",
+		"/%2e%2e%2fsecret.txt",
+		"/%2E%2E%5Csecret.txt",
+		"/..%5csecret.txt",
+		"/sub/..%2f..%2fsecret.txt",
+		"/sub/%2e%2e/%2e%2e/secret.txt",
+		"/public.txt%00",
+		"/%00../secret.txt",
+		// Double encoded, decoded once these are names of files that don't exist.
+		"/%252e%252e/secret.txt",
+		"/%252e%252e%252fsecret.txt",
+		"/..%252fsecret.txt",
+		"/%252E%252E%255Csecret.txt",
+	}
+
+	for target in targets {
+		res := static_test_request(&h, target)
+		defer response_file_close(&res)
+
+		if res.status != .NotFound {
+			testing.errorf(t, "%q: expected 404 Not Found, got %v", target, res.status)
+		}
+
+		// No file is opened, let alone the one outside the root.
+		if _, has_file := res._file.?; has_file {
+			testing.errorf(t, "%q: a file was opened", target)
+		}
+		if strings.contains(string(res.body.buf[:]), Static_Test_Secret) {
+			testing.errorf(t, "%q: the secret was served", target)
+		}
+	}
+}
+
+@(test)
+test_static_path_decodes_once :: proc(t: ^testing.T) {
+	opts := Default_Static_Opts
+
+	req: Request
+	request_init(&req, context.temp_allocator)
+
+	req.url = url_parse("/%252e%252e/secret.txt", context.temp_allocator)
+	rel, status := static_path(&req, &opts)
+	testing.expect_value(t, status, Status.Ok)
+	testing.expect_value(t, rel, "/%2e%2e/secret.txt")
+
+	req.url = url_parse("/%2e%2e/secret.txt", context.temp_allocator)
+	_, status = static_path(&req, &opts)
+	testing.expect_value(t, status, Status.NotFound)
+}
+
+@(test)
+test_static_path_strip_prefix_whole_segments :: proc(t: ^testing.T) {
+	opts := Default_Static_Opts
+	opts.strip_prefix = "/static"
+
+	Case :: struct {
+		path: string,
+		rel:  string,
+	}
+
+	cases := []Case{
+		{"/static/public.txt", "/public.txt"},
+		{"/static/", "/"},
+		{"/static", ""},
+		{"/staticfoo/public.txt", "/staticfoo/public.txt"},
+		{"/staticfoo", "/staticfoo"},
+		{"/other/static/public.txt", "/other/static/public.txt"},
+	}
+
+	for prefix in ([]string{"/static", "/static/"}) {
+		opts.strip_prefix = prefix
+		for c in cases {
+			req: Request
+			request_init(&req, context.temp_allocator)
+			req.url = url_parse(c.path, context.temp_allocator)
+
+			rel, status := static_path(&req, &opts)
+			testing.expectf(t, status == .Ok && rel == c.rel, "%q with prefix %q: expected %q, got %q (%v)", c.path, prefix, c.rel, rel, status)
+		}
+	}
+}
+
+@(test)
+test_static_dotfiles :: proc(t: ^testing.T) {
+	dir, root := static_test_setup(t, "dotfiles")
+	defer static_test_teardown(dir)
+
+	Case :: struct {
+		dotfiles: Static_Dotfiles,
+		status:   Status,
+	}
+
+	cases := []Case{{.Hide, .NotFound}, {.Forbid, .Forbidden}, {.Allow, .Ok}}
+
+	for c in cases {
+		opts := Default_Static_Opts
+		opts.dotfiles = c.dotfiles
+
+		h := static_handler(root, &opts)
+		defer free(h.user_data)
+
+		res := static_test_request(&h, "/.hidden.txt")
+		defer response_file_close(&res)
+		testing.expectf(t, res.status == c.status, "%v: expected %v, got %v", c.dotfiles, c.status, res.status)
+
+		// Encoded dots are the same file.
+		eres := static_test_request(&h, "/%2ehidden.txt")
+		defer response_file_close(&eres)
+		testing.expectf(t, eres.status == c.status, "%v encoded: expected %v, got %v", c.dotfiles, c.status, eres.status)
+
+		// Only the listing of a policy that allows them shows dotfiles.
+		opts.listing = true
+		lres := static_test_request(&h, "/")
+		listing := string(lres.body.buf[:])
+		testing.expect_value(t, lres.status, Status.Ok)
+		testing.expect(t, strings.contains(listing, "public.txt"), "the listing shows files")
+		testing.expectf(t, strings.contains(listing, ".hidden.txt") == (c.dotfiles == .Allow), "%v: dotfiles in the listing", c.dotfiles)
+	}
+}
+
+@(test)
+test_static_symlinks :: proc(t: ^testing.T) {
+	when ODIN_OS == .Windows {
+		return
+	}
+
+	dir, root := static_test_setup(t, "symlinks")
+	defer static_test_teardown(dir)
+
+	Case :: struct {
+		symlinks: Static_Symlinks,
+		target:   string,
+		status:   Status,
+	}
+
+	cases := []Case{
+		{.Within_Root, "/inside.txt", .Ok},
+		{.Within_Root, "/outside.txt", .NotFound},
+		{.Follow, "/inside.txt", .Ok},
+		{.Follow, "/outside.txt", .Ok},
+		{.Deny, "/inside.txt", .NotFound},
+		{.Deny, "/outside.txt", .NotFound},
+		{.Deny, "/public.txt", .Ok},
+	}
+
+	for c in cases {
+		opts := Default_Static_Opts
+		opts.symlinks = c.symlinks
+
+		h := static_handler(root, &opts)
+		defer free(h.user_data)
+
+		res := static_test_request(&h, c.target)
+		defer response_file_close(&res)
+		testing.expectf(t, res.status == c.status, "%v %q: expected %v, got %v", c.symlinks, c.target, c.status, res.status)
+	}
+}
@@ -27,7 +27,7 @@ _socket_stream_proc :: proc(
 ) {
 	#partial switch mode {
 	case .Query:
-		return io.query_utility(io.Stream_Mode_Set{.Query, .Read})
+		return io.query_utility(io.Stream_Mode_Set{.Query, .Read, .Write})
 	case .Read:
 		sock := net.TCP_Socket(uintptr(stream_data))
 		received, recv_err := net.recv_tcp(sock, p)
@@ -37,8 +37,11 @@ _socket_stream_proc :: proc(
 		case net.TCP_Recv_Error:
 			#partial switch ex {
 			case .None:
-				err = .None
-			case .Shutdown, .Not_Connected, .Aborted, .Connection_Closed, .Host_Unreachable, .Timeout:
+				err = .EOF if received == 0 else .None
+			case .Timeout:
+				// The receive timeout set by the server expired, it detects and handles this itself.
+				err = .Unexpected_EOF
+			case .Shutdown, .Not_Connected, .Aborted, .Connection_Closed, .Host_Unreachable:
 				log.errorf("unexpected error reading tcp: %s", ex)
 				err = .Unexpected_EOF
 			case:
@@ -46,10 +49,20 @@ _socket_stream_proc :: proc(
 				err = .Unknown
 			}
 		case nil:
-			err = .None
+			// Receiving nothing without an error means the peer closed the connection.
+			err = .EOF if received == 0 else .None
 		case:
 			assert(false, "recv_tcp only returns TCP_Recv_Error or nil")
 		}
+	case .Write:
+		sock := net.TCP_Socket(uintptr(stream_data))
+		sent, send_err := net.send_tcp(sock, p)
+		n = i64(sent)
+
+		if send_err != nil {
+			log.errorf("unexpected error writing tcp: %s", send_err)
+			err = .Unexpected_EOF
+		}
 	case:
 		err = .Empty
 	}
@@ -75,15 +88,30 @@ _ssl_stream_proc :: proc(
 ) {
 	#partial switch mode {
 	case .Query:
-		return io.query_utility(io.Stream_Mode_Set{.Query, .Read})
+		return io.query_utility(io.Stream_Mode_Set{.Query, .Read, .Write})
 	case .Read:
 		ssl := cast(^openssl.SSL)stream_data
 		ret := openssl.SSL_read(ssl, raw_data(p), c.int(len(p)))
 		if ret <= 0 {
+			// The peer sent a close_notify, a clean end of the stream.
+			if openssl.SSL_get_error(ssl, ret) == openssl.SSL_ERROR_ZERO_RETURN {
+				return 0, .EOF
+			}
 			return 0, .Unexpected_EOF
 		}
 
 		return i64(ret), nil
+	case .Write:
+		ssl := cast(^openssl.SSL)stream_data
+		for n < i64(len(p)) {
+			ret := openssl.SSL_write(ssl, raw_data(p[n:]), c.int(len(p) - int(n)))
+			if ret <= 0 {
+				log.errorf("unexpected error writing tls: %i", openssl.SSL_get_error(ssl, ret))
+				return n, .Unexpected_EOF
+			}
+
+			n += i64(ret)
+		}
 	case:
 		err = .Empty
 	}
@@ -0,0 +1,215 @@
+package http
+
+import "core:c"
+import "core:log"
+import "core:runtime"
+import "core:strings"
+
+import "openssl"
+
+TLS_Certificate :: struct {
+	// Path to a PEM encoded certificate, can contain the chain of intermediate certificates after it.
+	cert_file: string,
+	// Path to the PEM encoded private key of the certificate.
+	key_file:  string,
+	// The host names this certificate is selected for using SNI, can start with a wildcard like "*.example.com".
+	// Not used for the default certificate.
+	hosts:     []string,
+}
+
+Server_TLS_Opts :: struct {
+	// The certificate that is used when the client does not send a server name (SNI),
+	// or when none of the sni_certificates match it.
+	certificate:      TLS_Certificate,
+	// Certificates that are selected based on the server name the client requests (SNI).
+	sni_certificates: []TLS_Certificate,
+	// The protocols advertised using ALPN, in order of preference.
+	// Defaults to "h2" and "http/1.1", or just "http/1.1" when Server_Opts.http2 is false.
+	alpn:             []string,
+}
+
+TLS_Error :: enum {
+	None,
+	// Could not create the OpenSSL context.
+	Context_Failed,
+	// Could not load the certificate (chain) file.
+	Certificate_Failed,
+	// Could not load the private key file.
+	Private_Key_Failed,
+	// The private key does not belong to the certificate.
+	Private_Key_Mismatch,
+}
+
+@(private)
+Server_TLS_SNI :: struct {
+	hosts: []string,
+	ctx:   ^openssl.SSL_CTX,
+}
+
+@(private)
+Server_TLS :: struct {
+	// Context of the default certificate, every connection is created with this context.
+	ctx:  ^openssl.SSL_CTX,
+	sni:  [dynamic]Server_TLS_SNI,
+	// The protocols to advertise in the ALPN wire format (length prefixed).
+	alpn: []byte,
+}
+
+@(private)
+Default_ALPN := []string{"http/1.1"}
+
+@(private)
+Default_ALPN_H2 := []string{"h2", "http/1.1"}
+
+// Loads the certificates and sets up the OpenSSL contexts.
+@(private)
+server_tls_init :: proc(t: ^Server_TLS, opts: Server_TLS_Opts) -> (err: TLS_Error) {
+	defer if err != nil do server_tls_destroy(t)
+
+	protocols := opts.alpn if len(opts.alpn) > 0 else Default_ALPN
+	alpn := strings.builder_make()
+	for proto in protocols {
+		strings.write_byte(&alpn, u8(len(proto)))
+		strings.write_string(&alpn, proto)
+	}
+	t.alpn = alpn.buf[:]
+
+	t.ctx = server_tls_ctx(t, opts.certificate) or_return
+
+	t.sni = make([dynamic]Server_TLS_SNI, 0, len(opts.sni_certificates))
+	for cert in opts.sni_certificates {
+		ctx := server_tls_ctx(t, cert) or_return
+		append(&t.sni, Server_TLS_SNI{hosts = cert.hosts, ctx = ctx})
+	}
+
+	return nil
+}
+
+@(private)
+server_tls_destroy :: proc(t: ^Server_TLS) {
+	if t.ctx != nil do openssl.SSL_CTX_free(t.ctx)
+	for sni in t.sni {
+		openssl.SSL_CTX_free(sni.ctx)
+	}
+	delete(t.sni)
+	delete(t.alpn)
+}
+
+@(private)
+server_tls_ctx :: proc(t: ^Server_TLS, cert: TLS_Certificate) -> (ctx: ^openssl.SSL_CTX, err: TLS_Error) {
+	ctx = openssl.SSL_CTX_new(openssl.TLS_server_method())
+	if ctx == nil {
+		return nil, .Context_Failed
+	}
+	defer if err != nil do openssl.SSL_CTX_free(ctx)
+
+	cert_file := strings.clone_to_cstring(cert.cert_file, context.temp_allocator)
+	if openssl.SSL_CTX_use_certificate_chain_file(ctx, cert_file) != 1 {
+		log.errorf("could not load TLS certificate %q", cert.cert_file)
+		return nil, .Certificate_Failed
+	}
+
+	key_file := strings.clone_to_cstring(cert.key_file, context.temp_allocator)
+	if openssl.SSL_CTX_use_PrivateKey_file(ctx, key_file, openssl.SSL_FILETYPE_PEM) != 1 {
+		log.errorf("could not load TLS private key %q", cert.key_file)
+		return nil, .Private_Key_Failed
+	}
+
+	if openssl.SSL_CTX_check_private_key(ctx) != 1 {
+		log.errorf("TLS private key %q does not match certificate %q", cert.key_file, cert.cert_file)
+		return nil, .Private_Key_Mismatch
+	}
+
+	// Set on every context because the context is switched to the SNI one before ALPN is negotiated.
+	openssl.SSL_CTX_set_alpn_select_cb(ctx, server_tls_alpn_select, t)
+	openssl.SSL_CTX_set_tlsext_servername_callback(ctx, server_tls_servername)
+	openssl.SSL_CTX_set_tlsext_servername_arg(ctx, t)
+
+	return ctx, nil
+}
+
+// Picks the first protocol in our preference list that the client also supports.
+@(private)
+server_tls_alpn_select :: proc "c" (
+	ssl: ^openssl.SSL,
+	out: ^[^]byte,
+	outlen: ^u8,
+	input: [^]byte,
+	inlen: c.uint,
+	arg: rawptr,
+) -> c.int {
+	t := (^Server_TLS)(arg)
+
+	res := openssl.SSL_select_next_proto(out, outlen, raw_data(t.alpn), c.uint(len(t.alpn)), input, inlen)
+	if res != openssl.OPENSSL_NPN_NEGOTIATED {
+		return openssl.SSL_TLSEXT_ERR_NOACK
+	}
+
+	return openssl.SSL_TLSEXT_ERR_OK
+}
+
+// Switches the connection to the context of the certificate that matches the requested server name.
+@(private)
+server_tls_servername :: proc "c" (ssl: ^openssl.SSL, alert: ^c.int, arg: rawptr) -> c.int {
+	context = runtime.default_context()
+	t := (^Server_TLS)(arg)
+
+	name := openssl.SSL_get_servername(ssl, openssl.TLSEXT_NAMETYPE_host_name)
+	if name == nil {
+		return openssl.SSL_TLSEXT_ERR_OK
+	}
+
+	if ctx, ok := server_tls_sni_match(t, string(name)); ok {
+		openssl.SSL_set_SSL_CTX(ssl, ctx)
+	}
+
+	return openssl.SSL_TLSEXT_ERR_OK
+}
+
+@(private)
+server_tls_sni_match :: proc(t: ^Server_TLS, name: string) -> (^openssl.SSL_CTX, bool) {
+	for sni in t.sni {
+		for host in sni.hosts {
+			if tls_host_matches(host, name) {
+				return sni.ctx, true
+			}
+		}
+	}
+
+	return nil, false
+}
+
+// Whether the host name matches the pattern, the pattern can start with a "*." wildcard,
+// which matches exactly one label (like certificates do).
+@(private)
+tls_host_matches :: proc(pattern, name: string) -> bool {
+	if !strings.has_prefix(pattern, "*.") {
+		return strings.equal_fold(pattern, name)
+	}
+
+	dot := strings.index_byte(name, '.')
+	if dot <= 0 do return false
+
+	return strings.equal_fold(pattern[1:], name[dot:])
+}
+
+// Performs the TLS handshake of a newly accepted connection.
+// Blocks until the handshake is done, the event loop makes sure the client has sent something already.
+@(private)
+connection_tls_accept :: proc(conn: ^Connection) -> bool {
+	ret := openssl.SSL_accept(conn.ssl)
+	if ret != 1 {
+		log.warnf("TLS handshake with %v failed: %i", conn.client.address, openssl.SSL_get_error(conn.ssl, ret))
+		return false
+	}
+
+	proto: [^]byte
+	proto_len: c.uint
+	openssl.SSL_get0_alpn_selected(conn.ssl, &proto, &proto_len)
+	if proto_len > 0 {
+		conn.protocol = string(proto[:proto_len])
+	}
+
+	conn.tls_accepted = true
+	return true
+}
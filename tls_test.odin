@@ -0,0 +1,149 @@
+package http
+
+import "core:c"
+import "core:net"
+import "core:strings"
+import "core:testing"
+
+import "openssl"
+
+// Self-signed certificates for "localhost" (the default) and "sni.test", created with:
+// openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes -days 36500 \
+//   -subj "/CN=$name" -addext "subjectAltName=DNS:$name" -keyout $name.key -out $name.pem
+@(private)
+TLS_Test_Default :: TLS_Certificate{cert_file = "testdata/localhost.pem", key_file = "testdata/localhost.key"}
+
+// Starts a server with the default certificate, and the sni.test certificate for the given hosts.
+@(private)
+tls_test_start :: proc(t: ^testing.T, s: ^Test_Server, sni_hosts: []string) -> bool {
+	tls := Server_TLS_Opts{
+		certificate      = TLS_Test_Default,
+		sni_certificates = {{cert_file = "testdata/sni.test.pem", key_file = "testdata/sni.test.key", hosts = sni_hosts}},
+	}
+
+	h := handler(proc(req: ^Request, res: ^Response) {
+		respond_plain(res, "hello over TLS")
+	})
+	return test_server_start(t, s, h, Default_Server_Opts, tls)
+}
+
+// Connects to the test server, sending the server name when it is not empty, and offering the protocols using ALPN.
+// The handshake only succeeds when the server presents a certificate for host, signed by (the self-signed) ca_file.
+// Returns the protocol the server selected, and the response to a request when that is HTTP/1.1.
+@(private)
+tls_test_connect :: proc(s: ^Test_Server, server_name, host, ca_file: string, alpn: []string) -> (selected, response: string, ok: bool) {
+	using openssl
+
+	ctx := SSL_CTX_new(TLS_client_method())
+	defer SSL_CTX_free(ctx)
+	SSL_CTX_load_verify_locations(ctx, strings.clone_to_cstring(ca_file, context.temp_allocator), nil)
+	SSL_CTX_set_verify(ctx, SSL_VERIFY_PEER, nil)
+
+	sock, err := net.dial_tcp(s.endpoint)
+	if err != nil do return
+	defer net.close(sock)
+
+	ssl := SSL_new(ctx)
+	defer SSL_free(ssl)
+	SSL_set_fd(ssl, c.int(sock))
+	SSL_set1_host(ssl, strings.clone_to_cstring(host, context.temp_allocator))
+	if server_name != "" {
+		SSL_set_tlsext_host_name(ssl, strings.clone_to_cstring(server_name, context.temp_allocator))
+	}
+
+	wire := strings.builder_make(context.temp_allocator)
+	for proto in alpn {
+		strings.write_byte(&wire, u8(len(proto)))
+		strings.write_string(&wire, proto)
+	}
+	SSL_set_alpn_protos(ssl, raw_data(wire.buf), c.uint(len(wire.buf)))
+
+	if SSL_connect(ssl) != 1 do return
+
+	proto: [^]byte
+	proto_len: c.uint
+	SSL_get0_alpn_selected(ssl, &proto, &proto_len)
+	selected = strings.clone(string(proto[:proto_len]), context.temp_allocator)
+
+	if selected == "http/1.1" {
+		request := "GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n"
+		SSL_write(ssl, raw_data(request), c.int(len(request)))
+
+		b := strings.builder_make(context.temp_allocator)
+		buf: [1024]byte
+		for {
+			n := SSL_read(ssl, &buf[0], c.int(len(buf)))
+			if n <= 0 do break
+			strings.write_bytes(&b, buf[:n])
+		}
+		response = strings.to_string(b)
+	}
+
+	SSL_shutdown(ssl)
+	return selected, response, true
+}
+
+@(test)
+test_tls_default_certificate :: proc(t: ^testing.T) {
+	s: Test_Server
+	if !tls_test_start(t, &s, {"sni.test"}) do return
+	defer test_server_stop(&s)
+
+	_, response, ok := tls_test_connect(&s, "", "localhost", "testdata/localhost.pem", {"http/1.1"})
+	testing.expect(t, ok, "the default certificate is used without SNI")
+	testing.expect(t, strings.has_prefix(response, "HTTP/1.1 200"), "a request over TLS is answered")
+	testing.expect(t, strings.has_suffix(response, "hello over TLS"), "a request over TLS is answered")
+
+	_, _, ok = tls_test_connect(&s, "unknown.test", "localhost", "testdata/localhost.pem", {"http/1.1"})
+	testing.expect(t, ok, "the default certificate is used for server names without a certificate")
+}
+
+@(test)
+test_tls_sni_selects_certificate :: proc(t: ^testing.T) {
+	s: Test_Server
+	if !tls_test_start(t, &s, {"sni.test"}) do return
+	defer test_server_stop(&s)
+
+	_, _, ok := tls_test_connect(&s, "sni.test", "sni.test", "testdata/sni.test.pem", {"http/1.1"})
+	testing.expect(t, ok, "the certificate of the server name is used")
+
+	_, _, ok = tls_test_connect(&s, "sni.test", "localhost", "testdata/localhost.pem", {"http/1.1"})
+	testing.expect(t, !ok, "the default certificate is not used when the server name has a certificate")
+}
+
+@(test)
+test_tls_sni_wildcard :: proc(t: ^testing.T) {
+	s: Test_Server
+	if !tls_test_start(t, &s, {"*.test"}) do return
+	defer test_server_stop(&s)
+
+	// The certificate is selected by the wildcard, it is only valid for sni.test though, which is what is checked.
+	_, _, ok := tls_test_connect(&s, "sni.test", "sni.test", "testdata/sni.test.pem", {"http/1.1"})
+	testing.expect(t, ok, "a wildcard matches subdomains")
+
+	_, _, ok = tls_test_connect(&s, "deeper.sni.test", "localhost", "testdata/localhost.pem", {"http/1.1"})
+	testing.expect(t, ok, "a wildcard matches a single label")
+}
+
+@(test)
+test_tls_alpn :: proc(t: ^testing.T) {
+	s: Test_Server
+	if !tls_test_start(t, &s, {"sni.test"}) do return
+	defer test_server_stop(&s)
+
+	selected, _, ok := tls_test_connect(&s, "", "localhost", "testdata/localhost.pem", {"h2", "http/1.1"})
+	testing.expect(t, ok, "handshake with ALPN")
+	testing.expect_value(t, selected, "h2")
+
+	selected, _, ok = tls_test_connect(&s, "", "localhost", "testdata/localhost.pem", {"http/1.1", "h2"})
+	testing.expect(t, ok, "handshake with ALPN")
+	testing.expect_value(t, selected, "h2")
+
+	selected, _, ok = tls_test_connect(&s, "", "localhost", "testdata/localhost.pem", {"http/1.1"})
+	testing.expect(t, ok, "handshake with ALPN")
+	testing.expect_value(t, selected, "http/1.1")
+
+	selected, _, ok = tls_test_connect(&s, "", "localhost", "testdata/localhost.pem", {"spdy/3"})
+	testing.expect(t, ok, "a client without a protocol in common still connects")
+	testing.expect_value(t, selected, "")
+}
@@ -0,0 +1,460 @@
+package http
+
+import "core:bytes"
+import "core:crypto/sha1"
+import "core:encoding/base64"
+import "core:io"
+import "core:log"
+import "core:mem"
+import "core:strings"
+import "core:sync"
+import "core:unicode/utf8"
+
+// WebSockets (RFC 6455).
+//
+// A handler calls websocket_upgrade, which responds to the handshake and takes the connection over from the server,
+// the handler can then send and receive messages, on its thread or by passing the Websocket to another thread.
+// The handler's thread is not part of the worker pool anymore, see response_hijack, so open WebSockets don't hold workers.
+// When the server shuts down, WebSockets that are still open after Server_Opts.shutdown_timeout are cut off,
+// websocket_read then returns an error, after which the Websocket is closed with websocket_close as usual.
+//
+// WebSockets are only supported over HTTP/1.1.
+
+Websocket_Opts :: struct {
+	// The maximum size of a message, after combining its fragments.
+	// Defaults to 16MB.
+	max_message_size: int,
+	// The subprotocols supported, in order of preference.
+	// The first one that the client also requests is selected.
+	protocols:        []string,
+	// Called with the Origin header of the request (empty if there is none), return false to deny the upgrade.
+	// Defaults to nil, which allows every origin.
+	check_origin:     proc(req: ^Request, origin: string) -> bool,
+}
+
+Default_Websocket_Opts :: Websocket_Opts {
+	max_message_size = 16 << 20,
+}
+
+Websocket_Error :: enum {
+	None,
+	// The request is not a valid WebSocket handshake.
+	Invalid_Handshake,
+	// The client uses a version of the protocol other than 13.
+	Unsupported_Version,
+	// The check_origin procedure denied the upgrade.
+	Origin_Denied,
+	// The client violated the protocol, the connection has been closed.
+	Protocol_Error,
+	// A message is bigger than max_message_size, the connection has been closed.
+	Message_Too_Big,
+	// A text message is not valid UTF-8, the connection has been closed.
+	Invalid_Utf8,
+	// Reading or writing the connection failed, the connection has been closed.
+	Connection_Failed,
+	// The WebSocket is closed, by us or the client.
+	Closed,
+}
+
+Websocket_Message_Type :: enum {
+	Text,
+	Binary,
+}
+
+Websocket_Message :: struct {
+	type: Websocket_Message_Type,
+	data: []byte,
+}
+
+// RFC 6455 7.4.1.
+Websocket_Close_Code :: enum u16 {
+	Normal              = 1000,
+	Going_Away          = 1001,
+	Protocol_Error      = 1002,
+	Unsupported_Data    = 1003,
+	// Used when the client closed without a code, never sent.
+	No_Status           = 1005,
+	// Used when the connection closed without a close frame, never sent.
+	Abnormal            = 1006,
+	Invalid_Payload     = 1007,
+	Policy_Violation    = 1008,
+	Message_Too_Big     = 1009,
+	Mandatory_Extension = 1010,
+	Internal_Error      = 1011,
+}
+
+Websocket :: struct {
+	// The subprotocol that was selected, empty if none.
+	protocol:     string,
+	// Set once the client sent a close frame.
+	close_code:   Websocket_Close_Code,
+	close_reason: string,
+
+	conn:         ^Connection,
+	opts:         Websocket_Opts,
+	allocator:    mem.Allocator,
+	// Writes can come from different threads, like a broadcast, frames should not be interleaved.
+	write_mu:     sync.Mutex,
+	close_sent:   bool,
+	closed:       bool,
+}
+
+@(private)
+Websocket_Opcode :: enum u8 {
+	Continuation = 0x0,
+	Text         = 0x1,
+	Binary       = 0x2,
+	Close        = 0x8,
+	Ping         = 0x9,
+	Pong         = 0xa,
+}
+
+@(private)
+WEBSOCKET_GUID :: "258EAFA5-E914-47DA-95CA-C5AB0DC11D65"
+
+// Upgrades the request to a WebSocket, the 101 response is sent right away and the connection is taken over.
+// The Response should not be used after a successful upgrade, and the handler can return whenever it wants.
+//
+// When the handshake is invalid, the response is set to the appropriate error status and an error is returned.
+//
+// The Websocket is allocated with the given allocator (not the request's, it outlives the request),
+// and has to be closed with websocket_close, which also frees it.
+//
+// With TLS, OpenSSL does not allow reading and writing at the same time,
+// so the Websocket should then only be used from one thread at a time.
+websocket_upgrade :: proc(
+	req: ^Request,
+	res: ^Response,
+	opts := Default_Websocket_Opts,
+	allocator := context.allocator,
+) -> (
+	ws: ^Websocket,
+	err: Websocket_Error,
+) {
+	conn := res._conn
+	assert(conn != nil, "response is not attached to a connection")
+
+	res.status = .Bad_Request
+
+	// RFC 6455 4.2.1.
+	rline := req.line.(Requestline)
+	if conn.h2 != nil || rline.method != .Get || rline.version != (Version{1, 1}) {
+		return nil, .Invalid_Handshake
+	}
+
+	if !header_has_token(headers_get(&req.headers, "upgrade") or_else "", "websocket") ||
+	   !header_has_token(headers_get(&req.headers, "connection") or_else "", "upgrade") {
+		return nil, .Invalid_Handshake
+	}
+
+	if version := headers_get(&req.headers, "sec-websocket-version") or_else ""; version != "13" {
+		res.status = .Upgrade_Required
+		headers_set(&res.headers, "sec-websocket-version", "13")
+		return nil, .Unsupported_Version
+	}
+
+	key := headers_get(&req.headers, "sec-websocket-key") or_else ""
+	if decoded := base64.decode(key, base64.DEC_TABLE, req.allocator); len(decoded) != 16 {
+		return nil, .Invalid_Handshake
+	}
+
+	if opts.check_origin != nil && !opts.check_origin(req, headers_get(&req.headers, "origin") or_else "") {
+		res.status = .Forbidden
+		return nil, .Origin_Denied
+	}
+
+	protocol: string
+	if requested, ok := headers_get(&req.headers, "sec-websocket-protocol"); ok {
+		Protocols: for supported in opts.protocols {
+			requested := requested
+			for p in strings.split_iterator(&requested, ",") {
+				if strings.trim_space(p) == supported {
+					protocol = supported
+					break Protocols
+				}
+			}
+		}
+	}
+
+	accept := sha1.hash_string(strings.concatenate({key, WEBSOCKET_GUID}, req.allocator))
+
+	res.status = .Switching_Protocols
+	headers_clear(&res.headers)
+	headers_set(&res.headers, "upgrade", "websocket")
+	headers_set(&res.headers, "connection", "Upgrade")
+	headers_set(&res.headers, "sec-websocket-accept", base64.encode(accept[:], base64.ENC_TABLE, req.allocator))
+	if protocol != "" {
+		headers_set(&res.headers, "sec-websocket-protocol", protocol)
+	}
+
+	head: bytes.Buffer
+	bytes.buffer_init_allocator(&head, 0, 160, req.allocator)
+	response_write_head(res, &head, req.allocator)
+
+	// Taken over, the server won't respond or handle any more requests on this connection.
+	connection_hijack(conn)
+	res._headers_sent = true
+
+	if _, werr := io.write(io.to_writer(conn.stream), bytes.buffer_to_bytes(&head)); werr != nil {
+		log.warnf("could not send websocket handshake response: %s", werr)
+		connection_close(conn)
+		return nil, .Connection_Failed
+	}
+
+	ws = new(Websocket, allocator)
+	ws.conn = conn
+	ws.opts = opts
+	ws.allocator = allocator
+	ws.protocol = protocol
+	ws.close_code = .No_Status
+	return ws, nil
+}
+
+// Reads the next message, blocking until there is one.
+// Control frames are handled while reading, pings are answered with a pong.
+//
+// When the client closes the connection, .Closed is returned and the close code is available in ws.close_code,
+// the Websocket should then be closed with websocket_close.
+websocket_read :: proc(ws: ^Websocket, allocator := context.allocator) -> (msg: Websocket_Message, err: Websocket_Error) {
+	if ws.closed do return msg, .Closed
+
+	data := make([dynamic]byte, allocator)
+	defer if err != nil do delete(data)
+
+	started := false
+	for {
+		header: [2]byte
+		websocket_read_full(ws, header[:]) or_return
+
+		fin    := header[0] & 0x80 != 0
+		rsv    := header[0] & 0x70
+		opcode := Websocket_Opcode(header[0] & 0x0f)
+		masked := header[1] & 0x80 != 0
+		length := u64(header[1] & 0x7f)
+
+		// No extensions are negotiated, so the reserved bits must be zero.
+		// RFC 6455 5.1: The server MUST close the connection upon receiving a frame that is not masked.
+		if rsv != 0 || !masked {
+			return msg, websocket_fail(ws, .Protocol_Error, .Protocol_Error)
+		}
+
+		switch length {
+		case 126:
+			ext: [2]byte
+			websocket_read_full(ws, ext[:]) or_return
+			length = u64(ext[0]) << 8 | u64(ext[1])
+		case 127:
+			ext: [8]byte
+			websocket_read_full(ws, ext[:]) or_return
+			length = 0
+			for b in ext do length = length << 8 | u64(b)
+		}
+
+		mask: [4]byte
+		websocket_read_full(ws, mask[:]) or_return
+
+		#partial switch opcode {
+		case .Close, .Ping, .Pong:
+			// RFC 6455 5.5: Control frames have a payload of at most 125 bytes, and can't be fragmented.
+			if !fin || length > 125 {
+				return msg, websocket_fail(ws, .Protocol_Error, .Protocol_Error)
+			}
+
+			payload_buf: [125]byte
+			payload := payload_buf[:int(length)]
+			websocket_read_full(ws, payload) or_return
+			websocket_unmask(payload, mask)
+
+			#partial switch opcode {
+			case .Ping:
+				websocket_write_frame(ws, .Pong, payload) or_return
+			case .Close:
+				return msg, websocket_on_close(ws, payload)
+			}
+			continue
+
+		case .Continuation:
+			if !started do return msg, websocket_fail(ws, .Protocol_Error, .Protocol_Error)
+
+		case .Text, .Binary:
+			if started do return msg, websocket_fail(ws, .Protocol_Error, .Protocol_Error)
+			started = true
+			msg.type = .Text if opcode == .Text else .Binary
+
+		case:
+			return msg, websocket_fail(ws, .Protocol_Error, .Protocol_Error)
+		}
+
+		if u64(len(data)) + length > u64(ws.opts.max_message_size) {
+			return msg, websocket_fail(ws, .Message_Too_Big, .Message_Too_Big)
+		}
+
+		prev := len(data)
+		resize(&data, prev + int(length))
+		websocket_read_full(ws, data[prev:]) or_return
+		websocket_unmask(data[prev:], mask)
+
+		if !fin do continue
+
+		if msg.type == .Text && !utf8.valid_string(string(data[:])) {
+			return msg, websocket_fail(ws, .Invalid_Payload, .Invalid_Utf8)
+		}
+
+		msg.data = data[:]
+		return msg, nil
+	}
+}
+
+// Sends a message, can be called from multiple threads (see websocket_upgrade about TLS).
+websocket_write :: proc(ws: ^Websocket, data: []byte, type: Websocket_Message_Type = .Text) -> Websocket_Error {
+	return websocket_write_frame(ws, .Text if type == .Text else .Binary, data)
+}
+
+websocket_write_string :: proc(ws: ^Websocket, data: string, type: Websocket_Message_Type = .Text) -> Websocket_Error {
+	return websocket_write(ws, transmute([]byte)data, type)
+}
+
+// Sends a ping, the client responds with a pong, which websocket_read ignores.
+websocket_ping :: proc(ws: ^Websocket, payload: []byte = nil) -> Websocket_Error {
+	assert(len(payload) <= 125, "ping payload can be at most 125 bytes")
+	return websocket_write_frame(ws, .Ping, payload)
+}
+
+// Closes the WebSocket with the given code and reason, and frees it.
+websocket_close :: proc(ws: ^Websocket, code := Websocket_Close_Code.Normal, reason := "") {
+	if !ws.closed {
+		websocket_send_close(ws, code, reason)
+		ws.closed = true
+		connection_close(ws.conn)
+	}
+
+	delete(ws.close_reason, ws.allocator)
+	free(ws, ws.allocator)
+}
+
+@(private)
+websocket_on_close :: proc(ws: ^Websocket, payload: []byte) -> Websocket_Error {
+	if len(payload) == 1 {
+		return websocket_fail(ws, .Protocol_Error, .Protocol_Error)
+	}
+
+	ws.close_code = .No_Status
+	if len(payload) >= 2 {
+		code := u16(payload[0]) << 8 | u16(payload[1])
+		reason := string(payload[2:])
+
+		// RFC 6455 7.4: 1005 and 1006 are never sent, and 1012-2999 are reserved.
+		valid := (code >= 1000 && code <= 1011 && code != 1004 && code != 1005 && code != 1006) || (code >= 3000 && code <= 4999)
+		if !valid {
+			return websocket_fail(ws, .Protocol_Error, .Protocol_Error)
+		}
+
+		if !utf8.valid_string(reason) {
+			return websocket_fail(ws, .Invalid_Payload, .Invalid_Utf8)
+		}
+
+		ws.close_code = Websocket_Close_Code(code)
+		ws.close_reason = strings.clone(reason, ws.allocator)
+	}
+
+	// Echo the close back, RFC 6455 5.5.1.
+	code := ws.close_code if ws.close_code != .No_Status else .Normal
+	websocket_send_close(ws, code, "")
+
+	ws.closed = true
+	connection_close(ws.conn)
+	return .Closed
+}
+
+// Closes the connection because of an error, returns the given error to pass along.
+@(private)
+websocket_fail :: proc(ws: ^Websocket, code: Websocket_Close_Code, err: Websocket_Error) -> Websocket_Error {
+	log.debugf("closing websocket: %v", err)
+
+	websocket_send_close(ws, code, "")
+	ws.closed = true
+	connection_close(ws.conn)
+	return err
+}
+
+@(private)
+websocket_send_close :: proc(ws: ^Websocket, code: Websocket_Close_Code, reason: string) {
+	if ws.close_sent do return
+
+	payload: [125]byte
+	payload[0] = u8(u16(code) >> 8)
+	payload[1] = u8(u16(code))
+	n := 2 + copy(payload[2:], reason)
+
+	websocket_write_frame(ws, .Close, payload[:n])
+	ws.close_sent = true
+}
+
+@(private)
+websocket_write_frame :: proc(ws: ^Websocket, opcode: Websocket_Opcode, payload: []byte) -> Websocket_Error {
+	sync.guard(&ws.write_mu)
+
+	if ws.closed || ws.close_sent do return .Closed
+
+	// Frames from the server are never masked.
+	header: [10]byte
+	header[0] = 0x80 | u8(opcode)
+	n := 2
+	switch {
+	case len(payload) < 126:
+		header[1] = u8(len(payload))
+	case len(payload) <= 0xffff:
+		header[1] = 126
+		header[2] = u8(len(payload) >> 8)
+		header[3] = u8(len(payload))
+		n = 4
+	case:
+		header[1] = 127
+		length := u64(len(payload))
+		for i in 0 ..< 8 {
+			header[9 - i] = u8(length >> (8 * uint(i)))
+		}
+		n = 10
+	}
+
+	w := io.to_writer(ws.conn.stream)
+	if _, err := io.write(w, header[:n]); err != nil {
+		log.warnf("could not write websocket frame: %s", err)
+		ws.closed = true
+		connection_close(ws.conn)
+		return .Connection_Failed
+	}
+
+	if _, err := io.write(w, payload); err != nil {
+		log.warnf("could not write websocket frame: %s", err)
+		ws.closed = true
+		connection_close(ws.conn)
+		return .Connection_Failed
+	}
+
+	return nil
+}
+
+@(private)
+websocket_read_full :: proc(ws: ^Websocket, buf: []byte) -> Websocket_Error {
+	n := 0
+	for n < len(buf) {
+		read, err := scanner_read(&ws.conn.scanner, buf[n:])
+		n += read
+
+		if err != nil && n < len(buf) {
+			ws.closed = true
+			ws.close_code = .Abnormal
+			connection_close(ws.conn)
+			return .Connection_Failed if err != .EOF else .Closed
+		}
+	}
+	return nil
+}
+
+@(private)
+websocket_unmask :: proc(data: []byte, mask: [4]byte) {
+	for _, i in data {
+		data[i] ~= mask[i % 4]
+	}
+}